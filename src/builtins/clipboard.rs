@@ -0,0 +1,142 @@
+use std::io::{IsTerminal, Write};
+use std::process::{Command, Stdio};
+
+use crate::builtins::system;
+use crate::eval;
+use crate::types::{State, Value};
+
+/// A clipboard command and the display server it needs ("" for none).
+struct Tool {
+    program: &'static str,
+    args: &'static [&'static str],
+    needs: &'static str,
+}
+
+/// Commands that copy their stdin, in order of preference.
+const COPY_TOOLS: &[Tool] = &[
+    Tool { program: "pbcopy", args: &[], needs: "" },
+    Tool { program: "wl-copy", args: &[], needs: "WAYLAND_DISPLAY" },
+    Tool { program: "xclip", args: &["-selection", "clipboard"], needs: "DISPLAY" },
+    Tool { program: "xsel", args: &["--clipboard", "--input"], needs: "DISPLAY" },
+];
+
+/// Commands that print the clipboard, in order of preference.
+const PASTE_TOOLS: &[Tool] = &[
+    Tool { program: "pbpaste", args: &[], needs: "" },
+    Tool { program: "wl-paste", args: &["--no-newline"], needs: "WAYLAND_DISPLAY" },
+    Tool { program: "xclip", args: &["-selection", "clipboard", "-o"], needs: "DISPLAY" },
+    Tool { program: "xsel", args: &["--clipboard", "--output"], needs: "DISPLAY" },
+];
+
+/// The first tool that is installed and whose display server is running.
+fn find_tool(tools: &'static [Tool]) -> Option<(&'static Tool, String)> {
+    tools.iter().find_map(|tool| {
+        if !tool.needs.is_empty() && std::env::var_os(tool.needs).is_none() {
+            return None;
+        }
+        eval::find_in_path(tool.program).map(|path| (tool, path))
+    })
+}
+
+/// `>clip` ( a -- ) Copy the top value to the system clipboard.
+///
+/// Uses pbcopy, wl-copy, xclip, or xsel, whichever is available. Without
+/// one, falls back to the OSC 52 escape, which most terminals (including
+/// over SSH and in tmux) turn into a clipboard write.
+pub fn to_clip(state: &mut State) -> Result<(), String> {
+    system::deny_restricted(state, ">clip")?;
+    // The value stays on the stack unless the copy worked
+    let text = state.stack.last().ok_or(">clip: stack underflow")?.to_string();
+    copy(&text)?;
+    state.stack.pop();
+    Ok(())
+}
+
+/// Put `text` on the system clipboard.
+fn copy(text: &str) -> Result<(), String> {
+    if let Some((tool, path)) = find_tool(COPY_TOOLS) {
+        let mut child = Command::new(path)
+            .args(tool.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn()
+            .map_err(|e| format!(">clip: {}: {}", tool.program, e))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin
+                .write_all(text.as_bytes())
+                .map_err(|e| format!(">clip: {}: {}", tool.program, e))?;
+        }
+        let status = child
+            .wait()
+            .map_err(|e| format!(">clip: {}: {}", tool.program, e))?;
+        return if status.success() {
+            Ok(())
+        } else {
+            Err(format!(">clip: {} failed", tool.program))
+        };
+    }
+    if !std::io::stdout().is_terminal() {
+        return Err(">clip: no clipboard tool found (pbcopy, wl-copy, xclip, or xsel)".into());
+    }
+    print!("\x1b]52;c;{}\x07", base64(text.as_bytes()));
+    std::io::stdout().flush().map_err(|e| format!(">clip: {}", e))
+}
+
+/// `clip>` ( -- str ) Push the system clipboard's text.
+pub fn from_clip(state: &mut State) -> Result<(), String> {
+    system::deny_restricted(state, "clip>")?;
+    let (tool, path) = find_tool(PASTE_TOOLS)
+        .ok_or("clip>: no clipboard tool found (pbpaste, wl-paste, xclip, or xsel)")?;
+    let out = Command::new(path)
+        .args(tool.args)
+        .stderr(Stdio::null())
+        .output()
+        .map_err(|e| format!("clip>: {}: {}", tool.program, e))?;
+    if !out.status.success() {
+        return Err(format!("clip>: {} failed", tool.program));
+    }
+    state
+        .stack
+        .push(Value::Str(String::from_utf8_lossy(&out.stdout).into_owned()));
+    Ok(())
+}
+
+/// Standard base64 with padding, as OSC 52 expects.
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"hello world\n"), "aGVsbG8gd29ybGQK");
+    }
+
+    #[test]
+    fn test_to_clip_underflow() {
+        let mut s = State::new();
+        assert_eq!(to_clip(&mut s), Err(">clip: stack underflow".into()));
+    }
+}
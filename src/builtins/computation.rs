@@ -1,50 +1,104 @@
+use std::fs::File;
+use std::io::Read;
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::builtins::text;
+use crate::tokenizer;
 use crate::types::{LoopInfo, State, Value};
 
 // ========== Helpers ==========
 
+/// The integer a value stands for: an Int, or with `set-coerce` on, a Str or
+/// Output holding just a number (surrounding whitespace is ignored).
+pub(crate) fn coerce_int(state: &State, val: &Value) -> Option<i64> {
+    match val {
+        Value::Int(n) => Some(*n),
+        Value::Str(s) | Value::Output(s, _) if state.coerce => tokenizer::parse_int(s.trim()),
+        _ => None,
+    }
+}
+
+/// Pop the top `n` items as integers, deepest first. Leaves the stack
+/// untouched and returns `None` if any of them isn't one.
+fn take_ints(state: &mut State, n: usize) -> Option<Vec<i64>> {
+    let start = state.stack.len().checked_sub(n)?;
+    let ints = state.stack[start..]
+        .iter()
+        .map(|v| coerce_int(state, v))
+        .collect::<Option<Vec<i64>>>()?;
+    state.stack.truncate(start);
+    Some(ints)
+}
+
 /// Pop two integers from the stack: top = b, second = a.
 fn pop_two_ints(state: &mut State, op: &str) -> Result<(i64, i64), String> {
     if state.stack.len() < 2 {
         return Err(format!("{}: stack underflow", op));
     }
-    let b = match state.stack.pop().unwrap() {
-        Value::Int(n) => n,
-        other => {
-            state.stack.push(other);
-            return Err(format!("{}: requires two integers", op));
-        }
-    };
-    let a = match state.stack.pop().unwrap() {
-        Value::Int(n) => n,
-        other => {
-            state.stack.push(other);
-            state.stack.push(Value::Int(b));
-            return Err(format!("{}: requires two integers", op));
+    match take_ints(state, 2).as_deref() {
+        Some(&[a, b]) => Ok((a, b)),
+        _ => Err(format!("{}: requires two integers", op)),
+    }
+}
+
+/// Fill a buffer with bytes from the system random source.
+fn random_bytes(buf: &mut [u8], op: &str) -> Result<(), String> {
+    File::open("/dev/urandom")
+        .and_then(|mut f| f.read_exact(buf))
+        .map_err(|e| format!("{}: /dev/urandom: {}", op, e))
+}
+
+/// Uniform random integer in `0..bound` (rejection sampling avoids modulo bias).
+fn random_below(bound: u64, op: &str) -> Result<u64, String> {
+    let zone = u64::MAX - (u64::MAX % bound);
+    loop {
+        let mut buf = [0u8; 8];
+        random_bytes(&mut buf, op)?;
+        let n = u64::from_le_bytes(buf);
+        if n < zone {
+            return Ok(n % bound);
         }
-    };
-    Ok((a, b))
+    }
 }
 
 // ========== Arithmetic ==========
 
-/// `+` ( a b -- a+b ) Add two integers.
+/// `+` ( a b -- a+b ) Add two integers (error on overflow).
 pub fn add(state: &mut State) -> Result<(), String> {
     let (a, b) = pop_two_ints(state, "+")?;
-    state.stack.push(Value::Int(a + b));
+    let sum = a.checked_add(b).ok_or("+: integer overflow")?;
+    state.stack.push(Value::Int(sum));
     Ok(())
 }
 
-/// `-` ( a b -- a-b ) Subtract b from a.
+/// `-` ( a b -- a-b ) Subtract b from a (error on overflow).
 pub fn sub(state: &mut State) -> Result<(), String> {
     let (a, b) = pop_two_ints(state, "-")?;
-    state.stack.push(Value::Int(a - b));
+    let diff = a.checked_sub(b).ok_or("-: integer overflow")?;
+    state.stack.push(Value::Int(diff));
     Ok(())
 }
 
-/// `*` ( a b -- a*b ) Multiply two integers.
+/// `*` ( a b -- a*b ) Multiply two integers (error on overflow).
 pub fn mul(state: &mut State) -> Result<(), String> {
     let (a, b) = pop_two_ints(state, "*")?;
-    state.stack.push(Value::Int(a * b));
+    let product = a.checked_mul(b).ok_or("*: integer overflow")?;
+    state.stack.push(Value::Int(product));
+    Ok(())
+}
+
+/// `wrap+` ( a b -- a+b ) Add two integers with two's complement wrapping.
+pub fn wrap_add(state: &mut State) -> Result<(), String> {
+    let (a, b) = pop_two_ints(state, "wrap+")?;
+    state.stack.push(Value::Int(a.wrapping_add(b)));
+    Ok(())
+}
+
+/// `wrap*` ( a b -- a*b ) Multiply two integers with two's complement wrapping.
+pub fn wrap_mul(state: &mut State) -> Result<(), String> {
+    let (a, b) = pop_two_ints(state, "wrap*")?;
+    state.stack.push(Value::Int(a.wrapping_mul(b)));
     Ok(())
 }
 
@@ -54,7 +108,8 @@ pub fn div(state: &mut State) -> Result<(), String> {
     if b == 0 {
         return Err("/: division by zero".into());
     }
-    state.stack.push(Value::Int(a / b));
+    let quot = a.checked_div(b).ok_or("/: integer overflow")?;
+    state.stack.push(Value::Int(quot));
     Ok(())
 }
 
@@ -64,7 +119,8 @@ pub fn mod_op(state: &mut State) -> Result<(), String> {
     if b == 0 {
         return Err("mod: division by zero".into());
     }
-    state.stack.push(Value::Int(a % b));
+    let rem = a.checked_rem(b).ok_or("mod: integer overflow")?;
+    state.stack.push(Value::Int(rem));
     Ok(())
 }
 
@@ -74,44 +130,239 @@ pub fn divmod(state: &mut State) -> Result<(), String> {
     if b == 0 {
         return Err("/mod: division by zero".into());
     }
-    state.stack.push(Value::Int(a / b));
+    let quot = a.checked_div(b).ok_or("/mod: integer overflow")?;
+    state.stack.push(Value::Int(quot));
     state.stack.push(Value::Int(a % b));
     Ok(())
 }
 
 /// `*/` ( a b c -- (a*b)/c ) Multiply then divide.
+///
+/// The intermediate product is computed at double width, as in Forth, so only
+/// a final result outside the i64 range is an overflow.
 pub fn muldiv(state: &mut State) -> Result<(), String> {
     if state.stack.len() < 3 {
         return Err("*/: stack underflow".into());
     }
-    let c = match state.stack.pop().unwrap() {
-        Value::Int(n) => n,
-        other => {
+    let Some(&[a, b, c]) = take_ints(state, 3).as_deref() else {
+        return Err("*/: requires three integers".into());
+    };
+    if c == 0 {
+        return Err("*/: division by zero".into());
+    }
+    let result = i64::try_from((a as i128 * b as i128) / c as i128)
+        .map_err(|_| "*/: integer overflow")?;
+    state.stack.push(Value::Int(result));
+    Ok(())
+}
+
+/// `min` ( a b -- min ) Smaller of two integers.
+pub fn min(state: &mut State) -> Result<(), String> {
+    let (a, b) = pop_two_ints(state, "min")?;
+    state.stack.push(Value::Int(a.min(b)));
+    Ok(())
+}
+
+/// `max` ( a b -- max ) Larger of two integers.
+pub fn max(state: &mut State) -> Result<(), String> {
+    let (a, b) = pop_two_ints(state, "max")?;
+    state.stack.push(Value::Int(a.max(b)));
+    Ok(())
+}
+
+/// Pop one integer and push `f(n)`, or an overflow error if `f` returns None.
+fn unary_int(state: &mut State, op: &str, f: fn(i64) -> Option<i64>) -> Result<(), String> {
+    let n = match state.stack.last() {
+        Some(val) => coerce_int(state, val).ok_or_else(|| format!("{}: requires integer", op))?,
+        None => return Err(format!("{}: stack underflow", op)),
+    };
+    let result = f(n).ok_or_else(|| format!("{}: integer overflow", op))?;
+    state.stack.pop();
+    state.stack.push(Value::Int(result));
+    Ok(())
+}
+
+/// `abs` ( n -- |n| ) Absolute value (error on overflow).
+pub fn abs(state: &mut State) -> Result<(), String> {
+    unary_int(state, "abs", i64::checked_abs)
+}
+
+/// `negate` ( n -- -n ) Negate an integer (error on overflow).
+pub fn negate(state: &mut State) -> Result<(), String> {
+    unary_int(state, "negate", i64::checked_neg)
+}
+
+/// `clamp` ( n lo hi -- n' ) Limit n to the range `lo..=hi`.
+pub fn clamp(state: &mut State) -> Result<(), String> {
+    let start = match state.stack.len().checked_sub(3) {
+        Some(start) => start,
+        None => return Err("clamp: stack underflow".into()),
+    };
+    // Coerce like `min`/`max`, but only pop once all three are usable
+    let ints: Option<Vec<i64>> =
+        state.stack[start..].iter().map(|v| coerce_int(state, v)).collect();
+    let (n, lo, hi) = match ints.as_deref() {
+        Some(&[n, lo, hi]) => (n, lo, hi),
+        _ => return Err("clamp: requires three integers".into()),
+    };
+    if lo > hi {
+        return Err("clamp: lo must not exceed hi".into());
+    }
+    state.stack.truncate(start);
+    state.stack.push(Value::Int(n.clamp(lo, hi)));
+    Ok(())
+}
+
+// ========== Random numbers ==========
+
+/// `random` ( max -- n ) Random integer in `0..max` (max exclusive).
+pub fn random(state: &mut State) -> Result<(), String> {
+    let max = match state.stack.pop() {
+        Some(Value::Int(n)) => n,
+        Some(other) => {
             state.stack.push(other);
-            return Err("*/: requires three integers".into());
+            return Err("random: requires integer".into());
         }
+        None => return Err("random: stack underflow".into()),
     };
-    let b = match state.stack.pop().unwrap() {
-        Value::Int(n) => n,
-        other => {
-            state.stack.push(other);
-            state.stack.push(Value::Int(c));
-            return Err("*/: requires three integers".into());
+    if max <= 0 {
+        return Err("random: max must be positive".into());
+    }
+    let n = random_below(max as u64, "random")?;
+    state.stack.push(Value::Int(n as i64));
+    Ok(())
+}
+
+/// `random-range` ( lo hi -- n ) Random integer in `lo..=hi` (both inclusive).
+pub fn random_range(state: &mut State) -> Result<(), String> {
+    let (lo, hi) = pop_two_ints(state, "random-range")?;
+    if lo > hi {
+        return Err("random-range: lo must not exceed hi".into());
+    }
+    // hi - lo always fits in u64; only the full i64 range overflows the bound
+    let span = hi.wrapping_sub(lo) as u64;
+    let offset = match span.checked_add(1) {
+        Some(bound) => random_below(bound, "random-range")?,
+        None => {
+            let mut buf = [0u8; 8];
+            random_bytes(&mut buf, "random-range")?;
+            u64::from_le_bytes(buf)
         }
     };
-    let a = match state.stack.pop().unwrap() {
-        Value::Int(n) => n,
-        other => {
+    state.stack.push(Value::Int(lo.wrapping_add(offset as i64)));
+    Ok(())
+}
+
+/// `uuid` ( -- str ) Random (version 4) UUID.
+pub fn uuid(state: &mut State) -> Result<(), String> {
+    let mut b = [0u8; 16];
+    random_bytes(&mut b, "uuid")?;
+    b[6] = (b[6] & 0x0f) | 0x40; // version 4
+    b[8] = (b[8] & 0x3f) | 0x80; // RFC 4122 variant
+    let hex: String = b.iter().map(|x| format!("{:02x}", x)).collect();
+    let id = format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    );
+    state.stack.push(Value::Str(id));
+    Ok(())
+}
+
+// ========== Number bases ==========
+
+/// Format an integer in the given base (2-36), lowercase, with a leading `-` if negative.
+fn format_radix(n: i64, base: u32) -> String {
+    let mut magnitude = n.unsigned_abs();
+    if magnitude == 0 {
+        return "0".to_string();
+    }
+    let mut digits = Vec::new();
+    while magnitude > 0 {
+        let d = (magnitude % base as u64) as u32;
+        digits.push(std::char::from_digit(d, base).unwrap());
+        magnitude /= base as u64;
+    }
+    if n < 0 {
+        digits.push('-');
+    }
+    digits.iter().rev().collect()
+}
+
+/// Pop an integer and push its representation in the given base as Str.
+fn push_in_base(state: &mut State, base: u32, op: &str) -> Result<(), String> {
+    match state.stack.pop() {
+        Some(Value::Int(n)) => {
+            state.stack.push(Value::Str(format_radix(n, base)));
+            Ok(())
+        }
+        Some(other) => {
             state.stack.push(other);
-            state.stack.push(Value::Int(b));
-            state.stack.push(Value::Int(c));
-            return Err("*/: requires three integers".into());
+            Err(format!("{}: requires integer", op))
         }
-    };
-    if c == 0 {
-        return Err("*/: division by zero".into());
+        None => Err(format!("{}: stack underflow", op)),
+    }
+}
+
+/// Validate a base popped from the stack.
+fn check_base(base: i64, op: &str) -> Result<u32, String> {
+    if (2..=36).contains(&base) {
+        Ok(base as u32)
+    } else {
+        Err(format!("{}: base must be 2-36", op))
     }
-    state.stack.push(Value::Int((a * b) / c));
+}
+
+/// `hex` ( n -- str ) Format integer in hexadecimal.
+pub fn hex(state: &mut State) -> Result<(), String> {
+    push_in_base(state, 16, "hex")
+}
+
+/// `oct` ( n -- str ) Format integer in octal.
+pub fn oct(state: &mut State) -> Result<(), String> {
+    push_in_base(state, 8, "oct")
+}
+
+/// `bin` ( n -- str ) Format integer in binary.
+pub fn bin(state: &mut State) -> Result<(), String> {
+    push_in_base(state, 2, "bin")
+}
+
+/// `>base` ( n base -- str ) Format integer in an arbitrary base (2-36).
+pub fn to_base(state: &mut State) -> Result<(), String> {
+    let (n, base) = pop_two_ints(state, ">base")?;
+    let radix = match check_base(base, ">base") {
+        Ok(radix) => radix,
+        Err(e) => {
+            state.stack.push(Value::Int(n));
+            state.stack.push(Value::Int(base));
+            return Err(e);
+        }
+    };
+    state.stack.push(Value::Str(format_radix(n, radix)));
+    Ok(())
+}
+
+/// `base>` ( str base -- n ) Parse a string as an integer in the given base (2-36).
+pub fn from_base(state: &mut State) -> Result<(), String> {
+    let len = state.stack.len();
+    if len < 2 {
+        return Err("base>: stack underflow".into());
+    }
+    // Read in place, so every error leaves both arguments on the stack
+    let n = match (&state.stack[len - 2], &state.stack[len - 1]) {
+        (Value::Str(s), Value::Int(base)) => {
+            let base = check_base(*base, "base>")?;
+            i64::from_str_radix(s.trim(), base)
+                .map_err(|_| format!("base>: invalid base-{} number: {}", base, s))?
+        }
+        _ => return Err("base>: requires string and integer base".into()),
+    };
+    state.stack.truncate(len - 2);
+    state.stack.push(Value::Int(n));
     Ok(())
 }
 
@@ -127,6 +378,12 @@ pub fn eq(state: &mut State) -> Result<(), String> {
     let result = match (&a, &b) {
         (Value::Int(x), Value::Int(y)) => x == y,
         (Value::Str(x), Value::Str(y)) => x == y,
+        // With `set-coerce`, a number against numeric text compares as numbers
+        (Value::Int(_), _) | (_, Value::Int(_))
+            if coerce_int(state, &a).is_some() && coerce_int(state, &b).is_some() =>
+        {
+            coerce_int(state, &a) == coerce_int(state, &b)
+        }
         _ => {
             state.stack.push(a);
             state.stack.push(b);
@@ -147,6 +404,12 @@ pub fn neq(state: &mut State) -> Result<(), String> {
     let result = match (&a, &b) {
         (Value::Int(x), Value::Int(y)) => x != y,
         (Value::Str(x), Value::Str(y)) => x != y,
+        // With `set-coerce`, a number against numeric text compares as numbers
+        (Value::Int(_), _) | (_, Value::Int(_))
+            if coerce_int(state, &a).is_some() && coerce_int(state, &b).is_some() =>
+        {
+            coerce_int(state, &a) != coerce_int(state, &b)
+        }
         _ => {
             state.stack.push(a);
             state.stack.push(b);
@@ -251,6 +514,114 @@ pub fn concat(state: &mut State) -> Result<(), String> {
     }
 }
 
+/// What `length` and `substr` count: grapheme clusters (what reads as one
+/// character, such as "é" written as e plus an accent), chars (Unicode code
+/// points), or bytes.
+#[derive(Clone, Copy)]
+enum TextUnit {
+    Grapheme,
+    Char,
+    Byte,
+}
+
+/// Byte offsets where each unit of `s` starts, followed by `s.len()`.
+fn unit_bounds(s: &str, unit: TextUnit) -> Vec<usize> {
+    let mut bounds: Vec<usize> = match unit {
+        TextUnit::Grapheme => s.grapheme_indices(true).map(|(i, _)| i).collect(),
+        TextUnit::Char => s.char_indices().map(|(i, _)| i).collect(),
+        TextUnit::Byte => (0..s.len()).collect(),
+    };
+    bounds.push(s.len());
+    bounds
+}
+
+/// Number of grapheme clusters in `s`.
+pub(crate) fn grapheme_count(s: &str) -> usize {
+    s.graphemes(true).count()
+}
+
+/// The first `n` grapheme clusters of `s` (all of it if shorter).
+pub(crate) fn grapheme_prefix(s: &str, n: usize) -> &str {
+    match s.grapheme_indices(true).nth(n) {
+        Some((end, _)) => &s[..end],
+        None => s,
+    }
+}
+
+fn length_impl(state: &mut State, op: &str, unit: TextUnit) -> Result<(), String> {
+    let s = text::pop_text(state, op)?;
+    let n = match unit {
+        TextUnit::Grapheme => grapheme_count(&s),
+        TextUnit::Char => s.chars().count(),
+        TextUnit::Byte => s.len(),
+    };
+    state.stack.push(Value::Int(n as i64));
+    Ok(())
+}
+
+/// `length` ( str -- n ) Length in grapheme clusters.
+pub fn length(state: &mut State) -> Result<(), String> {
+    length_impl(state, "length", TextUnit::Grapheme)
+}
+
+/// `length-chars` ( str -- n ) Length in Unicode code points.
+pub fn length_chars(state: &mut State) -> Result<(), String> {
+    length_impl(state, "length-chars", TextUnit::Char)
+}
+
+/// `length-bytes` ( str -- n ) Length in bytes of UTF-8.
+pub fn length_bytes(state: &mut State) -> Result<(), String> {
+    length_impl(state, "length-bytes", TextUnit::Byte)
+}
+
+fn substr_impl(state: &mut State, op: &str, unit: TextUnit) -> Result<(), String> {
+    if state.stack.len() < 3 {
+        return Err(format!("{}: stack underflow", op));
+    }
+    let n = state.stack.len();
+    let (start, count) = match (&state.stack[n - 3], &state.stack[n - 2], &state.stack[n - 1]) {
+        (Value::Str(_) | Value::Output(_, _), Value::Int(start), Value::Int(count)) => {
+            if *start < 0 || *count < 0 {
+                return Err(format!("{}: start and length must not be negative", op));
+            }
+            (*start as usize, *count as usize)
+        }
+        _ => return Err(format!("{}: requires string, start, and length", op)),
+    };
+    let s = match &state.stack[n - 3] {
+        Value::Str(s) | Value::Output(s, _) => s,
+        _ => unreachable!(),
+    };
+    let bounds = unit_bounds(s, unit);
+    let last = bounds.len() - 1;
+    let from = bounds[start.min(last)];
+    let to = bounds[start.saturating_add(count).min(last)];
+    let piece = s
+        .get(from..to)
+        .ok_or_else(|| format!("{}: would split a character", op))?
+        .to_string();
+    state.stack.truncate(n - 3);
+    state.stack.push(Value::Str(piece));
+    Ok(())
+}
+
+/// `substr` ( str start len -- str ) Up to `len` grapheme clusters starting
+/// at `start` (from 0). Ranges past the end are cut short.
+pub fn substr(state: &mut State) -> Result<(), String> {
+    substr_impl(state, "substr", TextUnit::Grapheme)
+}
+
+/// `substr-chars` ( str start len -- str ) Like `substr`, counting code points.
+pub fn substr_chars(state: &mut State) -> Result<(), String> {
+    substr_impl(state, "substr-chars", TextUnit::Char)
+}
+
+/// `substr-bytes` ( str start len -- str ) Like `substr`, counting bytes;
+/// an error if that would cut a character in two.
+pub fn substr_bytes(state: &mut State) -> Result<(), String> {
+    substr_impl(state, "substr-bytes", TextUnit::Byte)
+}
+
 // ========== Conditional string helpers ==========
 
 /// `?prefix` ( str sep -- result ) Prepend separator if string is non-empty.
@@ -339,11 +710,14 @@ pub fn cond_wrap(state: &mut State) -> Result<(), String> {
 pub fn loop_i(state: &mut State) -> Result<(), String> {
     match state.loop_stack.last() {
         Some(LoopInfo::DoCountedLoop { current, .. })
-        | Some(LoopInfo::DoPlusCountedLoop { current, .. }) => {
+        | Some(LoopInfo::DoPlusCountedLoop { current, .. })
+        | Some(LoopInfo::EachLoop { current }) => {
             state.stack.push(Value::Int(*current));
             Ok(())
         }
-        Some(LoopInfo::BeginUntilLoop) | Some(LoopInfo::BeginWhileLoop) => {
+        Some(LoopInfo::BeginUntilLoop)
+        | Some(LoopInfo::BeginWhileLoop)
+        | Some(LoopInfo::BeginAgainLoop) => {
             Err("i: loop index not available (not a counted loop)".into())
         }
         None => Err("i: not inside a loop".into()),
@@ -358,16 +732,29 @@ pub fn loop_j(state: &mut State) -> Result<(), String> {
     }
     match &state.loop_stack[len - 2] {
         LoopInfo::DoCountedLoop { current, .. }
-        | LoopInfo::DoPlusCountedLoop { current, .. } => {
+        | LoopInfo::DoPlusCountedLoop { current, .. }
+        | LoopInfo::EachLoop { current } => {
             state.stack.push(Value::Int(*current));
             Ok(())
         }
-        LoopInfo::BeginUntilLoop | LoopInfo::BeginWhileLoop => {
+        LoopInfo::BeginUntilLoop | LoopInfo::BeginWhileLoop | LoopInfo::BeginAgainLoop => {
             Err("j: outer loop index not available (not a counted loop)".into())
         }
     }
 }
 
+/// `leave` ( -- ) Stop the innermost loop, continuing after it.
+///
+/// Returns an error so the rest of the body (and any words it is inside)
+/// stop; the loop sees `leave_requested` and ends without reporting it.
+pub fn leave(state: &mut State) -> Result<(), String> {
+    if state.loop_stack.is_empty() {
+        return Err("leave: not inside a loop".into());
+    }
+    state.leave_requested = true;
+    Err("leave".into())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -401,6 +788,42 @@ mod tests {
         assert!(add(&mut s).is_err());
     }
 
+    #[test]
+    fn test_coerce_off_keeps_types_strict() {
+        let mut s = state_with(vec![Value::output("3\n"), Value::Int(1)]);
+        assert_eq!(add(&mut s), Err("+: requires two integers".into()));
+        assert_eq!(s.stack, vec![Value::output("3\n"), Value::Int(1)]);
+    }
+
+    #[test]
+    fn test_coerce_numeric_text() {
+        let mut s = state_with(vec![Value::output("  3\n"), Value::Str("0x10".into())]);
+        s.coerce = true;
+        add(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::Int(19)]);
+
+        s.stack = vec![Value::output("12\n"), Value::Int(10)];
+        gt(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::Int(1)]);
+
+        s.stack = vec![Value::output("7\n"), Value::Int(7)];
+        eq(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::Int(1)]);
+
+        s.stack = vec![Value::Str("-4".into())];
+        abs(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::Int(4)]);
+
+        // Text that isn't a number is still an error, and is left in place
+        s.stack = vec![Value::Str("abc".into()), Value::Int(1)];
+        assert!(add(&mut s).is_err());
+        assert_eq!(s.stack, vec![Value::Str("abc".into()), Value::Int(1)]);
+        // Two strings still compare as strings
+        s.stack = vec![Value::Str("1".into()), Value::Str("01".into())];
+        eq(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::Int(0)]);
+    }
+
     #[test]
     fn test_sub() {
         let mut s = state_with(vec![Value::Int(10), Value::Int(3)]);
@@ -475,6 +898,290 @@ mod tests {
         assert!(muldiv(&mut s).is_err());
     }
 
+    #[test]
+    fn test_add_overflow() {
+        let mut s = state_with(vec![Value::Int(i64::MAX), Value::Int(1)]);
+        assert_eq!(add(&mut s), Err("+: integer overflow".into()));
+    }
+
+    #[test]
+    fn test_sub_overflow() {
+        let mut s = state_with(vec![Value::Int(i64::MIN), Value::Int(1)]);
+        assert!(sub(&mut s).is_err());
+    }
+
+    #[test]
+    fn test_mul_overflow() {
+        let mut s = state_with(vec![Value::Int(i64::MAX), Value::Int(2)]);
+        assert_eq!(mul(&mut s), Err("*: integer overflow".into()));
+    }
+
+    #[test]
+    fn test_div_overflow() {
+        let mut s = state_with(vec![Value::Int(i64::MIN), Value::Int(-1)]);
+        assert!(div(&mut s).is_err());
+    }
+
+    #[test]
+    fn test_mod_overflow() {
+        let mut s = state_with(vec![Value::Int(i64::MIN), Value::Int(-1)]);
+        assert!(mod_op(&mut s).is_err());
+    }
+
+    #[test]
+    fn test_divmod_overflow() {
+        let mut s = state_with(vec![Value::Int(i64::MIN), Value::Int(-1)]);
+        assert!(divmod(&mut s).is_err());
+    }
+
+    #[test]
+    fn test_muldiv_wide_intermediate() {
+        // i64::MAX * 4 overflows i64, but the final result fits
+        let mut s = state_with(vec![Value::Int(i64::MAX), Value::Int(4), Value::Int(8)]);
+        muldiv(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::Int(i64::MAX / 2)]);
+    }
+
+    #[test]
+    fn test_muldiv_overflow() {
+        let mut s = state_with(vec![Value::Int(i64::MAX), Value::Int(4), Value::Int(2)]);
+        assert!(muldiv(&mut s).is_err());
+    }
+
+    #[test]
+    fn test_wrap_add() {
+        let mut s = state_with(vec![Value::Int(i64::MAX), Value::Int(1)]);
+        wrap_add(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::Int(i64::MIN)]);
+    }
+
+    #[test]
+    fn test_wrap_mul() {
+        let mut s = state_with(vec![Value::Int(i64::MAX), Value::Int(2)]);
+        wrap_mul(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::Int(-2)]);
+    }
+
+    #[test]
+    fn test_min_max() {
+        let mut s = state_with(vec![Value::Int(3), Value::Int(-7)]);
+        min(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::Int(-7)]);
+        let mut s = state_with(vec![Value::Int(3), Value::Int(-7)]);
+        max(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::Int(3)]);
+    }
+
+    #[test]
+    fn test_abs_negate() {
+        let mut s = state_with(vec![Value::Int(-5)]);
+        abs(&mut s).unwrap();
+        negate(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::Int(-5)]);
+    }
+
+    #[test]
+    fn test_abs_negate_overflow() {
+        let mut s = state_with(vec![Value::Int(i64::MIN)]);
+        assert!(abs(&mut s).is_err());
+        assert!(negate(&mut s).is_err());
+        assert_eq!(s.stack, vec![Value::Int(i64::MIN)]);
+    }
+
+    #[test]
+    fn test_clamp() {
+        for (n, expected) in [(-3, 0), (5, 5), (42, 10)] {
+            let mut s = state_with(vec![Value::Int(n), Value::Int(0), Value::Int(10)]);
+            clamp(&mut s).unwrap();
+            assert_eq!(s.stack, vec![Value::Int(expected)]);
+        }
+    }
+
+    #[test]
+    fn test_clamp_errors() {
+        let mut s = state_with(vec![Value::Int(5), Value::Int(10), Value::Int(0)]);
+        assert!(clamp(&mut s).is_err());
+        assert_eq!(s.stack.len(), 3);
+        let mut s = state_with(vec![Value::Str("x".into()), Value::Int(0), Value::Int(10)]);
+        assert!(clamp(&mut s).is_err());
+        assert_eq!(s.stack.len(), 3);
+        let mut s = state_with(vec![Value::Int(0), Value::Int(10)]);
+        assert_eq!(clamp(&mut s), Err("clamp: stack underflow".to_string()));
+    }
+
+    #[test]
+    fn test_clamp_coerces_numeric_text() {
+        let mut s = state_with(vec![
+            Value::output("42\n"),
+            Value::Str("0".into()),
+            Value::Str("10".into()),
+        ]);
+        s.coerce = true;
+        clamp(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::Int(10)]);
+    }
+
+    // ===== Random numbers =====
+
+    #[test]
+    fn test_random_in_range() {
+        for _ in 0..50 {
+            let mut s = state_with(vec![Value::Int(3)]);
+            random(&mut s).unwrap();
+            match s.stack[..] {
+                [Value::Int(n)] => assert!((0..3).contains(&n)),
+                ref other => panic!("expected one Int, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_random_non_positive() {
+        let mut s = state_with(vec![Value::Int(0)]);
+        assert!(random(&mut s).is_err());
+    }
+
+    #[test]
+    fn test_random_wrong_type() {
+        let mut s = state_with(vec![Value::Str("10".into())]);
+        assert!(random(&mut s).is_err());
+        assert_eq!(s.stack.len(), 1);
+    }
+
+    #[test]
+    fn test_random_range_inclusive() {
+        for _ in 0..50 {
+            let mut s = state_with(vec![Value::Int(-2), Value::Int(2)]);
+            random_range(&mut s).unwrap();
+            match s.stack[..] {
+                [Value::Int(n)] => assert!((-2..=2).contains(&n)),
+                ref other => panic!("expected one Int, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_random_range_single_value() {
+        let mut s = state_with(vec![Value::Int(7), Value::Int(7)]);
+        random_range(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::Int(7)]);
+    }
+
+    #[test]
+    fn test_random_range_full_span() {
+        let mut s = state_with(vec![Value::Int(i64::MIN), Value::Int(i64::MAX)]);
+        random_range(&mut s).unwrap();
+        assert_eq!(s.stack.len(), 1);
+    }
+
+    #[test]
+    fn test_random_range_reversed() {
+        let mut s = state_with(vec![Value::Int(5), Value::Int(1)]);
+        assert!(random_range(&mut s).is_err());
+    }
+
+    #[test]
+    fn test_uuid_format() {
+        let mut s = state_with(vec![]);
+        uuid(&mut s).unwrap();
+        match &s.stack[..] {
+            [Value::Str(id)] => {
+                assert_eq!(id.len(), 36);
+                let parts: Vec<&str> = id.split('-').collect();
+                assert_eq!(parts.iter().map(|p| p.len()).collect::<Vec<_>>(), vec![8, 4, 4, 4, 12]);
+                assert!(parts[2].starts_with('4'));
+                assert!(matches!(parts[3].chars().next(), Some('8' | '9' | 'a' | 'b')));
+            }
+            other => panic!("expected one Str, got {:?}", other),
+        }
+    }
+
+    // ===== Number bases =====
+
+    #[test]
+    fn test_hex() {
+        let mut s = state_with(vec![Value::Int(255)]);
+        hex(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::Str("ff".into())]);
+    }
+
+    #[test]
+    fn test_oct_file_mode() {
+        let mut s = state_with(vec![Value::Int(0o755)]);
+        oct(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::Str("755".into())]);
+    }
+
+    #[test]
+    fn test_bin_negative() {
+        let mut s = state_with(vec![Value::Int(-5)]);
+        bin(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::Str("-101".into())]);
+    }
+
+    #[test]
+    fn test_bin_zero() {
+        let mut s = state_with(vec![Value::Int(0)]);
+        bin(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::Str("0".into())]);
+    }
+
+    #[test]
+    fn test_hex_min() {
+        let mut s = state_with(vec![Value::Int(i64::MIN)]);
+        hex(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::Str("-8000000000000000".into())]);
+    }
+
+    #[test]
+    fn test_hex_wrong_type() {
+        let mut s = state_with(vec![Value::Str("ff".into())]);
+        assert!(hex(&mut s).is_err());
+        assert_eq!(s.stack.len(), 1);
+    }
+
+    #[test]
+    fn test_to_base() {
+        let mut s = state_with(vec![Value::Int(35), Value::Int(36)]);
+        to_base(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::Str("z".into())]);
+    }
+
+    #[test]
+    fn test_to_base_invalid_base() {
+        let mut s = state_with(vec![Value::Int(35), Value::Int(1)]);
+        assert!(to_base(&mut s).is_err());
+        assert_eq!(s.stack, vec![Value::Int(35), Value::Int(1)]);
+    }
+
+    #[test]
+    fn test_from_base() {
+        let mut s = state_with(vec![Value::Str("755".into()), Value::Int(8)]);
+        from_base(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::Int(493)]);
+    }
+
+    #[test]
+    fn test_from_base_invalid_digits() {
+        let mut s = state_with(vec![Value::Str("19".into()), Value::Int(8)]);
+        assert!(from_base(&mut s).is_err());
+        assert_eq!(s.stack, vec![Value::Str("19".into()), Value::Int(8)]);
+    }
+
+    #[test]
+    fn test_from_base_invalid_base() {
+        let mut s = state_with(vec![Value::Str("11".into()), Value::Int(40)]);
+        assert_eq!(from_base(&mut s), Err("base>: base must be 2-36".to_string()));
+        assert_eq!(s.stack, vec![Value::Str("11".into()), Value::Int(40)]);
+    }
+
+    #[test]
+    fn test_from_base_wrong_type() {
+        let mut s = state_with(vec![Value::Int(7), Value::Int(8)]);
+        assert!(from_base(&mut s).is_err());
+        assert_eq!(s.stack.len(), 2);
+    }
+
     // ===== Comparisons =====
 
     #[test]
@@ -721,6 +1428,61 @@ mod tests {
         assert!(concat(&mut s).is_err());
     }
 
+    // e + combining acute accent is one grapheme but two chars (and three bytes)
+    const ACCENTED: &str = "cafe\u{301}!";
+
+    #[test]
+    fn test_length_units() {
+        for (word, expected) in [
+            (length as fn(&mut State) -> Result<(), String>, 5),
+            (length_chars, 6),
+            (length_bytes, 7),
+        ] {
+            let mut s = state_with(vec![Value::Str(ACCENTED.into())]);
+            word(&mut s).unwrap();
+            assert_eq!(s.stack, vec![Value::Int(expected)]);
+        }
+    }
+
+    #[test]
+    fn test_substr_graphemes() {
+        let mut s = state_with(vec![Value::Str(ACCENTED.into()), Value::Int(3), Value::Int(1)]);
+        substr(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::Str("e\u{301}".into())]);
+        let mut s = state_with(vec![Value::Str("日本語".into()), Value::Int(1), Value::Int(10)]);
+        substr(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::Str("本語".into())]);
+    }
+
+    #[test]
+    fn test_substr_chars_and_bytes() {
+        let mut s = state_with(vec![Value::Str(ACCENTED.into()), Value::Int(3), Value::Int(1)]);
+        substr_chars(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::Str("e".into())]);
+        let mut s = state_with(vec![Value::Str("日本".into()), Value::Int(3), Value::Int(3)]);
+        substr_bytes(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::Str("本".into())]);
+        let mut s = state_with(vec![Value::Str("日本".into()), Value::Int(1), Value::Int(3)]);
+        assert_eq!(
+            substr_bytes(&mut s),
+            Err("substr-bytes: would split a character".into())
+        );
+        assert_eq!(s.stack.len(), 3);
+    }
+
+    #[test]
+    fn test_substr_errors_keep_stack() {
+        let mut s = state_with(vec![Value::Str("abc".into()), Value::Int(-1), Value::Int(1)]);
+        assert!(substr(&mut s).is_err());
+        assert_eq!(s.stack.len(), 3);
+        let mut s = state_with(vec![Value::Int(5), Value::Int(0), Value::Int(1)]);
+        assert_eq!(
+            substr(&mut s),
+            Err("substr: requires string, start, and length".into())
+        );
+        assert_eq!(s.stack.len(), 3);
+    }
+
     // ===== Type error tests =====
 
     #[test]
@@ -0,0 +1,213 @@
+use super::structured;
+use crate::types::{State, Value};
+
+/// Parse delimited text into a List of rows, each a List of Str fields.
+/// Rows may have different numbers of fields.
+fn parse(text: &str, delimiter: u8, quoting: bool) -> Result<Vec<Value>, ::csv::Error> {
+    let mut reader = ::csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .delimiter(delimiter)
+        .quoting(quoting)
+        .from_reader(text.as_bytes());
+    let mut rows = Vec::new();
+    for record in reader.records() {
+        let fields = record?.iter().map(|f| Value::Str(f.to_string())).collect();
+        rows.push(Value::List(fields));
+    }
+    Ok(rows)
+}
+
+fn parse_word(state: &mut State, op: &str, delimiter: u8, quoting: bool) -> Result<(), String> {
+    structured::parse_word(state, op, |text| {
+        parse(text, delimiter, quoting)
+            .map(Value::List)
+            .map_err(|e| e.to_string())
+    })
+}
+
+/// `csv-parse` ( output -- rows ) Parse CSV into a list of rows, honoring
+/// quoted fields (commas, quotes, and newlines inside `"..."`).
+pub fn csv_parse(state: &mut State) -> Result<(), String> {
+    parse_word(state, "csv-parse", b',', true)
+}
+
+/// `tsv-parse` ( output -- rows ) Parse tab-separated text into a list of
+/// rows. TSV has no quoting, so `"` is an ordinary character.
+pub fn tsv_parse(state: &mut State) -> Result<(), String> {
+    parse_word(state, "tsv-parse", b'\t', false)
+}
+
+/// `csv-get` ( row n -- str ) Field `n` (from 0) of a row, consuming it.
+/// Works on the rows list too, giving row `n`.
+pub fn csv_get(state: &mut State) -> Result<(), String> {
+    if state.stack.len() < 2 {
+        return Err("csv-get: stack underflow".into());
+    }
+    let index = match state.stack.pop() {
+        Some(Value::Int(n)) => n,
+        Some(other) => {
+            state.stack.push(other);
+            return Err("csv-get: index must be an integer".into());
+        }
+        None => unreachable!(),
+    };
+    let len = match state.stack.last() {
+        Some(Value::List(items)) => items.len(),
+        _ => {
+            state.stack.push(Value::Int(index));
+            return Err("csv-get: requires a list".into());
+        }
+    };
+    if index < 0 || index as usize >= len {
+        state.stack.push(Value::Int(index));
+        return Err(format!(
+            "csv-get: index {} out of range ({} item{})",
+            index,
+            len,
+            if len == 1 { "" } else { "s" }
+        ));
+    }
+    match state.stack.pop() {
+        Some(Value::List(mut items)) => state.stack.push(items.swap_remove(index as usize)),
+        _ => unreachable!(),
+    }
+    Ok(())
+}
+
+/// Render rows as CSV, quoting fields only where needed.
+fn emit(rows: &[Value]) -> Result<String, String> {
+    let mut writer = ::csv::WriterBuilder::new()
+        .flexible(true)
+        .terminator(::csv::Terminator::Any(b'\n'))
+        .from_writer(Vec::new());
+    for row in rows {
+        let Value::List(fields) = row else {
+            return Err("csv-emit: each row must be a list".into());
+        };
+        let mut record = Vec::with_capacity(fields.len());
+        for field in fields {
+            match field {
+                Value::Str(s) | Value::Output(s, _) => record.push(s.clone()),
+                Value::Int(n) => record.push(n.to_string()),
+                Value::Map(_) | Value::List(_) => {
+                    return Err("csv-emit: fields must be strings or integers".into())
+                }
+            }
+        }
+        writer.write_record(&record).map_err(|e| format!("csv-emit: {}", e))?;
+    }
+    let bytes = writer.into_inner().map_err(|e| format!("csv-emit: {}", e))?;
+    String::from_utf8(bytes).map_err(|e| format!("csv-emit: {}", e))
+}
+
+/// `csv-emit` ( rows -- output ) The inverse of `csv-parse`: a list of rows
+/// as CSV text.
+pub fn csv_emit(state: &mut State) -> Result<(), String> {
+    let rows = match state.stack.pop() {
+        Some(Value::List(rows)) => rows,
+        Some(other) => {
+            state.stack.push(other);
+            return Err("csv-emit: requires a list of rows".into());
+        }
+        None => return Err("csv-emit: stack underflow".into()),
+    };
+    match emit(&rows) {
+        Ok(text) => {
+            state.stack.push(Value::output(text));
+            Ok(())
+        }
+        Err(e) => {
+            state.stack.push(Value::List(rows));
+            Err(e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builtins;
+    use crate::eval;
+
+    fn new_state() -> State {
+        let mut s = State::new();
+        builtins::register_builtins(&mut s);
+        s
+    }
+
+    fn row(fields: &[&str]) -> Value {
+        Value::List(fields.iter().map(|f| Value::Str(f.to_string())).collect())
+    }
+
+    #[test]
+    fn test_csv_parse_quoting() {
+        let mut s = new_state();
+        s.stack = vec![Value::Output(
+            "name,note\n\"Smith, J\",\"said \"\"hi\"\"\"\nx,\"two\nlines\"\n".into(),
+            None,
+        )];
+        csv_parse(&mut s).unwrap();
+        assert_eq!(
+            s.stack,
+            vec![Value::List(vec![
+                row(&["name", "note"]),
+                row(&["Smith, J", "said \"hi\""]),
+                row(&["x", "two\nlines"]),
+            ])]
+        );
+    }
+
+    #[test]
+    fn test_tsv_parse() {
+        let mut s = new_state();
+        s.stack = vec![Value::Str("a\tb,c\t5\" disk\nd\n".into())];
+        tsv_parse(&mut s).unwrap();
+        assert_eq!(
+            s.stack,
+            vec![Value::List(vec![row(&["a", "b,c", "5\" disk"]), row(&["d"])])]
+        );
+    }
+
+    #[test]
+    fn test_csv_get() {
+        let mut s = new_state();
+        s.stack = vec![Value::output("a,b\n\"c,d\",e\n")];
+        eval::eval_line(&mut s, "csv-parse 1 csv-get 0 csv-get").unwrap();
+        assert_eq!(s.stack, vec![Value::Str("c,d".into())]);
+    }
+
+    #[test]
+    fn test_csv_get_out_of_range_restores_stack() {
+        let mut s = new_state();
+        s.stack = vec![row(&["a", "b"]), Value::Int(2)];
+        assert_eq!(
+            csv_get(&mut s),
+            Err("csv-get: index 2 out of range (2 items)".into())
+        );
+        assert_eq!(s.stack, vec![row(&["a", "b"]), Value::Int(2)]);
+        s.stack = vec![Value::Str("a,b".into()), Value::Int(0)];
+        assert_eq!(csv_get(&mut s), Err("csv-get: requires a list".into()));
+        assert_eq!(s.stack, vec![Value::Str("a,b".into()), Value::Int(0)]);
+    }
+
+    #[test]
+    fn test_csv_emit_round_trip() {
+        let mut s = new_state();
+        let text = "id,name\n1,\"Smith, J\"\n2,\"say \"\"hi\"\"\"\n";
+        s.stack = vec![Value::output(text)];
+        csv_parse(&mut s).unwrap();
+        csv_emit(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::output(text)]);
+    }
+
+    #[test]
+    fn test_csv_emit_type_errors() {
+        let mut s = new_state();
+        s.stack = vec![Value::List(vec![Value::Str("flat".into())])];
+        assert_eq!(csv_emit(&mut s), Err("csv-emit: each row must be a list".into()));
+        assert_eq!(s.stack.len(), 1);
+        s.stack = vec![Value::Str("a,b".into())];
+        assert_eq!(csv_emit(&mut s), Err("csv-emit: requires a list of rows".into()));
+    }
+}
@@ -0,0 +1,654 @@
+use std::fs;
+use std::io::{ErrorKind, Write};
+#[cfg(feature = "native")]
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::system::{self, expand_tilde};
+use super::text;
+use crate::eval::{expand_glob, glob_matches};
+use crate::types::{State, Value};
+
+// ========== Helpers ==========
+
+/// Pop a path string from the stack, expanding a leading `~`.
+fn pop_path(state: &mut State, op: &str) -> Result<String, String> {
+    match state.stack.pop() {
+        Some(Value::Str(p)) => Ok(expand_tilde(&p)),
+        Some(other) => {
+            state.stack.push(other);
+            Err(format!("{}: requires path string", op))
+        }
+        None => Err(format!("{}: stack underflow", op)),
+    }
+}
+
+/// Pop two paths: top = destination, second = source.
+fn pop_two_paths(state: &mut State, op: &str) -> Result<(String, String), String> {
+    if state.stack.len() < 2 {
+        return Err(format!("{}: stack underflow", op));
+    }
+    let dst = state.stack.pop().unwrap();
+    let src = state.stack.pop().unwrap();
+    match (src, dst) {
+        (Value::Str(s), Value::Str(d)) => Ok((expand_tilde(&s), expand_tilde(&d))),
+        (s, d) => {
+            state.stack.push(s);
+            state.stack.push(d);
+            Err(format!("{}: requires source and destination paths", op))
+        }
+    }
+}
+
+/// Resolve the destination for `cp`/`mv`: an existing directory receives the source's file name.
+fn resolve_destination(src: &Path, dst: &str) -> PathBuf {
+    let dst = PathBuf::from(dst);
+    match src.file_name() {
+        Some(name) if dst.is_dir() => dst.join(name),
+        _ => dst,
+    }
+}
+
+/// Pop a path and push 1 if `test` holds for it, 0 otherwise.
+fn push_flag(state: &mut State, op: &str, test: fn(&Path) -> bool) -> Result<(), String> {
+    let path = pop_path(state, op)?;
+    let flag = test(Path::new(&path));
+    state.stack.push(Value::Int(if flag { 1 } else { 0 }));
+    Ok(())
+}
+
+// ========== Predicates ==========
+
+/// `exists?` ( path -- flag ) 1 if the path exists (following symlinks), else 0.
+pub fn exists(state: &mut State) -> Result<(), String> {
+    push_flag(state, "exists?", |p| p.exists())
+}
+
+/// `dir?` ( path -- flag ) 1 if the path is a directory, else 0.
+pub fn is_dir(state: &mut State) -> Result<(), String> {
+    push_flag(state, "dir?", |p| p.is_dir())
+}
+
+/// `file?` ( path -- flag ) 1 if the path is a regular file, else 0.
+pub fn is_file(state: &mut State) -> Result<(), String> {
+    push_flag(state, "file?", |p| p.is_file())
+}
+
+// ========== Metadata ==========
+
+/// `file-size` ( path -- n ) Size of a file in bytes.
+pub fn file_size(state: &mut State) -> Result<(), String> {
+    let path = pop_path(state, "file-size")?;
+    let meta = fs::metadata(&path).map_err(|e| format!("file-size: {}: {}", path, e))?;
+    state.stack.push(Value::Int(meta.len() as i64));
+    Ok(())
+}
+
+/// `file-mtime` ( path -- n ) Modification time in seconds since the Unix epoch.
+pub fn file_mtime(state: &mut State) -> Result<(), String> {
+    let path = pop_path(state, "file-mtime")?;
+    let modified = fs::metadata(&path)
+        .and_then(|m| m.modified())
+        .map_err(|e| format!("file-mtime: {}: {}", path, e))?;
+    let secs = match modified.duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_secs() as i64,
+        Err(e) => -(e.duration().as_secs() as i64),
+    };
+    state.stack.push(Value::Int(secs));
+    Ok(())
+}
+
+/// `readlink` ( path -- target ) Target of a symbolic link.
+pub fn readlink(state: &mut State) -> Result<(), String> {
+    let path = pop_path(state, "readlink")?;
+    let target = fs::read_link(&path).map_err(|e| format!("readlink: {}: {}", path, e))?;
+    state
+        .stack
+        .push(Value::Str(target.to_string_lossy().to_string()));
+    Ok(())
+}
+
+// ========== File operations ==========
+
+/// `mkdir-p` ( path -- ) Create a directory and any missing parents.
+pub fn mkdir_p(state: &mut State) -> Result<(), String> {
+    system::deny_restricted(state, "mkdir-p")?;
+    let path = pop_path(state, "mkdir-p")?;
+    fs::create_dir_all(&path).map_err(|e| format!("mkdir-p: {}: {}", path, e))
+}
+
+/// `rm` ( path -- ) Remove a file (directories need `rm-rf`).
+pub fn rm(state: &mut State) -> Result<(), String> {
+    system::deny_restricted(state, "rm")?;
+    let path = pop_path(state, "rm")?;
+    // A symlink to a directory is just a link, so it can be removed
+    if fs::symlink_metadata(&path).is_ok_and(|meta| meta.is_dir()) {
+        return Err(format!("rm: {}: is a directory (use rm-rf)", path));
+    }
+    fs::remove_file(&path).map_err(|e| format!("rm: {}: {}", path, e))
+}
+
+/// `rm-rf` ( path -- ) Remove a file or directory tree; a missing path is not an error.
+pub fn rm_rf(state: &mut State) -> Result<(), String> {
+    system::deny_restricted(state, "rm-rf")?;
+    let path = pop_path(state, "rm-rf")?;
+    let meta = match fs::symlink_metadata(&path) {
+        Ok(meta) => meta,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(format!("rm-rf: {}: {}", path, e)),
+    };
+    // Symlinks to directories are removed as links, never followed
+    let result = if meta.is_dir() {
+        fs::remove_dir_all(&path)
+    } else {
+        fs::remove_file(&path)
+    };
+    result.map_err(|e| format!("rm-rf: {}: {}", path, e))
+}
+
+/// `cp` ( src dst -- ) Copy a file; if dst is a directory, copy into it.
+pub fn cp(state: &mut State) -> Result<(), String> {
+    system::deny_restricted(state, "cp")?;
+    let (src, dst) = pop_two_paths(state, "cp")?;
+    let src_path = Path::new(&src);
+    if src_path.is_dir() {
+        return Err(format!("cp: {}: is a directory", src));
+    }
+    let target = resolve_destination(src_path, &dst);
+    fs::copy(src_path, &target)
+        .map(|_| ())
+        .map_err(|e| format!("cp: {} -> {}: {}", src, target.display(), e))
+}
+
+/// `mv` ( src dst -- ) Move or rename a path; if dst is a directory, move into it.
+pub fn mv(state: &mut State) -> Result<(), String> {
+    system::deny_restricted(state, "mv")?;
+    let (src, dst) = pop_two_paths(state, "mv")?;
+    let src_path = Path::new(&src);
+    let target = resolve_destination(src_path, &dst);
+    match fs::rename(src_path, &target) {
+        // Another filesystem: copy, then remove the original
+        Err(e) if e.kind() == ErrorKind::CrossesDevices => move_by_copy(src_path, &target),
+        result => result,
+    }
+    .map_err(|e| format!("mv: {} -> {}: {}", src, target.display(), e))
+}
+
+/// Move `src` to `dst` by copying and then removing `src`. A failed copy
+/// removes what was copied so far and leaves `src` alone.
+fn move_by_copy(src: &Path, dst: &Path) -> std::io::Result<()> {
+    if let Err(e) = copy_tree(src, dst) {
+        let _ = remove_path(dst);
+        return Err(e);
+    }
+    remove_path(src)
+}
+
+/// Copy a file, symlink (as a link), or directory tree.
+fn copy_tree(src: &Path, dst: &Path) -> std::io::Result<()> {
+    let meta = fs::symlink_metadata(src)?;
+    if meta.is_dir() {
+        fs::create_dir(dst)?;
+        for entry in fs::read_dir(src)? {
+            let entry = entry?;
+            copy_tree(&entry.path(), &dst.join(entry.file_name()))?;
+        }
+        fs::set_permissions(dst, meta.permissions())
+    } else if meta.is_symlink() {
+        copy_symlink(src, dst)
+    } else {
+        fs::copy(src, dst).map(|_| ())
+    }
+}
+
+#[cfg(feature = "native")]
+fn copy_symlink(src: &Path, dst: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(fs::read_link(src)?, dst)
+}
+
+#[cfg(not(feature = "native"))]
+fn copy_symlink(src: &Path, dst: &Path) -> std::io::Result<()> {
+    fs::copy(src, dst).map(|_| ())
+}
+
+/// Remove a file, symlink, or directory tree without following links.
+fn remove_path(path: &Path) -> std::io::Result<()> {
+    if fs::symlink_metadata(path)?.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+/// `touch` ( path -- ) Create an empty file, or update the mtime of an existing one.
+pub fn touch(state: &mut State) -> Result<(), String> {
+    system::deny_restricted(state, "touch")?;
+    let path = pop_path(state, "touch")?;
+    let file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .map_err(|e| format!("touch: {}: {}", path, e))?;
+    file.set_modified(SystemTime::now())
+        .map_err(|e| format!("touch: {}: {}", path, e))
+}
+
+// ========== Searching ==========
+
+/// Recursively collect paths under `dir` whose file name matches `pattern`.
+///
+/// Entries are visited in sorted order so results are stable. Symlinked
+/// directories are not followed, and unreadable subdirectories are skipped.
+fn walk(dir: &Path, pattern: &str, out: &mut Vec<String>) {
+    let mut entries: Vec<_> = match fs::read_dir(dir) {
+        Ok(entries) => entries.filter_map(|e| e.ok()).collect(),
+        Err(_) => return,
+    };
+    entries.sort_by_key(|e| e.file_name());
+    for entry in entries {
+        let path = entry.path();
+        if glob_matches(pattern, &entry.file_name().to_string_lossy()) {
+            out.push(path.to_string_lossy().to_string());
+        }
+        if entry.file_type().is_ok_and(|t| t.is_dir()) {
+            walk(&path, pattern, out);
+        }
+    }
+}
+
+/// `find-files` ( dir pattern -- output ) Paths under dir whose name matches a glob, one per line.
+pub fn find_files(state: &mut State) -> Result<(), String> {
+    let (dir, pattern) = pop_two_paths(state, "find-files")?;
+    if !Path::new(&dir).is_dir() {
+        return Err(format!("find-files: {}: not a directory", dir));
+    }
+    let mut matches = Vec::new();
+    walk(Path::new(&dir), &pattern, &mut matches);
+    let mut output = String::new();
+    for path in matches {
+        output.push_str(&path);
+        output.push('\n');
+    }
+    state.stack.push(Value::output(output));
+    Ok(())
+}
+
+/// `glob` ( pattern -- output ) Paths matching a glob, one per line, as a
+/// single value instead of one Str per match.
+pub fn glob(state: &mut State) -> Result<(), String> {
+    let pattern = pop_path(state, "glob")?;
+    let output: String = expand_glob(&pattern, state.glob_dotfiles)
+        .into_iter()
+        .map(|path| format!("{}\n", path))
+        .collect();
+    state.stack.push(Value::output(output));
+    Ok(())
+}
+
+/// `glob-count` ( pattern -- n ) How many paths match a glob.
+pub fn glob_count(state: &mut State) -> Result<(), String> {
+    let pattern = pop_path(state, "glob-count")?;
+    let count = expand_glob(&pattern, state.glob_dotfiles).len();
+    state.stack.push(Value::Int(count as i64));
+    Ok(())
+}
+
+// ========== Process substitution ==========
+
+/// `psub` ( output -- path ) Write text to a new temp file and push its
+/// path, for commands that only take file arguments. The file is removed
+/// when the line that made it finishes.
+pub fn psub(state: &mut State) -> Result<(), String> {
+    system::deny_restricted(state, "psub")?;
+    let text = text::pop_text(state, "psub")?;
+    let dir = std::env::temp_dir();
+    let mut attempt = 0;
+    let (path, mut file) = loop {
+        let name = format!("yafsh-psub-{}-{}", std::process::id(), state.psub_files.len() + attempt);
+        let path = dir.join(name);
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(feature = "native")]
+        options.mode(0o600);
+        match options.open(&path) {
+            Ok(file) => break (path, file),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists && attempt < 100 => attempt += 1,
+            Err(e) => return Err(format!("psub: {}: {}", path.display(), e)),
+        }
+    };
+    let written = file.write_all(text.as_bytes());
+    state.psub_files.push(path.clone());
+    written.map_err(|e| format!("psub: {}: {}", path.display(), e))?;
+    state.stack.push(Value::Str(path.to_string_lossy().into_owned()));
+    Ok(())
+}
+
+/// Remove the `psub` temp files made since `mark` (an earlier length of
+/// `state.psub_files`).
+pub(crate) fn remove_psub_files(state: &mut State, mark: usize) {
+    for path in state.psub_files.drain(mark.min(state.psub_files.len())..) {
+        let _ = fs::remove_file(path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state_with(vals: Vec<Value>) -> State {
+        let mut s = State::new();
+        s.stack = vals;
+        s
+    }
+
+    /// Create a fresh scratch directory for a test.
+    fn scratch(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(name);
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn path_val(p: &std::path::Path) -> Value {
+        Value::Str(p.to_string_lossy().to_string())
+    }
+
+    #[test]
+    fn test_exists_dir_file() {
+        let dir = scratch("yafsh_test_files_flags");
+        let file = dir.join("a.txt");
+        std::fs::write(&file, "hello").unwrap();
+        let missing = dir.join("missing");
+
+        let mut s = state_with(vec![path_val(&file)]);
+        exists(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::Int(1)]);
+
+        let mut s = state_with(vec![path_val(&missing)]);
+        exists(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::Int(0)]);
+
+        let mut s = state_with(vec![path_val(&dir), path_val(&file)]);
+        is_dir(&mut s).unwrap();
+        assert_eq!(s.stack[1], Value::Int(0));
+        s.stack.pop();
+        is_dir(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::Int(1)]);
+
+        let mut s = state_with(vec![path_val(&file)]);
+        is_file(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::Int(1)]);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_file_size_and_mtime() {
+        let dir = scratch("yafsh_test_files_meta");
+        let file = dir.join("a.txt");
+        std::fs::write(&file, "hello").unwrap();
+
+        let mut s = state_with(vec![path_val(&file)]);
+        file_size(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::Int(5)]);
+
+        let mut s = state_with(vec![path_val(&file)]);
+        file_mtime(&mut s).unwrap();
+        let now = std::time::SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+        match s.stack[0] {
+            Value::Int(t) => assert!((now - t).abs() < 60),
+            ref other => panic!("expected Int, got {:?}", other),
+        }
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_file_size_missing() {
+        let mut s = state_with(vec![Value::Str("/nonexistent/yafsh_size".into())]);
+        let err = file_size(&mut s).unwrap_err();
+        assert!(err.starts_with("file-size: /nonexistent/yafsh_size"));
+    }
+
+    #[test]
+    fn test_readlink() {
+        let dir = scratch("yafsh_test_files_link");
+        let link = dir.join("link");
+        std::os::unix::fs::symlink("target.txt", &link).unwrap();
+        let mut s = state_with(vec![path_val(&link)]);
+        readlink(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::Str("target.txt".into())]);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_mkdir_p_and_rm_rf() {
+        let dir = scratch("yafsh_test_files_mkdir");
+        let nested = dir.join("a/b/c");
+        let mut s = state_with(vec![path_val(&nested)]);
+        mkdir_p(&mut s).unwrap();
+        assert!(nested.is_dir());
+
+        std::fs::write(nested.join("f.txt"), "x").unwrap();
+        let mut s = state_with(vec![path_val(&dir.join("a"))]);
+        rm_rf(&mut s).unwrap();
+        assert!(!dir.join("a").exists());
+
+        // Already gone: still fine
+        let mut s = state_with(vec![path_val(&dir.join("a"))]);
+        rm_rf(&mut s).unwrap();
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_rm() {
+        let dir = scratch("yafsh_test_files_rm");
+        let file = dir.join("f.txt");
+        std::fs::write(&file, "x").unwrap();
+        let mut s = state_with(vec![path_val(&file)]);
+        rm(&mut s).unwrap();
+        assert!(!file.exists());
+
+        let mut s = state_with(vec![path_val(&file)]);
+        assert!(rm(&mut s).is_err());
+
+        let mut s = state_with(vec![path_val(&dir)]);
+        let err = rm(&mut s).unwrap_err();
+        assert!(err.contains("is a directory"));
+        assert!(dir.exists());
+
+        // A link to a directory goes, the directory stays
+        let sub = dir.join("sub");
+        std::fs::create_dir(&sub).unwrap();
+        let link = dir.join("link");
+        std::os::unix::fs::symlink(&sub, &link).unwrap();
+        let mut s = state_with(vec![path_val(&link)]);
+        rm(&mut s).unwrap();
+        assert!(std::fs::symlink_metadata(&link).is_err());
+        assert!(sub.is_dir());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    #[cfg(feature = "native")]
+    fn test_move_by_copy() {
+        // The fallback mv uses across filesystems
+        let dir = scratch("yafsh_test_files_move_copy");
+        let src = dir.join("tree");
+        std::fs::create_dir_all(src.join("inner")).unwrap();
+        std::fs::write(src.join("inner/f.txt"), "data").unwrap();
+        std::os::unix::fs::symlink("inner/f.txt", src.join("link")).unwrap();
+
+        let dst = dir.join("moved");
+        move_by_copy(&src, &dst).unwrap();
+        assert!(!src.exists());
+        assert_eq!(std::fs::read_to_string(dst.join("inner/f.txt")).unwrap(), "data");
+        assert_eq!(
+            std::fs::read_link(dst.join("link")).unwrap(),
+            Path::new("inner/f.txt")
+        );
+
+        // A copy that fails leaves the source in place
+        std::fs::write(&src, "x").unwrap();
+        assert!(move_by_copy(&src, &dir.join("missing/dst")).is_err());
+        assert!(src.is_file());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_cp_and_mv() {
+        let dir = scratch("yafsh_test_files_cp_mv");
+        let src = dir.join("src.txt");
+        std::fs::write(&src, "data").unwrap();
+        let sub = dir.join("sub");
+        std::fs::create_dir(&sub).unwrap();
+
+        let mut s = state_with(vec![path_val(&src), path_val(&dir.join("copy.txt"))]);
+        cp(&mut s).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(dir.join("copy.txt")).unwrap(),
+            "data"
+        );
+
+        // Copy into a directory keeps the file name
+        let mut s = state_with(vec![path_val(&src), path_val(&sub)]);
+        cp(&mut s).unwrap();
+        assert!(sub.join("src.txt").is_file());
+
+        let mut s = state_with(vec![
+            path_val(&dir.join("copy.txt")),
+            path_val(&dir.join("moved.txt")),
+        ]);
+        mv(&mut s).unwrap();
+        assert!(!dir.join("copy.txt").exists());
+        assert!(dir.join("moved.txt").is_file());
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_cp_errors_keep_stack() {
+        let mut s = state_with(vec![Value::Str("a".into()), Value::Int(1)]);
+        assert!(cp(&mut s).is_err());
+        assert_eq!(s.stack.len(), 2);
+
+        let mut s = state_with(vec![
+            Value::Str("/nonexistent/yafsh_cp".into()),
+            Value::Str("/tmp/yafsh_cp_out".into()),
+        ]);
+        assert!(cp(&mut s).is_err());
+    }
+
+    #[test]
+    fn test_touch() {
+        let dir = scratch("yafsh_test_files_touch");
+        let file = dir.join("t.txt");
+        let mut s = state_with(vec![path_val(&file)]);
+        touch(&mut s).unwrap();
+        assert!(file.is_file());
+
+        // Existing contents are preserved and the mtime moves forward
+        std::fs::write(&file, "keep").unwrap();
+        let old = SystemTime::now() - std::time::Duration::from_secs(3600);
+        std::fs::File::options()
+            .write(true)
+            .open(&file)
+            .unwrap()
+            .set_modified(old)
+            .unwrap();
+        let mut s = state_with(vec![path_val(&file)]);
+        touch(&mut s).unwrap();
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "keep");
+        assert!(std::fs::metadata(&file).unwrap().modified().unwrap() > old);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_find_files() {
+        let dir = scratch("yafsh_test_files_find");
+        std::fs::create_dir_all(dir.join("src/nested")).unwrap();
+        std::fs::write(dir.join("top.rs"), "").unwrap();
+        std::fs::write(dir.join("src/lib.rs"), "").unwrap();
+        std::fs::write(dir.join("src/nested/deep.rs"), "").unwrap();
+        std::fs::write(dir.join("src/readme.md"), "").unwrap();
+
+        let root = dir.to_string_lossy().to_string();
+        let mut s = state_with(vec![Value::Str(root.clone()), Value::Str("*.rs".into())]);
+        find_files(&mut s).unwrap();
+        assert_eq!(
+            s.stack,
+            vec![Value::output(format!("{0}/src/lib.rs\n{0}/src/nested/deep.rs\n{0}/top.rs\n", root))]
+        );
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_find_files_not_a_directory() {
+        let mut s = state_with(vec![
+            Value::Str("/nonexistent/yafsh_find".into()),
+            Value::Str("*".into()),
+        ]);
+        assert!(find_files(&mut s).is_err());
+    }
+
+    #[test]
+    fn test_wrong_type() {
+        let mut s = state_with(vec![Value::Int(3)]);
+        assert!(exists(&mut s).is_err());
+        assert_eq!(s.stack, vec![Value::Int(3)]);
+    }
+
+    #[test]
+    fn test_glob_and_glob_count() {
+        let dir = scratch("yafsh_test_files_glob");
+        for name in ["b.log", "a.log", "c.txt", ".hidden"] {
+            std::fs::write(dir.join(name), "").unwrap();
+        }
+        let pattern = Value::Str(format!("{}/*.log", dir.display()));
+        let mut s = state_with(vec![pattern.clone()]);
+        glob(&mut s).unwrap();
+        assert_eq!(
+            s.stack,
+            vec![Value::output(format!("{0}/a.log\n{0}/b.log\n", dir.display()))]
+        );
+        let mut s = state_with(vec![pattern.clone()]);
+        glob_count(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::Int(2)]);
+
+        // Hidden names match too, unless glob-dotfiles is off
+        let all = Value::Str(format!("{}/*", dir.display()));
+        let mut s = state_with(vec![all.clone()]);
+        glob_count(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::Int(4)]);
+        let mut s = state_with(vec![all]);
+        s.glob_dotfiles = false;
+        glob_count(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::Int(3)]);
+        let mut s = state_with(vec![Value::Str(format!("{}/.h*", dir.display()))]);
+        s.glob_dotfiles = false;
+        glob_count(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::Int(1)]);
+        let mut s = state_with(vec![Value::Str(format!("{}/*.md", dir.display()))]);
+        glob(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::output(String::new())]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_psub_file_lasts_until_line_ends() {
+        let mut s = state_with(vec![Value::output("one\ntwo\n")]);
+        psub(&mut s).unwrap();
+        let path = match s.stack.pop() {
+            Some(Value::Str(path)) => path,
+            other => panic!("expected a path, got {:?}", other),
+        };
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "one\ntwo\n");
+        remove_psub_files(&mut s, 0);
+        assert!(!Path::new(&path).exists());
+        assert!(s.psub_files.is_empty());
+    }
+}
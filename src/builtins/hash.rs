@@ -0,0 +1,161 @@
+use std::fs::File;
+use std::io::Read;
+
+use md5::Md5;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+use crate::types::{State, Value};
+
+/// Lowercase hex encoding of a digest.
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Hash the Str/Output on top of the stack and push the hex digest as Str.
+fn hash_value<D: Digest>(state: &mut State, op: &str) -> Result<(), String> {
+    let data = match state.stack.pop() {
+        Some(Value::Str(s)) | Some(Value::Output(s, _)) => s,
+        Some(other) => {
+            state.stack.push(other);
+            return Err(format!("{}: requires string or output", op));
+        }
+        None => return Err(format!("{}: stack underflow", op)),
+    };
+    let digest = D::digest(data.as_bytes());
+    state.stack.push(Value::Str(to_hex(&digest)));
+    Ok(())
+}
+
+/// Hash the file named on top of the stack, streaming its raw bytes.
+fn hash_file<D: Digest>(state: &mut State, op: &str) -> Result<(), String> {
+    let path = match state.stack.pop() {
+        Some(Value::Str(p)) => p,
+        Some(other) => {
+            state.stack.push(other);
+            return Err(format!("{}: requires path string", op));
+        }
+        None => return Err(format!("{}: stack underflow", op)),
+    };
+    let mut file = File::open(&path).map_err(|e| format!("{}: {}: {}", op, path, e))?;
+    let mut hasher = D::new();
+    let mut buf = [0u8; 64 * 1024];
+    loop {
+        let n = file
+            .read(&mut buf)
+            .map_err(|e| format!("{}: {}: {}", op, path, e))?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    state.stack.push(Value::Str(to_hex(&hasher.finalize())));
+    Ok(())
+}
+
+/// `sha256` ( str/output -- str ) Hex SHA-256 digest.
+pub fn sha256(state: &mut State) -> Result<(), String> {
+    hash_value::<Sha256>(state, "sha256")
+}
+
+/// `sha1` ( str/output -- str ) Hex SHA-1 digest.
+pub fn sha1(state: &mut State) -> Result<(), String> {
+    hash_value::<Sha1>(state, "sha1")
+}
+
+/// `md5` ( str/output -- str ) Hex MD5 digest.
+pub fn md5(state: &mut State) -> Result<(), String> {
+    hash_value::<Md5>(state, "md5")
+}
+
+/// `sha256-file` ( path -- str ) Hex SHA-256 digest of a file's bytes.
+pub fn sha256_file(state: &mut State) -> Result<(), String> {
+    hash_file::<Sha256>(state, "sha256-file")
+}
+
+/// `sha1-file` ( path -- str ) Hex SHA-1 digest of a file's bytes.
+pub fn sha1_file(state: &mut State) -> Result<(), String> {
+    hash_file::<Sha1>(state, "sha1-file")
+}
+
+/// `md5-file` ( path -- str ) Hex MD5 digest of a file's bytes.
+pub fn md5_file(state: &mut State) -> Result<(), String> {
+    hash_file::<Md5>(state, "md5-file")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state_with(vals: Vec<Value>) -> State {
+        let mut s = State::new();
+        s.stack = vals;
+        s
+    }
+
+    #[test]
+    fn test_sha256_str() {
+        let mut s = state_with(vec![Value::Str("abc".into())]);
+        sha256(&mut s).unwrap();
+        assert_eq!(
+            s.stack,
+            vec![Value::Str(
+                "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad".into()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_sha1_output() {
+        let mut s = state_with(vec![Value::output("abc")]);
+        sha1(&mut s).unwrap();
+        assert_eq!(
+            s.stack,
+            vec![Value::Str("a9993e364706816aba3e25717850c26c9cd0d89d".into())]
+        );
+    }
+
+    #[test]
+    fn test_md5_empty() {
+        let mut s = state_with(vec![Value::Str("".into())]);
+        md5(&mut s).unwrap();
+        assert_eq!(
+            s.stack,
+            vec![Value::Str("d41d8cd98f00b204e9800998ecf8427e".into())]
+        );
+    }
+
+    #[test]
+    fn test_hash_wrong_type() {
+        let mut s = state_with(vec![Value::Int(1)]);
+        assert!(sha256(&mut s).is_err());
+        assert_eq!(s.stack, vec![Value::Int(1)]);
+    }
+
+    #[test]
+    fn test_hash_underflow() {
+        let mut s = state_with(vec![]);
+        assert!(md5(&mut s).is_err());
+    }
+
+    #[test]
+    fn test_sha256_file() {
+        let path = std::env::temp_dir().join("yafsh_test_sha256_file.bin");
+        std::fs::write(&path, b"abc").unwrap();
+        let mut s = state_with(vec![Value::Str(path.to_string_lossy().to_string())]);
+        sha256_file(&mut s).unwrap();
+        assert_eq!(
+            s.stack,
+            vec![Value::Str(
+                "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad".into()
+            )]
+        );
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_hash_file_missing() {
+        let mut s = state_with(vec![Value::Str("/nonexistent/yafsh_hash".into())]);
+        assert!(md5_file(&mut s).is_err());
+    }
+}
@@ -0,0 +1,174 @@
+use crate::builtins::system;
+use crate::types::{State, Value};
+
+/// Build an HTTP agent that reports non-2xx responses as normal responses.
+///
+/// The status is recorded in `State::last_http_status` instead of becoming an error,
+/// so scripts can inspect it with `http-status`.
+fn agent() -> ureq::Agent {
+    ureq::Agent::config_builder()
+        .http_status_as_error(false)
+        .build()
+        .into()
+}
+
+/// Record the response status and push the response body as Output.
+fn finish(
+    state: &mut State,
+    op: &str,
+    result: Result<ureq::http::Response<ureq::Body>, ureq::Error>,
+) -> Result<(), String> {
+    let mut response = result.map_err(|e| format!("{}: {}", op, e))?;
+    state.last_http_status = response.status().as_u16();
+    let body = response
+        .body_mut()
+        .read_to_string()
+        .map_err(|e| format!("{}: {}", op, e))?;
+    state.stack.push(Value::output(body));
+    Ok(())
+}
+
+/// `http-get` ( url -- output ) Fetch a URL and push the response body.
+pub fn http_get(state: &mut State) -> Result<(), String> {
+    system::deny_restricted(state, "http-get")?;
+    let url = match state.stack.pop() {
+        Some(Value::Str(u)) => u,
+        Some(other) => {
+            state.stack.push(other);
+            return Err("http-get: requires url string".into());
+        }
+        None => return Err("http-get: stack underflow".into()),
+    };
+    let result = agent().get(&url).call();
+    finish(state, "http-get", result)
+}
+
+/// `http-post` ( body url -- output ) POST a Str or Output body to a URL.
+pub fn http_post(state: &mut State) -> Result<(), String> {
+    system::deny_restricted(state, "http-post")?;
+    if state.stack.len() < 2 {
+        return Err("http-post: stack underflow".into());
+    }
+    let url = state.stack.pop().unwrap();
+    let body = state.stack.pop().unwrap();
+    let (body, url) = match (body, url) {
+        (Value::Str(b) | Value::Output(b, _), Value::Str(u)) => (b, u),
+        (b, u) => {
+            state.stack.push(b);
+            state.stack.push(u);
+            return Err("http-post: requires body and url string".into());
+        }
+    };
+    let result = agent().post(&url).send(body.as_bytes());
+    finish(state, "http-post", result)
+}
+
+/// `http-status` ( -- code ) Push the status code of the last HTTP response (0 if none).
+pub fn http_status(state: &mut State) -> Result<(), String> {
+    state.stack.push(Value::Int(state.last_http_status as i64));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    /// Serve a single canned HTTP response on a local port, returning the base URL
+    /// and a handle yielding the raw request that was received.
+    fn serve_once(status: &str, body: &'static str) -> (String, std::thread::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let status = status.to_string();
+        let handle = std::thread::spawn(move || {
+            let (mut conn, _) = listener.accept().unwrap();
+            let mut buf = [0u8; 4096];
+            let mut request = String::new();
+            // Read headers (and a small body) until the client pauses
+            while !request.contains("\r\n\r\n") {
+                let n = conn.read(&mut buf).unwrap();
+                request.push_str(&String::from_utf8_lossy(&buf[..n]));
+            }
+            if let Some(len) = request
+                .lines()
+                .find_map(|l| l.to_ascii_lowercase().strip_prefix("content-length: ").map(|v| v.trim().parse::<usize>().unwrap()))
+            {
+                let have = request.split("\r\n\r\n").nth(1).map_or(0, |b| b.len());
+                if have < len {
+                    let mut rest = vec![0u8; len - have];
+                    conn.read_exact(&mut rest).unwrap();
+                    request.push_str(&String::from_utf8_lossy(&rest));
+                }
+            }
+            let response = format!(
+                "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            );
+            conn.write_all(response.as_bytes()).unwrap();
+            request
+        });
+        (url, handle)
+    }
+
+    #[test]
+    fn test_http_get() {
+        let (url, server) = serve_once("200 OK", "hello");
+        let mut s = State::new();
+        s.stack.push(Value::Str(url));
+        http_get(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::output("hello")]);
+        assert_eq!(s.last_http_status, 200);
+        assert!(server.join().unwrap().starts_with("GET / "));
+    }
+
+    #[test]
+    fn test_http_get_error_status_is_not_an_error() {
+        let (url, server) = serve_once("404 Not Found", "missing");
+        let mut s = State::new();
+        s.stack.push(Value::Str(url));
+        http_get(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::output("missing")]);
+        http_status(&mut s).unwrap();
+        assert_eq!(s.stack[1], Value::Int(404));
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_http_post() {
+        let (url, server) = serve_once("201 Created", "ok");
+        let mut s = State::new();
+        s.stack.push(Value::output("payload"));
+        s.stack.push(Value::Str(url));
+        http_post(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::output("ok")]);
+        assert_eq!(s.last_http_status, 201);
+        let request = server.join().unwrap();
+        assert!(request.starts_with("POST / "));
+        assert!(request.ends_with("payload"));
+    }
+
+    #[test]
+    fn test_http_get_wrong_type() {
+        let mut s = State::new();
+        s.stack.push(Value::Int(1));
+        assert!(http_get(&mut s).is_err());
+        assert_eq!(s.stack, vec![Value::Int(1)]);
+    }
+
+    #[test]
+    fn test_http_post_underflow() {
+        let mut s = State::new();
+        s.stack.push(Value::Str("http://localhost/".into()));
+        assert!(http_post(&mut s).is_err());
+    }
+
+    #[test]
+    fn test_http_status_default() {
+        let mut s = State::new();
+        http_status(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::Int(0)]);
+    }
+}
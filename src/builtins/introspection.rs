@@ -1,76 +1,287 @@
+use std::fs;
+use std::io::{ErrorKind, Write};
+#[cfg(feature = "native")]
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
 use std::process::Command;
+use std::time::Duration;
 
-use crate::types::{State, Value, Word};
+use super::{system, vocab};
+use crate::eval;
+use crate::history::{self, HistoryRecord};
+use crate::{out, outln};
+use crate::tokenizer::{self, Token};
+use crate::types::{CompletionKind, State, Value, Word, MAIN_STACK};
 
-/// `words` ( -- ) List all available words in the dictionary.
+/// `words` ( -- ) List all available words in the dictionary, followed by
+/// one line per vocabulary.
 pub fn words(state: &mut State) -> Result<(), String> {
-    let mut names: Vec<&String> = state.dict.keys().collect();
+    let mut names: Vec<&String> = state
+        .dict
+        .keys()
+        .filter(|k| vocab::split_qualified(state, k).is_none())
+        .collect();
     names.sort();
     for name in &names {
-        print!("{} ", name);
+        out!(state, "{} ", name);
+    }
+    outln!(state);
+    for vocab_name in &state.vocabularies {
+        let mut members: Vec<&str> = state
+            .dict
+            .keys()
+            .filter_map(|k| vocab::split_qualified(state, k))
+            .filter(|(v, _)| v == vocab_name)
+            .map(|(_, name)| name)
+            .collect();
+        members.sort();
+        outln!(state, "{}: {}", vocab_name, members.join(" "));
     }
-    println!();
     Ok(())
 }
 
 /// `help` ( -- ) Show comprehensive help information.
-pub fn help(_state: &mut State) -> Result<(), String> {
-    println!("Forth Shell - Available Commands");
-    println!();
-    println!("Stack Operations:");
-    println!("  dup swap drop over rot    - manipulate stack");
-    println!("  .s                        - show stack contents");
-    println!();
-    println!("Printing:");
-    println!("  .                         - print top of stack");
-    println!("  type                      - print without newline");
-    println!();
-    println!("Arithmetic:");
-    println!("  + - * / mod /mod */       - math operations");
-    println!("  = < > <= >= <>            - comparisons");
-    println!();
-    println!("Boolean Logic:");
-    println!("  and or not xor            - boolean operations");
-    println!();
-    println!("String Operations:");
-    println!("  concat                    - concatenate two strings");
-    println!();
-    println!("Control Flow:");
-    println!("  if ... then               - conditional");
-    println!("  if ... else ... then      - conditional with else");
-    println!();
-    println!("Loops:");
-    println!("  begin ... until           - loop until condition is true");
-    println!("  begin ... while ... repeat - loop while condition is true");
-    println!("  start limit do ... loop   - counted loop (step 1)");
-    println!("  start limit do ... +loop  - counted loop (step from stack)");
-    println!("  output each ... then      - iterate over output lines");
-    println!("  i j                       - loop indices");
-    println!();
-    println!("Word Definition:");
-    println!("  : name ... ;              - define new word");
-    println!();
-    println!("Type Conversions:");
-    println!("  >output >string           - convert between types");
-    println!();
-    println!("File I/O:");
-    println!("  >file >>file              - write/append output to file");
-    println!();
-    println!("Environment:");
-    println!("  getenv setenv unsetenv    - environment variables");
-    println!();
-    println!("Directory:");
-    println!("  cd pushd popd             - directory navigation");
-    println!();
-    println!("Help System:");
-    println!("  words                     - list all words");
-    println!("  \"word\" see                - show word definition");
-    println!("  help                      - show this help");
-    println!();
-    println!("Type 'words' to see all available commands");
+pub fn help(state: &mut State) -> Result<(), String> {
+    outln!(state, "Forth Shell - Available Commands");
+    outln!(state);
+    outln!(state, "Stack Operations:");
+    outln!(state, "  dup swap drop over rot    - manipulate stack");
+    outln!(state, "  .s                        - show stack contents");
+    outln!(state);
+    outln!(state, "Printing:");
+    outln!(state, "  .                         - print top of stack");
+    outln!(state, "  type                      - print without newline");
+    outln!(state);
+    outln!(state, "Arithmetic:");
+    outln!(state, "  + - * / mod /mod */       - math operations");
+    outln!(state, "  wrap+ wrap*               - wrapping (no overflow error)");
+    outln!(state, "  min max abs negate clamp  - integer helpers");
+    outln!(state, "  = < > <= >= <>            - comparisons");
+    outln!(state, "  hex oct bin >base base>   - number base conversion");
+    outln!(state, "  random random-range uuid  - random numbers and ids");
+    outln!(state);
+    outln!(state, "Boolean Logic:");
+    outln!(state, "  and or not xor            - boolean operations");
+    outln!(state);
+    outln!(state, "String Operations:");
+    outln!(state, "  concat                    - concatenate two strings");
+    outln!(state);
+    outln!(state, "Output Processing:");
+    outln!(state, "  head tail                 - first/last n lines of output");
+    outln!(state, "  line-count nth-line       - count lines, pick line n");
+    outln!(state, "  chomp trim out>int        - drop trailing newline / whitespace, parse number");
+    outln!(state, "  sort-lines sort-lines-num - sort lines (bytewise/numeric)");
+    outln!(state, "  uniq-lines count-uniq     - collapse adjacent duplicates");
+    outln!(state, "  field fields              - pick column n (whitespace)");
+    outln!(state, "  field-by fields-by        - pick column n (delimiter)");
+    outln!(state, "  table table-by            - align fields into columns");
+    outln!(state, "  table-header              - highlight the header row");
+    outln!(state, "  diff                      - unified diff of two outputs");
+    outln!(state);
+    outln!(state, "Control Flow:");
+    outln!(state, "  if ... then               - conditional");
+    outln!(state, "  if ... else ... then      - conditional with else");
+    outln!(state);
+    outln!(state, "Loops:");
+    outln!(state, "  begin ... until           - loop until condition is true");
+    outln!(state, "  begin ... while ... repeat - loop while condition is true");
+    outln!(state, "  start limit do ... loop   - counted loop (step 1)");
+    outln!(state, "  start limit do ... +loop  - counted loop (step from stack)");
+    outln!(state, "  output each ... then      - iterate over output lines");
+    outln!(state, "  i j                       - loop indices");
+    outln!(state);
+    outln!(state, "Word Definition:");
+    outln!(state, "  : name ... ;              - define new word");
+    outln!(state, "  : on-not-found ... ;      - hook run with each unknown token");
+    outln!(state, "  redefine-ok : dup ... ;   - replace a builtin (builtin:dup is the original)");
+    outln!(state, "  \"f\" defer \"impl\" \"f\" is   - declare f now, choose what it runs later");
+    outln!(state, "  \"-la ls\" \"ll\" alias       - make ll run \"-la ls\" (unalias, aliases)");
+    outln!(state, "  set-suggest               - \"off\": no \"did you mean\" hints");
+    outln!(state);
+    outln!(state, "Vocabularies:");
+    outln!(state, "  \"git\" vocabulary          - create a vocabulary (words used as git:name)");
+    outln!(state, "  \"git\" in : status ... ;   - define a word in a vocabulary");
+    outln!(state, "  \"git\" also previous order - search it first / stop / show search order");
+    outln!(state);
+    outln!(state, "Testing:");
+    outln!(state, "  assert assert=            - fail unless flag is true / values are equal");
+    outln!(state, "  run-tests                 - run every test-* word, report, set ?");
+    outln!(state);
+    outln!(state, "Type Conversions:");
+    outln!(state, "  >output >string >int      - convert between types");
+    outln!(state, "  set-coerce                - \"on\": arithmetic reads numbers from text/output");
+    outln!(state, "  set-errexit               - \"on\": scripts and RC files stop at the first failing line");
+    outln!(state, "  typeof int? str? output?  - inspect the top item's type (it stays)");
+    outln!(state);
+    outln!(state, "File I/O:");
+    outln!(state, "  >file >>file              - write/append output to file");
+    outln!(state, "  tee                       - write output to file, keep on stack");
+    outln!(state, "  <stdin                    - stream a file as the next command's stdin");
+    outln!(state);
+    outln!(state, "Parallel:");
+    outln!(state, "  par-exec                  - run n command strings concurrently");
+    outln!(state, "  peach                     - run command once per line, concurrently");
+    outln!(state, "  out-status out-command    - exit code / name of the command behind an output");
+    outln!(state);
+    outln!(state, "Processes:");
+    outln!(state, "  kill pkill                - signal a pid / every process with a name");
+    outln!(state, "  procs                     - pid, name, cpu %, memory of every process");
+    outln!(state, "  pid                       - this shell's process ID");
+    outln!(state, "  spawn-detached            - start a command that outlives the shell, push its pid");
+    outln!(state);
+    outln!(state, "Timing:");
+    outln!(state, "  sleep sleep-ms            - pause (seconds / milliseconds)");
+    outln!(state, "  timed                     - run body, print real/user/sys time");
+    outln!(state, "  set-report-time           - print times of commands slower than n seconds");
+    outln!(state, "  watch watch-path          - re-run body on interval / file change");
+    outln!(state);
+    outln!(state, "Environment:");
+    outln!(state, "  getenv setenv unsetenv    - environment variables");
+    outln!(state);
+    outln!(state, "Filesystem:");
+    outln!(state, "  exists? dir? file?        - test a path (1 or 0)");
+    outln!(state, "  file-size file-mtime      - size in bytes / mtime in Unix seconds");
+    outln!(state, "  readlink                  - symlink target");
+    outln!(state, "  mkdir-p rm rm-rf cp mv touch - file operations");
+    outln!(state, "  find-files                - recursive search by name glob");
+    outln!(state, "  glob glob-count           - glob matches as one output / their count");
+    outln!(state, "  set-glob-dotfiles         - \"off\": globs skip names starting with . unless asked");
+    outln!(state, "  psub                      - output as a temp file path, like <(cmd)");
+    outln!(state);
+    outln!(state, "Hashing:");
+    outln!(state, "  sha256 sha1 md5           - hex digest of string/output");
+    outln!(state, "  sha256-file sha1-file md5-file - hex digest of a file");
+    outln!(state);
+    outln!(state, "HTTP:");
+    outln!(state, "  http-get http-post        - fetch URL / post body to URL");
+    outln!(state, "  http-status               - status code of last response");
+    outln!(state);
+    outln!(state, "Colors:");
+    outln!(state, "  fg bg bold dim underline  - ANSI style escapes");
+    outln!(state, "  reset-style color-wrap    - reset / wrap a string in a color");
+    outln!(state, "  \"word\" \"bold\" theme       - style a token class (theme-list shows all)");
+    outln!(state, "  set-color                 - \"off\": plain text everywhere (also NO_COLOR)");
+    outln!(state);
+    outln!(state, "Date and time:");
+    outln!(state, "  \"%Y-%m-%d\" $date         - format current time (strftime codes)");
+    outln!(state);
+    outln!(state, "System status:");
+    outln!(state, "  $loadavg $battery         - 1-minute load / battery percent (\"\" if none)");
+    outln!(state, "  $columns $rows            - terminal size (follows resizes)");
+    outln!(state);
+    outln!(state, "Prompt segments:");
+    outln!(state, "  segment segment-if        - register a segment / attach a condition");
+    outln!(state, "  segment-sep segments      - set separator / list segments");
+    outln!(state, "  $prompt-segments          - render non-empty segments");
+    outln!(state);
+    outln!(state, "Directory:");
+    outln!(state, "  cd pushd popd dirs        - directory navigation (\"-\" cd: go back)");
+    outln!(state, "  set-autocd                - \"on\": typing a directory name cds into it");
+    outln!(state, "  set-local-rc              - \"on\": load allowed .yafshrc.local files");
+    outln!(state, "  local-allow local-deny    - trust / distrust this directory's .yafshrc.local");
+    outln!(state);
+    outln!(state, "Line editing:");
+    outln!(state, "  \"vi\" set-keymode          - vi or emacs keybindings");
+    outln!(state, "  $vimode                   - [I]/[N] vi mode indicator for the prompt");
+    outln!(state, "  set-autosuggest           - \"on\": suggest from history (accept with →)");
+    outln!(state, "  set-stack-preview         - \"on\": show the resulting stack while typing");
+    outln!(state);
+    outln!(state, "Help System:");
+    outln!(state, "  words                     - list all words");
+    outln!(state, "  \"word\" see                - show word definition");
+    outln!(state, "  \"word\" edit               - edit a definition in $EDITOR, then reload it");
+    outln!(state, "  \"token\" which             - how a token resolves (builtin, command <path>, ...)");
+    outln!(state, "  help                      - show this help");
+    outln!(state, "  \"cd\" \"dirs\" complete-with - Tab-complete a word's arguments from a source");
+    outln!(state);
+    outln!(state, "History:");
+    outln!(state, "  history history-clear     - list numbered entries / forget them");
+    outln!(state, "  !! !n !-n                 - re-run last / n-th / n-th most recent entry");
+    outln!(state, "  history-stats             - most-used words and slowest entries");
+    outln!(state, "  history-size history-ignore - limit entries / skip lines matching a glob");
+    outln!(state, "  set-history-dedupe set-history-ignore-space - \"on\"/\"off\"");
+    outln!(state);
+    outln!(state, "Type 'words' to see all available commands");
+    Ok(())
+}
+
+/// `history` ( -- ) Print numbered history entries, for use with `!n`.
+pub fn history(state: &mut State) -> Result<(), String> {
+    let width = state.history.len().to_string().len();
+    for (i, entry) in state.history.iter().enumerate() {
+        outln!(state, "{:>width$}  {}", i + 1, entry, width = width);
+    }
+    Ok(())
+}
+
+/// `history-clear` ( -- ) Forget all history entries.
+pub fn history_clear(state: &mut State) -> Result<(), String> {
+    state.history.clear();
+    Ok(())
+}
+
+/// `history-stats` ( -- ) Show most-used words and slowest entries from recorded history.
+pub fn history_stats(state: &mut State) -> Result<(), String> {
+    let path = crate::config::history_meta_path().ok_or("history-stats: HOME not set")?;
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(format!("history-stats: {}: {}", path.display(), e)),
+    };
+    let records: Vec<HistoryRecord> = contents.lines().filter_map(HistoryRecord::parse).collect();
+    if records.is_empty() {
+        outln!(state, "(no recorded history yet)");
+        return Ok(());
+    }
+
+    let stats = history::stats(&records, 10);
+    outln!(state, "Most used ({} entries):", records.len());
+    for (word, count) in &stats.top_words {
+        outln!(state, "  {:>6}  {}", count, word);
+    }
+    outln!(state);
+    outln!(state, "Slowest:");
+    for record in &stats.slowest {
+        let status = if record.exit_code == 0 {
+            String::new()
+        } else {
+            format!("  [exit {}]", record.exit_code)
+        };
+        outln!(state, "  {:>8.1}s  {}{}", record.duration_ms as f64 / 1000.0, record.line, status);
+    }
     Ok(())
 }
 
+/// `complete-with` ( word kind -- ) Complete arguments of `word` from a source:
+/// "dirs", "files", "env", "words", or "commands" ("none" removes it).
+pub fn complete_with(state: &mut State) -> Result<(), String> {
+    if state.stack.len() < 2 {
+        return Err("complete-with: stack underflow".into());
+    }
+    let kind = state.stack.pop().unwrap();
+    let word = state.stack.pop().unwrap();
+    let (Value::Str(word_name), Value::Str(kind_name)) = (&word, &kind) else {
+        state.stack.push(word);
+        state.stack.push(kind);
+        return Err("complete-with: requires word name and kind strings".into());
+    };
+    if kind_name == "none" {
+        state.completers.remove(word_name);
+        return Ok(());
+    }
+    match CompletionKind::parse(kind_name) {
+        Some(k) => {
+            state.completers.insert(word_name.clone(), k);
+            Ok(())
+        }
+        None => {
+            state.stack.push(word);
+            state.stack.push(kind);
+            Err("complete-with: kind must be dirs, files, env, words, commands, or none".into())
+        }
+    }
+}
+
 /// `see` ( name -- ) Show the definition or documentation for a word.
 ///
 /// Pops a string from the stack and looks it up in the dictionary.
@@ -84,26 +295,328 @@ pub fn see(state: &mut State) -> Result<(), String> {
         }
     };
 
-    match state.dict.get(&name) {
-        Some(Word::Builtin(_, Some(doc))) => {
-            println!("{}: {}", name, doc);
+    let text = match state.lookup(&name) {
+        Some(Word::Builtin(_, Some(doc))) => format!("{}: {}", name, doc),
+        Some(Word::Builtin(_, None)) => format!("{} is a builtin function", name),
+        Some(Word::Defined(tokens, _)) => {
+            let mut text = format!(": {} ", name);
+            for t in tokens {
+                text.push_str(&format!("{} ", t));
+            }
+            text + ";"
         }
-        Some(Word::Builtin(_, None)) => {
-            println!("{} is a builtin function", name);
+        Some(Word::Alias(text)) => format!("{} is an alias for: {}", name, text),
+        Some(Word::Deferred(Some(target))) => format!("{} is deferred to: {}", name, target),
+        Some(Word::Deferred(None)) => format!("{} is deferred (not set yet)", name),
+        Some(Word::ShellCmd(cmd)) => format!("{} is a shell command: {}", name, cmd),
+        None => format!("{} is not defined", name),
+    };
+    outln!(state, "{}", text);
+    Ok(())
+}
+
+/// `alias` ( expansion name -- ) Make `name` expand to `expansion` in place.
+///
+/// An expansion that is just an external command is stored as that
+/// command's path, so `"git" "g" alias` skips the PATH search. Builtins
+/// can't be aliased over; defined words and earlier aliases are replaced.
+pub fn alias(state: &mut State) -> Result<(), String> {
+    if state.stack.len() < 2 {
+        return Err("alias: stack underflow".into());
+    }
+    let name = state.stack.pop().unwrap();
+    let expansion = state.stack.pop().unwrap();
+    let (Value::Str(text), Value::Str(name_str)) = (&expansion, &name) else {
+        state.stack.push(expansion);
+        state.stack.push(name);
+        return Err("alias: requires expansion and name strings".into());
+    };
+    let problem = if name_str.is_empty() || name_str.contains(char::is_whitespace) {
+        Some(format!("alias: invalid name '{}'", name_str))
+    } else if matches!(state.dict.get(name_str), Some(Word::Builtin(..))) {
+        Some(format!("alias: {} is a builtin", name_str))
+    } else {
+        None
+    };
+    if let Some(e) = problem {
+        state.stack.push(expansion);
+        state.stack.push(name);
+        return Err(e);
+    }
+
+    let word = match tokenizer::tokenize(text).as_slice() {
+        [only] if !only.quoted
+            && tokenizer::parse_int(&only.text).is_none()
+            && !state.dict.contains_key(&only.text) =>
+        {
+            match state.runner.find(&only.text) {
+                Some(path) => Word::ShellCmd(path),
+                None => Word::Alias(text.clone()),
+            }
         }
-        Some(Word::Defined(tokens)) => {
-            print!(": {} ", name);
-            for t in tokens {
-                print!("{} ", t);
+        _ => Word::Alias(text.clone()),
+    };
+    state.dict_mut().insert(name_str.clone(), word);
+    Ok(())
+}
+
+/// `which` ( name -- str ) How a token would resolve, e.g. "builtin" or
+/// "command /usr/bin/ls" (see [`eval::resolve`] for the full list).
+pub fn which(state: &mut State) -> Result<(), String> {
+    let val = state.stack.pop().ok_or("which: stack underflow")?;
+    let Value::Str(name) = &val else {
+        state.stack.push(val);
+        return Err("which: requires string (token)".into());
+    };
+    let resolution = eval::resolve(state, name);
+    state.stack.push(Value::Str(resolution));
+    Ok(())
+}
+
+/// `edit` ( name -- ) Open a word's definition in `$VISUAL`/`$EDITOR` and
+/// load the saved result. An undefined name starts from an empty definition.
+pub fn edit(state: &mut State) -> Result<(), String> {
+    system::deny_restricted(state, "edit")?;
+    let val = state.stack.pop().ok_or("edit: stack underflow")?;
+    let Value::Str(name) = &val else {
+        state.stack.push(val);
+        return Err("edit: requires string (word name)".into());
+    };
+    let source = match state.dict.get(name) {
+        Some(word) => word_source(name, word),
+        None => Some(format!(": {}\n;\n", name)),
+    };
+    let Some(source) = source else {
+        let e = format!("edit: {} is a builtin", name);
+        state.stack.push(val);
+        return Err(e);
+    };
+
+    let path = write_edit_file(&source)?;
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    // Through sh, so an editor with arguments ("code -w") works
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$1\"", editor))
+        .arg("sh")
+        .arg(&path)
+        .status();
+    let edited = fs::read_to_string(&path);
+    let _ = fs::remove_file(&path);
+
+    match status {
+        Ok(s) if s.success() => {}
+        Ok(_) => return Err(format!("edit: {} exited with an error; nothing changed", editor)),
+        Err(e) => return Err(format!("edit: cannot run {}: {}", editor, e)),
+    }
+    let edited = edited.map_err(|e| format!("edit: {}", e))?;
+    if edited == source {
+        return Ok(());
+    }
+    load_edited(state, name, &edited)
+}
+
+/// A new private temp file holding `source`, for the editor to open.
+fn write_edit_file(source: &str) -> Result<std::path::PathBuf, String> {
+    let dir = std::env::temp_dir();
+    let mut attempt = 0;
+    loop {
+        let path = dir.join(format!("yafsh-edit-{}-{}.yafsh", std::process::id(), attempt));
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(feature = "native")]
+        options.mode(0o600);
+        match options.open(&path) {
+            Ok(mut file) => {
+                let written = file.write_all(source.as_bytes());
+                if let Err(e) = written {
+                    let _ = fs::remove_file(&path);
+                    return Err(format!("edit: {}: {}", path.display(), e));
+                }
+                return Ok(path);
             }
-            println!(";");
+            Err(e) if e.kind() == ErrorKind::AlreadyExists && attempt < 100 => attempt += 1,
+            Err(e) => return Err(format!("edit: {}: {}", path.display(), e)),
         }
-        Some(Word::ShellCmd(cmd)) => {
-            println!("{} is a shell command: {}", name, cmd);
+    }
+}
+
+/// Source text that recreates `word` as `name`, or `None` for builtins.
+///
+/// Body tokens are written as they were typed, quoted ones in quotes.
+pub fn word_source(name: &str, word: &Word) -> Option<String> {
+    let quote = |text: &str| Token::new(text, true).to_string();
+    match word {
+        Word::Builtin(..) => None,
+        Word::Defined(tokens, _) => {
+            let body: Vec<String> = tokens.iter().map(|t| t.to_string()).collect();
+            Some(format!(": {}\n  {}\n;\n", name, body.join(" ")))
         }
-        None => {
-            println!("{} is not defined", name);
+        Word::Alias(text) | Word::ShellCmd(text) => {
+            Some(format!("{} {} alias\n", quote(text), quote(name)))
+        }
+        Word::Deferred(target) => {
+            let mut out = format!("{} defer\n", quote(name));
+            if let Some(target) = target {
+                out.push_str(&format!("{} {} is\n", quote(target), quote(name)));
+            }
+            Some(out)
+        }
+    }
+}
+
+/// Evaluate edited source in place of `name`'s current definition, putting
+/// the old one back if the new text fails.
+///
+/// `name` keeps its definition unless the text defines it again, so
+/// renaming the word in the editor adds a word rather than moving it.
+fn load_edited(state: &mut State, name: &str, text: &str) -> Result<(), String> {
+    let old = state.dict.get(name).cloned();
+    state.editing = Some(name.to_string());
+    let mut result = Ok(());
+    for line in text.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        result = eval::eval_line(state, trimmed);
+        if result.is_err() {
+            break;
+        }
+    }
+    state.editing = None;
+    if result.is_ok() && state.defining.is_some() {
+        result = Err("unterminated definition (missing ;)".into());
+    }
+    if let Err(e) = result {
+        state.defining = None;
+        state.def_body.clear();
+        match old {
+            Some(old) => state.dict_mut().insert(name.to_string(), old),
+            None => state.dict_mut().remove(name),
+        };
+        return Err(format!("edit: {}", e));
+    }
+    Ok(())
+}
+
+/// `defer` ( name -- ) Declare a word whose implementation is set later
+/// with `is`, and can be swapped without redefining its callers.
+pub fn defer(state: &mut State) -> Result<(), String> {
+    let val = state.stack.pop().ok_or("defer: stack underflow")?;
+    let Value::Str(name) = &val else {
+        state.stack.push(val);
+        return Err("defer: requires string (word name)".into());
+    };
+    let problem = if name.is_empty() || name.contains(char::is_whitespace) {
+        Some(format!("defer: invalid name '{}'", name))
+    } else if matches!(state.dict.get(name), Some(Word::Builtin(..))) {
+        Some(format!("defer: {} is a builtin", name))
+    } else {
+        None
+    };
+    if let Some(e) = problem {
+        state.stack.push(val);
+        return Err(e);
+    }
+    if !matches!(state.dict.get(name), None | Some(Word::Deferred(_))) {
+        eprintln!("warning: redefining {}", name);
+    }
+    state.dict_mut().insert(name.clone(), Word::Deferred(None));
+    Ok(())
+}
+
+/// Whether running `target` reaches `name` by following deferred words, so
+/// pointing `name` at it would loop forever.
+fn runs_through(state: &State, target: &str, name: &str) -> bool {
+    let mut current = target;
+    // Each hop is a different dictionary entry, so this bounds the walk
+    for _ in 0..=state.dict.len() {
+        if current == name {
+            return true;
         }
+        match state.lookup(current) {
+            Some(Word::Deferred(Some(next))) => current = next,
+            _ => return false,
+        }
+    }
+    true
+}
+
+/// `is` ( impl name -- ) Make the deferred word `name` run `impl`.
+pub fn is(state: &mut State) -> Result<(), String> {
+    if state.stack.len() < 2 {
+        return Err("is: stack underflow".into());
+    }
+    let name = state.stack.pop().unwrap();
+    let target = state.stack.pop().unwrap();
+    let (Value::Str(target_str), Value::Str(name_str)) = (&target, &name) else {
+        state.stack.push(target);
+        state.stack.push(name);
+        return Err("is: requires implementation and word name strings".into());
+    };
+    let problem = if !matches!(state.dict.get(name_str), Some(Word::Deferred(_))) {
+        Some(format!("is: {} is not a deferred word (declare it with defer)", name_str))
+    } else if runs_through(state, target_str, name_str) {
+        Some(format!("is: {} can't run itself", name_str))
+    } else if state.lookup(target_str).is_none() {
+        Some(format!("is: unknown word '{}'", target_str))
+    } else {
+        None
+    };
+    if let Some(e) = problem {
+        state.stack.push(target);
+        state.stack.push(name);
+        return Err(e);
+    }
+    state
+        .dict_mut()
+        .insert(name_str.clone(), Word::Deferred(Some(target_str.clone())));
+    Ok(())
+}
+
+/// `redefine-ok` ( -- ) Let the next `: name ... ;` replace a builtin.
+///
+/// The original stays reachable as `builtin:name`.
+pub fn redefine_ok(state: &mut State) -> Result<(), String> {
+    state.redefine_ok = true;
+    Ok(())
+}
+
+/// `unalias` ( name -- ) Remove an alias made with `alias`.
+pub fn unalias(state: &mut State) -> Result<(), String> {
+    let val = state.stack.pop().ok_or("unalias: stack underflow")?;
+    let Value::Str(name) = &val else {
+        state.stack.push(val);
+        return Err("unalias: requires string (alias name)".into());
+    };
+    if matches!(
+        state.dict.get(name),
+        Some(Word::Alias(_)) | Some(Word::ShellCmd(_))
+    ) {
+        state.dict_mut().remove(name);
+        return Ok(());
+    }
+    let e = format!("unalias: {} is not an alias", name);
+    state.stack.push(val);
+    Err(e)
+}
+
+/// `aliases` ( -- ) List aliases and what they expand to.
+pub fn aliases(state: &mut State) -> Result<(), String> {
+    let mut list: Vec<(&String, &str)> = state
+        .dict
+        .iter()
+        .filter_map(|(name, word)| match word {
+            Word::Alias(text) | Word::ShellCmd(text) => Some((name, text.as_str())),
+            _ => None,
+        })
+        .collect();
+    list.sort();
+    for (name, text) in list {
+        outln!(state, "{:<12} {}", name, text);
     }
     Ok(())
 }
@@ -125,8 +638,8 @@ fn count_stack(stack: &[Value]) -> (usize, usize) {
     let mut outputs = 0;
     for val in stack {
         match val {
-            Value::Str(_) | Value::Int(_) => inputs += 1,
-            Value::Output(_) => outputs += 1,
+            Value::Str(_) | Value::Int(_) | Value::Map(_) | Value::List(_) => inputs += 1,
+            Value::Output(_, _) => outputs += 1,
         }
     }
     (inputs, outputs)
@@ -151,6 +664,17 @@ pub fn dollar_stack(state: &mut State) -> Result<(), String> {
     Ok(())
 }
 
+/// `$stackname` ( -- str ) Push the active stack's name, or "" on the main stack.
+pub fn dollar_stackname(state: &mut State) -> Result<(), String> {
+    let name = if state.stack_name == MAIN_STACK {
+        String::new()
+    } else {
+        state.stack_name.clone()
+    };
+    state.stack.push(Value::Str(name));
+    Ok(())
+}
+
 /// `$in` ( -- int ) Push count of input items on the stack.
 pub fn dollar_in(state: &mut State) -> Result<(), String> {
     let stack = prompt_stack(state);
@@ -167,10 +691,14 @@ pub fn dollar_out(state: &mut State) -> Result<(), String> {
     Ok(())
 }
 
-/// `$gitbranch` ( -- str ) Push current git branch name (empty if not in a git repo).
-pub fn dollar_gitbranch(state: &mut State) -> Result<(), String> {
-    let branch = Command::new("git")
+/// How long a cached `$gitbranch` result is used before a background refresh.
+const GIT_PROMPT_MAX_AGE: Duration = Duration::from_secs(2);
+
+/// Current git branch of `dir` (empty if not in a git repo).
+fn git_branch_in(dir: &str) -> String {
+    Command::new("git")
         .args(["branch", "--show-current"])
+        .current_dir(dir)
         .output()
         .ok()
         .and_then(|out| {
@@ -180,11 +708,127 @@ pub fn dollar_gitbranch(state: &mut State) -> Result<(), String> {
                 None
             }
         })
+        .unwrap_or_default()
+}
+
+/// `$gitbranch` ( -- str ) Push current git branch name (empty if not in a git repo).
+///
+/// Cached per directory and refreshed in the background, so rendering the
+/// prompt doesn't wait on git. `prompt-refresh` forces a fresh lookup.
+pub fn dollar_gitbranch(state: &mut State) -> Result<(), String> {
+    let cwd = std::env::current_dir()
+        .map(|d| d.to_string_lossy().to_string())
         .unwrap_or_default();
+    let key = format!("gitbranch:{}", cwd);
+    let branch = state
+        .prompt_cache
+        .get(&key, GIT_PROMPT_MAX_AGE, move || git_branch_in(&cwd));
     state.stack.push(Value::Str(branch));
     Ok(())
 }
 
+/// Repository state parsed from `git status --porcelain=v2 --branch`.
+#[derive(Debug, Default, PartialEq)]
+struct GitStatus {
+    ahead: u64,
+    behind: u64,
+    staged: bool,
+    unstaged: bool,
+    untracked: bool,
+}
+
+impl GitStatus {
+    fn parse(porcelain: &str) -> GitStatus {
+        let mut status = GitStatus::default();
+        for line in porcelain.lines() {
+            if let Some(ab) = line.strip_prefix("# branch.ab ") {
+                // "+<ahead> -<behind>"
+                for part in ab.split_whitespace() {
+                    if let Some(n) = part.strip_prefix('+') {
+                        status.ahead = n.parse().unwrap_or(0);
+                    } else if let Some(n) = part.strip_prefix('-') {
+                        status.behind = n.parse().unwrap_or(0);
+                    }
+                }
+            } else if line.starts_with("1 ") || line.starts_with("2 ") {
+                // "1 XY ...": X is the index (staged) state, Y the worktree state
+                let xy = line.as_bytes().get(2..4).unwrap_or(b"..");
+                status.staged |= xy[0] != b'.';
+                status.unstaged |= xy[1] != b'.';
+            } else if line.starts_with("u ") {
+                status.unstaged = true;
+            } else if line.starts_with("? ") {
+                status.untracked = true;
+            }
+        }
+        status
+    }
+}
+
+/// Raw `git status --porcelain=v2 --branch` output for `dir` (empty outside a repo).
+fn git_status_in(dir: &str) -> String {
+    Command::new("git")
+        .args(["status", "--porcelain=v2", "--branch"])
+        .current_dir(dir)
+        .output()
+        .ok()
+        .filter(|out| out.status.success())
+        .map(|out| String::from_utf8_lossy(&out.stdout).into_owned())
+        .unwrap_or_default()
+}
+
+/// Git status of the current directory, via the prompt cache.
+fn cached_git_status(state: &mut State) -> GitStatus {
+    let cwd = std::env::current_dir()
+        .map(|d| d.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let key = format!("gitstatus:{}", cwd);
+    let raw = state
+        .prompt_cache
+        .get(&key, GIT_PROMPT_MAX_AGE, move || git_status_in(&cwd));
+    GitStatus::parse(&raw)
+}
+
+/// Push a count as Str, or the empty string for zero (so `?prefix` drops it).
+fn push_count(state: &mut State, n: u64) {
+    let s = if n == 0 { String::new() } else { n.to_string() };
+    state.stack.push(Value::Str(s));
+}
+
+/// `$gitdirty` ( -- str ) Push "*" if the worktree has unstaged or untracked changes, else "".
+pub fn dollar_gitdirty(state: &mut State) -> Result<(), String> {
+    let status = cached_git_status(state);
+    let dirty = status.unstaged || status.untracked;
+    state.stack.push(Value::Str(if dirty { "*" } else { "" }.into()));
+    Ok(())
+}
+
+/// `$gitstaged` ( -- str ) Push "+" if changes are staged for commit, else "".
+pub fn dollar_gitstaged(state: &mut State) -> Result<(), String> {
+    let status = cached_git_status(state);
+    state.stack.push(Value::Str(if status.staged { "+" } else { "" }.into()));
+    Ok(())
+}
+
+/// `$gitahead` ( -- str ) Push the number of commits ahead of upstream ("" if none).
+pub fn dollar_gitahead(state: &mut State) -> Result<(), String> {
+    let status = cached_git_status(state);
+    push_count(state, status.ahead);
+    Ok(())
+}
+
+/// `$gitbehind` ( -- str ) Push the number of commits behind upstream ("" if none).
+pub fn dollar_gitbehind(state: &mut State) -> Result<(), String> {
+    let status = cached_git_status(state);
+    push_count(state, status.behind);
+    Ok(())
+}
+
+/// `prompt-refresh` ( -- ) Discard cached prompt helper results.
+pub fn prompt_refresh(state: &mut State) -> Result<(), String> {
+    state.prompt_cache.clear();
+    Ok(())
+}
 /// `$cwd` ( -- str ) Push the current working directory.
 pub fn dollar_cwd(state: &mut State) -> Result<(), String> {
     let cwd = std::env::current_dir()
@@ -206,15 +850,42 @@ pub fn dollar_basename(state: &mut State) -> Result<(), String> {
 
 /// `$hostname` ( -- str ) Push the system hostname.
 pub fn dollar_hostname(state: &mut State) -> Result<(), String> {
-    let hostname = Command::new("hostname")
-        .output()
-        .ok()
-        .map(|out| String::from_utf8_lossy(&out.stdout).trim().to_string())
-        .unwrap_or_else(|| "unknown".to_string());
+    // The hostname practically never changes, so look it up once
+    let hostname = state.prompt_cache.get("hostname", Duration::MAX, || {
+        native_hostname()
+            .or_else(|| {
+                Command::new("hostname")
+                    .output()
+                    .ok()
+                    .map(|out| String::from_utf8_lossy(&out.stdout).trim().to_string())
+                    .filter(|name| !name.is_empty())
+            })
+            .unwrap_or_else(|| "unknown".to_string())
+    });
     state.stack.push(Value::Str(hostname));
     Ok(())
 }
 
+/// Hostname from gethostname(2), or `None` if the call fails.
+#[cfg(feature = "native")]
+fn native_hostname() -> Option<String> {
+    let mut buf = [0u8; 256];
+    // SAFETY: gethostname writes at most `buf.len()` bytes into our buffer.
+    let rc = unsafe { libc::gethostname(buf.as_mut_ptr().cast(), buf.len()) };
+    if rc != 0 {
+        return None;
+    }
+    // Truncated names may lack the terminating NUL; take the whole buffer then
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    let name = String::from_utf8_lossy(&buf[..len]).into_owned();
+    (!name.is_empty()).then_some(name)
+}
+
+#[cfg(not(feature = "native"))]
+fn native_hostname() -> Option<String> {
+    None
+}
+
 /// `$username` ( -- str ) Push the current username.
 pub fn dollar_username(state: &mut State) -> Result<(), String> {
     let username = std::env::var("USER")
@@ -224,6 +895,22 @@ pub fn dollar_username(state: &mut State) -> Result<(), String> {
     Ok(())
 }
 
+/// `$vimode` ( -- str ) Push the vi mode indicator ("[I]", redrawn as "[N]" in
+/// normal mode), or "" when emacs keybindings are active.
+pub fn dollar_vimode(state: &mut State) -> Result<(), String> {
+    #[cfg(feature = "native")]
+    let indicator = if state.vi_mode {
+        crate::highlight::VI_INSERT_INDICATOR
+    } else {
+        ""
+    };
+    // No line editor, so no vi mode to show
+    #[cfg(not(feature = "native"))]
+    let indicator = "";
+    state.stack.push(Value::Str(indicator.into()));
+    Ok(())
+}
+
 /// `$exitcode` ( -- str ) Push the last exit code as a string.
 pub fn dollar_exitcode(state: &mut State) -> Result<(), String> {
     state
@@ -232,18 +919,191 @@ pub fn dollar_exitcode(state: &mut State) -> Result<(), String> {
     Ok(())
 }
 
+/// `$pid` ( -- str ) Push this shell's process ID as a string.
+pub fn dollar_pid(state: &mut State) -> Result<(), String> {
+    state.stack.push(Value::Str(std::process::id().to_string()));
+    Ok(())
+}
+
+/// Lines quicker than this leave `$cmdtime` empty, unless changed with
+/// `set-cmdtime-min`.
+pub const DEFAULT_CMDTIME_MIN: Duration = Duration::from_secs(3);
+
+/// A duration for the prompt: "4.2s", "1m 5s", or "2h 3m".
+fn format_duration(d: Duration) -> String {
+    let secs = d.as_secs();
+    if secs < 60 {
+        format!("{:.1}s", d.as_secs_f64())
+    } else if secs < 3600 {
+        format!("{}m {}s", secs / 60, secs % 60)
+    } else {
+        format!("{}h {}m", secs / 3600, secs % 3600 / 60)
+    }
+}
+
+/// `$cmdtime` ( -- str ) How long the last line took, like "4.2s" or
+/// "1m 5s", or "" if it was quicker than `set-cmdtime-min` (3 seconds).
+pub fn dollar_cmdtime(state: &mut State) -> Result<(), String> {
+    let text = match state.last_duration {
+        Some(d) if d >= state.cmdtime_min => format_duration(d),
+        _ => String::new(),
+    };
+    state.stack.push(Value::Str(text));
+    Ok(())
+}
+
+/// `set-cmdtime-min` ( seconds -- ) Shortest line duration `$cmdtime` shows.
+pub fn set_cmdtime_min(state: &mut State) -> Result<(), String> {
+    match state.stack.pop() {
+        Some(Value::Int(n)) if n >= 0 => {
+            state.cmdtime_min = Duration::from_secs(n as u64);
+            Ok(())
+        }
+        Some(other) => {
+            state.stack.push(other);
+            Err("set-cmdtime-min: requires non-negative integer".into())
+        }
+        None => Err("set-cmdtime-min: stack underflow".into()),
+    }
+}
+
+/// The terminal's size in one dimension, or else `var` from the environment
+/// (as set by other shells when not attached to a terminal), or else `default`.
+fn terminal_dimension(live: Option<usize>, var: &str, default: usize) -> i64 {
+    live.or_else(|| std::env::var(var).ok()?.trim().parse().ok())
+        .unwrap_or(default) as i64
+}
+
+/// `$columns` ( -- n ) Width of the terminal in columns, read afresh each
+/// time so it follows window resizes; $COLUMNS or 80 without a terminal.
+pub fn dollar_columns(state: &mut State) -> Result<(), String> {
+    state.stack.push(Value::Int(terminal_dimension(crate::terminal::width(), "COLUMNS", 80)));
+    Ok(())
+}
+
+/// `$rows` ( -- n ) Height of the terminal in rows, like `$columns`;
+/// $LINES or 24 without a terminal.
+pub fn dollar_rows(state: &mut State) -> Result<(), String> {
+    state.stack.push(Value::Int(terminal_dimension(crate::terminal::rows(), "LINES", 24)));
+    Ok(())
+}
+
+/// `$loadavg` ( -- str ) Push the 1-minute load average, e.g. "0.42".
+pub fn dollar_loadavg(state: &mut State) -> Result<(), String> {
+    let load = load_average().map(|load| format!("{:.2}", load)).unwrap_or_default();
+    state.stack.push(Value::Str(load));
+    Ok(())
+}
+
+/// The 1-minute load average from getloadavg(3).
+#[cfg(feature = "native")]
+fn load_average() -> Option<f64> {
+    let mut loads = [0f64; 3];
+    // SAFETY: getloadavg writes at most `nelem` doubles into the array we pass.
+    let n = unsafe { libc::getloadavg(loads.as_mut_ptr(), 3) };
+    (n >= 1).then_some(loads[0])
+}
+
+#[cfg(not(feature = "native"))]
+fn load_average() -> Option<f64> {
+    None
+}
+
+/// `$battery` ( -- str ) Push the battery charge percentage, or "" without a battery.
+pub fn dollar_battery(state: &mut State) -> Result<(), String> {
+    let percent = battery_percent(Path::new("/sys/class/power_supply"))
+        .map(|p| p.to_string())
+        .unwrap_or_default();
+    state.stack.push(Value::Str(percent));
+    Ok(())
+}
+
+/// Charge of the first battery listed under a sysfs power_supply directory.
+fn battery_percent(supplies: &Path) -> Option<u32> {
+    let mut entries: Vec<_> = fs::read_dir(supplies).ok()?.flatten().collect();
+    entries.sort_by_key(|e| e.file_name());
+    entries.iter().find_map(|entry| {
+        let dir = entry.path();
+        let kind = fs::read_to_string(dir.join("type")).ok()?;
+        if kind.trim() != "Battery" {
+            return None;
+        }
+        fs::read_to_string(dir.join("capacity"))
+            .ok()?
+            .trim()
+            .parse()
+            .ok()
+    })
+}
+
 /// `$time` ( -- str ) Push current time as HH:MM.
 pub fn dollar_time(state: &mut State) -> Result<(), String> {
-    let time_str = Command::new("date")
-        .arg("+%H:%M")
-        .output()
-        .ok()
-        .map(|out| String::from_utf8_lossy(&out.stdout).trim().to_string())
-        .unwrap_or_else(|| "??:??".to_string());
-    state.stack.push(Value::Str(time_str));
+    let time = format_local_time("%H:%M").unwrap_or_else(|| "??:??".to_string());
+    state.stack.push(Value::Str(time));
     Ok(())
 }
 
+/// `$date` ( fmt -- str ) Format the current local time with strftime(3) codes.
+pub fn dollar_date(state: &mut State) -> Result<(), String> {
+    let fmt = match state.stack.pop() {
+        Some(Value::Str(s)) => s,
+        Some(other) => {
+            state.stack.push(other);
+            return Err("$date: requires format string".into());
+        }
+        None => return Err("$date: stack underflow".into()),
+    };
+    match format_local_time(&fmt) {
+        Some(date) => {
+            state.stack.push(Value::Str(date));
+            Ok(())
+        }
+        None => {
+            state.stack.push(Value::Str(fmt));
+            Err("$date: invalid format".into())
+        }
+    }
+}
+
+/// Current local time formatted with strftime(3), read natively instead of
+/// spawning date(1). Returns `None` if the format contains a NUL byte.
+#[cfg(feature = "native")]
+fn format_local_time(fmt: &str) -> Option<String> {
+    if fmt.is_empty() {
+        return Some(String::new());
+    }
+    let c_fmt = std::ffi::CString::new(fmt).ok()?;
+    // SAFETY: time(NULL) has no preconditions, localtime_r only writes into
+    // the tm struct we own, and strftime never writes past `buf.len()` bytes.
+    unsafe {
+        let now = libc::time(std::ptr::null_mut());
+        let mut tm: libc::tm = std::mem::zeroed();
+        if libc::localtime_r(&now, &mut tm).is_null() {
+            return None;
+        }
+        // strftime returns 0 both on overflow and for legitimately empty
+        // output (e.g. "%p" in some locales), so grow a few times before
+        // settling for ""
+        let mut size = 256;
+        while size <= 64 * 1024 {
+            let mut buf = vec![0u8; size];
+            let n = libc::strftime(buf.as_mut_ptr().cast(), buf.len(), c_fmt.as_ptr(), &tm);
+            if n > 0 {
+                buf.truncate(n);
+                return Some(String::from_utf8_lossy(&buf).into_owned());
+            }
+            size *= 4;
+        }
+        Some(String::new())
+    }
+}
+
+/// Without the C library there is no local time zone or strftime to use.
+#[cfg(not(feature = "native"))]
+fn format_local_time(fmt: &str) -> Option<String> {
+    fmt.is_empty().then(String::new)
+}
+
 /// `trace` ( level -- ) Set trace verbosity level.
 ///
 /// Accepts a string or integer:
@@ -284,10 +1144,38 @@ pub fn trace_mode(state: &mut State) -> Result<(), String> {
     }
 }
 
+/// `trace-file` ( path -- ) Trace every step to a file as JSON lines.
+///
+/// Each line records the token, the values it popped and pushed, the stack
+/// depth after it, and how long it took in microseconds. Stderr trace
+/// output stops while a trace file is open; `"" trace-file` closes it.
+pub fn trace_file(state: &mut State) -> Result<(), String> {
+    system::deny_restricted(state, "trace-file")?;
+    let path = match state.stack.pop() {
+        Some(Value::Str(s)) => s,
+        Some(other) => {
+            state.stack.push(other);
+            return Err("trace-file: requires a path string".into());
+        }
+        None => return Err("trace-file: stack underflow".into()),
+    };
+    if path.is_empty() {
+        if state.trace_file.take().is_some() {
+            eprintln!("Trace file closed");
+        }
+        return Ok(());
+    }
+    let file = std::fs::File::create(&path).map_err(|e| format!("trace-file: {}: {}", path, e))?;
+    state.trace_file = Some(std::io::LineWriter::new(file));
+    eprintln!("Tracing to {}", path);
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::builtins;
+    use crate::sink::Sink;
     use crate::types::Value;
 
     fn new_state() -> State {
@@ -299,44 +1187,54 @@ mod tests {
     #[test]
     fn test_words_runs_without_error() {
         let mut s = new_state();
+        s.out = Sink::capture();
         words(&mut s).unwrap();
         // Stack should be unmodified
         assert!(s.stack.is_empty());
+        assert!(s.out.take_captured().split(' ').any(|w| w == "dup"));
     }
 
     #[test]
     fn test_help_runs_without_error() {
         let mut s = new_state();
+        s.out = Sink::capture();
         help(&mut s).unwrap();
         assert!(s.stack.is_empty());
+        assert!(s.out.take_captured().starts_with("Forth Shell - Available Commands\n"));
     }
 
     #[test]
     fn test_see_builtin_with_doc() {
         let mut s = new_state();
+        s.out = Sink::capture();
         s.stack.push(Value::Str("dup".into()));
         see(&mut s).unwrap();
         assert!(s.stack.is_empty());
+        assert!(s.out.take_captured().starts_with("dup: ( a -- a a )"));
     }
 
     #[test]
     fn test_see_defined_word() {
         let mut s = new_state();
-        s.dict.insert(
+        s.dict_mut().insert(
             "greet".to_string(),
-            Word::Defined(vec!["\"hello\"".to_string()]),
+            Word::defined(tokenizer::tokenize("\"hello\"")),
         );
+        s.out = Sink::capture();
         s.stack.push(Value::Str("greet".into()));
         see(&mut s).unwrap();
         assert!(s.stack.is_empty());
+        assert_eq!(s.out.take_captured(), ": greet \"hello\" ;\n");
     }
 
     #[test]
     fn test_see_undefined() {
         let mut s = new_state();
+        s.out = Sink::capture();
         s.stack.push(Value::Str("nonexistent".into()));
         see(&mut s).unwrap(); // Should not error, just print "not defined"
         assert!(s.stack.is_empty());
+        assert_eq!(s.out.take_captured(), "nonexistent is not defined\n");
     }
 
     #[test]
@@ -352,6 +1250,133 @@ mod tests {
         assert!(see(&mut s).is_err());
     }
 
+    #[test]
+    fn test_alias_of_words() {
+        let mut s = new_state();
+        eval::eval_line(&mut s, "\"dup *\" \"sq\" alias 3 sq").unwrap();
+        assert_eq!(s.stack, vec![Value::Int(9)]);
+        assert!(matches!(s.dict.get("sq"), Some(Word::Alias(t)) if t == "dup *"));
+    }
+
+    #[test]
+    #[cfg(feature = "native")]
+    fn test_alias_of_command_caches_path() {
+        let mut s = new_state();
+        eval::eval_line(&mut s, "\"echo\" \"say\" alias").unwrap();
+        assert!(matches!(s.dict.get("say"), Some(Word::ShellCmd(p)) if p.ends_with("/echo")));
+    }
+
+    #[test]
+    fn test_alias_refuses_builtins() {
+        let mut s = new_state();
+        let err = eval::eval_line(&mut s, "\"1 .\" \"dup\" alias").unwrap_err();
+        assert_eq!(err, "alias: dup is a builtin");
+        assert_eq!(s.stack.len(), 2);
+        assert!(matches!(s.dict.get("dup"), Some(Word::Builtin(..))));
+    }
+
+    #[test]
+    fn test_unalias() {
+        let mut s = new_state();
+        eval::eval_line(&mut s, "\"dup\" \"d\" alias \"d\" unalias").unwrap();
+        assert!(!s.dict.contains_key("d"));
+        // Only aliases can be removed this way
+        let err = eval::eval_line(&mut s, "\"dup\" unalias").unwrap_err();
+        assert_eq!(err, "unalias: dup is not an alias");
+        assert_eq!(s.stack, vec![Value::Str("dup".into())]);
+    }
+
+    #[cfg(feature = "native")]
+    fn which_of(s: &mut State, token: &str) -> String {
+        s.stack.push(Value::Str(token.into()));
+        which(s).unwrap();
+        match s.stack.pop() {
+            Some(Value::Str(r)) => r,
+            other => panic!("expected Str, got {:?}", other),
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "native")]
+    fn test_which() {
+        let mut s = new_state();
+        eval::eval_line(&mut s, ": sq dup * ; \"-la ls\" \"ll\" alias").unwrap();
+        eval::eval_line(&mut s, "\"v\" vocabulary \"v\" in : vw ; \"v\" also").unwrap();
+        assert_eq!(which_of(&mut s, "if"), "keyword");
+        assert_eq!(which_of(&mut s, "0x1f"), "integer");
+        assert_eq!(which_of(&mut s, "dup"), "builtin");
+        assert_eq!(which_of(&mut s, "sh-c"), "builtin");
+        assert_eq!(which_of(&mut s, "sq"), "word");
+        assert_eq!(which_of(&mut s, "vw"), "word v:vw");
+        assert_eq!(which_of(&mut s, ";"), "keyword");
+        // The search order shadows the bare name, so that's what runs
+        eval::eval_line(&mut s, "\"v\" in : sq 1 ;").unwrap();
+        assert!(s.dict.contains_key("v:sq"));
+        assert_eq!(which_of(&mut s, "sq"), "word v:sq");
+        assert_eq!(which_of(&mut s, "ll"), "alias -la ls");
+        assert!(which_of(&mut s, "sh").starts_with("command /"));
+        assert!(which_of(&mut s, "src/*.rs").starts_with("glob "));
+        assert_eq!(which_of(&mut s, "zz-no-such"), "string");
+        eval::eval_line(&mut s, ": on-not-found drop ;").unwrap();
+        assert_eq!(which_of(&mut s, "zz-no-such"), "on-not-found");
+    }
+
+    #[test]
+    fn test_which_wrong_type() {
+        let mut s = new_state();
+        s.stack.push(Value::Int(1));
+        assert!(which(&mut s).is_err());
+        assert_eq!(s.stack, vec![Value::Int(1)]);
+    }
+
+    #[test]
+    fn test_word_source_round_trips() {
+        let mut s = new_state();
+        eval::eval_line(&mut s, ": greet \"hello world\" . ;").unwrap();
+        let source = word_source("greet", &s.dict["greet"]).unwrap();
+        assert_eq!(source, ": greet\n  \"hello world\" .\n;\n");
+        assert_eq!(word_source("dup", &s.dict["dup"]), None);
+        assert_eq!(
+            word_source("ll", &Word::Alias("-la ls".into())).unwrap(),
+            "\"-la ls\" \"ll\" alias\n"
+        );
+        let alias = Word::Alias(r#""my dir" ls"#.into());
+        let source = word_source("md", &alias).unwrap();
+        assert_eq!(source, "\"\\\"my dir\\\" ls\" \"md\" alias\n");
+        eval::eval_line(&mut s, source.trim()).unwrap();
+        assert!(matches!(&s.dict["md"], Word::Alias(t) if t == r#""my dir" ls"#));
+    }
+
+    #[test]
+    fn test_load_edited_rename_keeps_original() {
+        let mut s = new_state();
+        eval::eval_line(&mut s, ": n 1 ;").unwrap();
+        load_edited(&mut s, "n", ": m\n  2\n;\n").unwrap();
+        eval::eval_line(&mut s, "n m").unwrap();
+        assert_eq!(s.stack, vec![Value::Int(1), Value::Int(2)]);
+        assert!(s.editing.is_none());
+    }
+
+    #[test]
+    fn test_load_edited_replaces_definition() {
+        let mut s = new_state();
+        eval::eval_line(&mut s, ": n 1 ;").unwrap();
+        load_edited(&mut s, "n", ": n\n  2\n;\n").unwrap();
+        eval::eval_line(&mut s, "n").unwrap();
+        assert_eq!(s.stack, vec![Value::Int(2)]);
+    }
+
+    #[test]
+    fn test_load_edited_keeps_old_on_error() {
+        let mut s = new_state();
+        eval::eval_line(&mut s, ": n 1 ;").unwrap();
+        let err = load_edited(&mut s, "n", ": n 2").unwrap_err();
+        assert_eq!(err, "edit: unterminated definition (missing ;)");
+        assert!(s.defining.is_none());
+        eval::eval_line(&mut s, "n").unwrap();
+        assert_eq!(s.stack, vec![Value::Int(1)]);
+    }
+
     // ===== Prompt helper tests =====
 
     #[test]
@@ -375,7 +1400,7 @@ mod tests {
     #[test]
     fn test_dollar_stack_outputs_only() {
         let mut s = new_state();
-        s.stack.push(Value::Output("data".into()));
+        s.stack.push(Value::output("data"));
         dollar_stack(&mut s).unwrap();
         assert_eq!(s.stack.len(), 2);
         assert_eq!(s.stack[1], Value::Str("[:1]".into()));
@@ -385,7 +1410,7 @@ mod tests {
     fn test_dollar_stack_mixed() {
         let mut s = new_state();
         s.stack.push(Value::Int(1));
-        s.stack.push(Value::Output("data".into()));
+        s.stack.push(Value::output("data"));
         dollar_stack(&mut s).unwrap();
         assert_eq!(s.stack.len(), 3);
         assert_eq!(s.stack[2], Value::Str("[1:1]".into()));
@@ -396,7 +1421,7 @@ mod tests {
         let mut s = new_state();
         s.stack.push(Value::Int(1));
         s.stack.push(Value::Str("x".into()));
-        s.stack.push(Value::Output("data".into()));
+        s.stack.push(Value::output("data"));
         dollar_in(&mut s).unwrap();
         assert_eq!(s.stack.len(), 4);
         assert_eq!(s.stack[3], Value::Int(2));
@@ -405,7 +1430,7 @@ mod tests {
     #[test]
     fn test_dollar_out() {
         let mut s = new_state();
-        s.stack.push(Value::Output("data".into()));
+        s.stack.push(Value::output("data"));
         dollar_out(&mut s).unwrap();
         assert_eq!(s.stack.len(), 2);
         assert_eq!(s.stack[1], Value::Int(1));
@@ -459,6 +1484,45 @@ mod tests {
         assert_eq!(s.stack, vec![Value::Str("0".into())]);
     }
 
+    #[test]
+    fn test_dollar_cmdtime() {
+        let mut s = new_state();
+        dollar_cmdtime(&mut s).unwrap();
+        s.last_duration = Some(Duration::from_millis(2900));
+        dollar_cmdtime(&mut s).unwrap();
+        s.last_duration = Some(Duration::from_millis(4210));
+        dollar_cmdtime(&mut s).unwrap();
+        eval::eval_line(&mut s, "1 set-cmdtime-min").unwrap();
+        s.last_duration = Some(Duration::from_millis(1200));
+        dollar_cmdtime(&mut s).unwrap();
+        assert_eq!(
+            s.stack,
+            vec![
+                Value::Str("".into()),
+                Value::Str("".into()),
+                Value::Str("4.2s".into()),
+                Value::Str("1.2s".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_terminal_dimension_fallbacks() {
+        assert_eq!(terminal_dimension(Some(132), "YAFSH_TEST_NO_SUCH_VAR", 80), 132);
+        assert_eq!(terminal_dimension(None, "YAFSH_TEST_NO_SUCH_VAR", 80), 80);
+        std::env::set_var("YAFSH_TEST_COLUMNS", "100");
+        assert_eq!(terminal_dimension(None, "YAFSH_TEST_COLUMNS", 80), 100);
+        std::env::set_var("YAFSH_TEST_COLUMNS", "wide");
+        assert_eq!(terminal_dimension(None, "YAFSH_TEST_COLUMNS", 80), 80);
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(Duration::from_millis(30)), "0.0s");
+        assert_eq!(format_duration(Duration::from_secs(65)), "1m 5s");
+        assert_eq!(format_duration(Duration::from_secs(7380)), "2h 3m");
+    }
+
     #[test]
     fn test_dollar_gitbranch() {
         let mut s = new_state();
@@ -467,6 +1531,78 @@ mod tests {
         assert_eq!(s.stack.len(), 1);
     }
 
+    #[test]
+    fn test_prompt_helpers_cached_until_refresh() {
+        let mut s = new_state();
+        s.prompt_cache
+            .get("hostname", Duration::MAX, || "cached-host".to_string());
+        dollar_hostname(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::Str("cached-host".into())]);
+
+        prompt_refresh(&mut s).unwrap();
+        dollar_hostname(&mut s).unwrap();
+        assert_ne!(s.stack[1], Value::Str("cached-host".into()));
+    }
+
+    #[test]
+    fn test_git_status_parse() {
+        let porcelain = "\
+# branch.oid 1234abcd
+# branch.head main
+# branch.upstream origin/main
+# branch.ab +2 -3
+1 M. N... 100644 100644 100644 aaa bbb src/a.rs
+? notes.txt
+";
+        assert_eq!(
+            GitStatus::parse(porcelain),
+            GitStatus {
+                ahead: 2,
+                behind: 3,
+                staged: true,
+                unstaged: false,
+                untracked: true,
+            }
+        );
+    }
+
+    #[test]
+    fn test_git_status_parse_clean_and_unstaged() {
+        assert_eq!(GitStatus::parse("# branch.head main\n"), GitStatus::default());
+        let status = GitStatus::parse("1 .M N... 100644 100644 100644 aaa bbb f\n");
+        assert!(status.unstaged && !status.staged);
+    }
+
+    #[test]
+    fn test_complete_with() {
+        let mut s = new_state();
+        s.stack = vec![Value::Str("mygrep".into()), Value::Str("files".into())];
+        complete_with(&mut s).unwrap();
+        assert_eq!(s.completers.get("mygrep"), Some(&CompletionKind::Files));
+        assert_eq!(s.completers.get("cd"), Some(&CompletionKind::Dirs));
+
+        s.stack = vec![Value::Str("cd".into()), Value::Str("none".into())];
+        complete_with(&mut s).unwrap();
+        assert!(!s.completers.contains_key("cd"));
+
+        s.stack = vec![Value::Str("x".into()), Value::Str("colors".into())];
+        assert!(complete_with(&mut s).is_err());
+        assert_eq!(s.stack.len(), 2);
+    }
+
+    #[test]
+    #[cfg(feature = "native")]
+    fn test_dollar_vimode() {
+        let mut s = new_state();
+        dollar_vimode(&mut s).unwrap();
+        s.vi_mode = true;
+        dollar_vimode(&mut s).unwrap();
+        assert_eq!(
+            s.stack,
+            vec![Value::Str("".into()), Value::Str("[I]".into())]
+        );
+    }
+
     #[test]
     fn test_dollar_hostname() {
         let mut s = new_state();
@@ -478,6 +1614,74 @@ mod tests {
         }
     }
 
+    #[test]
+    #[cfg(feature = "native")]
+    fn test_dollar_date() {
+        let mut s = new_state();
+        s.stack.push(Value::Str("%Y-%m-%d".into()));
+        dollar_date(&mut s).unwrap();
+        match &s.stack[..] {
+            [Value::Str(v)] => {
+                assert_eq!(v.len(), 10, "unexpected date: {}", v);
+                assert_eq!(v.matches('-').count(), 2);
+            }
+            other => panic!("expected one Str, got {:?}", other),
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "native")]
+    fn test_dollar_date_literal_text_and_errors() {
+        let mut s = new_state();
+        s.stack.push(Value::Str("day %%".into()));
+        dollar_date(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::Str("day %".into())]);
+
+        let mut s = new_state();
+        s.stack.push(Value::Int(5));
+        assert!(dollar_date(&mut s).is_err());
+        assert_eq!(s.stack, vec![Value::Int(5)]);
+    }
+
+    #[test]
+    #[cfg(feature = "native")]
+    fn test_dollar_loadavg() {
+        let mut s = new_state();
+        dollar_loadavg(&mut s).unwrap();
+        match &s.stack[..] {
+            [Value::Str(v)] => assert!(v.parse::<f64>().is_ok(), "not a number: {}", v),
+            other => panic!("expected one Str, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_battery_percent() {
+        let root = std::env::temp_dir().join(format!("yafsh_battery_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("AC")).unwrap();
+        fs::write(root.join("AC/type"), "Mains\n").unwrap();
+        assert_eq!(battery_percent(&root), None);
+
+        fs::create_dir_all(root.join("BAT0")).unwrap();
+        fs::write(root.join("BAT0/type"), "Battery\n").unwrap();
+        fs::write(root.join("BAT0/capacity"), "87\n").unwrap();
+        assert_eq!(battery_percent(&root), Some(87));
+        assert_eq!(battery_percent(&root.join("missing")), None);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "native")]
+    fn test_native_hostname_matches_command() {
+        let native = native_hostname().expect("gethostname failed");
+        if let Ok(out) = Command::new("hostname").output() {
+            let cmd = String::from_utf8_lossy(&out.stdout).trim().to_string();
+            if out.status.success() && !cmd.is_empty() {
+                assert_eq!(native, cmd);
+            }
+        }
+    }
+
     #[test]
     fn test_dollar_time() {
         let mut s = new_state();
@@ -499,7 +1703,7 @@ mod tests {
         s.prompt_eval_original_stack = Some(vec![
             Value::Int(1),
             Value::Int(2),
-            Value::Output("x".into()),
+            Value::output("x"),
         ]);
         // Current stack is empty (cleared for prompt eval)
         dollar_stack(&mut s).unwrap();
@@ -517,7 +1721,7 @@ mod tests {
     #[test]
     fn test_dollar_out_uses_original_during_prompt_eval() {
         let mut s = new_state();
-        s.prompt_eval_original_stack = Some(vec![Value::Output("data".into())]);
+        s.prompt_eval_original_stack = Some(vec![Value::output("data")]);
         dollar_out(&mut s).unwrap();
         assert_eq!(s.stack, vec![Value::Int(1)]);
     }
@@ -1,61 +1,111 @@
 use std::fs::OpenOptions;
-use std::io::Write;
+use std::io::{BufRead, BufReader, IsTerminal, Write};
+use std::process::{Command, Stdio};
 
+use crate::builtins::{system, text};
+use crate::{out, outln};
+use crate::tokenizer;
 use crate::types::{State, Value};
 #[cfg(test)]
 use crate::builtins;
 
 
 /// `.` ( a -- ) Print and remove top item with newline.
+///
+/// Outputs taller than the terminal go through the pager (see `set-pager`).
 pub fn dot(state: &mut State) -> Result<(), String> {
     let val = state.stack.pop().ok_or(".: stack underflow")?;
-    println!("{}", val);
+    if let Value::Output(s, _) = &val {
+        if page_if_long(state, s) {
+            return Ok(());
+        }
+    }
+    outln!(state, "{}", val);
     Ok(())
 }
 
 /// `type` ( a -- ) Print and remove top item without newline.
 pub fn type_word(state: &mut State) -> Result<(), String> {
     let val = state.stack.pop().ok_or("type: stack underflow")?;
-    print!("{}", val);
+    out!(state, "{}", val);
     Ok(())
 }
 
-/// `.s` ( -- ) Display entire stack without modifying it.
+/// Longest preview of an Output's first line shown by `.s`, in characters.
+const PREVIEW_CHARS: usize = 40;
+
+/// `.s` ( -- ) Display entire stack without modifying it, one item per line
+/// with its index from the top. Outputs are summarized.
 pub fn dot_s(state: &mut State) -> Result<(), String> {
-    print!("<{}> ", state.stack.len());
-    for val in &state.stack {
-        match val {
-            Value::Str(s) => print!("\"{}\" ", s),
-            Value::Int(n) => print!("{} ", n),
-            Value::Output(s) => print!("«{}» ", s.trim_end()),
-        }
-    }
-    println!();
+    outln!(state, "{}", format_stack(&state.stack, false));
     Ok(())
 }
 
+/// `.s-full` ( -- ) Like `.s`, but with Outputs shown in full.
+pub fn dot_s_full(state: &mut State) -> Result<(), String> {
+    outln!(state, "{}", format_stack(&state.stack, true));
+    Ok(())
+}
+
+/// The `.s` listing: a `<depth>` header, then each item bottom to top.
+fn format_stack(stack: &[Value], full: bool) -> String {
+    let mut out = format!("<{}>", stack.len());
+    let width = stack.len().saturating_sub(1).to_string().len();
+    for (i, val) in stack.iter().enumerate() {
+        let shown = match val {
+            Value::Str(s) => format!("\"{}\"", s),
+            Value::Int(n) => n.to_string(),
+            Value::Output(s, _) if full => format!("«{}»", s.trim_end()),
+            Value::Output(s, _) => format!("«{}»", summarize(s)),
+            Value::Map(_) | Value::List(_) => val.to_string(),
+        };
+        out.push_str(&format!("\n{:>width$}  {}", stack.len() - 1 - i, shown));
+    }
+    out
+}
+
+/// "N lines, M bytes: first line …" for an Output.
+fn summarize(text: &str) -> String {
+    let count = text.lines().count();
+    let mut out = format!(
+        "{} line{}, {} byte{}",
+        count,
+        if count == 1 { "" } else { "s" },
+        text.len(),
+        if text.len() == 1 { "" } else { "s" }
+    );
+    let mut lines = text.lines();
+    if let Some(first) = lines.next() {
+        let preview: String = first.chars().take(PREVIEW_CHARS).collect();
+        let cut = preview.len() < first.len() || lines.next().is_some();
+        out.push_str(&format!(": {}{}", preview, if cut { " …" } else { "" }));
+    }
+    out
+}
+
 /// `>output` ( string -- output ) Convert Str to Output for piping.
 pub fn to_output(state: &mut State) -> Result<(), String> {
     let val = state.stack.pop().ok_or(">output: stack underflow")?;
     match val {
         Value::Str(s) => {
-            state.stack.push(Value::Output(s));
+            state.stack.push(Value::output(s));
             Ok(())
         }
-        Value::Output(_) => {
+        Value::Output(_, _) => {
             // Already an output, push back
             state.stack.push(val);
             Ok(())
         }
-        Value::Int(_) => Err(">output: requires string".into()),
+        Value::Int(_) | Value::Map(_) | Value::List(_) => Err(">output: requires string".into()),
     }
 }
 
-/// `>string` ( output/int -- string ) Convert Output or Int to Str.
+/// `>string` ( output/int/map/list -- string ) Convert Output, Int, Map, or
+/// List to Str.
 pub fn to_string_word(state: &mut State) -> Result<(), String> {
     let val = state.stack.pop().ok_or(">string: stack underflow")?;
     match val {
-        Value::Output(s) => {
+        Value::Output(s, _) => {
             state.stack.push(Value::Str(s));
             Ok(())
         }
@@ -63,6 +113,10 @@ pub fn to_string_word(state: &mut State) -> Result<(), String> {
             state.stack.push(Value::Str(n.to_string()));
             Ok(())
         }
+        Value::Map(_) | Value::List(_) => {
+            state.stack.push(Value::Str(val.to_string()));
+            Ok(())
+        }
         Value::Str(_) => {
             // Already a string, push back
             state.stack.push(val);
@@ -71,17 +125,367 @@ pub fn to_string_word(state: &mut State) -> Result<(), String> {
     }
 }
 
+/// `>int` ( str/output -- int ) Parse a number, ignoring surrounding
+/// whitespace (so command output with its trailing newline works).
+pub fn to_int(state: &mut State) -> Result<(), String> {
+    let val = state.stack.pop().ok_or(">int: stack underflow")?;
+    let parsed = match &val {
+        Value::Int(n) => Ok(*n),
+        Value::Str(s) | Value::Output(s, _) => tokenizer::parse_int(s.trim())
+            .ok_or_else(|| format!(">int: not a number: \"{}\"", s.trim())),
+        Value::Map(_) | Value::List(_) => Err(">int: requires string or output".into()),
+    };
+    match parsed {
+        Ok(n) => {
+            state.stack.push(Value::Int(n));
+            Ok(())
+        }
+        Err(e) => {
+            state.stack.push(val);
+            Err(e)
+        }
+    }
+}
+
+// ========== Paging ==========
+
+/// Pager used when $PAGER is unset or empty.
+const DEFAULT_PAGER: &str = "less -R";
+
+/// `page` ( output -- ) Show text through $PAGER (default `less -R`).
+pub fn page(state: &mut State) -> Result<(), String> {
+    system::deny_restricted(state, "page")?;
+    let text = text::pop_text(state, "page")?;
+    run_pager(&text).map_err(|e| format!("page: {}", e))
+}
+
+/// Show `text` through the pager if paging is on, the shell is interactive,
+/// and the text has more lines than the terminal. Returns whether it did.
+pub fn page_if_long(state: &State, text: &str) -> bool {
+    if !state.pager
+        || state.restricted.is_some()
+        || !state.out.is_stdout()
+        || !std::io::stdin().is_terminal() || !std::io::stdout().is_terminal() {
+        return false;
+    }
+    // Leave a row for the prompt that follows
+    let fits = crate::terminal::rows().is_none_or(|rows| text.lines().count() < rows);
+    !fits && run_pager(text).is_ok()
+}
+
+/// Pipe `text` into the pager and wait for it to quit.
+fn run_pager(text: &str) -> Result<(), String> {
+    let pager = std::env::var("PAGER")
+        .ok()
+        .filter(|p| !p.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_PAGER.to_string());
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(&pager)
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("{}: {}", pager, e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // The pager may quit before reading everything; that's not an error
+        let _ = stdin.write_all(text.as_bytes());
+    }
+    let status = child.wait().map_err(|e| format!("{}: {}", pager, e))?;
+    match status.code() {
+        Some(127) => Err(format!("{}: command not found", pager)),
+        _ => Ok(()),
+    }
+}
+
+// ========== Type inspection ==========
+
+/// `typeof` ( a -- a str ) Push the type name of the top item, keeping it.
+pub fn type_of(state: &mut State) -> Result<(), String> {
+    let name = state.stack.last().ok_or("typeof: stack underflow")?.type_name();
+    state.stack.push(Value::Str(name.to_string()));
+    Ok(())
+}
+
+/// Push 1 if the top item (kept) has type `name`, else 0.
+fn type_is(state: &mut State, op: &str, name: &str) -> Result<(), String> {
+    let val = state
+        .stack
+        .last()
+        .ok_or_else(|| format!("{}: stack underflow", op))?;
+    let flag = (val.type_name() == name) as i64;
+    state.stack.push(Value::Int(flag));
+    Ok(())
+}
+
+/// `int?` ( a -- a flag ) 1 if the top item is an Int, keeping it.
+pub fn is_int(state: &mut State) -> Result<(), String> {
+    type_is(state, "int?", "int")
+}
+
+/// `str?` ( a -- a flag ) 1 if the top item is a Str, keeping it.
+pub fn is_str(state: &mut State) -> Result<(), String> {
+    type_is(state, "str?", "str")
+}
+
+/// `output?` ( a -- a flag ) 1 if the top item is an Output, keeping it.
+pub fn is_output(state: &mut State) -> Result<(), String> {
+    type_is(state, "output?", "output")
+}
+
+/// `map?` ( a -- a flag ) 1 if the top item is a Map, else 0.
+pub fn is_map(state: &mut State) -> Result<(), String> {
+    type_is(state, "map?", "map")
+}
+
+/// `list?` ( a -- a flag ) 1 if the top item is a List, else 0.
+pub fn is_list(state: &mut State) -> Result<(), String> {
+    type_is(state, "list?", "list")
+}
+
+// ========== User input ==========
+
+/// Read one line from `input` without its line ending; end of input is an error.
+fn read_line_from(input: &mut impl BufRead, op: &str) -> Result<String, String> {
+    let mut line = String::new();
+    let n = input
+        .read_line(&mut line)
+        .map_err(|e| format!("{}: {}", op, e))?;
+    if n == 0 {
+        return Err(format!("{}: end of input", op));
+    }
+    let len = line.trim_end_matches(['\n', '\r']).len();
+    line.truncate(len);
+    Ok(line)
+}
+
+/// The controlling terminal, opened for the words that ask the user
+/// something, so in pipe mode they don't eat the script's own input.
+fn open_tty(op: &str) -> Result<std::fs::File, String> {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")
+        .map_err(|_| format!("{}: no terminal to ask on", op))
+}
+
+/// Pop a prompt and print it on the terminal followed by `suffix`,
+/// returning the terminal to read the answer from.
+///
+/// Without a terminal the prompt stays on the stack.
+fn prompt_tty(state: &mut State, op: &str, suffix: &str) -> Result<std::fs::File, String> {
+    let prompt = state
+        .stack
+        .pop()
+        .ok_or_else(|| format!("{}: stack underflow", op))?;
+    let mut tty = match open_tty(op) {
+        Ok(tty) => tty,
+        Err(e) => {
+            state.stack.push(prompt);
+            return Err(e);
+        }
+    };
+    write!(tty, "{}{}", prompt, suffix).map_err(|e| format!("{}: {}", op, e))?;
+    Ok(tty)
+}
+
+/// `read-line` ( -- str ) Read a line from standard input.
+///
+/// In pipe mode this is the next line of the script's input.
+pub fn read_line(state: &mut State) -> Result<(), String> {
+    let line = read_line_from(&mut std::io::stdin().lock(), "read-line")?;
+    state.stack.push(Value::Str(line));
+    Ok(())
+}
+
+/// `ask` ( prompt -- str ) Print a prompt on the terminal and read the answer.
+pub fn ask(state: &mut State) -> Result<(), String> {
+    let tty = prompt_tty(state, "ask", "")?;
+    let line = read_line_from(&mut BufReader::new(&tty), "ask")?;
+    state.stack.push(Value::Str(line));
+    Ok(())
+}
+
+/// `ask-secret` ( prompt -- str ) Like `ask`, but the answer isn't echoed.
+pub fn ask_secret(state: &mut State) -> Result<(), String> {
+    let mut tty = prompt_tty(state, "ask-secret", "")?;
+    let line = {
+        let echo = EchoOff::new(&tty);
+        let line = read_line_from(&mut BufReader::new(&tty), "ask-secret");
+        if echo.is_active() {
+            // The Enter key wasn't echoed either
+            let _ = writeln!(tty);
+        }
+        line?
+    };
+    state.stack.push(Value::Str(line));
+    Ok(())
+}
+
+/// `confirm` ( prompt -- flag ) Ask a yes/no question on the terminal,
+/// defaulting to no.
+///
+/// Pushes 1 only for "y" or "yes" (any case); anything else, including
+/// end of input, is 0. Without a terminal it is an error.
+pub fn confirm(state: &mut State) -> Result<(), String> {
+    let mut tty = prompt_tty(state, "confirm", " [y/N] ")?;
+    let answer = match read_line_from(&mut BufReader::new(&tty), "confirm") {
+        Ok(line) => line,
+        Err(_) => {
+            let _ = writeln!(tty);
+            String::new()
+        }
+    };
+    state.stack.push(Value::Int(is_yes(&answer) as i64));
+    Ok(())
+}
+
+/// Whether a reply to `confirm` means yes.
+fn is_yes(answer: &str) -> bool {
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+/// `choose` ( output -- str ) Pick one line of an Output from a numbered menu.
+///
+/// Typing a number picks that line; typing text narrows the menu to lines
+/// containing it (ignoring case), picking straight away if only one is
+/// left. An empty reply or end of input cancels.
+///
+/// The menu is shown and answered on the terminal, as with `confirm`.
+pub fn choose(state: &mut State) -> Result<(), String> {
+    let original = state.stack.last().cloned();
+    let text = text::pop_text(state, "choose")?;
+    let lines: Vec<&str> = text.lines().filter(|l| !l.trim().is_empty()).collect();
+    if lines.is_empty() {
+        return Err("choose: nothing to choose from".into());
+    }
+    let mut shown = lines.clone();
+    if shown.len() == 1 {
+        state.stack.push(Value::Str(shown[0].to_string()));
+        return Ok(());
+    }
+    let mut tty = match open_tty("choose") {
+        Ok(tty) => tty,
+        Err(e) => {
+            state.stack.extend(original);
+            return Err(e);
+        }
+    };
+
+    loop {
+        if shown.len() == 1 {
+            state.stack.push(Value::Str(shown[0].to_string()));
+            return Ok(());
+        }
+        let width = shown.len().to_string().len();
+        let mut menu = String::new();
+        for (i, line) in shown.iter().enumerate() {
+            menu.push_str(&format!("{:>width$}) {}\n", i + 1, line));
+        }
+        write!(tty, "{}#? ", menu).map_err(|e| format!("choose: {}", e))?;
+        let answer = read_line_from(&mut BufReader::new(&tty), "choose")
+            .map_err(|_| "choose: cancelled".to_string())?;
+        match pick(&shown, &lines, &answer) {
+            Pick::Line(line) => {
+                state.stack.push(Value::Str(line.to_string()));
+                return Ok(());
+            }
+            Pick::Narrow(matches) if matches.is_empty() => {
+                let _ = writeln!(tty, "choose: no line matches");
+            }
+            Pick::Narrow(matches) => shown = matches,
+            Pick::Cancel => return Err("choose: cancelled".into()),
+        }
+    }
+}
+
+/// What a reply at the `choose` menu asks for.
+#[derive(Debug, PartialEq)]
+enum Pick<'a> {
+    Line(&'a str),
+    Narrow(Vec<&'a str>),
+    Cancel,
+}
+
+/// Interpret `answer` given the lines `shown` (numbered from 1) and all lines.
+fn pick<'a>(shown: &[&'a str], all: &[&'a str], answer: &str) -> Pick<'a> {
+    let answer = answer.trim();
+    if answer.is_empty() {
+        return Pick::Cancel;
+    }
+    if let Ok(n) = answer.parse::<usize>() {
+        if (1..=shown.len()).contains(&n) {
+            return Pick::Line(shown[n - 1]);
+        }
+    }
+    let needle = answer.to_lowercase();
+    Pick::Narrow(
+        all.iter()
+            .filter(|l| l.to_lowercase().contains(&needle))
+            .copied()
+            .collect(),
+    )
+}
+
+/// Terminal echo turned off on `tty` until dropped (a no-op when it
+/// isn't a terminal).
+#[cfg(feature = "native")]
+struct EchoOff(std::os::fd::RawFd, Option<libc::termios>);
+
+#[cfg(feature = "native")]
+impl EchoOff {
+    fn new(tty: &std::fs::File) -> Self {
+        use std::os::fd::AsRawFd;
+        let fd = tty.as_raw_fd();
+        let mut attrs: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(fd, &mut attrs) } != 0 {
+            return EchoOff(fd, None);
+        }
+        let saved = attrs;
+        attrs.c_lflag &= !libc::ECHO;
+        unsafe { libc::tcsetattr(fd, libc::TCSANOW, &attrs) };
+        EchoOff(fd, Some(saved))
+    }
+
+    fn is_active(&self) -> bool {
+        self.1.is_some()
+    }
+}
+
+#[cfg(feature = "native")]
+impl Drop for EchoOff {
+    fn drop(&mut self) {
+        if let Some(saved) = &self.1 {
+            unsafe { libc::tcsetattr(self.0, libc::TCSANOW, saved) };
+        }
+    }
+}
+
+/// Without a terminal to control, there is no echo to turn off.
+#[cfg(not(feature = "native"))]
+struct EchoOff;
+
+#[cfg(not(feature = "native"))]
+impl EchoOff {
+    fn new(_tty: &std::fs::File) -> Self {
+        EchoOff
+    }
+
+    fn is_active(&self) -> bool {
+        false
+    }
+}
+
 // ========== File I/O ==========
 
 /// `>file` ( content filename -- ) Write output to file (create/truncate).
 pub fn write_file(state: &mut State) -> Result<(), String> {
+    system::deny_restricted(state, ">file")?;
     if state.stack.len() < 2 {
         return Err(">file: stack underflow".into());
     }
     let filename = state.stack.pop().unwrap();
     let content = state.stack.pop().unwrap();
     match (content, filename) {
-        (Value::Output(data), Value::Str(path)) => {
+        (Value::Output(data, _), Value::Str(path)) => {
             let mut file = OpenOptions::new()
                 .write(true)
                 .create(true)
@@ -101,13 +505,14 @@ pub fn write_file(state: &mut State) -> Result<(), String> {
 
 /// `>>file` ( content filename -- ) Append output to file (create if needed).
 pub fn append_file(state: &mut State) -> Result<(), String> {
+    system::deny_restricted(state, ">>file")?;
     if state.stack.len() < 2 {
         return Err(">>file: stack underflow".into());
     }
     let filename = state.stack.pop().unwrap();
     let content = state.stack.pop().unwrap();
     match (content, filename) {
-        (Value::Output(data), Value::Str(path)) => {
+        (Value::Output(data, _), Value::Str(path)) => {
             let mut file = OpenOptions::new()
                 .append(true)
                 .create(true)
@@ -124,9 +529,43 @@ pub fn append_file(state: &mut State) -> Result<(), String> {
     }
 }
 
+/// `tee` ( output filename -- output ) Write output to file and keep it on the stack.
+pub fn tee(state: &mut State) -> Result<(), String> {
+    system::deny_restricted(state, "tee")?;
+    if state.stack.len() < 2 {
+        return Err("tee: stack underflow".into());
+    }
+    let filename = state.stack.pop().unwrap();
+    let content = state.stack.pop().unwrap();
+    match (content, filename) {
+        (Value::Output(data, origin), Value::Str(path)) => {
+            let written = OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(&path)
+                .and_then(|mut file| file.write_all(data.as_bytes()));
+            // Passed through as it was, still knowing its command and status
+            state.stack.push(Value::Output(data, origin));
+            if let Err(e) = written {
+                state.stack.push(Value::Str(path.clone()));
+                return Err(format!("tee: {}: {}", path, e));
+            }
+            Ok(())
+        }
+        (c, f) => {
+            state.stack.push(c);
+            state.stack.push(f);
+            Err("tee: requires output and filename string".into())
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::sink::Sink;
+    use crate::types::Origin;
 
     fn state_with(vals: Vec<Value>) -> State {
         let mut s = State::new();
@@ -135,12 +574,123 @@ mod tests {
         s
     }
 
-    // dot and type_word print to stdout -- we test they pop correctly
+    #[test]
+    fn test_read_line_from() {
+        let mut input = std::io::Cursor::new("yes\r\nsecond\nlast");
+        assert_eq!(read_line_from(&mut input, "ask"), Ok("yes".into()));
+        assert_eq!(read_line_from(&mut input, "ask"), Ok("second".into()));
+        assert_eq!(read_line_from(&mut input, "ask"), Ok("last".into()));
+        assert_eq!(
+            read_line_from(&mut input, "ask"),
+            Err("ask: end of input".into())
+        );
+    }
+
+    #[test]
+    fn test_is_yes() {
+        assert!(is_yes("y"));
+        assert!(is_yes(" YES "));
+        assert!(!is_yes(""));
+        assert!(!is_yes("n"));
+        assert!(!is_yes("yep"));
+    }
+
+    #[test]
+    fn test_page_if_long_off() {
+        let mut s = state_with(vec![]);
+        s.pager = false;
+        assert!(!page_if_long(&s, &"line\n".repeat(1000)));
+    }
+
+    #[test]
+    fn test_pick() {
+        let all = ["main", "feature/login", "feature/logout"];
+        assert_eq!(pick(&all, &all, "2"), Pick::Line("feature/login"));
+        assert_eq!(
+            pick(&all, &all, "LOG"),
+            Pick::Narrow(vec!["feature/login", "feature/logout"])
+        );
+        // A number out of range is matched as text
+        assert_eq!(pick(&all, &all, "7"), Pick::Narrow(vec![]));
+        assert_eq!(pick(&all[1..], &all, "1"), Pick::Line("feature/login"));
+        assert_eq!(pick(&all, &all, "  "), Pick::Cancel);
+    }
+
+    #[test]
+    fn test_choose_empty() {
+        let mut s = state_with(vec![Value::output("\n\n")]);
+        assert_eq!(
+            choose(&mut s),
+            Err("choose: nothing to choose from".into())
+        );
+        let mut s = state_with(vec![Value::Int(1)]);
+        assert!(choose(&mut s).is_err());
+        assert_eq!(s.stack, vec![Value::Int(1)]);
+    }
+
+    #[test]
+    fn test_choose_single_line_needs_no_terminal() {
+        let mut s = state_with(vec![Value::output("main\n")]);
+        choose(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::Str("main".into())]);
+    }
+
+    #[test]
+    fn test_confirm_underflow() {
+        let mut s = state_with(vec![]);
+        assert_eq!(confirm(&mut s), Err("confirm: stack underflow".into()));
+    }
+
+    #[test]
+    fn test_ask_underflow() {
+        let mut s = state_with(vec![]);
+        assert_eq!(ask(&mut s), Err("ask: stack underflow".into()));
+    }
+
+    #[test]
+    fn test_to_int() {
+        let mut s = state_with(vec![Value::output("  42\n")]);
+        to_int(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::Int(42)]);
+
+        let mut s = state_with(vec![Value::Str("4x".into())]);
+        assert_eq!(to_int(&mut s), Err(">int: not a number: \"4x\"".into()));
+        assert_eq!(s.stack, vec![Value::Str("4x".into())]);
+    }
+
+    #[test]
+    fn test_typeof_keeps_value() {
+        let mut s = state_with(vec![Value::output("x\n")]);
+        type_of(&mut s).unwrap();
+        assert_eq!(
+            s.stack,
+            vec![Value::output("x\n"), Value::Str("output".into())]
+        );
+        assert!(type_of(&mut state_with(vec![])).is_err());
+    }
+
+    #[test]
+    fn test_type_predicates() {
+        let mut s = state_with(vec![Value::Int(3)]);
+        is_int(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::Int(3), Value::Int(1)]);
+
+        let mut s = state_with(vec![Value::Str("3".into())]);
+        is_output(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::Str("3".into()), Value::Int(0)]);
+        s.stack.pop();
+        is_str(&mut s).unwrap();
+        assert_eq!(s.stack[1], Value::Int(1));
+        assert!(is_int(&mut state_with(vec![])).is_err());
+    }
+
     #[test]
     fn test_dot_pops() {
         let mut s = state_with(vec![Value::Int(1), Value::Int(2)]);
+        s.out = Sink::capture();
         dot(&mut s).unwrap();
         assert_eq!(s.stack, vec![Value::Int(1)]);
+        assert_eq!(s.out.take_captured(), "2\n");
     }
 
     #[test]
@@ -152,8 +702,10 @@ mod tests {
     #[test]
     fn test_type_word_pops() {
         let mut s = state_with(vec![Value::Str("hi".into())]);
+        s.out = Sink::capture();
         type_word(&mut s).unwrap();
         assert!(s.stack.is_empty());
+        assert_eq!(s.out.take_captured(), "hi");
     }
 
     #[test]
@@ -162,25 +714,56 @@ mod tests {
         assert!(type_word(&mut s).is_err());
     }
 
+    #[test]
+    fn test_format_stack() {
+        let stack = vec![
+            Value::Str("a".into()),
+            Value::Int(42),
+            Value::output("Cargo.lock\nCargo.toml\nsrc\n"),
+        ];
+        assert_eq!(
+            format_stack(&stack, false),
+            "<3>\n2  \"a\"\n1  42\n0  «3 lines, 26 bytes: Cargo.lock …»"
+        );
+        assert_eq!(
+            format_stack(&stack[2..], true),
+            "<1>\n0  «Cargo.lock\nCargo.toml\nsrc»"
+        );
+        assert_eq!(format_stack(&[], false), "<0>");
+    }
+
+    #[test]
+    fn test_summarize() {
+        assert_eq!(summarize(""), "0 lines, 0 bytes");
+        assert_eq!(summarize("x\n"), "1 line, 2 bytes: x");
+        let long = "y".repeat(50);
+        assert_eq!(
+            summarize(&long),
+            format!("1 line, 50 bytes: {} …", "y".repeat(40))
+        );
+    }
+
     #[test]
     fn test_dot_s_preserves_stack() {
         let mut s = state_with(vec![Value::Int(1), Value::Str("x".into())]);
+        s.out = Sink::capture();
         dot_s(&mut s).unwrap();
         assert_eq!(s.stack.len(), 2); // unchanged
+        assert_eq!(s.out.take_captured(), "<2>\n1  1\n0  \"x\"\n");
     }
 
     #[test]
     fn test_to_output_from_str() {
         let mut s = state_with(vec![Value::Str("data".into())]);
         to_output(&mut s).unwrap();
-        assert_eq!(s.stack, vec![Value::Output("data".into())]);
+        assert_eq!(s.stack, vec![Value::output("data")]);
     }
 
     #[test]
     fn test_to_output_already_output() {
-        let mut s = state_with(vec![Value::Output("data".into())]);
+        let mut s = state_with(vec![Value::output("data")]);
         to_output(&mut s).unwrap();
-        assert_eq!(s.stack, vec![Value::Output("data".into())]);
+        assert_eq!(s.stack, vec![Value::output("data")]);
     }
 
     #[test]
@@ -197,7 +780,7 @@ mod tests {
 
     #[test]
     fn test_to_string_from_output() {
-        let mut s = state_with(vec![Value::Output("data".into())]);
+        let mut s = state_with(vec![Value::output("data")]);
         to_string_word(&mut s).unwrap();
         assert_eq!(s.stack, vec![Value::Str("data".into())]);
     }
@@ -231,7 +814,7 @@ mod tests {
         let path_str = path.to_string_lossy().to_string();
 
         let mut s = state_with(vec![
-            Value::Output("hello file\n".into()),
+            Value::output("hello file\n"),
             Value::Str(path_str.clone()),
         ]);
         write_file(&mut s).unwrap();
@@ -252,7 +835,7 @@ mod tests {
         std::fs::write(&path, "old content").unwrap();
 
         let mut s = state_with(vec![
-            Value::Output("new".into()),
+            Value::output("new"),
             Value::Str(path_str.clone()),
         ]);
         write_file(&mut s).unwrap();
@@ -271,7 +854,7 @@ mod tests {
         std::fs::write(&path, "first\n").unwrap();
 
         let mut s = state_with(vec![
-            Value::Output("second\n".into()),
+            Value::output("second\n"),
             Value::Str(path_str.clone()),
         ]);
         append_file(&mut s).unwrap();
@@ -303,4 +886,56 @@ mod tests {
         let mut s = state_with(vec![Value::Str("file.txt".into())]);
         assert!(append_file(&mut s).is_err());
     }
+
+    #[test]
+    fn test_tee_keeps_output() {
+        let dir = std::env::temp_dir();
+        let path = dir.join("yafsh_test_tee.txt");
+        let path_str = path.to_string_lossy().to_string();
+
+        let teed = Value::Output(
+            "teed\n".into(),
+            Some(Origin {
+                command: "sh".into(),
+                code: 3,
+            }),
+        );
+        let mut s = state_with(vec![teed.clone(), Value::Str(path_str.clone())]);
+        tee(&mut s).unwrap();
+        assert_eq!(s.stack, vec![teed]);
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(contents, "teed\n");
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_tee_underflow() {
+        let mut s = state_with(vec![Value::Str("file.txt".into())]);
+        assert!(tee(&mut s).is_err());
+    }
+
+    #[test]
+    fn test_tee_wrong_types() {
+        let mut s = state_with(vec![
+            Value::Str("not output".into()),
+            Value::Str("file.txt".into()),
+        ]);
+        assert!(tee(&mut s).is_err());
+        assert_eq!(s.stack.len(), 2);
+    }
+
+    #[test]
+    fn test_tee_unwritable_keeps_stack() {
+        let mut s = state_with(vec![
+            Value::output("x"),
+            Value::Str("/nonexistent/yafsh_tee".into()),
+        ]);
+        let err = tee(&mut s).unwrap_err();
+        assert!(err.starts_with("tee: /nonexistent/yafsh_tee: "));
+        assert_eq!(
+            s.stack,
+            vec![Value::output("x"), Value::Str("/nonexistent/yafsh_tee".into())]
+        );
+    }
 }
@@ -0,0 +1,201 @@
+use std::collections::HashMap;
+
+use crate::types::{sorted_entries, State, Value};
+
+/// Pop a string key, with the map expected below it.
+fn pop_key(state: &mut State, op: &str) -> Result<String, String> {
+    match state.stack.pop() {
+        Some(Value::Str(key)) => Ok(key),
+        Some(other) => {
+            state.stack.push(other);
+            Err(format!("{}: key must be a string", op))
+        }
+        None => Err(format!("{}: stack underflow", op)),
+    }
+}
+
+/// Pop a map, putting `key` back on error so the stack is as it was.
+fn pop_map(
+    state: &mut State,
+    op: &str,
+    key: String,
+) -> Result<(HashMap<String, Value>, String), String> {
+    match state.stack.pop() {
+        Some(Value::Map(map)) => Ok((map, key)),
+        Some(other) => {
+            state.stack.push(other);
+            state.stack.push(Value::Str(key));
+            Err(format!("{}: requires a map", op))
+        }
+        None => {
+            state.stack.push(Value::Str(key));
+            Err(format!("{}: stack underflow", op))
+        }
+    }
+}
+
+/// `map-new` ( -- map ) Push an empty map.
+pub fn map_new(state: &mut State) -> Result<(), String> {
+    state.stack.push(Value::Map(HashMap::new()));
+    Ok(())
+}
+
+/// `map-set` ( map value key -- map ) Set a key, replacing any old value.
+pub fn map_set(state: &mut State) -> Result<(), String> {
+    if state.stack.len() < 3 {
+        return Err("map-set: stack underflow".into());
+    }
+    let key = pop_key(state, "map-set")?;
+    let value = state.stack.pop().unwrap();
+    match state.stack.last_mut() {
+        Some(Value::Map(map)) => {
+            map.insert(key, value);
+            Ok(())
+        }
+        _ => {
+            state.stack.push(value);
+            state.stack.push(Value::Str(key));
+            Err("map-set: requires a map".into())
+        }
+    }
+}
+
+/// `map-get` ( map key -- value ) Push a key's value, consuming the map.
+pub fn map_get(state: &mut State) -> Result<(), String> {
+    let key = pop_key(state, "map-get")?;
+    let (mut map, key) = pop_map(state, "map-get", key)?;
+    match map.remove(&key) {
+        Some(value) => {
+            state.stack.push(value);
+            Ok(())
+        }
+        None => {
+            state.stack.push(Value::Map(map));
+            state.stack.push(Value::Str(key.clone()));
+            Err(format!("map-get: no key \"{}\"", key))
+        }
+    }
+}
+
+/// `map-has?` ( map key -- map flag ) 1 if the map has the key, else 0.
+pub fn map_has(state: &mut State) -> Result<(), String> {
+    let key = pop_key(state, "map-has?")?;
+    let (map, key) = pop_map(state, "map-has?", key)?;
+    let found = map.contains_key(&key);
+    state.stack.push(Value::Map(map));
+    state.stack.push(Value::Int(found as i64));
+    Ok(())
+}
+
+/// `map-del` ( map key -- map ) Remove a key; a missing key is not an error.
+pub fn map_del(state: &mut State) -> Result<(), String> {
+    let key = pop_key(state, "map-del")?;
+    let (mut map, key) = pop_map(state, "map-del", key)?;
+    map.remove(&key);
+    state.stack.push(Value::Map(map));
+    Ok(())
+}
+
+/// `map-keys` ( map -- output ) The keys in sorted order, one per line, so
+/// `each` can walk them.
+pub fn map_keys(state: &mut State) -> Result<(), String> {
+    let map = match state.stack.pop() {
+        Some(Value::Map(map)) => map,
+        Some(other) => {
+            state.stack.push(other);
+            return Err("map-keys: requires a map".into());
+        }
+        None => return Err("map-keys: stack underflow".into()),
+    };
+    let keys: String = sorted_entries(&map)
+        .into_iter()
+        .map(|(key, _)| format!("{}\n", key))
+        .collect();
+    state.stack.push(Value::output(keys));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builtins;
+    use crate::eval;
+
+    fn new_state() -> State {
+        let mut s = State::new();
+        builtins::register_builtins(&mut s);
+        s
+    }
+
+    #[test]
+    fn test_map_set_get() {
+        let mut s = new_state();
+        eval::eval_line(&mut s, "map-new 8080 \"port\" map-set \"web\" \"name\" map-set").unwrap();
+        eval::eval_line(&mut s, "dup \"port\" map-get swap \"name\" map-get").unwrap();
+        assert_eq!(s.stack, vec![Value::Int(8080), Value::Str("web".into())]);
+    }
+
+    #[test]
+    fn test_map_set_replaces() {
+        let mut s = new_state();
+        eval::eval_line(&mut s, "map-new 1 \"a\" map-set 2 \"a\" map-set \"a\" map-get").unwrap();
+        assert_eq!(s.stack, vec![Value::Int(2)]);
+    }
+
+    #[test]
+    fn test_map_get_missing_restores_stack() {
+        let mut s = new_state();
+        eval::eval_line(&mut s, "map-new").unwrap();
+        let result = eval::eval_line(&mut s, "\"nope\" map-get");
+        assert_eq!(result, Err("map-get: no key \"nope\"".into()));
+        assert_eq!(
+            s.stack,
+            vec![Value::Map(HashMap::new()), Value::Str("nope".into())]
+        );
+    }
+
+    #[test]
+    fn test_map_has_del_keys() {
+        let mut s = new_state();
+        eval::eval_line(&mut s, "map-new 1 \"b\" map-set 2 \"a\" map-set 3 \"c\" map-set").unwrap();
+        eval::eval_line(&mut s, "\"c\" map-del \"c\" map-has? swap \"a\" map-has? swap map-keys")
+            .unwrap();
+        assert_eq!(
+            s.stack,
+            vec![
+                Value::Int(0),
+                Value::Int(1),
+                Value::output("a\nb\n")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_map_type_errors() {
+        let mut s = new_state();
+        s.stack = vec![Value::Int(1), Value::Int(2), Value::Str("k".into())];
+        assert_eq!(map_set(&mut s), Err("map-set: requires a map".into()));
+        assert_eq!(
+            s.stack,
+            vec![Value::Int(1), Value::Int(2), Value::Str("k".into())]
+        );
+        s.stack = vec![Value::Map(HashMap::new()), Value::Int(1)];
+        assert_eq!(map_get(&mut s), Err("map-get: key must be a string".into()));
+        assert_eq!(map_keys(&mut s), Err("map-keys: requires a map".into()));
+    }
+
+    #[test]
+    fn test_map_display() {
+        let mut s = new_state();
+        eval::eval_line(
+            &mut s,
+            "map-new 1 \"n\" map-set \"x y\" \"s\" map-set map-new \"v\" \"k\" map-set \"inner\" map-set",
+        )
+        .unwrap();
+        assert_eq!(
+            s.stack[0].to_string(),
+            "{\"inner\": {\"k\": \"v\"}, \"n\": 1, \"s\": \"x y\"}"
+        );
+        assert_eq!(s.stack[0].type_name(), "map");
+    }
+}
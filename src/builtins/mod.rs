@@ -1,15 +1,33 @@
+pub mod clipboard;
 pub mod computation;
+pub mod csv;
+pub mod files;
+pub mod hash;
+#[cfg(feature = "native")]
+pub mod http;
 pub mod introspection;
 pub mod io;
+pub mod map;
+pub mod parallel;
+#[cfg(feature = "native")]
+pub mod process;
+pub mod segments;
 pub mod stack;
+pub mod structured;
+pub mod style;
 pub mod system;
+pub mod testing;
+pub mod text;
+pub mod vocab;
+pub mod watch;
 
-use crate::types::{State, Word};
+use crate::types::{CompletionKind, State, Word};
 
 /// Register all builtin words into the state dictionary.
 pub fn register_builtins(state: &mut State) {
     let reg = |state: &mut State, name: &str, f: fn(&mut State) -> Result<(), String>, doc: &'static str| {
-        state.dict.insert(name.to_string(), Word::Builtin(f, Some(doc)));
+        state.dict_mut().insert(name.to_string(), Word::Builtin(f, Some(doc)));
+        state.builtins.insert(name.to_string(), Word::Builtin(f, Some(doc)));
     };
 
     // Stack manipulation
@@ -19,22 +37,78 @@ pub fn register_builtins(state: &mut State) {
     reg(state, "clear", stack::clear, "( ... -- ) Clear entire stack");
     reg(state, "over", stack::over, "( a b -- a b a ) Copy second item to top");
     reg(state, "rot", stack::rot, "( a b c -- b c a ) Rotate top three items");
+    reg(state, "undo", stack::undo, "( -- ) Restore the stack from before the last line that changed it");
+    reg(state, "redo", stack::redo, "( -- ) Bring back the stack the last undo replaced");
+    reg(state, "stash", stack::stash, "( ... name -- ) Save the whole stack under name and clear it");
+    reg(state, "unstash", stack::unstash, "( name -- ... ) Push a stashed stack back on top and drop the stash");
+    reg(state, "stashes", stack::stashes, "( -- ) List stashes with their depths");
+    reg(state, "stack-new", stack::stack_new, "( name -- ) Create an empty named stack");
+    reg(state, "stack-switch", stack::stack_switch, "( name -- ) Make the named stack the active one (\"main\" is the first)");
+    reg(state, "stack-drop", stack::stack_drop, "( name -- ) Delete a stack that isn't active");
+    reg(state, "stacks", stack::stacks, "( -- ) List stacks with their depths, marking the active one");
 
     // I/O
     reg(state, ".", io::dot, "( a -- ) Print and remove top item with newline");
     reg(state, "type", io::type_word, "( a -- ) Print and remove top item without newline");
-    reg(state, ".s", io::dot_s, "( -- ) Display entire stack without modifying it");
+    reg(state, ".s", io::dot_s, "( -- ) Display entire stack, indexed from the top, with Outputs summarized");
+    reg(state, ".s-full", io::dot_s_full, "( -- ) Like .s, but show Outputs in full");
+    reg(state, "page", io::page, "( output -- ) Show text through $PAGER (default less -R)");
+    reg(state, "set-pager", system::set_pager, "( flag -- ) Page Outputs taller than the terminal: \"on\"/\"off\" or 1/0");
     reg(state, ">output", io::to_output, "( string -- output ) Convert Str to Output for piping");
-    reg(state, ">string", io::to_string_word, "( output/int -- string ) Convert Output or Int to Str");
+    reg(state, ">string", io::to_string_word, "( output/int/map/list -- string ) Convert Output, Int, Map, or List to Str");
+    reg(state, ">int", io::to_int, "( str/output -- int ) Parse a number (surrounding whitespace is ignored)");
+    reg(state, "typeof", io::type_of, "( a -- a str ) Type of the top item: \"str\", \"int\", \"output\", \"map\", or \"list\"");
+    reg(state, "int?", io::is_int, "( a -- a flag ) 1 if the top item is an Int (it stays)");
+    reg(state, "str?", io::is_str, "( a -- a flag ) 1 if the top item is a Str (it stays)");
+    reg(state, "output?", io::is_output, "( a -- a flag ) 1 if the top item is an Output (it stays)");
+    reg(state, "map?", io::is_map, "( a -- a flag ) 1 if the top item is a Map (it stays)");
+    reg(state, "list?", io::is_list, "( a -- a flag ) 1 if the top item is a List (it stays)");
+
+    // User input
+    reg(state, "read-line", io::read_line, "( -- str ) Read a line from standard input");
+    reg(state, "ask", io::ask, "( prompt -- str ) Print prompt and read a line");
+    reg(state, "ask-secret", io::ask_secret, "( prompt -- str ) Print prompt and read a line without echo");
+    reg(state, "choose", io::choose, "( output -- str ) Pick a line from a numbered menu (type text to narrow it)");
+    reg(state, "confirm", io::confirm, "( prompt -- flag ) Ask a [y/N] question: 1 for y/yes, else 0");
 
     // File I/O
     reg(state, ">file", io::write_file, "( content filename -- ) Write output to file");
     reg(state, ">>file", io::append_file, "( content filename -- ) Append output to file");
+    reg(state, "tee", io::tee, "( output filename -- output ) Write output to file and keep it on stack");
 
     // System
     reg(state, "exec", system::exec_word, "( args... cmd -- output ) Execute shell command");
+    reg(state, "sh-c", system::sh_c, "( str -- output ) Run a one-liner through /bin/sh -c (pipes, redirects, && chains)");
+    #[cfg(feature = "native")]
+    reg(state, "spawn-detached", system::spawn_detached, "( args... cmd -- pid ) Start a command detached from yafsh (it outlives the shell)");
+    reg(state, "<stdin", system::stdin_from, "( filename -- ) Stream a file as stdin to the next command on this line");
+    reg(state, "exit", system::exit, "( [code] -- ) Leave the shell with status code (default 0)");
+    reg(state, "bye", system::exit, "( [code] -- ) Leave the shell with status code (default 0)");
+    reg(state, "quit", system::exit, "( [code] -- ) Leave the shell with status code (default 0)");
+    reg(state, "set-restricted", system::set_restricted, "( flag -- ) Restricted mode: no commands, file writes, HTTP, setenv, or cd out (can't be undone)");
+    reg(state, "dry-run", system::dry_run, "( flag -- ) Print external commands instead of running them: \"on\"/\"off\"");
+    reg(state, "merge-stderr", system::merge_stderr, "( flag -- ) Capture stderr with stdout into each command's output, like 2>&1: \"on\"/\"off\"");
     reg(state, "?", system::exit_code, "( -- code ) Push exit code of last command");
+    reg(state, "out-status", system::out_status, "( output -- output code ) Exit code of the command that made this output");
+    reg(state, "out-command", system::out_command, "( output -- output name ) Name of the command that made this output");
     reg(state, "cd", system::cd, "( path -- ) Change directory");
+    reg(state, "par-exec", parallel::par_exec, "( cmd1 ... cmdN n -- out1 ... outN ) Run n commands concurrently");
+    reg(state, "peach", parallel::peach, "( output cmd -- outputs... ) Run cmd per line concurrently");
+    reg(state, "sleep", system::sleep, "( seconds -- ) Pause for n seconds");
+    reg(state, "sleep-ms", system::sleep_ms, "( ms -- ) Pause for n milliseconds");
+    reg(state, "timed", system::timed, "( ... body -- ... ) Run body, then print its real, user, and system time");
+    reg(state, "set-report-time", system::set_report_time, "( seconds -- ) Print times of commands running at least n seconds (\"off\" to stop)");
+    reg(state, "watch", watch::watch, "( seconds body -- ) Re-run body every n seconds until Ctrl-C");
+    reg(state, "watch-path", watch::watch_path, "( path body -- ) Re-run body when path changes until Ctrl-C");
+
+    // Processes
+    #[cfg(feature = "native")]
+    {
+        reg(state, "kill", process::kill, "( pid sig -- ) Send a signal (number or name like \"TERM\") to a process");
+        reg(state, "pkill", process::pkill, "( name -- ) Send SIGTERM to processes with this exact name (? is 1 if none)");
+        reg(state, "procs", process::procs, "( -- output ) Processes, one per line: pid, name, cpu %, memory bytes (tab-separated)");
+        reg(state, "pid", process::pid, "( -- pid ) This shell's process ID");
+    }
 
     // Environment
     reg(state, "getenv", system::getenv, "( key -- value ) Get environment variable");
@@ -44,9 +118,80 @@ pub fn register_builtins(state: &mut State) {
     reg(state, "env-prepend", system::env_prepend, "( value key -- ) Prepend to colon-separated env var");
     reg(state, "env", system::env_all, "( -- vars... ) Push all environment variables");
 
+    // Filesystem
+    reg(state, "exists?", files::exists, "( path -- flag ) 1 if path exists, else 0");
+    reg(state, "dir?", files::is_dir, "( path -- flag ) 1 if path is a directory, else 0");
+    reg(state, "file?", files::is_file, "( path -- flag ) 1 if path is a regular file, else 0");
+    reg(state, "file-size", files::file_size, "( path -- n ) File size in bytes");
+    reg(state, "file-mtime", files::file_mtime, "( path -- n ) Modification time (Unix seconds)");
+    reg(state, "readlink", files::readlink, "( path -- target ) Target of a symbolic link");
+    reg(state, "mkdir-p", files::mkdir_p, "( path -- ) Create directory and missing parents");
+    reg(state, "rm", files::rm, "( path -- ) Remove a file");
+    reg(state, "rm-rf", files::rm_rf, "( path -- ) Remove file or directory tree (missing is ok)");
+    reg(state, "cp", files::cp, "( src dst -- ) Copy a file (into dst if it is a directory)");
+    reg(state, "mv", files::mv, "( src dst -- ) Move or rename (into dst if it is a directory)");
+    reg(state, "touch", files::touch, "( path -- ) Create file or update its mtime");
+    reg(state, "find-files", files::find_files, "( dir pattern -- output ) Recursively find names matching glob");
+    reg(state, "glob", files::glob, "( pattern -- output ) Glob matches as one value, one path per line");
+    reg(state, "glob-count", files::glob_count, "( pattern -- n ) Number of paths matching a glob");
+    reg(state, "psub", files::psub, "( output -- path ) Write output to a temp file (removed after the line) and push its path");
+
+    // Hashing
+    reg(state, "sha256", hash::sha256, "( str/output -- str ) Hex SHA-256 digest");
+    reg(state, "sha1", hash::sha1, "( str/output -- str ) Hex SHA-1 digest");
+    reg(state, "md5", hash::md5, "( str/output -- str ) Hex MD5 digest");
+    reg(state, "sha256-file", hash::sha256_file, "( path -- str ) Hex SHA-256 digest of file contents");
+    reg(state, "sha1-file", hash::sha1_file, "( path -- str ) Hex SHA-1 digest of file contents");
+    reg(state, "md5-file", hash::md5_file, "( path -- str ) Hex MD5 digest of file contents");
+
+    // HTTP
+    #[cfg(feature = "native")]
+    {
+        reg(state, "http-get", http::http_get, "( url -- output ) Fetch URL and push response body");
+        reg(state, "http-post", http::http_post, "( body url -- output ) POST body to URL and push response body");
+        reg(state, "http-status", http::http_status, "( -- code ) Push status code of last HTTP response");
+    }
+
+    // Maps
+    reg(state, "map-new", map::map_new, "( -- map ) Push an empty map");
+    reg(state, "map-set", map::map_set, "( map value key -- map ) Set a key's value");
+    reg(state, "map-get", map::map_get, "( map key -- value ) Value of a key (error if missing)");
+    reg(state, "map-has?", map::map_has, "( map key -- map flag ) 1 if the map has the key");
+    reg(state, "map-del", map::map_del, "( map key -- map ) Remove a key");
+    reg(state, "map-keys", map::map_keys, "( map -- output ) Keys in sorted order, one per line");
+
+    // CSV/TSV
+    reg(state, "csv-parse", csv::csv_parse, "( output -- rows ) Parse CSV (quoted fields allowed) into a list of rows");
+    reg(state, "tsv-parse", csv::tsv_parse, "( output -- rows ) Parse tab-separated text into a list of rows");
+    reg(state, "csv-get", csv::csv_get, "( row n -- str ) Field n (from 0) of a row, or row n of the rows");
+    reg(state, "csv-emit", csv::csv_emit, "( rows -- output ) Format a list of rows as CSV");
+
+    // TOML/YAML
+    reg(state, "toml-parse", structured::toml_parse, "( output -- map ) Parse a TOML document into a map");
+    reg(state, "yaml-parse", structured::yaml_parse, "( output -- value ) Parse a YAML document into maps and lists");
+    reg(state, "get-path", structured::get_path, "( value path -- value ) Look up a dotted path like \"package.version\" (numbers index lists)");
+
+    // Clipboard
+    reg(state, ">clip", clipboard::to_clip, "( a -- ) Copy to the system clipboard (pbcopy/wl-copy/xclip/xsel, else OSC 52)");
+    reg(state, "clip>", clipboard::from_clip, "( -- str ) Paste text from the system clipboard");
+
     // Directory navigation
-    reg(state, "pushd", system::pushd, "( path -- ) Push current dir and change to path");
+    reg(state, "pushd", system::pushd, "( path -- ) Push current dir and change to path (no path: swap)");
     reg(state, "popd", system::popd, "( -- ) Pop and change to directory from stack");
+    reg(state, "dirs", system::dirs, "( -- ) Show current directory and directory stack");
+    reg(state, "set-suggest", system::set_suggest, "( flag -- ) \"Did you mean\" hints for unknown words: \"on\"/\"off\" or 1/0");
+    reg(state, "set-max-iterations", system::set_max_iterations, "( n -- ) Fail a loop after n iterations (0: no limit)");
+    reg(state, "set-exit-status", system::set_exit_status, "( flag -- ) Pipe mode exits with the last line's status: \"on\"/\"off\"");
+    reg(state, "set-errexit", system::set_errexit, "( flag -- ) Pipe mode and RC files stop at the first failing line: \"on\"/\"off\"");
+    reg(state, "set-glob-dotfiles", system::set_glob_dotfiles, "( flag -- ) Globs match names starting with '.': \"on\"/\"off\"");
+    reg(state, "set-coerce", system::set_coerce, "( flag -- ) Arithmetic and comparisons read numbers from Str/Output: \"on\"/\"off\"");
+    reg(state, "set-autocd", system::set_autocd, "( flag -- ) Bare directory names cd into them: \"on\"/\"off\" or 1/0");
+    reg(state, "set-autosuggest", system::set_autosuggest, "( flag -- ) Suggest the rest of a matching history entry: \"on\"/\"off\"");
+    reg(state, "set-stack-preview", system::set_stack_preview, "( flag -- ) Preview the resulting stack below the input: \"on\"/\"off\"");
+    reg(state, "set-local-rc", system::set_local_rc, "( flag -- ) Load allowed .yafshrc.local files per directory: \"on\"/\"off\"");
+    reg(state, "local-allow", system::local_allow, "( -- ) Trust this directory's .yafshrc.local (until it changes)");
+    reg(state, "local-deny", system::local_deny, "( -- ) Stop trusting this directory's .yafshrc.local and unload it");
+    reg(state, "set-keymode", system::set_keymode, "( mode -- ) Line editing keybindings: \"vi\" or \"emacs\"");
 
     // Arithmetic
     reg(state, "+", computation::add, "( a b -- a+b ) Add two numbers");
@@ -56,6 +201,25 @@ pub fn register_builtins(state: &mut State) {
     reg(state, "mod", computation::mod_op, "( a b -- a%b ) Modulo (remainder of a/b)");
     reg(state, "/mod", computation::divmod, "( a b -- quot rem ) Quotient and remainder");
     reg(state, "*/", computation::muldiv, "( a b c -- (a*b)/c ) Multiply then divide");
+    reg(state, "wrap+", computation::wrap_add, "( a b -- a+b ) Add with wrapping on overflow");
+    reg(state, "wrap*", computation::wrap_mul, "( a b -- a*b ) Multiply with wrapping on overflow");
+    reg(state, "min", computation::min, "( a b -- min ) Smaller of two integers");
+    reg(state, "max", computation::max, "( a b -- max ) Larger of two integers");
+    reg(state, "abs", computation::abs, "( n -- |n| ) Absolute value");
+    reg(state, "negate", computation::negate, "( n -- -n ) Negate integer");
+    reg(state, "clamp", computation::clamp, "( n lo hi -- n' ) Limit n to lo..hi");
+
+    // Number bases
+    reg(state, "hex", computation::hex, "( n -- str ) Format integer in hexadecimal");
+    reg(state, "oct", computation::oct, "( n -- str ) Format integer in octal");
+    reg(state, "bin", computation::bin, "( n -- str ) Format integer in binary");
+    reg(state, ">base", computation::to_base, "( n base -- str ) Format integer in base 2-36");
+    reg(state, "base>", computation::from_base, "( str base -- n ) Parse string as integer in base 2-36");
+
+    // Random numbers
+    reg(state, "random", computation::random, "( max -- n ) Random integer from 0 to max-1");
+    reg(state, "random-range", computation::random_range, "( lo hi -- n ) Random integer from lo to hi inclusive");
+    reg(state, "uuid", computation::uuid, "( -- str ) Random version 4 UUID");
 
     // Comparisons
     reg(state, "=", computation::eq, "( a b -- flag ) Test equality (1 if equal, 0 if not)");
@@ -73,31 +237,134 @@ pub fn register_builtins(state: &mut State) {
 
     // String operations
     reg(state, "concat", computation::concat, "( a b -- a+b ) Concatenate two strings");
+    reg(state, "length", computation::length, "( str -- n ) Length in grapheme clusters (user-visible characters)");
+    reg(state, "length-chars", computation::length_chars, "( str -- n ) Length in Unicode code points");
+    reg(state, "length-bytes", computation::length_bytes, "( str -- n ) Length in UTF-8 bytes");
+    reg(state, "substr", computation::substr, "( str start len -- str ) Slice by grapheme clusters (start from 0)");
+    reg(state, "substr-chars", computation::substr_chars, "( str start len -- str ) Slice by code points");
+    reg(state, "substr-bytes", computation::substr_bytes, "( str start len -- str ) Slice by bytes (error if it splits a character)");
 
     // Conditional string helpers
     reg(state, "?prefix", computation::cond_prefix, "( str sep -- result ) Prepend separator if string non-empty");
     reg(state, "?suffix", computation::cond_suffix, "( str sep -- result ) Append separator if string non-empty");
     reg(state, "?wrap", computation::cond_wrap, "( str prefix suffix -- result ) Wrap string if non-empty");
+    reg(state, "fmt", text::fmt, "( args... template -- str ) Fill {} / {N} placeholders, e.g. {:>8} {:<8} {:05} {:.3}");
+
+    // Output line processing
+    reg(state, "head", text::head, "( output n -- output ) Keep the first n lines");
+    reg(state, "tail", text::tail, "( output n -- output ) Keep the last n lines");
+    reg(state, "line-count", text::line_count, "( output -- n ) Count lines in output");
+    reg(state, "nth-line", text::nth_line, "( output n -- str ) Get line n (1-based) of output");
+    reg(state, "chomp", text::chomp, "( output/str -- str ) Drop one trailing newline");
+    reg(state, "trim", text::trim, "( output/str -- str ) Drop leading and trailing whitespace");
+    reg(state, "out>int", text::out_to_int, "( output -- int ) Parse command output as a number");
+    reg(state, "sort-lines", text::sort_lines, "( output -- output ) Sort lines bytewise");
+    reg(state, "sort-lines-num", text::sort_lines_num, "( output -- output ) Sort lines by leading number");
+    reg(state, "uniq-lines", text::uniq_lines, "( output -- output ) Collapse adjacent duplicate lines");
+    reg(state, "count-uniq", text::count_uniq, "( output -- output ) Collapse adjacent duplicates with counts");
+    reg(state, "field", text::field, "( line n -- str ) Pick whitespace-separated field n (1-based)");
+    reg(state, "fields", text::fields, "( output n -- output ) Pick whitespace-separated field n of each line");
+    reg(state, "field-by", text::field_by, "( line n delim -- str ) Pick field n split on delimiter");
+    reg(state, "fields-by", text::fields_by, "( output n delim -- output ) Pick field n of each line split on delimiter");
+    reg(state, "table", text::table, "( output -- output ) Align whitespace-separated fields into columns");
+    reg(state, "table-by", text::table_by, "( output delim -- output ) Align delimiter-separated fields into columns");
+    reg(state, "table-header", text::table_header, "( output -- output ) Highlight the first line in bold");
+    reg(state, "diff", text::diff, "( output1 output2 -- output ) Unified diff between two outputs");
 
     // Loop indices
     reg(state, "i", computation::loop_i, "( -- index ) Push current loop index");
     reg(state, "j", computation::loop_j, "( -- index ) Push outer loop index (nested loops)");
+    reg(state, "leave", computation::leave, "( -- ) Stop the innermost loop, continuing after it");
 
     // Introspection
     reg(state, "words", introspection::words, "List all available words");
     reg(state, "help", introspection::help, "Show comprehensive help information");
+    reg(state, "complete-with", introspection::complete_with, "( word kind -- ) Tab-complete word's arguments: dirs, files, env, words, commands, none");
     reg(state, "see", introspection::see, "( name -- ) Show word definition or documentation");
+    reg(state, "alias", introspection::alias, "( expansion name -- ) Make name expand to the expansion (\"git\" \"g\" alias)");
+    reg(state, "which", introspection::which, "( name -- str ) How a token resolves: builtin, word, alias, command <path>, glob, string ...");
+    reg(state, "edit", introspection::edit, "( name -- ) Edit a word's definition in $EDITOR and load the result");
+    reg(state, "defer", introspection::defer, "( name -- ) Declare a word to be implemented later with is");
+    reg(state, "is", introspection::is, "( impl name -- ) Make a deferred word run impl");
+    reg(state, "redefine-ok", introspection::redefine_ok, "( -- ) Let the next definition replace a builtin (still reachable as builtin:name)");
+    reg(state, "unalias", introspection::unalias, "( name -- ) Remove an alias");
+    reg(state, "aliases", introspection::aliases, "( -- ) List aliases and their expansions");
+    reg(state, "vocabulary", vocab::vocabulary, "( name -- ) Create a vocabulary; its words are used as name:word");
+    reg(state, "in", vocab::in_vocab, "( name -- ) Put the next definition into a vocabulary");
+    reg(state, "also", vocab::also, "( name -- ) Search a vocabulary first, so its words work unqualified");
+    reg(state, "previous", vocab::previous, "( -- ) Remove the first vocabulary from the search order");
+    reg(state, "order", vocab::order, "( -- ) Show the vocabulary search order");
+    reg(state, "history", introspection::history, "( -- ) Show numbered history (re-run with !n or !!)");
+    reg(state, "history-clear", introspection::history_clear, "( -- ) Forget this session's history entries (the file is kept)");
+    reg(state, "history-stats", introspection::history_stats, "( -- ) Most-used words and slowest entries from recorded history");
+    reg(state, "history-size", system::history_size, "( n -- ) Keep at most n history entries (set in ~/.yafshrc)");
+    reg(state, "set-history-dedupe", system::set_history_dedupe, "( flag -- ) Skip lines repeating the previous entry: \"on\"/\"off\"");
+    reg(state, "set-history-ignore-space", system::set_history_ignore_space, "( flag -- ) Skip lines typed with a leading space: \"on\"/\"off\"");
+    reg(state, "history-ignore", system::history_ignore, "( pattern -- ) Never record lines matching a glob pattern");
+    reg(state, "assert", testing::assert, "( flag msg -- ) Fail with msg unless flag is non-zero");
+    reg(state, "assert=", testing::assert_eq, "( actual expected -- ) Fail unless the values are equal");
+    reg(state, "run-tests", testing::run_tests, "( -- ) Run all test-* words and report; ? is 1 if any failed");
     reg(state, "trace", introspection::trace_mode, "( level -- ) Set trace verbosity: \"on\"/\"off\" or 0-3");
+    reg(state, "trace-file", introspection::trace_file, "( path -- ) Trace steps to a file as JSON lines (\"\" to stop)");
 
     // Prompt helpers
     reg(state, "$stack", introspection::dollar_stack, "( -- str ) Formatted [n:m] stack indicator");
+    reg(state, "$stackname", introspection::dollar_stackname, "( -- str ) Name of the active stack, or \"\" on main");
     reg(state, "$in", introspection::dollar_in, "( -- int ) Count of input items on stack");
     reg(state, "$out", introspection::dollar_out, "( -- int ) Count of output items on stack");
     reg(state, "$gitbranch", introspection::dollar_gitbranch, "( -- str ) Current git branch name");
+    reg(state, "$gitdirty", introspection::dollar_gitdirty, "( -- str ) \"*\" if worktree has changes, else \"\"");
+    reg(state, "$gitstaged", introspection::dollar_gitstaged, "( -- str ) \"+\" if changes are staged, else \"\"");
+    reg(state, "$gitahead", introspection::dollar_gitahead, "( -- str ) Commits ahead of upstream (\"\" if none)");
+    reg(state, "$gitbehind", introspection::dollar_gitbehind, "( -- str ) Commits behind upstream (\"\" if none)");
     reg(state, "$cwd", introspection::dollar_cwd, "( -- str ) Current working directory");
     reg(state, "$basename", introspection::dollar_basename, "( -- str ) Basename of current directory");
     reg(state, "$hostname", introspection::dollar_hostname, "( -- str ) System hostname");
     reg(state, "$username", introspection::dollar_username, "( -- str ) Current username");
+    reg(state, "$vimode", introspection::dollar_vimode, "( -- str ) Vi mode indicator [I]/[N], or \"\" in emacs mode");
     reg(state, "$exitcode", introspection::dollar_exitcode, "( -- str ) Last exit code as string");
+    reg(state, "$pid", introspection::dollar_pid, "( -- str ) This shell's process ID as string");
+    reg(state, "$cmdtime", introspection::dollar_cmdtime, "( -- str ) How long the last line took, e.g. \"4.2s\" (\"\" if under set-cmdtime-min)");
+    reg(state, "set-cmdtime-min", introspection::set_cmdtime_min, "( seconds -- ) Shortest line duration $cmdtime shows (default 3)");
+    reg(state, "$columns", introspection::dollar_columns, "( -- n ) Terminal width in columns (follows resizes)");
+    reg(state, "$rows", introspection::dollar_rows, "( -- n ) Terminal height in rows (follows resizes)");
+    reg(state, "$date", introspection::dollar_date, "( fmt -- str ) Current time formatted with strftime codes");
+    reg(state, "$loadavg", introspection::dollar_loadavg, "( -- str ) 1-minute load average");
+    reg(state, "$battery", introspection::dollar_battery, "( -- str ) Battery charge percent, or \"\" without a battery");
     reg(state, "$time", introspection::dollar_time, "( -- str ) Current time as HH:MM");
+    reg(state, "prompt-refresh", introspection::prompt_refresh, "( -- ) Discard cached prompt helper results");
+
+    // Colors and styles
+    reg(state, "fg", style::fg, "( color -- str ) Foreground color escape: name, bright-name, 0-255, or #rrggbb");
+    reg(state, "bg", style::bg, "( color -- str ) Background color escape: name, bright-name, 0-255, or #rrggbb");
+    reg(state, "bold", style::bold, "( -- str ) Bold text escape");
+    reg(state, "dim", style::dim, "( -- str ) Dim text escape");
+    reg(state, "underline", style::underline, "( -- str ) Underlined text escape");
+    reg(state, "reset-style", style::reset_style, "( -- str ) Reset colors and attributes escape");
+    reg(state, "color-wrap", style::color_wrap, "( str color -- str ) Color a string, resetting afterwards");
+    reg(state, "theme", style::theme, "( class style -- ) Color a token class, e.g. \"keyword\" \"bold blue\"");
+    reg(state, "theme-list", style::theme_list, "( -- ) Show token classes and their colors");
+    reg(state, "set-color", style::set_color, "( flag -- ) Turn all colors on or off: \"on\"/\"off\" or 1/0");
+
+    // Prompt segments
+    reg(state, "segment", segments::segment, "( name source style -- ) Register a prompt segment (style: color or \"\")");
+    reg(state, "segment-if", segments::segment_if, "( name condition -- ) Show segment only when condition is true");
+    reg(state, "segment-remove", segments::segment_remove, "( name -- ) Unregister a prompt segment");
+    reg(state, "segment-sep", segments::segment_sep, "( str -- ) Set the separator between prompt segments");
+    reg(state, "segments", segments::segments, "( -- ) List prompt segments");
+    reg(state, "$prompt-segments", segments::prompt_segments, "( -- str ) Render non-empty prompt segments");
+
+    // Argument completion for builtins that take names
+    for (word, kind) in [
+        ("cd", CompletionKind::Dirs),
+        ("pushd", CompletionKind::Dirs),
+        ("getenv", CompletionKind::Env),
+        ("setenv", CompletionKind::Env),
+        ("unsetenv", CompletionKind::Env),
+        ("env-append", CompletionKind::Env),
+        ("env-prepend", CompletionKind::Env),
+        ("see", CompletionKind::Words),
+    ] {
+        state.completers.insert(word.to_string(), kind);
+    }
 }
@@ -0,0 +1,326 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use crate::builtins::system::{self, Completed, Job, StdinSource};
+use crate::tokenizer;
+use crate::types::{Origin, State, Value};
+
+/// Upper bound on concurrently running commands.
+///
+/// Fan-out jobs are usually I/O bound (network, disk), so this is deliberately
+/// higher than the CPU count while still keeping huge inputs from forking at once.
+const MAX_WORKERS: usize = 16;
+
+/// Result of one job: the command and its exit code, and captured stdout, or
+/// a spawn error.
+type JobResult = Result<(Origin, String), String>;
+
+/// Run `run` on every item on a bounded pool of worker threads, returning
+/// results in input order.
+pub(crate) fn run_pool<T: Sync, R: Send>(items: &[T], run: impl Fn(&T) -> R + Sync) -> Vec<R> {
+    let results: Vec<Mutex<Option<R>>> = items.iter().map(|_| Mutex::new(None)).collect();
+    let next = AtomicUsize::new(0);
+    let workers = items.len().min(MAX_WORKERS);
+
+    std::thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::SeqCst);
+                if i >= items.len() {
+                    break;
+                }
+                let result = run(&items[i]);
+                *results[i].lock().unwrap() = Some(result);
+            });
+        }
+    });
+
+    results
+        .into_iter()
+        .map(|slot| slot.into_inner().unwrap().expect("every job is run"))
+        .collect()
+}
+
+/// The words of a command line as `exec` would get them: split by the
+/// tokenizer (so quotes group), with `~` expanded.
+fn words(command: &str) -> Vec<String> {
+    tokenizer::tokenize(command)
+        .iter()
+        .map(|t| system::expand_tilde(&t.text))
+        .collect()
+}
+
+/// One job per non-empty command (program and arguments), all with stdin
+/// from the line's `<stdin` if there is one.
+fn jobs(state: &mut State, commands: Vec<Vec<String>>) -> Vec<Job> {
+    let stdin = match state.stdin_file.take() {
+        Some(path) => StdinSource::File(path),
+        None => StdinSource::None,
+    };
+    commands
+        .into_iter()
+        .filter(|words| !words.is_empty())
+        .map(|mut words| {
+            let cmd = words.remove(0);
+            Job { cmd, args: words, stdin: stdin.clone() }
+        })
+        .collect()
+}
+
+/// Run jobs through the shell's command runner, or with `dry-run` on,
+/// print each one and give empty results.
+fn run_or_print(state: &mut State, jobs: Vec<Job>) -> Vec<JobResult> {
+    let origins: Vec<String> = jobs
+        .iter()
+        .map(|job| system::cmd_basename(&job.cmd).to_string())
+        .collect();
+    let completed = if state.dry_run {
+        jobs.iter()
+            .map(|job| {
+                let mut line = system::dry_run_line(&job.cmd, &job.args, 0);
+                if let StdinSource::File(path) = &job.stdin {
+                    line.push_str(&format!(" < {}", system::sh_quote(&path.to_string_lossy())));
+                }
+                eprintln!("{}", line);
+                Ok(Completed { code: 0, stdout: String::new() })
+            })
+            .collect()
+    } else {
+        state.runner.run_all(jobs, state.merge_stderr)
+    };
+    origins
+        .into_iter()
+        .zip(completed)
+        .map(|(command, result)| {
+            let done = result?;
+            Ok((Origin { command, code: done.code }, done.stdout))
+        })
+        .collect()
+}
+
+/// Push job outputs in order, recording the first non-zero exit code.
+fn push_results(state: &mut State, op: &str, results: Vec<JobResult>) -> Result<(), String> {
+    let mut outputs = Vec::with_capacity(results.len());
+    let mut exit_code = 0;
+    for result in results {
+        let (origin, stdout) = result.map_err(|e| {
+            state.last_exit_code = 127;
+            // The runner words its errors for `exec`
+            format!("{}: {}", op, e.strip_prefix("exec: ").unwrap_or(&e))
+        })?;
+        if exit_code == 0 {
+            exit_code = origin.code;
+        }
+        outputs.push(Value::Output(stdout, Some(origin)));
+    }
+    state.last_exit_code = exit_code;
+    state.stack.extend(outputs);
+    Ok(())
+}
+
+/// `par-exec` ( cmd1 ... cmdN n -- out1 ... outN ) Run n command strings concurrently.
+///
+/// Each command string is split into a program and its arguments as the shell
+/// splits a line, so quotes group words. Outputs are pushed in the same order
+/// as the commands, regardless of which finished first. `?` reports the first
+/// non-zero exit code, or 0.
+pub fn par_exec(state: &mut State) -> Result<(), String> {
+    system::deny_restricted(state, "par-exec")?;
+    let n = match state.stack.pop() {
+        Some(Value::Int(n)) if n >= 0 => n as usize,
+        Some(other) => {
+            state.stack.push(other);
+            return Err("par-exec: requires command count".into());
+        }
+        None => return Err("par-exec: stack underflow".into()),
+    };
+    if state.stack.len() < n {
+        state.stack.push(Value::Int(n as i64));
+        return Err("par-exec: stack underflow".into());
+    }
+    let start = state.stack.len() - n;
+    if !state.stack[start..]
+        .iter()
+        .all(|v| matches!(v, Value::Str(_)))
+    {
+        state.stack.push(Value::Int(n as i64));
+        return Err("par-exec: commands must be strings".into());
+    }
+    let commands: Vec<Vec<String>> =
+        state.stack[start..].iter().map(|v| words(&v.to_string())).collect();
+    if commands.iter().any(Vec::is_empty) {
+        state.stack.push(Value::Int(n as i64));
+        return Err("par-exec: empty command".into());
+    }
+    state.stack.truncate(start);
+    let jobs = jobs(state, commands);
+    let results = run_or_print(state, jobs);
+    push_results(state, "par-exec", results)
+}
+
+/// `peach` ( output cmd -- out1 ... outN ) Run cmd once per input line, concurrently.
+///
+/// Each line is appended to cmd as its final argument, so `hosts "ping -c 1" peach`
+/// pings every host in parallel. cmd is split into words as `par-exec` does.
+/// One Output per line is pushed, in line order.
+pub fn peach(state: &mut State) -> Result<(), String> {
+    system::deny_restricted(state, "peach")?;
+    if state.stack.len() < 2 {
+        return Err("peach: stack underflow".into());
+    }
+    let cmd = state.stack.pop().unwrap();
+    let input = state.stack.pop().unwrap();
+    let (input, cmd) = match (input, cmd) {
+        (Value::Output(i, _), Value::Str(c)) => (i, c),
+        (i, c) => {
+            state.stack.push(i);
+            state.stack.push(c);
+            return Err("peach: requires output and command string".into());
+        }
+    };
+    let base = words(&cmd);
+    let commands = input
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let mut command = base.clone();
+            command.push(system::expand_tilde(line));
+            command
+        })
+        .collect();
+    let jobs = jobs(state, commands);
+    let results = run_or_print(state, jobs);
+    push_results(state, "peach", results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builtins::system::MockRunner;
+
+    fn state_with(vals: Vec<Value>) -> State {
+        let mut s = State::new();
+        s.stack = vals;
+        s
+    }
+
+    /// Output of a command that exited 0.
+    fn output_of(command: &str, text: &str) -> Value {
+        let origin = Origin { command: command.into(), code: 0 };
+        Value::Output(text.into(), Some(origin))
+    }
+
+    #[test]
+    fn test_par_exec_preserves_order() {
+        let mut s = state_with(vec![
+            Value::Str("keep".into()),
+            Value::Str("sleep 0.2".into()),
+            Value::Str("echo second".into()),
+            Value::Str("echo third".into()),
+            Value::Int(3),
+        ]);
+        par_exec(&mut s).unwrap();
+        assert_eq!(
+            s.stack,
+            vec![
+                Value::Str("keep".into()),
+                output_of("sleep", ""),
+                output_of("echo", "second\n"),
+                output_of("echo", "third\n"),
+            ]
+        );
+        assert_eq!(s.last_exit_code, 0);
+    }
+
+    #[test]
+    fn test_par_exec_runs_concurrently() {
+        let mut s = state_with(vec![
+            Value::Str("sleep 0.3".into()),
+            Value::Str("sleep 0.3".into()),
+            Value::Str("sleep 0.3".into()),
+            Value::Int(3),
+        ]);
+        let start = std::time::Instant::now();
+        par_exec(&mut s).unwrap();
+        assert!(start.elapsed() < std::time::Duration::from_millis(800));
+        assert_eq!(s.stack.len(), 3);
+    }
+
+    #[test]
+    fn test_par_exec_exit_code() {
+        let mut s = state_with(vec![
+            Value::Str("true".into()),
+            Value::Str("false".into()),
+            Value::Int(2),
+        ]);
+        par_exec(&mut s).unwrap();
+        assert_eq!(s.last_exit_code, 1);
+    }
+
+    #[test]
+    fn test_par_exec_missing_command() {
+        let mut s = state_with(vec![Value::Str("yafsh_no_such_cmd".into()), Value::Int(1)]);
+        assert!(par_exec(&mut s).is_err());
+        assert_eq!(s.last_exit_code, 127);
+    }
+
+    #[test]
+    fn test_par_exec_bad_args() {
+        let mut s = state_with(vec![Value::Int(5), Value::Int(1)]);
+        assert!(par_exec(&mut s).is_err());
+        assert_eq!(s.stack, vec![Value::Int(5), Value::Int(1)]);
+
+        let mut s = state_with(vec![Value::Str("echo".into()), Value::Int(2)]);
+        assert!(par_exec(&mut s).is_err());
+        assert_eq!(s.stack.len(), 2);
+    }
+
+    #[test]
+    fn test_peach() {
+        let mut s = state_with(vec![
+            Value::output("a\n\nb\n"),
+            Value::Str("echo item".into()),
+        ]);
+        peach(&mut s).unwrap();
+        assert_eq!(
+            s.stack,
+            vec![output_of("echo", "item a\n"), output_of("echo", "item b\n")]
+        );
+    }
+
+    #[test]
+    fn test_par_exec_splits_like_the_shell() {
+        let mut s = state_with(vec![Value::Str("printf %s \"a  b\"".into()), Value::Int(1)]);
+        par_exec(&mut s).unwrap();
+        assert_eq!(s.stack, vec![output_of("printf", "a  b")]);
+
+        let mut s = state_with(vec![Value::Str("  ".into()), Value::Int(1)]);
+        assert_eq!(par_exec(&mut s), Err("par-exec: empty command".into()));
+        assert_eq!(s.stack, vec![Value::Str("  ".into()), Value::Int(1)]);
+    }
+
+    #[test]
+    fn test_par_exec_and_peach_use_the_runner() {
+        let runner = MockRunner::new().on("git", 0, "ok\n");
+        let calls = runner.calls();
+        let mut s = state_with(vec![Value::Str("git status".into()), Value::Int(1)]);
+        s.runner = Box::new(runner);
+        s.stdin_file = Some("in.txt".into());
+        par_exec(&mut s).unwrap();
+        assert_eq!(s.stack, vec![output_of("git", "ok\n")]);
+        assert_eq!(calls.borrow()[0].stdin, StdinSource::File("in.txt".into()));
+        assert!(s.stdin_file.is_none());
+
+        s.stack = vec![Value::output("a b\n"), Value::Str("git show".into())];
+        peach(&mut s).unwrap();
+        assert_eq!(calls.borrow()[1].args, vec!["show".to_string(), "a b".to_string()]);
+        assert_eq!(calls.borrow()[1].stdin, StdinSource::None);
+    }
+
+    #[test]
+    fn test_peach_wrong_types() {
+        let mut s = state_with(vec![Value::Str("a".into()), Value::Str("echo".into())]);
+        assert!(peach(&mut s).is_err());
+        assert_eq!(s.stack.len(), 2);
+    }
+}
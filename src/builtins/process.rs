@@ -0,0 +1,210 @@
+use std::ffi::OsStr;
+use std::str::FromStr;
+
+use nix::errno::Errno;
+use nix::sys::signal::{self, Signal};
+use nix::unistd::Pid;
+use sysinfo::{ProcessRefreshKind, ProcessesToUpdate, System};
+
+use crate::builtins::system::deny_restricted;
+use crate::types::{State, Value};
+
+/// Parse a signal given as a number (9) or a name, with or without the
+/// SIG prefix and in any case ("TERM", "sigkill").
+fn parse_signal(val: &Value) -> Result<Signal, String> {
+    match val {
+        Value::Int(n) => i32::try_from(*n)
+            .ok()
+            .and_then(|n| Signal::try_from(n).ok())
+            .ok_or_else(|| format!("unknown signal {}", n)),
+        Value::Str(name) => {
+            let upper = name.to_ascii_uppercase();
+            let full = if upper.starts_with("SIG") { upper } else { format!("SIG{}", upper) };
+            Signal::from_str(&full).map_err(|_| format!("unknown signal {}", name))
+        }
+        _ => Err("signal must be a number or name".into()),
+    }
+}
+
+/// `kill` ( pid sig -- ) Send a signal, given as a number or a name such as
+/// "TERM" or "SIGKILL", to a process.
+pub fn kill(state: &mut State) -> Result<(), String> {
+    deny_restricted(state, "kill")?;
+    let n = state.stack.len();
+    if n < 2 {
+        return Err("kill: stack underflow".into());
+    }
+    let pid = match state.stack[n - 2] {
+        Value::Int(pid) if pid > 0 && pid <= i32::MAX as i64 => Pid::from_raw(pid as i32),
+        Value::Int(_) => return Err("kill: pid must be a positive integer".into()),
+        _ => return Err("kill: pid must be an integer".into()),
+    };
+    let sig = parse_signal(&state.stack[n - 1]).map_err(|e| format!("kill: {}", e))?;
+    signal::kill(pid, sig).map_err(|e| format!("kill: {}: {}", pid, e.desc()))?;
+    state.stack.truncate(n - 2);
+    Ok(())
+}
+
+/// PIDs of the processes named `name`, other than this shell.
+fn pids_named(name: &str) -> Vec<Pid> {
+    let mut sys = System::new();
+    sys.refresh_processes_specifics(ProcessesToUpdate::All, true, ProcessRefreshKind::nothing());
+    let own = std::process::id();
+    sys.processes()
+        .iter()
+        .filter(|(pid, proc)| {
+            proc.thread_kind().is_none() && proc.name() == OsStr::new(name) && pid.as_u32() != own
+        })
+        .map(|(pid, _)| Pid::from_raw(pid.as_u32() as i32))
+        .collect()
+}
+
+/// `pkill` ( name -- ) Send SIGTERM to every process with exactly this name.
+///
+/// Sets `?` to 0 if any were signalled and 1 if none have the name, like
+/// pkill(1); it's an error only if there were some but none could be signalled.
+pub fn pkill(state: &mut State) -> Result<(), String> {
+    deny_restricted(state, "pkill")?;
+    let name = match state.stack.last() {
+        Some(Value::Str(name)) => name.clone(),
+        Some(_) => return Err("pkill: requires a process name".into()),
+        None => return Err("pkill: stack underflow".into()),
+    };
+    let pids = pids_named(&name);
+    let mut signalled = 0;
+    let mut failure: Option<Errno> = None;
+    for pid in &pids {
+        match signal::kill(*pid, Signal::SIGTERM) {
+            Ok(()) => signalled += 1,
+            // Gone since we looked; it didn't need the signal
+            Err(Errno::ESRCH) => {}
+            Err(e) => failure = failure.or(Some(e)),
+        }
+    }
+    if signalled == 0 {
+        if let Some(e) = failure {
+            return Err(format!("pkill: {}: {}", name, e.desc()));
+        }
+    }
+    state.stack.pop();
+    state.last_exit_code = if signalled > 0 { 0 } else { 1 };
+    Ok(())
+}
+
+/// `procs` ( -- output ) One line per process, sorted by pid: pid, name,
+/// CPU use in percent, and resident memory in bytes, separated by tabs (see
+/// `tsv-parse`, `field-by`).
+///
+/// CPU use is measured over a short sample, so this takes a moment.
+pub fn procs(state: &mut State) -> Result<(), String> {
+    let mut sys = System::new();
+    let kind = ProcessRefreshKind::nothing().with_cpu().with_memory();
+    sys.refresh_processes_specifics(ProcessesToUpdate::All, true, kind);
+    std::thread::sleep(sysinfo::MINIMUM_CPU_UPDATE_INTERVAL);
+    sys.refresh_processes_specifics(ProcessesToUpdate::All, true, kind);
+
+    let mut procs: Vec<_> = sys
+        .processes()
+        .iter()
+        .filter(|(_, proc)| proc.thread_kind().is_none())
+        .collect();
+    procs.sort_by_key(|(pid, _)| **pid);
+    let text: String = procs
+        .into_iter()
+        .map(|(pid, proc)| {
+            format!(
+                "{}\t{}\t{:.1}\t{}\n",
+                pid,
+                proc.name().to_string_lossy(),
+                proc.cpu_usage(),
+                proc.memory()
+            )
+        })
+        .collect();
+    state.stack.push(Value::output(text));
+    Ok(())
+}
+
+/// `pid` ( -- pid ) This shell's process ID.
+pub fn pid(state: &mut State) -> Result<(), String> {
+    state.stack.push(Value::Int(std::process::id() as i64));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builtins;
+    use crate::eval;
+
+    fn new_state() -> State {
+        let mut s = State::new();
+        builtins::register_builtins(&mut s);
+        s
+    }
+
+    #[test]
+    fn test_parse_signal() {
+        assert_eq!(parse_signal(&Value::Int(9)), Ok(Signal::SIGKILL));
+        assert_eq!(parse_signal(&Value::Str("term".into())), Ok(Signal::SIGTERM));
+        assert_eq!(parse_signal(&Value::Str("SIGHUP".into())), Ok(Signal::SIGHUP));
+        assert_eq!(parse_signal(&Value::Str("NOPE".into())), Err("unknown signal NOPE".into()));
+        assert_eq!(parse_signal(&Value::Int(999)), Err("unknown signal 999".into()));
+    }
+
+    #[test]
+    fn test_kill_child() {
+        let mut child = std::process::Command::new("sleep").arg("30").spawn().unwrap();
+        let mut s = new_state();
+        s.stack = vec![Value::Int(child.id() as i64), Value::Str("KILL".into())];
+        kill(&mut s).unwrap();
+        assert!(s.stack.is_empty());
+        let status = child.wait().unwrap();
+        use std::os::unix::process::ExitStatusExt;
+        assert_eq!(status.signal(), Some(libc::SIGKILL));
+    }
+
+    #[test]
+    fn test_kill_errors_keep_stack() {
+        let mut s = new_state();
+        s.stack = vec![Value::Int(0), Value::Int(9)];
+        assert_eq!(kill(&mut s), Err("kill: pid must be a positive integer".into()));
+        s.stack = vec![Value::Int(1), Value::Str("BOGUS".into())];
+        assert_eq!(kill(&mut s), Err("kill: unknown signal BOGUS".into()));
+        assert_eq!(s.stack.len(), 2);
+    }
+
+    #[test]
+    fn test_pkill_no_match_sets_status() {
+        let mut s = new_state();
+        eval::eval_line(&mut s, "\"yafsh-no-such-process\" pkill ?").unwrap();
+        assert_eq!(s.stack, vec![Value::Int(1)]);
+    }
+
+    #[test]
+    fn test_procs_lists_this_process() {
+        let mut s = new_state();
+        eval::eval_line(&mut s, "procs tsv-parse").unwrap();
+        let Some(Value::List(rows)) = s.stack.pop() else {
+            panic!("procs should give rows");
+        };
+        let own = Value::Str(std::process::id().to_string());
+        let row = rows
+            .iter()
+            .find_map(|row| match row {
+                Value::List(fields) if fields[0] == own => Some(fields),
+                _ => None,
+            })
+            .expect("this process is listed");
+        assert_eq!(row.len(), 4);
+        assert!(row[2].to_string().parse::<f64>().is_ok(), "{}", row[2]);
+        assert!(row[3].to_string().parse::<u64>().unwrap() > 0);
+    }
+
+    #[test]
+    fn test_pid() {
+        let mut s = new_state();
+        pid(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::Int(std::process::id() as i64)]);
+    }
+}
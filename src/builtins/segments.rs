@@ -0,0 +1,254 @@
+use crate::builtins::style;
+use crate::eval;
+use crate::{out, outln};
+use crate::types::{PromptSegment, State, Value};
+
+/// Pop a string argument, restoring the stack on a type error.
+fn pop_str(state: &mut State, op: &str) -> Result<String, String> {
+    match state.stack.pop() {
+        Some(Value::Str(s)) => Ok(s),
+        Some(other) => {
+            state.stack.push(other);
+            Err(format!("{}: requires string", op))
+        }
+        None => Err(format!("{}: stack underflow", op)),
+    }
+}
+
+/// `segment` ( name source style -- ) Register a prompt segment.
+///
+/// `source` is evaluated when the prompt renders and its top value becomes
+/// the segment text; `style` is a color as accepted by `fg`, or "" for none.
+/// Registering an existing name replaces it in place.
+pub fn segment(state: &mut State) -> Result<(), String> {
+    if state.stack.len() < 3 {
+        return Err("segment: stack underflow".into());
+    }
+    let style = state.stack.pop().unwrap();
+    let valid_style =
+        matches!(&style, Value::Str(s) if s.is_empty()) || style::wrap("", &style).is_some();
+    let (Value::Str(name), Value::Str(source)) = (
+        &state.stack[state.stack.len() - 2],
+        &state.stack[state.stack.len() - 1],
+    ) else {
+        state.stack.push(style);
+        return Err("segment: requires name and source strings".into());
+    };
+    if !valid_style {
+        state.stack.push(style);
+        return Err("segment: unknown style".into());
+    }
+    let (name, source) = (name.clone(), source.clone());
+    state.stack.truncate(state.stack.len() - 2);
+
+    let new = PromptSegment {
+        name,
+        source,
+        style,
+        condition: None,
+    };
+    match state
+        .prompt_segments
+        .iter_mut()
+        .find(|s| s.name == new.name)
+    {
+        Some(existing) => {
+            let condition = existing.condition.take();
+            *existing = PromptSegment { condition, ..new };
+        }
+        None => state.prompt_segments.push(new),
+    }
+    Ok(())
+}
+
+/// `segment-if` ( name condition -- ) Show a segment only when `condition` leaves a true value.
+pub fn segment_if(state: &mut State) -> Result<(), String> {
+    let condition = pop_str(state, "segment-if")?;
+    let name = match pop_str(state, "segment-if") {
+        Ok(name) => name,
+        Err(e) => {
+            state.stack.push(Value::Str(condition));
+            return Err(e);
+        }
+    };
+    match state.prompt_segments.iter_mut().find(|s| s.name == name) {
+        Some(segment) => {
+            segment.condition = Some(condition);
+            Ok(())
+        }
+        None => {
+            let err = format!("segment-if: no segment named '{}'", name);
+            state.stack.push(Value::Str(name));
+            state.stack.push(Value::Str(condition));
+            Err(err)
+        }
+    }
+}
+
+/// `segment-remove` ( name -- ) Unregister a prompt segment.
+pub fn segment_remove(state: &mut State) -> Result<(), String> {
+    let name = pop_str(state, "segment-remove")?;
+    let before = state.prompt_segments.len();
+    state.prompt_segments.retain(|s| s.name != name);
+    if state.prompt_segments.len() == before {
+        let err = format!("segment-remove: no segment named '{}'", name);
+        state.stack.push(Value::Str(name));
+        return Err(err);
+    }
+    Ok(())
+}
+
+/// `segment-sep` ( str -- ) Set the text placed between prompt segments.
+pub fn segment_sep(state: &mut State) -> Result<(), String> {
+    state.segment_separator = pop_str(state, "segment-sep")?;
+    Ok(())
+}
+
+/// `segments` ( -- ) List registered prompt segments in display order.
+pub fn segments(state: &mut State) -> Result<(), String> {
+    if state.prompt_segments.is_empty() {
+        outln!(state, "(no segments)");
+        return Ok(());
+    }
+    for s in &state.prompt_segments {
+        let style = match &s.style {
+            Value::Str(st) if st.is_empty() => "-".to_string(),
+            other => other.to_string(),
+        };
+        out!(state, "{:<12} {:<20} {}", s.name, s.source, style);
+        if let Some(cond) = &s.condition {
+            out!(state, "  if {}", cond);
+        }
+        outln!(state);
+    }
+    Ok(())
+}
+
+/// Evaluate `source` on an empty stack and return its top value, if any.
+///
+/// The caller's stack and exit code are left untouched, so segments see
+/// (and leave) the same `$exitcode` the prompt does.
+fn eval_isolated(state: &mut State, source: &str) -> Result<Option<Value>, String> {
+    let saved_stack = std::mem::take(&mut state.stack);
+    let saved_exit_code = state.last_exit_code;
+    let result = eval::eval_line(state, source);
+    let top = state.stack.pop();
+    state.stack = saved_stack;
+    state.last_exit_code = saved_exit_code;
+    result.map(|_| top)
+}
+
+/// `$prompt-segments` ( -- str ) Render registered segments joined by the separator.
+///
+/// Segments whose condition is false or whose text comes out empty are
+/// skipped, so no doubled separators are left behind.
+pub fn prompt_segments(state: &mut State) -> Result<(), String> {
+    let mut parts = Vec::new();
+    for seg in state.prompt_segments.clone() {
+        let fail = |e: String| format!("$prompt-segments: {}: {}", seg.name, e);
+        if let Some(cond) = &seg.condition {
+            let shown = match eval_isolated(state, cond).map_err(fail)? {
+                Some(Value::Int(n)) => n != 0,
+                Some(Value::Str(s)) | Some(Value::Output(s, _)) => !s.is_empty(),
+                Some(Value::Map(map)) => !map.is_empty(),
+                Some(Value::List(items)) => !items.is_empty(),
+                None => false,
+            };
+            if !shown {
+                continue;
+            }
+        }
+        let text = match eval_isolated(state, &seg.source).map_err(fail)? {
+            Some(Value::Output(s, _)) => s.trim_end_matches('\n').to_string(),
+            Some(v) => v.to_string(),
+            None => String::new(),
+        };
+        if text.is_empty() {
+            continue;
+        }
+        let styled = match &seg.style {
+            _ if !state.theme.enabled => text,
+            Value::Str(s) if s.is_empty() => text,
+            color => style::wrap(&text, color).unwrap_or(text),
+        };
+        parts.push(styled);
+    }
+    state
+        .stack
+        .push(Value::Str(parts.join(&state.segment_separator)));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builtins;
+
+    fn new_state() -> State {
+        let mut s = State::new();
+        builtins::register_builtins(&mut s);
+        s
+    }
+
+    fn render(s: &mut State) -> String {
+        prompt_segments(s).unwrap();
+        match s.stack.pop() {
+            Some(Value::Str(out)) => out,
+            other => panic!("expected string, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_segments_join_and_skip_empty() {
+        let mut s = new_state();
+        eval::eval_line(&mut s, r#": first "one" ;"#).unwrap();
+        eval::eval_line(&mut s, r#": blank "" ;"#).unwrap();
+        eval::eval_line(&mut s, r#""a" "first" "" segment"#).unwrap();
+        eval::eval_line(&mut s, r#""b" "blank" "" segment"#).unwrap();
+        eval::eval_line(&mut s, r#""c" "3" "" segment"#).unwrap();
+        eval::eval_line(&mut s, r#"" | " segment-sep"#).unwrap();
+        assert_eq!(render(&mut s), "one | 3");
+    }
+
+    #[test]
+    fn test_segment_condition_and_style() {
+        let mut s = new_state();
+        eval::eval_line(&mut s, r#""err" "$exitcode" "red" segment"#).unwrap();
+        eval::eval_line(&mut s, r#""err" "? 0 <>" segment-if"#).unwrap();
+        assert_eq!(render(&mut s), "");
+        s.last_exit_code = 2;
+        assert_eq!(render(&mut s), "\x1b[31m2\x1b[0m");
+        assert_eq!(s.last_exit_code, 2);
+    }
+
+    #[test]
+    fn test_segment_replace_keeps_order_and_condition() {
+        let mut s = new_state();
+        eval::eval_line(&mut s, r#""x" "1" "" segment "y" "2" "" segment"#).unwrap();
+        eval::eval_line(&mut s, r#""x" "0" segment-if"#).unwrap();
+        eval::eval_line(&mut s, r#""x" "9" "" segment"#).unwrap();
+        assert_eq!(s.prompt_segments[0].source, "9");
+        assert_eq!(s.prompt_segments[0].condition.as_deref(), Some("0"));
+        assert_eq!(render(&mut s), "2");
+    }
+
+    #[test]
+    fn test_segment_errors_keep_stack() {
+        let mut s = new_state();
+        s.stack = vec![
+            Value::Str("x".into()),
+            Value::Str("1".into()),
+            Value::Str("mauve".into()),
+        ];
+        assert!(segment(&mut s).is_err());
+        assert_eq!(s.stack.len(), 3);
+
+        s.stack = vec![Value::Str("missing".into())];
+        assert!(segment_remove(&mut s).is_err());
+        assert_eq!(s.stack, vec![Value::Str("missing".into())]);
+
+        s.stack = vec![Value::Str("missing".into()), Value::Str("1".into())];
+        assert!(segment_if(&mut s).is_err());
+        assert_eq!(s.stack, vec![Value::Str("missing".into()), Value::Str("1".into())]);
+    }
+}
@@ -1,6 +1,5 @@
-use crate::types::State;
-#[cfg(test)]
-use crate::types::Value;
+use crate::outln;
+use crate::types::{NamedStack, State, Value};
 
 /// `dup` ( a -- a a ) Duplicate top item.
 pub fn dup(state: &mut State) -> Result<(), String> {
@@ -54,6 +53,137 @@ pub fn rot(state: &mut State) -> Result<(), String> {
     Ok(())
 }
 
+/// `undo` ( -- ) Restore the stack from before the last line that changed it.
+pub fn undo(state: &mut State) -> Result<(), String> {
+    if state.undo.undo(&mut state.stack) {
+        Ok(())
+    } else {
+        Err("undo: nothing to undo".into())
+    }
+}
+
+/// `redo` ( -- ) Bring back the stack the last `undo` replaced.
+pub fn redo(state: &mut State) -> Result<(), String> {
+    if state.undo.redo(&mut state.stack) {
+        Ok(())
+    } else {
+        Err("redo: nothing to redo".into())
+    }
+}
+
+/// Pop a stash name.
+fn pop_name(state: &mut State, op: &str) -> Result<String, String> {
+    match state.stack.pop() {
+        Some(Value::Str(name)) => Ok(name),
+        Some(other) => {
+            state.stack.push(other);
+            Err(format!("{}: requires name string", op))
+        }
+        None => Err(format!("{}: stack underflow", op)),
+    }
+}
+
+/// `stash` ( ... name -- ) Save the whole stack under a name and clear it.
+pub fn stash(state: &mut State) -> Result<(), String> {
+    let name = pop_name(state, "stash")?;
+    if state.stashes.contains_key(&name) {
+        let e = format!("stash: '{}' already exists (unstash it first)", name);
+        state.stack.push(Value::Str(name));
+        return Err(e);
+    }
+    let saved = std::mem::take(&mut state.stack);
+    state.stashes.insert(name, saved);
+    Ok(())
+}
+
+/// `unstash` ( name -- ... ) Push a stashed stack back on top and forget it.
+pub fn unstash(state: &mut State) -> Result<(), String> {
+    let name = pop_name(state, "unstash")?;
+    let Some(saved) = state.stashes.remove(&name) else {
+        let e = format!("unstash: no stash named '{}'", name);
+        state.stack.push(Value::Str(name));
+        return Err(e);
+    };
+    state.stack.extend(saved);
+    Ok(())
+}
+
+/// `stashes` ( -- ) List stashes and how many items each holds.
+pub fn stashes(state: &mut State) -> Result<(), String> {
+    let mut names: Vec<&String> = state.stashes.keys().collect();
+    names.sort();
+    for name in names {
+        outln!(state, "{} <{}>", name, state.stashes[name].len());
+    }
+    Ok(())
+}
+
+/// `stack-new` ( name -- ) Create an empty stack to switch to.
+pub fn stack_new(state: &mut State) -> Result<(), String> {
+    let name = pop_name(state, "stack-new")?;
+    if name.is_empty() || name == state.stack_name || state.other_stacks.contains_key(&name) {
+        let e = format!("stack-new: stack '{}' already exists", name);
+        state.stack.push(Value::Str(name));
+        return Err(e);
+    }
+    state.other_stacks.insert(name, NamedStack::default());
+    Ok(())
+}
+
+/// `stack-switch` ( name -- ) Make another stack the active one.
+///
+/// The stack left behind keeps its items and undo history.
+pub fn stack_switch(state: &mut State) -> Result<(), String> {
+    let name = pop_name(state, "stack-switch")?;
+    if name == state.stack_name {
+        return Ok(());
+    }
+    let Some(next) = state.other_stacks.remove(&name) else {
+        let e = format!("stack-switch: no stack named '{}' (create it with stack-new)", name);
+        state.stack.push(Value::Str(name));
+        return Err(e);
+    };
+    let left = NamedStack {
+        values: std::mem::replace(&mut state.stack, next.values),
+        undo: std::mem::replace(&mut state.undo, next.undo),
+    };
+    state.undo.skip_line();
+    let previous = std::mem::replace(&mut state.stack_name, name);
+    state.other_stacks.insert(previous, left);
+    Ok(())
+}
+
+/// `stack-drop` ( name -- ) Delete a stack that isn't active.
+pub fn stack_drop(state: &mut State) -> Result<(), String> {
+    let name = pop_name(state, "stack-drop")?;
+    if name == state.stack_name {
+        state.stack.push(Value::Str(name));
+        return Err("stack-drop: can't drop the active stack".into());
+    }
+    if state.other_stacks.remove(&name).is_none() {
+        let e = format!("stack-drop: no stack named '{}'", name);
+        state.stack.push(Value::Str(name));
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// `stacks` ( -- ) List stacks with their depths, marking the active one.
+pub fn stacks(state: &mut State) -> Result<(), String> {
+    let mut entries: Vec<(&str, usize)> = state
+        .other_stacks
+        .iter()
+        .map(|(name, s)| (name.as_str(), s.values.len()))
+        .collect();
+    entries.push((&state.stack_name, state.stack.len()));
+    entries.sort();
+    for (name, depth) in entries {
+        let mark = if name == state.stack_name { "*" } else { " " };
+        outln!(state, "{} {} <{}>", mark, name, depth);
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -156,4 +286,79 @@ mod tests {
         swap(&mut s).unwrap();
         assert_eq!(s.stack, vec![Value::Int(1), Value::Str("a".into())]);
     }
+
+    #[test]
+    fn test_stash_and_unstash() {
+        let mut s = state_with(vec![Value::Int(1), Value::Int(2), Value::Str("wip".into())]);
+        stash(&mut s).unwrap();
+        assert!(s.stack.is_empty());
+
+        s.stack = vec![Value::Int(9), Value::Str("wip".into())];
+        unstash(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::Int(9), Value::Int(1), Value::Int(2)]);
+        assert!(s.stashes.is_empty());
+    }
+
+    #[test]
+    fn test_stash_errors() {
+        let mut s = state_with(vec![Value::Str("a".into())]);
+        stash(&mut s).unwrap();
+        s.stack = vec![Value::Int(1), Value::Str("a".into())];
+        assert_eq!(
+            stash(&mut s),
+            Err("stash: 'a' already exists (unstash it first)".into())
+        );
+        assert_eq!(s.stack, vec![Value::Int(1), Value::Str("a".into())]);
+
+        s.stack = vec![Value::Str("b".into())];
+        assert_eq!(unstash(&mut s), Err("unstash: no stash named 'b'".into()));
+        s.stack = vec![Value::Int(3)];
+        assert!(unstash(&mut s).is_err());
+        assert_eq!(s.stack, vec![Value::Int(3)]);
+    }
+
+    #[test]
+    fn test_stack_switching() {
+        let mut s = state_with(vec![Value::Int(1), Value::Str("scratch".into())]);
+        stack_new(&mut s).unwrap();
+        s.stack.push(Value::Str("scratch".into()));
+        stack_switch(&mut s).unwrap();
+        assert_eq!(s.stack_name, "scratch");
+        assert!(s.stack.is_empty());
+
+        s.stack = vec![Value::Int(2), Value::Str("main".into())];
+        stack_switch(&mut s).unwrap();
+        assert_eq!(s.stack_name, "main");
+        assert_eq!(s.stack, vec![Value::Int(1)]);
+        assert_eq!(s.other_stacks["scratch"].values, vec![Value::Int(2)]);
+
+        s.stack.push(Value::Str("scratch".into()));
+        stack_drop(&mut s).unwrap();
+        assert!(s.other_stacks.is_empty());
+    }
+
+    #[test]
+    fn test_stack_errors() {
+        let mut s = state_with(vec![Value::Str("main".into())]);
+        assert_eq!(
+            stack_new(&mut s),
+            Err("stack-new: stack 'main' already exists".into())
+        );
+        s.stack = vec![Value::Str("nope".into())];
+        assert!(stack_switch(&mut s).is_err());
+        assert_eq!(s.stack, vec![Value::Str("nope".into())]);
+        s.stack = vec![Value::Str("main".into())];
+        assert_eq!(
+            stack_drop(&mut s),
+            Err("stack-drop: can't drop the active stack".into())
+        );
+    }
+
+    #[test]
+    fn test_undo_redo_empty() {
+        let mut s = state_with(vec![Value::Int(1)]);
+        assert_eq!(undo(&mut s), Err("undo: nothing to undo".into()));
+        assert_eq!(redo(&mut s), Err("redo: nothing to redo".into()));
+        assert_eq!(s.stack, vec![Value::Int(1)]);
+    }
 }
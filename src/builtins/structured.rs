@@ -0,0 +1,267 @@
+use std::collections::HashMap;
+
+use yaml_rust2::{Yaml, YamlLoader};
+
+use crate::types::{State, Value};
+
+/// Pop text (Str or Output), parse it with `parse`, and push the result;
+/// on error the text goes back on the stack.
+pub(crate) fn parse_word(
+    state: &mut State,
+    op: &str,
+    parse: impl FnOnce(&str) -> Result<Value, String>,
+) -> Result<(), String> {
+    let val = state.stack.pop().ok_or_else(|| format!("{}: stack underflow", op))?;
+    let parsed = match &val {
+        Value::Str(s) | Value::Output(s, _) => parse(s).map_err(|e| format!("{}: {}", op, e)),
+        _ => Err(format!("{}: requires string or output", op)),
+    };
+    match parsed {
+        Ok(doc) => {
+            state.stack.push(doc);
+            Ok(())
+        }
+        Err(e) => {
+            state.stack.push(val);
+            Err(e)
+        }
+    }
+}
+
+/// Convert a TOML value: tables become maps, arrays lists, booleans 1/0,
+/// and floats and dates their text.
+fn from_toml(val: toml::Value) -> Value {
+    match val {
+        toml::Value::String(s) => Value::Str(s),
+        toml::Value::Integer(n) => Value::Int(n),
+        toml::Value::Boolean(b) => Value::Int(b as i64),
+        toml::Value::Float(f) => Value::Str(f.to_string()),
+        toml::Value::Datetime(d) => Value::Str(d.to_string()),
+        toml::Value::Array(items) => Value::List(items.into_iter().map(from_toml).collect()),
+        toml::Value::Table(table) => {
+            Value::Map(table.into_iter().map(|(k, v)| (k, from_toml(v))).collect())
+        }
+    }
+}
+
+fn parse_toml(text: &str) -> Result<Value, String> {
+    match text.parse::<toml::Table>() {
+        Ok(table) => Ok(from_toml(toml::Value::Table(table))),
+        Err(e) => match e.span() {
+            Some(span) => {
+                let line = text[..span.start].matches('\n').count() + 1;
+                Err(format!("line {}: {}", line, e.message()))
+            }
+            None => Err(e.message().to_string()),
+        },
+    }
+}
+
+/// Convert a YAML node the same way as TOML; null becomes "".
+fn from_yaml(node: Yaml) -> Result<Value, String> {
+    Ok(match node {
+        Yaml::String(s) | Yaml::Real(s) => Value::Str(s),
+        Yaml::Integer(n) => Value::Int(n),
+        Yaml::Boolean(b) => Value::Int(b as i64),
+        Yaml::Null => Value::Str(String::new()),
+        Yaml::Array(items) => {
+            Value::List(items.into_iter().map(from_yaml).collect::<Result<_, _>>()?)
+        }
+        Yaml::Hash(hash) => {
+            let mut map = HashMap::new();
+            for (key, val) in hash {
+                let key = match key {
+                    Yaml::String(s) | Yaml::Real(s) => s,
+                    Yaml::Integer(n) => n.to_string(),
+                    Yaml::Boolean(b) => b.to_string(),
+                    _ => return Err("keys must be scalars".into()),
+                };
+                map.insert(key, from_yaml(val)?);
+            }
+            Value::Map(map)
+        }
+        Yaml::Alias(_) | Yaml::BadValue => return Err("unsupported value".into()),
+    })
+}
+
+fn parse_yaml(text: &str) -> Result<Value, String> {
+    let docs = YamlLoader::load_from_str(text).map_err(|e| e.to_string())?;
+    match docs.into_iter().next() {
+        Some(doc) => from_yaml(doc),
+        None => Ok(Value::Map(HashMap::new())),
+    }
+}
+
+/// `toml-parse` ( output -- map ) Parse a TOML document into a map.
+pub fn toml_parse(state: &mut State) -> Result<(), String> {
+    parse_word(state, "toml-parse", parse_toml)
+}
+
+/// `yaml-parse` ( output -- value ) Parse the first YAML document into maps,
+/// lists, and scalars. An empty document gives an empty map.
+pub fn yaml_parse(state: &mut State) -> Result<(), String> {
+    parse_word(state, "yaml-parse", parse_yaml)
+}
+
+/// Follow a dotted path: names index maps, numbers (from 0) index lists.
+fn lookup(mut val: Value, path: &str) -> Result<Value, String> {
+    let mut walked = String::new();
+    for part in path.split('.') {
+        let here = if walked.is_empty() { part.to_string() } else { format!("{}.{}", walked, part) };
+        val = match val {
+            Value::Map(mut map) => map
+                .remove(part)
+                .ok_or_else(|| format!("no key \"{}\"", here))?,
+            Value::List(mut items) => {
+                let len = items.len();
+                match part.parse::<usize>() {
+                    Ok(i) if i < len => items.swap_remove(i),
+                    _ => {
+                        return Err(format!(
+                            "no index \"{}\" ({} item{})",
+                            here,
+                            len,
+                            if len == 1 { "" } else { "s" }
+                        ))
+                    }
+                }
+            }
+            _ => return Err(format!("\"{}\" is not a map or list", walked)),
+        };
+        walked = here;
+    }
+    Ok(val)
+}
+
+/// `get-path` ( value path -- value ) Look up a dotted path such as
+/// `"package.version"` or `"jobs.test.steps.0.run"`, consuming the value.
+pub fn get_path(state: &mut State) -> Result<(), String> {
+    if state.stack.len() < 2 {
+        return Err("get-path: stack underflow".into());
+    }
+    let path = match state.stack.pop() {
+        Some(Value::Str(path)) => path,
+        Some(other) => {
+            state.stack.push(other);
+            return Err("get-path: path must be a string".into());
+        }
+        None => unreachable!(),
+    };
+    if !matches!(state.stack.last(), Some(Value::Map(_) | Value::List(_))) {
+        state.stack.push(Value::Str(path));
+        return Err("get-path: requires a map or list".into());
+    }
+    let val = state.stack.pop().unwrap();
+    match lookup(val.clone(), &path) {
+        Ok(found) => {
+            state.stack.push(found);
+            Ok(())
+        }
+        Err(e) => {
+            state.stack.push(val);
+            state.stack.push(Value::Str(path));
+            Err(format!("get-path: {}", e))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builtins;
+    use crate::eval;
+
+    fn new_state() -> State {
+        let mut s = State::new();
+        builtins::register_builtins(&mut s);
+        s
+    }
+
+    const CARGO: &str = "[package]\nname = \"demo\"\nversion = \"1.2.0\"\n\n\
+        [dependencies]\nserde = { version = \"1\", features = [\"derive\"] }\n\n\
+        [[bin]]\nname = \"demo\"\ntest = false\n";
+
+    #[test]
+    fn test_toml_parse_get_path() {
+        let mut s = new_state();
+        s.stack = vec![Value::output(CARGO)];
+        toml_parse(&mut s).unwrap();
+        eval::eval_line(
+            &mut s,
+            "dup \"package.version\" get-path swap dup \"dependencies.serde.features.0\" get-path swap \"bin.0.test\" get-path",
+        )
+        .unwrap();
+        assert_eq!(
+            s.stack,
+            vec![
+                Value::Str("1.2.0".into()),
+                Value::Str("derive".into()),
+                Value::Int(0)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_toml_parse_error_restores_stack() {
+        let mut s = new_state();
+        s.stack = vec![Value::output("a = 1\nb = \n")];
+        let err = toml_parse(&mut s).unwrap_err();
+        assert!(err.starts_with("toml-parse: line 2: "), "{}", err);
+        assert_eq!(s.stack, vec![Value::output("a = 1\nb = \n")]);
+    }
+
+    #[test]
+    fn test_yaml_parse_get_path() {
+        let mut s = new_state();
+        s.stack = vec![Value::Output(
+            "on: push\njobs:\n  test:\n    steps:\n      - uses: actions/checkout@v4\n      - run: cargo test\n        timeout: 10\n".into(),
+            None,
+        )];
+        yaml_parse(&mut s).unwrap();
+        eval::eval_line(
+            &mut s,
+            "dup \"jobs.test.steps.1.run\" get-path swap \"jobs.test.steps.1.timeout\" get-path",
+        )
+        .unwrap();
+        assert_eq!(
+            s.stack,
+            vec![Value::Str("cargo test".into()), Value::Int(10)]
+        );
+    }
+
+    #[test]
+    fn test_yaml_scalars() {
+        assert_eq!(
+            parse_yaml("[1, true, ~, 1.5, x]").unwrap(),
+            Value::List(vec![
+                Value::Int(1),
+                Value::Int(1),
+                Value::Str("".into()),
+                Value::Str("1.5".into()),
+                Value::Str("x".into()),
+            ])
+        );
+        assert_eq!(parse_yaml("").unwrap(), Value::Map(HashMap::new()));
+    }
+
+    #[test]
+    fn test_get_path_missing_restores_stack() {
+        let mut s = new_state();
+        s.stack = vec![Value::output("[a]\nb = [1]\n")];
+        toml_parse(&mut s).unwrap();
+        let doc = s.stack[0].clone();
+        s.stack.push(Value::Str("a.c".into()));
+        assert_eq!(get_path(&mut s), Err("get-path: no key \"a.c\"".into()));
+        assert_eq!(s.stack, vec![doc.clone(), Value::Str("a.c".into())]);
+        s.stack = vec![doc.clone(), Value::Str("a.b.1".into())];
+        assert_eq!(
+            get_path(&mut s),
+            Err("get-path: no index \"a.b.1\" (1 item)".into())
+        );
+        s.stack = vec![doc, Value::Str("a.b.0.x".into())];
+        assert_eq!(
+            get_path(&mut s),
+            Err("get-path: \"a.b.0\" is not a map or list".into())
+        );
+    }
+}
@@ -0,0 +1,312 @@
+use crate::builtins::system::pop_switch;
+use crate::outln;
+use crate::types::{State, Value};
+
+/// SGR sequence that clears all colors and attributes.
+const RESET: &str = "\x1b[0m";
+
+/// Basic color names, in ANSI order (30-37 foreground, 40-47 background).
+const COLOR_NAMES: &[&str] = &[
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+/// SGR parameters selecting a color, for foreground (`base` 30) or background (`base` 40).
+///
+/// Accepts a basic name ("red"), a bright name ("bright-red"), a 256-color
+/// index (0-255), or a "#rrggbb" hex triple.
+fn color_params(color: &Value, base: u8) -> Option<String> {
+    match color {
+        Value::Int(n) if (0..=255).contains(n) => Some(format!("{};5;{}", base + 8, n)),
+        Value::Str(name) => {
+            if let Some(hex) = name.strip_prefix('#') {
+                if hex.len() != 6 {
+                    return None;
+                }
+                let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+                let (r, g, b) = (channel(0)?, channel(2)?, channel(4)?);
+                return Some(format!("{};2;{};{};{}", base + 8, r, g, b));
+            }
+            let (bright, name) = match name.strip_prefix("bright-") {
+                Some(rest) => (true, rest),
+                None => (false, name.as_str()),
+            };
+            let index = COLOR_NAMES.iter().position(|c| *c == name)? as u8;
+            // Bright variants live at 90-97 / 100-107
+            let code = if bright {
+                base + 60 + index
+            } else {
+                base + index
+            };
+            Some(code.to_string())
+        }
+        _ => None,
+    }
+}
+
+/// Escape sequence for a theme style: space-separated colors (as accepted
+/// by `fg`) and the attributes "bold", "dim", and "underline".
+///
+/// An empty style gives an empty sequence; an unknown word gives `None`.
+pub(crate) fn style_sequence(spec: &str) -> Option<String> {
+    let mut params = Vec::new();
+    for part in spec.split_whitespace() {
+        let param = match part {
+            "bold" => "1".to_string(),
+            "dim" => "2".to_string(),
+            "underline" => "4".to_string(),
+            _ => {
+                let color = match part.parse::<i64>() {
+                    Ok(n) => Value::Int(n),
+                    Err(_) => Value::Str(part.to_string()),
+                };
+                color_params(&color, 30)?
+            }
+        };
+        params.push(param);
+    }
+    if params.is_empty() {
+        return Some(String::new());
+    }
+    Some(format!("\x1b[{}m", params.join(";")))
+}
+
+/// Push an escape sequence, or "" while colors are off.
+fn push_escape(state: &mut State, seq: &str) -> Result<(), String> {
+    let seq = if state.theme.enabled { seq } else { "" };
+    state.stack.push(Value::Str(seq.into()));
+    Ok(())
+}
+
+/// Pop a color and push the escape sequence selecting it.
+fn push_color(state: &mut State, op: &str, base: u8) -> Result<(), String> {
+    let color = state
+        .stack
+        .pop()
+        .ok_or_else(|| format!("{}: stack underflow", op))?;
+    match color_params(&color, base) {
+        Some(params) => push_escape(state, &format!("\x1b[{}m", params)),
+        None => {
+            state.stack.push(color);
+            Err(format!(
+                "{}: unknown color (use a name like \"red\", \"bright-red\", 0-255, or \"#rrggbb\")",
+                op
+            ))
+        }
+    }
+}
+
+/// `fg` ( color -- str ) Escape sequence setting the foreground color.
+pub fn fg(state: &mut State) -> Result<(), String> {
+    push_color(state, "fg", 30)
+}
+
+/// `bg` ( color -- str ) Escape sequence setting the background color.
+pub fn bg(state: &mut State) -> Result<(), String> {
+    push_color(state, "bg", 40)
+}
+
+/// `bold` ( -- str ) Escape sequence for bold text.
+pub fn bold(state: &mut State) -> Result<(), String> {
+    push_escape(state, "\x1b[1m")
+}
+
+/// `dim` ( -- str ) Escape sequence for dim text.
+pub fn dim(state: &mut State) -> Result<(), String> {
+    push_escape(state, "\x1b[2m")
+}
+
+/// `underline` ( -- str ) Escape sequence for underlined text.
+pub fn underline(state: &mut State) -> Result<(), String> {
+    push_escape(state, "\x1b[4m")
+}
+
+/// `reset-style` ( -- str ) Escape sequence clearing all colors and attributes.
+pub fn reset_style(state: &mut State) -> Result<(), String> {
+    push_escape(state, RESET)
+}
+
+/// `color-wrap` ( str color -- str ) Color a string and reset afterwards.
+///
+/// An empty string stays empty, so optional prompt pieces don't leave stray
+/// escape sequences behind. While colors are off the string is left as is.
+pub fn color_wrap(state: &mut State) -> Result<(), String> {
+    if state.stack.len() < 2 {
+        return Err("color-wrap: stack underflow".into());
+    }
+    let color = state.stack.pop().unwrap();
+    let text = match state.stack.pop().unwrap() {
+        Value::Str(s) => s,
+        other => {
+            state.stack.push(other);
+            state.stack.push(color);
+            return Err("color-wrap: requires string".into());
+        }
+    };
+    match wrap(&text, &color) {
+        Some(wrapped) => {
+            let result = if state.theme.enabled { wrapped } else { text };
+            state.stack.push(Value::Str(result));
+            Ok(())
+        }
+        None => {
+            state.stack.push(Value::Str(text));
+            state.stack.push(color);
+            Err("color-wrap: unknown color".into())
+        }
+    }
+}
+
+/// Wrap `text` in the foreground `color`, leaving empty text untouched.
+///
+/// Returns `None` for an unrecognized color.
+pub(crate) fn wrap(text: &str, color: &Value) -> Option<String> {
+    let params = color_params(color, 30)?;
+    if text.is_empty() {
+        return Some(String::new());
+    }
+    Some(format!("\x1b[{}m{}{}", params, text, RESET))
+}
+
+/// `theme` ( class style -- ) Set the color of a token class.
+///
+/// `style` is space-separated colors and attributes, e.g. "bold blue";
+/// "" leaves the class uncolored.
+pub fn theme(state: &mut State) -> Result<(), String> {
+    if state.stack.len() < 2 {
+        return Err("theme: stack underflow".into());
+    }
+    let n = state.stack.len();
+    let (Value::Str(class), Value::Str(spec)) = (&state.stack[n - 2], &state.stack[n - 1]) else {
+        return Err("theme: requires class and style strings".into());
+    };
+    state
+        .theme
+        .set(class, spec)
+        .map_err(|e| format!("theme: {}", e))?;
+    state.stack.truncate(n - 2);
+    Ok(())
+}
+
+/// `theme-list` ( -- ) Show each token class with its style, as a sample.
+pub fn theme_list(state: &mut State) -> Result<(), String> {
+    for (class, spec) in state.theme.entries() {
+        let spec = if spec.is_empty() { "-" } else { spec };
+        outln!(state, "{:<10} {}", class, state.theme.paint(class, spec));
+    }
+    if !state.theme.enabled {
+        outln!(state, "(colors are off)");
+    }
+    Ok(())
+}
+
+/// `set-color` ( flag -- ) Turn all colors on or off: "on"/"off" or 1/0.
+///
+/// Starts off when `NO_COLOR` is set or output isn't a terminal.
+pub fn set_color(state: &mut State) -> Result<(), String> {
+    state.theme.enabled = pop_switch(state, "set-color")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state_with(vals: Vec<Value>) -> State {
+        let mut s = State::new();
+        s.stack = vals;
+        s
+    }
+
+    #[test]
+    fn test_fg_named_colors() {
+        let mut s = state_with(vec![Value::Str("red".into())]);
+        fg(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::Str("\x1b[31m".into())]);
+
+        let mut s = state_with(vec![Value::Str("bright-cyan".into())]);
+        fg(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::Str("\x1b[96m".into())]);
+    }
+
+    #[test]
+    fn test_bg_256_and_truecolor() {
+        let mut s = state_with(vec![Value::Int(208)]);
+        bg(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::Str("\x1b[48;5;208m".into())]);
+
+        let mut s = state_with(vec![Value::Str("#ff8000".into())]);
+        fg(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::Str("\x1b[38;2;255;128;0m".into())]);
+    }
+
+    #[test]
+    fn test_unknown_color_keeps_stack() {
+        for bad in [
+            Value::Str("mauve".into()),
+            Value::Str("#12".into()),
+            Value::Int(256),
+        ] {
+            let mut s = state_with(vec![bad.clone()]);
+            assert!(fg(&mut s).is_err());
+            assert_eq!(s.stack, vec![bad]);
+        }
+    }
+
+    #[test]
+    fn test_color_wrap() {
+        let mut s = state_with(vec![Value::Str("main".into()), Value::Str("green".into())]);
+        color_wrap(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::Str("\x1b[32mmain\x1b[0m".into())]);
+    }
+
+    #[test]
+    fn test_color_wrap_empty_stays_empty() {
+        let mut s = state_with(vec![Value::Str("".into()), Value::Str("green".into())]);
+        color_wrap(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::Str("".into())]);
+    }
+
+    #[test]
+    fn test_colors_off_push_plain() {
+        let mut s = state_with(vec![Value::Str("red".into())]);
+        s.theme.enabled = false;
+        fg(&mut s).unwrap();
+        bold(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::Str("".into()), Value::Str("".into())]);
+
+        s.stack = vec![Value::Str("main".into()), Value::Str("green".into())];
+        color_wrap(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::Str("main".into())]);
+    }
+
+    #[test]
+    fn test_style_sequence() {
+        assert_eq!(style_sequence("bold red").as_deref(), Some("\x1b[1;31m"));
+        assert_eq!(style_sequence("208 underline").as_deref(), Some("\x1b[38;5;208;4m"));
+        assert_eq!(style_sequence("").as_deref(), Some(""));
+        assert_eq!(style_sequence("blink"), None);
+    }
+
+    #[test]
+    fn test_theme_word() {
+        let mut s = state_with(vec![Value::Str("keyword".into()), Value::Str("bold".into())]);
+        theme(&mut s).unwrap();
+        assert_eq!(s.theme.start("keyword"), "\x1b[1m");
+        assert!(s.stack.is_empty());
+
+        s.stack = vec![Value::Str("keyword".into()), Value::Str("mauve".into())];
+        assert_eq!(theme(&mut s), Err("theme: unknown style 'mauve'".into()));
+        assert_eq!(s.stack.len(), 2);
+    }
+
+    #[test]
+    fn test_attributes() {
+        let mut s = State::new();
+        bold(&mut s).unwrap();
+        reset_style(&mut s).unwrap();
+        assert_eq!(
+            s.stack,
+            vec![Value::Str("\x1b[1m".into()), Value::Str("\x1b[0m".into())]
+        );
+    }
+}
@@ -1,10 +1,30 @@
-use std::io::Write;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+#[cfg(feature = "native")]
+use std::os::unix::process::CommandExt;
 use std::process::{Command, Stdio};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
 
-use crate::types::{State, Value};
+use super::parallel;
+use crate::interrupt;
+use crate::local_rc;
+use crate::outln;
+use crate::types::{Origin, State, Value};
+
+/// Fail with "op: not allowed in restricted mode" once `set-restricted`
+/// (or `--restricted`) is on; called first by every word with side effects
+/// outside the shell.
+pub(crate) fn deny_restricted(state: &State, op: &str) -> Result<(), String> {
+    if state.restricted.is_some() {
+        return Err(format!("{}: not allowed in restricted mode", op));
+    }
+    Ok(())
+}
 
 /// Extract the short command name from a full path (e.g., "/usr/bin/grep" -> "grep").
-fn cmd_basename(cmd: &str) -> &str {
+pub(crate) fn cmd_basename(cmd: &str) -> &str {
     cmd.rsplit('/').next().unwrap_or(cmd)
 }
 
@@ -15,9 +35,13 @@ fn cmd_basename(cmd: &str) -> &str {
 /// - `Str` and `Int` values are collected as command arguments.
 /// - An `Int` immediately after the command name acts as a depth limit.
 pub fn exec_word(state: &mut State) -> Result<(), String> {
+    if let Some(Value::Str(cmd)) = state.stack.last() {
+        deny_restricted(state, cmd_basename(cmd))?;
+    }
+    let stdin_file = state.stdin_file.take();
     // Pop the command name
     let cmd = match state.stack.pop() {
-        Some(Value::Str(s)) => s,
+        Some(Value::Str(s)) => expand_tilde(&s),
         Some(other) => {
             state.stack.push(other);
             return Err("exec: top of stack must be a string (command name)".into());
@@ -53,7 +77,7 @@ pub fn exec_word(state: &mut State) -> Result<(), String> {
                     }
                     break;
                 }
-                cmd_args.push(s);
+                cmd_args.push(expand_tilde(&s));
                 count += 1;
             }
             Value::Int(n) => {
@@ -67,9 +91,18 @@ pub fn exec_word(state: &mut State) -> Result<(), String> {
                 cmd_args.push(n.to_string());
                 count += 1;
             }
-            Value::Output(s) => {
+            Value::Output(s, _) if stdin_file.is_none() => {
                 stdin_parts.push(s);
             }
+            Value::Output(_, _) | Value::Map(_) | Value::List(_) => {
+                // A map or list can't be an argument, and with `<stdin` an
+                // output isn't stdin; it and anything below stay put
+                remaining.push(val);
+                while let Some(v) = state.stack.pop() {
+                    remaining.push(v);
+                }
+                break;
+            }
         }
     }
 
@@ -85,75 +118,434 @@ pub fn exec_word(state: &mut State) -> Result<(), String> {
     let stdin_data: String = stdin_parts.into_iter().rev().collect();
     let has_stdin = !stdin_data.is_empty();
 
-    // Trace: show command details
-    if state.trace > 0 {
+    if state.dry_run {
+        let mut line = dry_run_line(&cmd, &cmd_args, stdin_data.len());
+        if let Some(path) = &stdin_file {
+            line.push_str(&format!(" < {}", sh_quote(&path.to_string_lossy())));
+        }
+        eprintln!("{}", line);
+        state.last_exit_code = 0;
+        let origin = Origin { command: cmd_basename(&cmd).to_string(), code: 0 };
+        state.stack.push(Value::Output(String::new(), Some(origin)));
+        return Ok(());
+    }
+
+    let command_line = std::iter::once(cmd_basename(&cmd).to_string())
+        .chain(cmd_args.iter().cloned())
+        .collect::<Vec<_>>()
+        .join(" ");
+    run_exec_hook(state, "$preexec", vec![Value::Str(command_line.clone())]);
+
+    // Trace: show command details (not when tracing to a file)
+    if state.trace > 0 && state.trace_file.is_none() {
         let name = cmd_basename(&cmd);
         let args_display = if cmd_args.is_empty() {
             name.to_string()
         } else {
             format!("{} {}", name, cmd_args.join(" "))
         };
+        let theme = &state.theme;
+        let exec = theme.paint("command", "exec");
+        let args_display = theme.paint("emphasis", &args_display);
         if has_stdin {
             eprintln!(
-                "  {:>28} \x1b[34mexec\x1b[0m [\x1b[1m{}\x1b[0m] \x1b[2mwith piped stdin\x1b[0m",
-                "", args_display
+                "  {:>28} {} [{}] {}",
+                "", exec, args_display, theme.paint("hint", "with piped stdin")
             );
         } else {
             eprintln!(
-                "  {:>28} \x1b[34mexec\x1b[0m [\x1b[1m{}\x1b[0m]",
-                "", args_display
+                "  {:>28} {} [{}]",
+                "", exec, args_display
             );
         }
         let _ = std::io::stderr().flush();
     }
 
     // Execute
-    let result = if has_stdin {
-        // Spawn with piped stdin
-        let child = Command::new(&cmd)
-            .args(&cmd_args)
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::inherit())
-            .spawn();
-
-        match child {
-            Ok(mut child) => {
-                // Write stdin data
-                if let Some(mut stdin) = child.stdin.take() {
-                    let data = stdin_data;
-                    // Write in a thread to avoid deadlock
-                    std::thread::spawn(move || {
-                        let _ = stdin.write_all(data.as_bytes());
-                    });
-                }
-                child
-                    .wait_with_output()
-                    .map_err(|e| format!("exec: {}", e))
-            }
-            Err(e) => Err(format!("exec: {}: {}", cmd, e)),
-        }
+    let stdin = if has_stdin {
+        StdinSource::Data(stdin_data)
+    } else if let Some(path) = stdin_file {
+        StdinSource::File(path)
     } else {
-        // Simple execution without stdin
-        Command::new(&cmd)
-            .args(&cmd_args)
-            .stdout(Stdio::piped())
-            .stderr(Stdio::inherit())
-            .output()
-            .map_err(|e| format!("exec: {}: {}", cmd, e))
+        StdinSource::None
     };
+    let stopwatch = Stopwatch::start();
+    let result = state.runner.run(&cmd, &cmd_args, stdin, state.merge_stderr);
+    if state.report_time.is_some_and(|secs| stopwatch.elapsed() >= Duration::from_secs(secs)) {
+        let report = format!("{}: {}", command_line, stopwatch.summary());
+        eprintln!("{}", state.theme.paint("hint", &report));
+    }
 
-    match result {
-        Ok(output) => {
-            state.last_exit_code = output.status.code().unwrap_or(128);
-            let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
-            state.stack.push(Value::Output(stdout));
+    let outcome = match result {
+        Ok(completed) => {
+            state.last_exit_code = completed.code;
+            let origin = Origin {
+                command: cmd_basename(&cmd).to_string(),
+                code: state.last_exit_code,
+            };
+            state.stack.push(Value::Output(completed.stdout, Some(origin)));
             Ok(())
         }
         Err(e) => {
             state.last_exit_code = 127;
             Err(e)
         }
+    };
+
+    let exit_code = Value::Int(state.last_exit_code as i64);
+    run_exec_hook(state, "$postexec", vec![Value::Str(command_line), exit_code]);
+    outcome
+}
+
+/// `sh-c` ( str -- output ) Run a string through `/bin/sh -c`, for one-liners
+/// that need sh's own pipes, redirects, or `&&` chains.
+///
+/// It runs like any command (hooks, `dry-run`, `<stdin`), and Outputs just
+/// below still become its stdin, but no other stack items are arguments.
+pub fn sh_c(state: &mut State) -> Result<(), String> {
+    deny_restricted(state, "sh-c")?;
+    let script = match state.stack.pop() {
+        Some(Value::Str(s)) => s,
+        Some(other) => {
+            state.stack.push(other);
+            return Err("sh-c: requires a string".into());
+        }
+        None => return Err("sh-c: stack underflow".into()),
+    };
+    state.stack.extend([
+        Value::Str("-c".into()),
+        Value::Str(script),
+        Value::Int(2),
+        Value::Str("/bin/sh".into()),
+    ]);
+    exec_word(state)
+}
+
+/// `spawn-detached` ( args... cmd -- pid ) Start a command in a session of
+/// its own and push its PID, without waiting for it.
+///
+/// It keeps running after yafsh exits, and hangups and Ctrl-C at the
+/// terminal don't reach it. Its stdin, stdout, and stderr are /dev/null.
+/// Arguments are taken as for `exec`, depth limit included, but an Output
+/// isn't piped in: it ends the arguments and stays on the stack.
+#[cfg(feature = "native")]
+pub fn spawn_detached(state: &mut State) -> Result<(), String> {
+    deny_restricted(state, "spawn-detached")?;
+    let cmd = match state.stack.pop() {
+        Some(Value::Str(s)) => expand_tilde(&s),
+        Some(other) => {
+            state.stack.push(other);
+            return Err("spawn-detached: top of stack must be a string (command name)".into());
+        }
+        None => return Err("spawn-detached: stack underflow".into()),
+    };
+    let depth_limit = match state.stack.last() {
+        Some(Value::Int(n)) => {
+            let n = *n;
+            state.stack.pop();
+            Some(n as usize)
+        }
+        _ => None,
+    };
+    let mut args = Vec::new();
+    while depth_limit.is_none_or(|limit| args.len() < limit) {
+        match state.stack.last() {
+            Some(Value::Str(s)) => args.push(expand_tilde(s)),
+            Some(Value::Int(n)) => args.push(n.to_string()),
+            _ => break,
+        }
+        state.stack.pop();
+    }
+    args.reverse();
+
+    if state.dry_run {
+        eprintln!("{} &", dry_run_line(&cmd, &args, 0));
+        state.stack.push(Value::Int(0));
+        return Ok(());
+    }
+
+    let mut command = Command::new(&cmd);
+    command
+        .args(&args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    // SAFETY: setsid is async-signal-safe, as required between fork and exec.
+    unsafe {
+        command.pre_exec(|| {
+            if libc::setsid() == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    let mut child = command
+        .spawn()
+        .map_err(|e| format!("spawn-detached: {}: {}", cmd, e))?;
+    state.stack.push(Value::Int(child.id() as i64));
+    // Reap it when it exits, so it doesn't linger as a zombie while we run
+    std::thread::spawn(move || {
+        let _ = child.wait();
+    });
+    Ok(())
+}
+
+/// Where an external command's stdin comes from.
+#[derive(Clone, Debug, PartialEq)]
+pub enum StdinSource {
+    None,
+    /// Outputs from the stack, written from a thread to avoid deadlock
+    Data(String),
+    /// A file from `<stdin`, streamed rather than read into memory
+    File(std::path::PathBuf),
+}
+
+/// One command of a batch run with [`CommandRunner::run_all`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Job {
+    pub cmd: String,
+    pub args: Vec<String>,
+    pub stdin: StdinSource,
+}
+
+/// A finished external command: its exit code and captured stdout.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Completed {
+    pub code: i32,
+    pub stdout: String,
+}
+
+/// How `exec` finds and runs external commands, kept in `State::runner`.
+///
+/// The shell uses [`SystemRunner`]; [`MockRunner`] answers from canned
+/// responses instead, so scripts can be evaluated without depending on
+/// PATH or spawning anything. `spawn-detached` always runs a real process.
+pub trait CommandRunner {
+    /// Resolve a bare word to the command to run, as PATH lookup does.
+    fn find(&self, cmd: &str) -> Option<String>;
+
+    /// Run a command and wait for it. With `merge_stderr`, stderr goes into
+    /// the captured text too.
+    fn run(
+        &mut self,
+        cmd: &str,
+        args: &[String],
+        stdin: StdinSource,
+        merge_stderr: bool,
+    ) -> Result<Completed, String>;
+
+    /// Run several commands (for `par-exec` and `peach`), returning their
+    /// results in the same order. One at a time unless the runner overrides it.
+    fn run_all(&mut self, jobs: Vec<Job>, merge_stderr: bool) -> Vec<Result<Completed, String>> {
+        jobs.into_iter()
+            .map(|job| self.run(&job.cmd, &job.args, job.stdin, merge_stderr))
+            .collect()
+    }
+}
+
+/// Runs commands as real child processes, found through PATH.
+pub struct SystemRunner;
+
+impl CommandRunner for SystemRunner {
+    fn find(&self, cmd: &str) -> Option<String> {
+        crate::eval::find_in_path(cmd)
+    }
+
+    fn run(
+        &mut self,
+        cmd: &str,
+        args: &[String],
+        stdin: StdinSource,
+        merge_stderr: bool,
+    ) -> Result<Completed, String> {
+        let output = run_external(cmd, args, stdin, merge_stderr)?;
+        Ok(Completed {
+            code: output.status.code().unwrap_or(128),
+            stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+        })
+    }
+
+    /// All at once, on a bounded pool of threads.
+    fn run_all(&mut self, jobs: Vec<Job>, merge_stderr: bool) -> Vec<Result<Completed, String>> {
+        parallel::run_pool(&jobs, |job| {
+            SystemRunner.run(&job.cmd, &job.args, job.stdin.clone(), merge_stderr)
+        })
+    }
+}
+
+/// One command run through a [`MockRunner`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct MockCall {
+    /// The command's name, without any directory
+    pub command: String,
+    pub args: Vec<String>,
+    pub stdin: StdinSource,
+}
+
+/// A [`CommandRunner`] that knows only the commands given to [`MockRunner::on`]
+/// and answers them without running anything, recording each call.
+///
+/// Commands are matched by name, so "/usr/bin/git" and "git" are the same;
+/// any other word is not a command, as if it weren't on PATH.
+#[derive(Default)]
+pub struct MockRunner {
+    responses: HashMap<String, Completed>,
+    calls: Rc<RefCell<Vec<MockCall>>>,
+}
+
+impl MockRunner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answer `cmd` with this exit code and stdout, whatever its arguments.
+    pub fn on(mut self, cmd: &str, code: i32, stdout: &str) -> Self {
+        let completed = Completed { code, stdout: stdout.to_string() };
+        self.responses.insert(cmd.to_string(), completed);
+        self
+    }
+
+    /// The calls made so far, oldest first. The log is shared, so it can be
+    /// kept to inspect after the runner is moved into a `State`.
+    pub fn calls(&self) -> Rc<RefCell<Vec<MockCall>>> {
+        Rc::clone(&self.calls)
+    }
+}
+
+impl CommandRunner for MockRunner {
+    fn find(&self, cmd: &str) -> Option<String> {
+        self.responses.contains_key(cmd_basename(cmd)).then(|| cmd.to_string())
+    }
+
+    fn run(
+        &mut self,
+        cmd: &str,
+        args: &[String],
+        stdin: StdinSource,
+        _merge_stderr: bool,
+    ) -> Result<Completed, String> {
+        let name = cmd_basename(cmd);
+        self.calls.borrow_mut().push(MockCall {
+            command: name.to_string(),
+            args: args.to_vec(),
+            stdin,
+        });
+        self.responses
+            .get(name)
+            .cloned()
+            .ok_or_else(|| format!("exec: {}: no such command", cmd))
+    }
+}
+
+/// Run an external command and wait for it, capturing its stdout.
+///
+/// With `merge_stderr`, stdout and stderr share one pipe, so the captured
+/// text has them interleaved in the order the command wrote them.
+fn run_external(
+    cmd: &str,
+    args: &[String],
+    stdin: StdinSource,
+    merge_stderr: bool,
+) -> Result<std::process::Output, String> {
+    let mut command = Command::new(cmd);
+    command.args(args);
+    let data = match stdin {
+        StdinSource::None => {
+            command.stdin(Stdio::null());
+            None
+        }
+        StdinSource::Data(data) => {
+            command.stdin(Stdio::piped());
+            Some(data)
+        }
+        StdinSource::File(path) => {
+            let file = std::fs::File::open(&path)
+                .map_err(|e| format!("exec: <stdin: {}: {}", path.display(), e))?;
+            command.stdin(Stdio::from(file));
+            None
+        }
+    };
+    let merged = if merge_stderr {
+        let (reader, writer) = std::io::pipe().map_err(|e| format!("exec: {}", e))?;
+        let writer2 = writer.try_clone().map_err(|e| format!("exec: {}", e))?;
+        command.stdout(writer).stderr(writer2);
+        Some(reader)
+    } else {
+        command.stdout(Stdio::piped()).stderr(Stdio::inherit());
+        None
+    };
+
+    let mut child = command.spawn().map_err(|e| format!("exec: {}: {}", cmd, e))?;
+    // The command holds our copies of the pipe's write end; reading would
+    // never see EOF while they are open
+    drop(command);
+    if let (Some(data), Some(mut stdin)) = (data, child.stdin.take()) {
+        std::thread::spawn(move || {
+            let _ = stdin.write_all(data.as_bytes());
+        });
+    }
+    match merged {
+        Some(mut reader) => {
+            let mut stdout = Vec::new();
+            reader.read_to_end(&mut stdout).map_err(|e| format!("exec: {}", e))?;
+            let status = child.wait().map_err(|e| format!("exec: {}", e))?;
+            Ok(std::process::Output { status, stdout, stderr: Vec::new() })
+        }
+        None => child.wait_with_output().map_err(|e| format!("exec: {}", e)),
+    }
+}
+
+/// What `dry-run` prints for a command: its resolved path and arguments,
+/// quoted as for `sh`, and how much stdin it would get.
+pub(crate) fn dry_run_line(cmd: &str, args: &[String], stdin_len: usize) -> String {
+    let path = if cmd.contains('/') {
+        cmd.to_string()
+    } else {
+        crate::eval::find_in_path(cmd).unwrap_or_else(|| cmd.to_string())
+    };
+    let mut line = std::iter::once(path.as_str())
+        .chain(args.iter().map(String::as_str))
+        .map(sh_quote)
+        .collect::<Vec<_>>()
+        .join(" ");
+    if stdin_len > 0 {
+        let unit = if stdin_len == 1 { "byte" } else { "bytes" };
+        line.push_str(&format!(" < ({} {} of stdin)", stdin_len, unit));
+    }
+    format!("dry-run: {}", line)
+}
+
+/// Quote a word for `sh` if it needs it.
+pub(crate) fn sh_quote(word: &str) -> String {
+    let plain = !word.is_empty()
+        && word
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c));
+    if plain {
+        word.to_string()
+    } else {
+        format!("'{}'", word.replace('\'', "'\\''"))
+    }
+}
+
+/// Run a user-defined `$preexec`/`$postexec` word, if there is one.
+///
+/// The hook sees only `args` on its stack, and neither its stack nor the exit
+/// codes of commands it runs leak back out. Commands run by a hook don't
+/// trigger hooks themselves, and hook errors are reported but not fatal.
+fn run_exec_hook(state: &mut State, hook: &str, args: Vec<Value>) {
+    if state.in_exec_hook || !state.dict.contains_key(hook) {
+        return;
+    }
+    let saved_stack = std::mem::replace(&mut state.stack, args);
+    let saved_exit_code = state.last_exit_code;
+    state.in_exec_hook = true;
+    let result = crate::eval::eval_token(state, hook, false);
+    state.in_exec_hook = false;
+    state.stack = saved_stack;
+    state.last_exit_code = saved_exit_code;
+    if let Err(e) = result {
+        eprintln!("{}: {}", hook, e);
     }
 }
 
@@ -163,19 +555,460 @@ pub fn exit_code(state: &mut State) -> Result<(), String> {
     Ok(())
 }
 
-/// `cd` ( path -- ) Change directory.
+/// The origin of the Output on top of the stack, which stays there.
+fn top_origin(state: &State, op: &str) -> Result<Origin, String> {
+    match state.stack.last() {
+        Some(Value::Output(_, Some(origin))) => Ok(origin.clone()),
+        Some(Value::Output(_, None)) => Err(format!("{}: output is not from a command", op)),
+        Some(_) => Err(format!("{}: requires output", op)),
+        None => Err(format!("{}: stack underflow", op)),
+    }
+}
+
+/// `out-status` ( output -- output code ) Exit code of the command that made
+/// an Output, unaffected by commands run since (unlike `?`).
+pub fn out_status(state: &mut State) -> Result<(), String> {
+    let origin = top_origin(state, "out-status")?;
+    state.stack.push(Value::Int(origin.code as i64));
+    Ok(())
+}
+
+/// `out-command` ( output -- output name ) Name of the command that made an
+/// Output.
+pub fn out_command(state: &mut State) -> Result<(), String> {
+    let origin = top_origin(state, "out-command")?;
+    state.stack.push(Value::Str(origin.command));
+    Ok(())
+}
+
+/// `exit` ( [code] -- ) Leave the shell with the Int on top of the stack as
+/// its status, or 0 without one.
+///
+/// Returns an error so the rest of the line (and any running words) stop;
+/// the REPL sees `exit_requested` and quits instead of reporting it.
+pub fn exit(state: &mut State) -> Result<(), String> {
+    let code = match state.stack.last() {
+        Some(&Value::Int(n)) => {
+            let code = i32::try_from(n).map_err(|_| format!("exit: status {} out of range", n))?;
+            state.stack.pop();
+            code
+        }
+        _ => 0,
+    };
+    state.exit_requested = Some(code);
+    Err("exit".into())
+}
+
+/// `cd` ( path -- ) Change directory. `"-" cd` returns to the previous directory.
 pub fn cd(state: &mut State) -> Result<(), String> {
     let val = state.stack.pop().ok_or("cd: stack underflow")?;
     match val {
+        Value::Str(path) if path == "-" => {
+            let previous = state.oldpwd.clone().ok_or("cd: OLDPWD not set")?;
+            change_dir(state, &previous, "cd")?;
+            outln!(state, "{}", previous);
+            Ok(())
+        }
         Value::Str(path) => {
             let expanded = expand_tilde(&path);
-            std::env::set_current_dir(&expanded)
-                .map_err(|e| format!("cd: {}: {}", expanded, e))
+            match resolve_cdpath(&expanded) {
+                Some(found) => {
+                    change_dir(state, &found, "cd")?;
+                    outln!(state, "{}", found);
+                    Ok(())
+                }
+                None => change_dir(state, &expanded, "cd"),
+            }
         }
         _ => Err("cd: requires string".into()),
     }
 }
 
+/// Look up a relative directory in $CDPATH, as bash does.
+///
+/// Only used when the path doesn't already exist relative to the current
+/// directory and doesn't start with `/`, `./` or `../`.
+fn resolve_cdpath(path: &str) -> Option<String> {
+    let first = path.split('/').next().unwrap_or("");
+    if path.starts_with('/') || first == "." || first == ".." {
+        return None;
+    }
+    if std::path::Path::new(path).is_dir() {
+        return None;
+    }
+    let cdpath = std::env::var("CDPATH").ok()?;
+    cdpath
+        .split(':')
+        .filter(|base| !base.is_empty())
+        .map(|base| std::path::Path::new(&expand_tilde(base)).join(path))
+        .find(|candidate| candidate.is_dir())
+        .map(|found| found.to_string_lossy().to_string())
+}
+
+/// `set-suggest` ( flag -- ) Enable ("on"/1) or disable ("off"/0) "did you mean" hints for unknown words.
+pub fn set_suggest(state: &mut State) -> Result<(), String> {
+    state.suggest = pop_switch(state, "set-suggest")?;
+    Ok(())
+}
+
+/// `set-coerce` ( flag -- ) Let arithmetic and comparisons read numbers from Str and Output values.
+pub fn set_coerce(state: &mut State) -> Result<(), String> {
+    state.coerce = pop_switch(state, "set-coerce")?;
+    Ok(())
+}
+
+/// `set-exit-status` ( flag -- ) Whether pipe mode exits with the last
+/// line's status (a failed command's code, or 1 after an error) instead of 0.
+pub fn set_exit_status(state: &mut State) -> Result<(), String> {
+    state.exit_status = pop_switch(state, "set-exit-status")?;
+    Ok(())
+}
+
+/// `set-errexit` ( flag -- ) Stop pipe mode and RC files at the first line
+/// that raises an error or whose last command exits non-zero.
+pub fn set_errexit(state: &mut State) -> Result<(), String> {
+    state.errexit = pop_switch(state, "set-errexit")?;
+    Ok(())
+}
+
+/// `set-glob-dotfiles` ( flag -- ) Whether globs match hidden names without a leading `.` in the pattern.
+pub fn set_glob_dotfiles(state: &mut State) -> Result<(), String> {
+    state.glob_dotfiles = pop_switch(state, "set-glob-dotfiles")?;
+    Ok(())
+}
+
+/// `set-pager` ( flag -- ) Whether Outputs taller than the terminal are
+/// shown through $PAGER in interactive mode.
+pub fn set_pager(state: &mut State) -> Result<(), String> {
+    state.pager = pop_switch(state, "set-pager")?;
+    Ok(())
+}
+
+/// Pop an on/off switch: "on"/"off" or an integer flag.
+pub(crate) fn pop_switch(state: &mut State, op: &str) -> Result<bool, String> {
+    match state.stack.pop() {
+        Some(Value::Str(s)) if s == "on" => Ok(true),
+        Some(Value::Str(s)) if s == "off" => Ok(false),
+        Some(Value::Int(n)) => Ok(n != 0),
+        Some(other) => {
+            state.stack.push(other);
+            Err(format!("{}: expected \"on\", \"off\", or integer flag", op))
+        }
+        None => Err(format!("{}: stack underflow", op)),
+    }
+}
+
+/// `set-restricted` ( flag -- ) Turn on restricted mode, for untrusted code.
+///
+/// Running commands, writing files, HTTP, and changing the environment all
+/// fail, and `cd` can't leave the directory restricted mode started in.
+/// There is no way back: turning it off is an error.
+pub fn set_restricted(state: &mut State) -> Result<(), String> {
+    let on = pop_switch(state, "set-restricted")?;
+    match (on, &state.restricted) {
+        (true, None) => restrict(state).map_err(|e| format!("set-restricted: {}", e)),
+        (false, Some(_)) => Err("set-restricted: restricted mode can't be turned off".into()),
+        _ => Ok(()),
+    }
+}
+
+/// Enter restricted mode, confining `cd` to the current directory.
+pub fn restrict(state: &mut State) -> Result<(), String> {
+    let cwd = std::env::current_dir().map_err(|e| e.to_string())?;
+    state.restricted = Some(std::fs::canonicalize(&cwd).unwrap_or(cwd));
+    Ok(())
+}
+
+/// `dry-run` ( flag -- ) Print external commands instead of running them.
+///
+/// Each command shows as its resolved path, arguments, and stdin size, and
+/// pushes an empty Output with exit code 0 so the rest of the line carries on.
+pub fn dry_run(state: &mut State) -> Result<(), String> {
+    state.dry_run = pop_switch(state, "dry-run")?;
+    Ok(())
+}
+
+/// `merge-stderr` ( flag -- ) Capture external commands' stderr along with
+/// their stdout, interleaved into one Output like `2>&1`.
+///
+/// Off by default, so error messages still reach the terminal.
+pub fn merge_stderr(state: &mut State) -> Result<(), String> {
+    state.merge_stderr = pop_switch(state, "merge-stderr")?;
+    Ok(())
+}
+
+/// `<stdin` ( filename -- ) Make the next external command read the file
+/// as its stdin. The file is streamed, not read into memory, so it can be
+/// any size. Outputs on the stack are left for later instead of piped in.
+/// Only lasts until the end of the line.
+pub fn stdin_from(state: &mut State) -> Result<(), String> {
+    let path = match state.stack.pop() {
+        Some(Value::Str(path)) => expand_tilde(&path),
+        Some(other) => {
+            state.stack.push(other);
+            return Err("<stdin: requires filename".into());
+        }
+        None => return Err("<stdin: stack underflow".into()),
+    };
+    let problem = match std::fs::metadata(&path) {
+        Ok(meta) if meta.is_dir() => Some("is a directory".to_string()),
+        Ok(_) => None,
+        Err(e) => Some(e.to_string()),
+    };
+    if let Some(problem) = problem {
+        state.stack.push(Value::Str(path.clone()));
+        return Err(format!("<stdin: {}: {}", path, problem));
+    }
+    state.stdin_file = Some(path.into());
+    Ok(())
+}
+
+/// `set-autocd` ( flag -- ) Enable ("on"/1) or disable ("off"/0) changing into bare directory names.
+pub fn set_autocd(state: &mut State) -> Result<(), String> {
+    state.autocd = pop_switch(state, "set-autocd")?;
+    Ok(())
+}
+
+/// `set-autosuggest` ( flag -- ) Suggest the rest of a matching history entry while typing.
+pub fn set_autosuggest(state: &mut State) -> Result<(), String> {
+    state.autosuggest = pop_switch(state, "set-autosuggest")?;
+    Ok(())
+}
+
+/// `set-stack-preview` ( flag -- ) Preview the stack the typed line would leave, below the input.
+pub fn set_stack_preview(state: &mut State) -> Result<(), String> {
+    state.stack_preview = pop_switch(state, "set-stack-preview")?;
+    Ok(())
+}
+
+/// `set-local-rc` ( flag -- ) Load allowed `.yafshrc.local` files from the current directory or its parents.
+pub fn set_local_rc(state: &mut State) -> Result<(), String> {
+    state.local_rc.enabled = pop_switch(state, "set-local-rc")?;
+    Ok(())
+}
+
+/// `local-allow` ( -- ) Trust the `.yafshrc.local` for the current directory as it is now.
+pub fn local_allow(state: &mut State) -> Result<(), String> {
+    deny_restricted(state, "local-allow")?;
+    let cwd = std::env::current_dir().map_err(|e| format!("local-allow: {}", e))?;
+    let path = local_rc::allow(state, &cwd).map_err(|e| format!("local-allow: {}", e))?;
+    outln!(state, "Allowed {}", path.display());
+    Ok(())
+}
+
+/// `local-deny` ( -- ) Stop trusting the `.yafshrc.local` for the current directory, unloading it.
+pub fn local_deny(state: &mut State) -> Result<(), String> {
+    deny_restricted(state, "local-deny")?;
+    let cwd = std::env::current_dir().map_err(|e| format!("local-deny: {}", e))?;
+    let path = local_rc::deny(state, &cwd).map_err(|e| format!("local-deny: {}", e))?;
+    outln!(state, "Denied {}", path.display());
+    Ok(())
+}
+
+/// `set-keymode` ( mode -- ) Use "vi" or "emacs" keybindings for line editing.
+pub fn set_keymode(state: &mut State) -> Result<(), String> {
+    match state.stack.pop() {
+        Some(Value::Str(mode)) if mode == "vi" || mode == "emacs" => {
+            state.vi_mode = mode == "vi";
+            Ok(())
+        }
+        Some(other) => {
+            state.stack.push(other);
+            Err("set-keymode: expected \"vi\" or \"emacs\"".into())
+        }
+        None => Err("set-keymode: stack underflow".into()),
+    }
+}
+
+/// `history-size` ( n -- ) Keep at most n history entries (read when the REPL starts).
+pub fn history_size(state: &mut State) -> Result<(), String> {
+    match state.stack.pop() {
+        Some(Value::Int(n)) if n > 0 => {
+            state.history_config.max_size = n as usize;
+            Ok(())
+        }
+        Some(other) => {
+            state.stack.push(other);
+            Err("history-size: requires positive integer".into())
+        }
+        None => Err("history-size: stack underflow".into()),
+    }
+}
+
+/// `set-max-iterations` ( n -- ) Cap how many times one loop may run
+/// before it fails (0 removes the cap).
+pub fn set_max_iterations(state: &mut State) -> Result<(), String> {
+    match state.stack.pop() {
+        Some(Value::Int(n)) if n >= 0 => {
+            state.max_iterations = n as u64;
+            Ok(())
+        }
+        Some(other) => {
+            state.stack.push(other);
+            Err("set-max-iterations: requires non-negative integer".into())
+        }
+        None => Err("set-max-iterations: stack underflow".into()),
+    }
+}
+
+/// `set-history-dedupe` ( flag -- ) Skip recording a line identical to the previous one.
+pub fn set_history_dedupe(state: &mut State) -> Result<(), String> {
+    state.history_config.dedupe = pop_switch(state, "set-history-dedupe")?;
+    Ok(())
+}
+
+/// `set-history-ignore-space` ( flag -- ) Skip recording lines typed with a leading space.
+pub fn set_history_ignore_space(state: &mut State) -> Result<(), String> {
+    state.history_config.ignore_space = pop_switch(state, "set-history-ignore-space")?;
+    Ok(())
+}
+
+/// `history-ignore` ( pattern -- ) Never record lines matching a glob pattern.
+pub fn history_ignore(state: &mut State) -> Result<(), String> {
+    match state.stack.pop() {
+        Some(Value::Str(pattern)) => {
+            state.history_config.ignore_patterns.push(pattern);
+            Ok(())
+        }
+        Some(other) => {
+            state.stack.push(other);
+            Err("history-ignore: requires string pattern".into())
+        }
+        None => Err("history-ignore: stack underflow".into()),
+    }
+}
+
+// ========== Sleeping ==========
+
+/// Pop a non-negative integer duration for `sleep`/`sleep-ms`.
+fn pop_duration(state: &mut State, op: &str) -> Result<u64, String> {
+    match state.stack.pop() {
+        Some(Value::Int(n)) if n >= 0 => Ok(n as u64),
+        Some(Value::Int(n)) => {
+            state.stack.push(Value::Int(n));
+            Err(format!("{}: duration must not be negative", op))
+        }
+        Some(other) => {
+            state.stack.push(other);
+            Err(format!("{}: requires integer", op))
+        }
+        None => Err(format!("{}: stack underflow", op)),
+    }
+}
+
+/// `sleep` ( seconds -- ) Pause for the given number of seconds.
+pub fn sleep(state: &mut State) -> Result<(), String> {
+    let secs = pop_duration(state, "sleep")?;
+    if !interrupt::sleep(Duration::from_secs(secs)) {
+        return Err("sleep: interrupted".into());
+    }
+    Ok(())
+}
+
+/// `sleep-ms` ( ms -- ) Pause for the given number of milliseconds.
+pub fn sleep_ms(state: &mut State) -> Result<(), String> {
+    let ms = pop_duration(state, "sleep-ms")?;
+    if !interrupt::sleep(Duration::from_millis(ms)) {
+        return Err("sleep-ms: interrupted".into());
+    }
+    Ok(())
+}
+
+/// CPU time used so far, user and system, by yafsh and the commands it
+/// has waited for.
+#[cfg(feature = "native")]
+fn cpu_time() -> (Duration, Duration) {
+    let timeval = |t: libc::timeval| {
+        Duration::from_secs(t.tv_sec as u64) + Duration::from_micros(t.tv_usec as u64)
+    };
+    let mut user = Duration::ZERO;
+    let mut sys = Duration::ZERO;
+    for who in [libc::RUSAGE_SELF, libc::RUSAGE_CHILDREN] {
+        // SAFETY: getrusage only writes a rusage struct into the pointer we pass.
+        let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+        if unsafe { libc::getrusage(who, &mut usage) } == 0 {
+            user += timeval(usage.ru_utime);
+            sys += timeval(usage.ru_stime);
+        }
+    }
+    (user, sys)
+}
+
+#[cfg(not(feature = "native"))]
+fn cpu_time() -> (Duration, Duration) {
+    (Duration::ZERO, Duration::ZERO)
+}
+
+/// Real and CPU time since a starting point, for `timed` and `set-report-time`.
+struct Stopwatch {
+    started: Instant,
+    user: Duration,
+    sys: Duration,
+}
+
+impl Stopwatch {
+    fn start() -> Self {
+        let (user, sys) = cpu_time();
+        Stopwatch { started: Instant::now(), user, sys }
+    }
+
+    fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// The times so far, e.g. "1.20s real  0.85s user  0.10s sys".
+    fn summary(&self) -> String {
+        let real = self.elapsed();
+        let (user, sys) = cpu_time();
+        format!(
+            "{:.2}s real  {:.2}s user  {:.2}s sys",
+            real.as_secs_f64(),
+            user.saturating_sub(self.user).as_secs_f64(),
+            sys.saturating_sub(self.sys).as_secs_f64()
+        )
+    }
+}
+
+/// `timed` ( ... body -- ... ) Run body (a string of code) on the stack as it
+/// is, then print how long it took: real time, and the CPU time yafsh and
+/// the commands it ran spent in user and system mode.
+pub fn timed(state: &mut State) -> Result<(), String> {
+    let body = match state.stack.pop() {
+        Some(Value::Str(body)) => body,
+        Some(other) => {
+            state.stack.push(other);
+            return Err("timed: body must be a string".into());
+        }
+        None => return Err("timed: stack underflow".into()),
+    };
+    let stopwatch = Stopwatch::start();
+    let result = crate::eval::eval_line(state, &body);
+    let report = format!("timed: {}", stopwatch.summary());
+    eprintln!("{}", state.theme.paint("hint", &report));
+    result
+}
+
+/// `set-report-time` ( seconds -- ) After any external command that runs at
+/// least this many seconds, print its real, user, and system time, like
+/// zsh's REPORTTIME. `"off"` stops the reports.
+pub fn set_report_time(state: &mut State) -> Result<(), String> {
+    match state.stack.pop() {
+        Some(Value::Int(n)) if n >= 0 => {
+            state.report_time = Some(n as u64);
+            Ok(())
+        }
+        Some(Value::Str(s)) if s == "off" => {
+            state.report_time = None;
+            Ok(())
+        }
+        Some(other) => {
+            state.stack.push(other);
+            Err("set-report-time: requires non-negative integer or \"off\"".into())
+        }
+        None => Err("set-report-time: stack underflow".into()),
+    }
+}
+
 // ========== Environment variables ==========
 
 /// `getenv` ( key -- value ) Get environment variable (empty string if unset).
@@ -196,6 +1029,7 @@ pub fn getenv(state: &mut State) -> Result<(), String> {
 
 /// `setenv` ( value key -- ) Set environment variable.
 pub fn setenv(state: &mut State) -> Result<(), String> {
+    deny_restricted(state, "setenv")?;
     if state.stack.len() < 2 {
         return Err("setenv: stack underflow".into());
     }
@@ -216,6 +1050,7 @@ pub fn setenv(state: &mut State) -> Result<(), String> {
 
 /// `unsetenv` ( key -- ) Unset environment variable.
 pub fn unsetenv(state: &mut State) -> Result<(), String> {
+    deny_restricted(state, "unsetenv")?;
     let val = state.stack.pop().ok_or("unsetenv: stack underflow")?;
     match val {
         Value::Str(key) => {
@@ -231,6 +1066,7 @@ pub fn unsetenv(state: &mut State) -> Result<(), String> {
 
 /// `env-append` ( value key -- ) Append value to colon-separated env var.
 pub fn env_append(state: &mut State) -> Result<(), String> {
+    deny_restricted(state, "env-append")?;
     if state.stack.len() < 2 {
         return Err("env-append: stack underflow".into());
     }
@@ -255,6 +1091,7 @@ pub fn env_append(state: &mut State) -> Result<(), String> {
 
 /// `env-prepend` ( value key -- ) Prepend value to colon-separated env var.
 pub fn env_prepend(state: &mut State) -> Result<(), String> {
+    deny_restricted(state, "env-prepend")?;
     if state.stack.len() < 2 {
         return Err("env-prepend: stack underflow".into());
     }
@@ -291,49 +1128,134 @@ pub fn env_all(state: &mut State) -> Result<(), String> {
 
 // ========== Directory navigation ==========
 
-/// `pushd` ( path -- ) Push current directory and change to path.
+/// Current directory as a string, for the directory stack and OLDPWD.
+fn current_dir_string(op: &str) -> Result<String, String> {
+    std::env::current_dir()
+        .map(|d| d.to_string_lossy().to_string())
+        .map_err(|e| format!("{}: {}", op, e))
+}
+
+/// Change directory, remembering where we came from as OLDPWD.
+fn change_dir(state: &mut State, target: &str, op: &str) -> Result<(), String> {
+    if let Some(root) = &state.restricted {
+        let dest = std::fs::canonicalize(target).map_err(|e| format!("{}: {}: {}", op, target, e))?;
+        if !dest.starts_with(root) {
+            return Err(format!(
+                "{}: {}: outside {} (restricted mode)",
+                op,
+                target,
+                root.display()
+            ));
+        }
+    }
+    let previous = current_dir_string(op).ok();
+    std::env::set_current_dir(target).map_err(|e| format!("{}: {}: {}", op, target, e))?;
+    if let Some(prev) = previous {
+        std::env::set_var("OLDPWD", &prev);
+        state.oldpwd = Some(prev);
+    }
+    if let Ok(now) = current_dir_string(op) {
+        std::env::set_var("PWD", now);
+    }
+    Ok(())
+}
+
+/// `pushd` ( path -- ) Push current dir and change to path.
+///
+/// With an empty stack, swaps the current directory with the top of the
+/// directory stack, like `pushd` with no arguments in bash.
 pub fn pushd(state: &mut State) -> Result<(), String> {
-    let val = state.stack.pop().ok_or("pushd: stack underflow")?;
-    match val {
-        Value::Str(path) => {
-            let current = std::env::current_dir()
-                .map_err(|e| format!("pushd: {}", e))?
-                .to_string_lossy()
-                .to_string();
-            let expanded = expand_tilde(&path);
-            std::env::set_current_dir(&expanded)
-                .map_err(|e| format!("pushd: {}: {}", expanded, e))?;
+    let current = current_dir_string("pushd")?;
+    match state.stack.pop() {
+        Some(Value::Str(path)) => {
+            change_dir(state, &expand_tilde(&path), "pushd")?;
             state.dir_stack.push(current);
             Ok(())
         }
-        other => {
+        Some(other) => {
             state.stack.push(other);
             Err("pushd: requires string".into())
         }
+        None => {
+            let target = state.dir_stack.pop().ok_or("pushd: no other directory")?;
+            if let Err(e) = change_dir(state, &target, "pushd") {
+                state.dir_stack.push(target);
+                return Err(e);
+            }
+            state.dir_stack.push(current);
+            Ok(())
+        }
     }
 }
 
-/// `popd` ( -- ) Pop directory from stack and change to it.
+/// `popd` ( -- ) Pop and change to directory from stack.
 pub fn popd(state: &mut State) -> Result<(), String> {
     let dir = state.dir_stack.pop().ok_or("popd: directory stack empty")?;
-    std::env::set_current_dir(&dir)
-        .map_err(|e| format!("popd: {}: {}", dir, e))
+    change_dir(state, &dir, "popd")
 }
 
-/// Expand `~` to $HOME at the start of a path.
-fn expand_tilde(path: &str) -> String {
-    if let Some(rest) = path.strip_prefix('~') {
-        if let Ok(home) = std::env::var("HOME") {
-            return format!("{}{}", home, rest);
+/// `dirs` ( -- ) Print the current directory followed by the directory stack, newest first.
+pub fn dirs(state: &mut State) -> Result<(), String> {
+    let home = std::env::var("HOME").ok();
+    let abbreviate = |dir: &str| {
+        let rest = home.as_deref().filter(|h| !h.is_empty()).and_then(|h| dir.strip_prefix(h));
+        match rest {
+            Some(rest) if rest.is_empty() || rest.starts_with('/') => format!("~{}", rest),
+            _ => dir.to_string(),
         }
+    };
+    let mut entries = vec![abbreviate(&current_dir_string("dirs")?)];
+    entries.extend(state.dir_stack.iter().rev().map(|d| abbreviate(d)));
+    outln!(state, "{}", entries.join(" "));
+    Ok(())
+}
+
+/// Expand a leading `~` to $HOME, or `~user` to that user's home directory.
+///
+/// Anything else, including `~name` for an unknown user, comes back as is.
+pub(crate) fn expand_tilde(path: &str) -> String {
+    let Some(rest) = path.strip_prefix('~') else {
+        return path.to_string();
+    };
+    let (user, tail) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+    let home = if user.is_empty() {
+        std::env::var("HOME").ok()
+    } else {
+        user_home(user)
+    };
+    match home {
+        Some(home) => format!("{}{}", home, tail),
+        None => path.to_string(),
+    }
+}
+
+/// A user's home directory from the password database.
+#[cfg(feature = "native")]
+fn user_home(user: &str) -> Option<String> {
+    let name = std::ffi::CString::new(user).ok()?;
+    let mut pwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut buf = vec![0 as libc::c_char; 4096];
+    let mut found: *mut libc::passwd = std::ptr::null_mut();
+    let rc = unsafe {
+        libc::getpwnam_r(name.as_ptr(), &mut pwd, buf.as_mut_ptr(), buf.len(), &mut found)
+    };
+    if rc != 0 || found.is_null() || pwd.pw_dir.is_null() {
+        return None;
     }
-    path.to_string()
+    let dir = unsafe { std::ffi::CStr::from_ptr(pwd.pw_dir) };
+    Some(dir.to_string_lossy().into_owned())
+}
+
+#[cfg(not(feature = "native"))]
+fn user_home(_user: &str) -> Option<String> {
+    None
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::builtins;
+    use crate::eval;
 
     fn new_state() -> State {
         let mut s = State::new();
@@ -349,7 +1271,7 @@ mod tests {
         exec_word(&mut s).unwrap();
         assert_eq!(s.last_exit_code, 0);
         match &s.stack[0] {
-            Value::Output(out) => assert_eq!(out.trim(), "hello"),
+            Value::Output(out, _) => assert_eq!(out.trim(), "hello"),
             other => panic!("expected Output, got {:?}", other),
         }
     }
@@ -357,14 +1279,14 @@ mod tests {
     #[test]
     fn test_exec_with_stdin() {
         let mut s = new_state();
-        s.stack.push(Value::Output("hello world\n".into()));
+        s.stack.push(Value::output("hello world\n"));
         s.stack.push(Value::Str("-c".into()));
         s.stack.push(Value::Str("/usr/bin/wc".into()));
         exec_word(&mut s).unwrap();
         assert_eq!(s.last_exit_code, 0);
         // wc -c counts bytes: "hello world\n" = 12
         match &s.stack[0] {
-            Value::Output(out) => {
+            Value::Output(out, _) => {
                 let n: i64 = out.trim().parse().unwrap();
                 assert_eq!(n, 12);
             }
@@ -386,6 +1308,41 @@ mod tests {
         assert!(exec_word(&mut s).is_err());
     }
 
+    #[test]
+    fn test_exec_with_mock_runner() {
+        let mut s = new_state();
+        let runner = MockRunner::new().on("git", 1, "main\n");
+        let calls = runner.calls();
+        s.runner = Box::new(runner);
+        s.stack = vec![
+            Value::output("in"),
+            Value::Str("status".into()),
+            Value::Str("/usr/bin/git".into()),
+        ];
+        exec_word(&mut s).unwrap();
+        assert_eq!(s.last_exit_code, 1);
+        let origin = Origin { command: "git".into(), code: 1 };
+        assert_eq!(s.stack, vec![Value::Output("main\n".into(), Some(origin))]);
+        assert_eq!(
+            *calls.borrow(),
+            vec![MockCall {
+                command: "git".into(),
+                args: vec!["status".into()],
+                stdin: StdinSource::Data("in".into()),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_mock_runner_unknown_command() {
+        let mut s = new_state();
+        s.runner = Box::new(MockRunner::new());
+        assert_eq!(s.runner.find("ls"), None);
+        s.stack = vec![Value::Str("ls".into())];
+        assert_eq!(exec_word(&mut s), Err("exec: ls: no such command".into()));
+        assert_eq!(s.last_exit_code, 127);
+    }
+
     #[test]
     fn test_exit_code() {
         let mut s = new_state();
@@ -404,6 +1361,24 @@ mod tests {
         assert_eq!(s.stack, vec![Value::Int(1)]);
     }
 
+    #[test]
+    fn test_exit_with_code() {
+        let mut s = new_state();
+        assert!(eval::eval_line(&mut s, "\"left\" 3 exit \"not reached\"").is_err());
+        assert_eq!(s.exit_requested, Some(3));
+        assert_eq!(s.stack, vec![Value::Str("left".into())]);
+    }
+
+    #[test]
+    fn test_exit_default_code() {
+        let mut s = new_state();
+        assert!(eval::eval_line(&mut s, "bye").is_err());
+        assert_eq!(s.exit_requested, Some(0));
+        let mut s = new_state();
+        assert!(eval::eval_line(&mut s, "99999999999 quit").is_err());
+        assert_eq!(s.exit_requested, None);
+    }
+
     #[test]
     fn test_cd_underflow() {
         let mut s = new_state();
@@ -418,11 +1393,41 @@ mod tests {
     }
 
     #[test]
+    fn test_sleep_ms() {
+        let mut s = new_state();
+        s.stack.push(Value::Int(20));
+        let start = std::time::Instant::now();
+        sleep_ms(&mut s).unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(20));
+        assert!(s.stack.is_empty());
+    }
+
+    #[test]
+    fn test_sleep_rejects_negative() {
+        let mut s = new_state();
+        s.stack.push(Value::Int(-1));
+        assert!(sleep(&mut s).is_err());
+        assert_eq!(s.stack, vec![Value::Int(-1)]);
+    }
+
+    #[test]
+    fn test_sleep_bad_type() {
+        let mut s = new_state();
+        s.stack.push(Value::Str("1".into()));
+        assert!(sleep_ms(&mut s).is_err());
+        assert_eq!(s.stack.len(), 1);
+    }
+
+    #[test]
+    #[cfg(feature = "native")]
     fn test_expand_tilde() {
         let home = std::env::var("HOME").unwrap();
         assert_eq!(expand_tilde("~/foo"), format!("{}/foo", home));
         assert_eq!(expand_tilde("/abs/path"), "/abs/path");
         assert_eq!(expand_tilde("relative"), "relative");
+        assert_eq!(expand_tilde("~"), home);
+        assert_eq!(expand_tilde("~root/x"), format!("{}/x", user_home("root").unwrap()));
+        assert_eq!(expand_tilde("~no-such-user-xyz/x"), "~no-such-user-xyz/x");
     }
 
     #[test]
@@ -438,7 +1443,7 @@ mod tests {
         assert_eq!(s.stack.len(), 2); // remaining "extra" + Output
         assert_eq!(s.stack[0], Value::Str("extra".into()));
         match &s.stack[1] {
-            Value::Output(out) => assert_eq!(out.trim(), "hello"),
+            Value::Output(out, _) => assert_eq!(out.trim(), "hello"),
             other => panic!("expected Output, got {:?}", other),
         }
     }
@@ -592,4 +1597,229 @@ mod tests {
         let mut s = new_state();
         assert!(popd(&mut s).is_err());
     }
+
+    #[test]
+    fn test_resolve_cdpath_skips_explicit_paths() {
+        assert_eq!(resolve_cdpath("/tmp"), None);
+        assert_eq!(resolve_cdpath("./x"), None);
+        assert_eq!(resolve_cdpath(".."), None);
+    }
+
+    #[test]
+    fn test_set_autocd() {
+        let mut s = new_state();
+        s.stack.push(Value::Str("on".into()));
+        set_autocd(&mut s).unwrap();
+        assert!(s.autocd);
+        s.stack.push(Value::Int(0));
+        set_autocd(&mut s).unwrap();
+        assert!(!s.autocd);
+        s.stack.push(Value::Str("maybe".into()));
+        assert!(set_autocd(&mut s).is_err());
+        assert_eq!(s.stack.len(), 1);
+    }
+
+    #[test]
+    fn test_set_keymode() {
+        let mut s = new_state();
+        s.stack.push(Value::Str("vi".into()));
+        set_keymode(&mut s).unwrap();
+        assert!(s.vi_mode);
+        s.stack.push(Value::Str("emacs".into()));
+        set_keymode(&mut s).unwrap();
+        assert!(!s.vi_mode);
+        s.stack.push(Value::Str("vim".into()));
+        assert!(set_keymode(&mut s).is_err());
+        assert_eq!(s.stack, vec![Value::Str("vim".into())]);
+    }
+
+    #[test]
+    fn test_history_settings() {
+        let mut s = new_state();
+        s.stack.push(Value::Int(500));
+        history_size(&mut s).unwrap();
+        s.stack.push(Value::Str("off".into()));
+        set_history_dedupe(&mut s).unwrap();
+        s.stack.push(Value::Str("on".into()));
+        set_history_ignore_space(&mut s).unwrap();
+        s.stack.push(Value::Str("exit".into()));
+        history_ignore(&mut s).unwrap();
+        assert_eq!(s.history_config.max_size, 500);
+        assert!(!s.history_config.dedupe);
+        assert!(s.history_config.ignore_space);
+        assert_eq!(s.history_config.ignore_patterns, vec!["exit".to_string()]);
+
+        s.stack.push(Value::Int(0));
+        assert!(history_size(&mut s).is_err());
+        assert_eq!(s.stack, vec![Value::Int(0)]);
+    }
+
+    #[test]
+    fn test_set_autosuggest() {
+        let mut s = new_state();
+        s.stack.push(Value::Str("on".into()));
+        set_autosuggest(&mut s).unwrap();
+        assert!(s.autosuggest);
+    }
+
+    #[test]
+    fn test_set_stack_preview() {
+        let mut s = new_state();
+        s.stack.push(Value::Int(1));
+        set_stack_preview(&mut s).unwrap();
+        assert!(s.stack_preview);
+    }
+
+    #[test]
+    fn test_set_suggest() {
+        let mut s = new_state();
+        assert!(!s.suggest);
+        s.stack.push(Value::Str("on".into()));
+        set_suggest(&mut s).unwrap();
+        assert!(s.suggest);
+        s.stack.push(Value::Str("off".into()));
+        set_suggest(&mut s).unwrap();
+        assert!(!s.suggest);
+    }
+
+    #[test]
+    fn test_set_max_iterations() {
+        let mut s = new_state();
+        s.stack.push(Value::Int(0));
+        set_max_iterations(&mut s).unwrap();
+        assert_eq!(s.max_iterations, 0);
+        s.stack.push(Value::Int(-1));
+        assert!(set_max_iterations(&mut s).is_err());
+        assert_eq!(s.stack, vec![Value::Int(-1)]);
+    }
+
+    #[test]
+    fn test_set_pager() {
+        let mut s = new_state();
+        assert!(s.pager);
+        s.stack.push(Value::Int(0));
+        set_pager(&mut s).unwrap();
+        assert!(!s.pager);
+    }
+
+    #[test]
+    fn test_dry_run_skips_command() {
+        let mut s = new_state();
+        let marker = std::env::temp_dir().join(format!("yafsh_dry_run_{}", std::process::id()));
+        s.stack.push(Value::Str("on".into()));
+        dry_run(&mut s).unwrap();
+        s.last_exit_code = 3;
+        s.stack.push(Value::Str(marker.display().to_string()));
+        s.stack.push(Value::Str("touch".into()));
+        exec_word(&mut s).unwrap();
+        assert!(!marker.exists());
+        let origin = Origin { command: "touch".into(), code: 0 };
+        assert_eq!(s.stack, vec![Value::Output(String::new(), Some(origin))]);
+        assert_eq!(s.last_exit_code, 0);
+    }
+
+    #[test]
+    fn test_set_restricted_is_one_way() {
+        let mut s = new_state();
+        s.stack.push(Value::Str("off".into()));
+        set_restricted(&mut s).unwrap();
+        assert!(s.restricted.is_none());
+        s.stack.push(Value::Str("on".into()));
+        set_restricted(&mut s).unwrap();
+        assert!(s.restricted.is_some());
+        s.stack.push(Value::Int(0));
+        assert_eq!(
+            set_restricted(&mut s),
+            Err("set-restricted: restricted mode can't be turned off".into())
+        );
+        assert!(s.restricted.is_some());
+    }
+
+    #[test]
+    fn test_restricted_denies_exec_setenv_and_cd_out() {
+        let mut s = new_state();
+        s.restricted = Some(std::env::temp_dir().join("yafsh_restricted_root"));
+        s.stack.push(Value::Str("echo".into()));
+        assert_eq!(
+            exec_word(&mut s),
+            Err("echo: not allowed in restricted mode".into())
+        );
+        assert_eq!(
+            eval::eval_line(&mut s, "\"v\" \"YAFSH_TEST_RESTRICTED\" setenv"),
+            Err("setenv: not allowed in restricted mode".into())
+        );
+        assert!(std::env::var_os("YAFSH_TEST_RESTRICTED").is_none());
+        let result = eval::eval_line(&mut s, "\"/\" cd");
+        assert!(result.unwrap_err().ends_with("(restricted mode)"));
+    }
+
+    #[test]
+    fn test_dry_run_line() {
+        let args = vec!["-n".to_string(), "it's here".to_string(), String::new()];
+        assert_eq!(
+            dry_run_line("/bin/echo", &args, 0),
+            "dry-run: /bin/echo -n 'it'\\''s here' ''"
+        );
+        assert_eq!(
+            dry_run_line("/bin/cat", &[], 1),
+            "dry-run: /bin/cat < (1 byte of stdin)"
+        );
+    }
+
+    #[test]
+    fn test_set_exit_status() {
+        let mut s = new_state();
+        assert!(s.exit_status);
+        s.stack.push(Value::Str("off".into()));
+        set_exit_status(&mut s).unwrap();
+        assert!(!s.exit_status);
+    }
+
+    #[test]
+    fn test_set_errexit_and_glob_dotfiles() {
+        let mut s = new_state();
+        assert!(!s.errexit);
+        assert!(s.glob_dotfiles);
+        s.stack.push(Value::Str("on".into()));
+        set_errexit(&mut s).unwrap();
+        s.stack.push(Value::Str("off".into()));
+        set_glob_dotfiles(&mut s).unwrap();
+        assert!(s.errexit);
+        assert!(!s.glob_dotfiles);
+    }
+
+    #[test]
+    fn test_timed_runs_body_on_stack() {
+        let mut s = new_state();
+        crate::eval::eval_line(&mut s, "3 \"dup *\" timed").unwrap();
+        assert_eq!(s.stack, vec![Value::Int(9)]);
+        // The body's error comes through
+        assert_eq!(
+            crate::eval::eval_line(&mut s, "\"drop drop\" timed"),
+            Err("drop: stack underflow".into())
+        );
+        s.stack = vec![Value::Int(1)];
+        assert_eq!(timed(&mut s), Err("timed: body must be a string".into()));
+        assert_eq!(s.stack, vec![Value::Int(1)]);
+    }
+
+    #[test]
+    fn test_set_report_time() {
+        let mut s = new_state();
+        assert_eq!(s.report_time, None);
+        crate::eval::eval_line(&mut s, "2 set-report-time").unwrap();
+        assert_eq!(s.report_time, Some(2));
+        crate::eval::eval_line(&mut s, "\"off\" set-report-time").unwrap();
+        assert_eq!(s.report_time, None);
+        s.stack = vec![Value::Int(-1)];
+        assert!(set_report_time(&mut s).is_err());
+        assert_eq!(s.stack, vec![Value::Int(-1)]);
+    }
+
+    #[test]
+    fn test_cd_dash_without_oldpwd() {
+        let mut s = new_state();
+        s.stack.push(Value::Str("-".into()));
+        assert!(cd(&mut s).is_err());
+    }
 }
@@ -0,0 +1,169 @@
+use crate::eval;
+use crate::outln;
+use crate::types::{State, Value};
+
+/// Prefix of the words `run-tests` runs.
+const TEST_PREFIX: &str = "test-";
+
+/// A value as it appears in assertion messages.
+fn describe(val: &Value) -> String {
+    match val {
+        Value::Str(s) => format!("\"{}\"", s),
+        Value::Int(n) => n.to_string(),
+        Value::Output(s, _) => format!("«{}»", s.trim_end()),
+        Value::Map(_) | Value::List(_) => val.to_string(),
+    }
+}
+
+/// `assert` ( flag msg -- ) Fail with `msg` unless the flag is non-zero.
+pub fn assert(state: &mut State) -> Result<(), String> {
+    if state.stack.len() < 2 {
+        return Err("assert: stack underflow".into());
+    }
+    let msg = state.stack.pop().unwrap();
+    let flag = state.stack.pop().unwrap();
+    match (&flag, &msg) {
+        (Value::Int(0), Value::Str(m)) => Err(format!("assert: {}", m)),
+        (Value::Int(_), Value::Str(_)) => Ok(()),
+        _ => {
+            state.stack.push(flag);
+            state.stack.push(msg);
+            Err("assert: requires integer flag and message string".into())
+        }
+    }
+}
+
+/// `assert=` ( actual expected -- ) Fail unless the two values are equal
+/// (same type and contents).
+pub fn assert_eq(state: &mut State) -> Result<(), String> {
+    if state.stack.len() < 2 {
+        return Err("assert=: stack underflow".into());
+    }
+    let expected = state.stack.pop().unwrap();
+    let actual = state.stack.pop().unwrap();
+    if actual == expected {
+        return Ok(());
+    }
+    Err(format!(
+        "assert=: expected {}, got {}",
+        describe(&expected),
+        describe(&actual)
+    ))
+}
+
+/// `run-tests` ( -- ) Run every word named `test-...` in name order, each on
+/// an empty stack, and report the results. `?` is 1 if any failed.
+///
+/// A test fails when it returns an error (e.g. from `assert`). If a test calls
+/// `exit`, it and the tests after it are reported as skipped.
+pub fn run_tests(state: &mut State) -> Result<(), String> {
+    let mut names: Vec<String> = state
+        .dict
+        .keys()
+        .filter(|name| name.starts_with(TEST_PREFIX))
+        .cloned()
+        .collect();
+    names.sort();
+
+    let saved = std::mem::take(&mut state.stack);
+    let mut passed = 0;
+    let mut failed = 0;
+    for name in &names {
+        let result = eval::eval_token(state, name, false);
+        state.stack.clear();
+        // A test that calls `exit` didn't finish; it and the rest are skipped
+        if state.exit_requested.is_some() {
+            break;
+        }
+        match result {
+            Ok(()) => {
+                outln!(state, "ok   {}", name);
+                passed += 1;
+            }
+            Err(e) => {
+                outln!(state, "FAIL {}: {}", name, e);
+                failed += 1;
+            }
+        }
+    }
+    state.stack = saved;
+
+    let skipped = names.len() - passed - failed;
+    if skipped > 0 {
+        outln!(state, "{} passed, {} failed, {} skipped", passed, failed, skipped);
+    } else {
+        outln!(state, "{} passed, {} failed", passed, failed);
+    }
+    state.last_exit_code = if failed > 0 || skipped > 0 { 1 } else { 0 };
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builtins;
+    use crate::sink::Sink;
+
+    fn new_state() -> State {
+        let mut s = State::new();
+        builtins::register_builtins(&mut s);
+        s
+    }
+
+    #[test]
+    fn test_assert() {
+        let mut s = new_state();
+        eval::eval_line(&mut s, "1 \"fine\" assert").unwrap();
+        let err = eval::eval_line(&mut s, "0 \"too small\" assert").unwrap_err();
+        assert_eq!(err, "assert: too small");
+        assert!(s.stack.is_empty());
+        assert!(eval::eval_line(&mut s, "\"x\" \"msg\" assert").is_err());
+        assert_eq!(s.stack.len(), 2);
+    }
+
+    #[test]
+    fn test_assert_eq() {
+        let mut s = new_state();
+        eval::eval_line(&mut s, "2 3 + 5 assert=").unwrap();
+        let err = eval::eval_line(&mut s, "\"5\" 5 assert=").unwrap_err();
+        assert_eq!(err, "assert=: expected 5, got \"5\"");
+        assert!(eval::eval_line(&mut s, "1 assert=").is_err());
+    }
+
+    #[test]
+    fn test_run_tests_counts_and_exit_code() {
+        let mut s = new_state();
+        s.out = Sink::capture();
+        eval::eval_line(&mut s, ": test-add 2 2 + 4 assert= ;").unwrap();
+        eval::eval_line(&mut s, ": helper 1 ;").unwrap();
+        s.stack.push(Value::Int(9));
+        run_tests(&mut s).unwrap();
+        assert_eq!(s.last_exit_code, 0);
+        assert_eq!(s.out.take_captured(), "ok   test-add\n1 passed, 0 failed\n");
+        // The caller's stack is left as it was
+        assert_eq!(s.stack, vec![Value::Int(9)]);
+
+        eval::eval_line(&mut s, ": test-bad 1 2 assert= ;").unwrap();
+        run_tests(&mut s).unwrap();
+        assert_eq!(s.last_exit_code, 1);
+        assert_eq!(
+            s.out.take_captured(),
+            "ok   test-add\nFAIL test-bad: assert=: expected 2, got 1\n1 passed, 1 failed\n"
+        );
+        assert_eq!(s.stack, vec![Value::Int(9)]);
+    }
+
+    #[test]
+    fn test_run_tests_reports_skipped_after_exit() {
+        let mut s = new_state();
+        s.out = Sink::capture();
+        eval::eval_line(&mut s, ": test-a 1 1 assert= ;").unwrap();
+        eval::eval_line(&mut s, ": test-b 0 exit ;").unwrap();
+        eval::eval_line(&mut s, ": test-c 1 1 assert= ;").unwrap();
+        run_tests(&mut s).unwrap();
+        let out = s.out.take_captured();
+        assert!(!out.contains("test-c"));
+        assert!(out.ends_with("1 passed, 0 failed, 2 skipped\n"), "{}", out);
+        assert_eq!(s.last_exit_code, 1);
+    }
+}
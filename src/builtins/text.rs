@@ -0,0 +1,1145 @@
+use crate::builtins::computation;
+use crate::tokenizer;
+use crate::types::{State, Value};
+
+// ========== Helpers ==========
+
+/// Run `f` on the Output and Int on top of the stack and replace them with
+/// its result. On any error the operands are left where they were.
+fn with_output_and_int(
+    state: &mut State,
+    op: &str,
+    f: impl FnOnce(&str, i64) -> Result<Value, String>,
+) -> Result<(), String> {
+    let len = state.stack.len();
+    if len < 2 {
+        return Err(format!("{}: stack underflow", op));
+    }
+    let result = match (&state.stack[len - 2], &state.stack[len - 1]) {
+        (Value::Output(s, _), Value::Int(n)) => f(s, *n)?,
+        _ => return Err(format!("{}: requires output and integer", op)),
+    };
+    state.stack.truncate(len - 2);
+    state.stack.push(result);
+    Ok(())
+}
+
+/// Pop a single Output from the stack.
+fn pop_output(state: &mut State, op: &str) -> Result<String, String> {
+    match state.stack.pop() {
+        Some(Value::Output(s, _)) => Ok(s),
+        Some(other) => {
+            state.stack.push(other);
+            Err(format!("{}: requires output", op))
+        }
+        None => Err(format!("{}: stack underflow", op)),
+    }
+}
+
+/// Join lines back into output text, one newline-terminated line each.
+fn join_lines<S: AsRef<str>>(lines: &[S]) -> String {
+    let mut result = String::new();
+    for line in lines {
+        result.push_str(line.as_ref());
+        result.push('\n');
+    }
+    result
+}
+
+/// Parse the leading number of a line the way `sort -n` does (non-numeric sorts as 0).
+fn leading_number(line: &str) -> f64 {
+    let trimmed = line.trim_start();
+    let end = trimmed
+        .char_indices()
+        .take_while(|&(i, c)| c.is_ascii_digit() || c == '.' || (i == 0 && c == '-'))
+        .map(|(i, c)| i + c.len_utf8())
+        .last()
+        .unwrap_or(0);
+    trimmed[..end].parse().unwrap_or(0.0)
+}
+
+/// Pick field n (1-based) of a line, splitting on whitespace or the given delimiter.
+///
+/// Missing fields yield an empty string, as in awk.
+fn pick_field<'a>(line: &'a str, n: usize, delim: Option<&str>) -> &'a str {
+    match delim {
+        Some(d) => line.split(d).nth(n - 1),
+        None => line.split_whitespace().nth(n - 1),
+    }
+    .unwrap_or("")
+}
+
+/// Validate a 1-based field number from the stack.
+fn field_number(n: i64, op: &str) -> Result<usize, String> {
+    if n < 1 {
+        return Err(format!("{}: field number must be 1 or greater", op));
+    }
+    Ok(n as usize)
+}
+
+/// Pop a Str delimiter from the stack (rejecting empty delimiters).
+fn pop_delimiter(state: &mut State, op: &str) -> Result<String, String> {
+    match state.stack.pop() {
+        Some(Value::Str(d)) if !d.is_empty() => Ok(d),
+        Some(Value::Str(d)) => {
+            state.stack.push(Value::Str(d));
+            Err(format!("{}: delimiter must not be empty", op))
+        }
+        Some(other) => {
+            state.stack.push(other);
+            Err(format!("{}: requires delimiter string", op))
+        }
+        None => Err(format!("{}: stack underflow", op)),
+    }
+}
+
+/// Align rows of fields into columns separated by two spaces (like `column -t`).
+///
+/// The last column is not padded, so lines carry no trailing whitespace.
+fn align_columns(rows: &[Vec<&str>]) -> String {
+    let mut widths: Vec<usize> = Vec::new();
+    for row in rows {
+        for (i, cell) in row.iter().enumerate() {
+            let w = computation::grapheme_count(cell);
+            if i == widths.len() {
+                widths.push(w);
+            } else if w > widths[i] {
+                widths[i] = w;
+            }
+        }
+    }
+
+    let mut result = String::new();
+    for row in rows {
+        for (i, cell) in row.iter().enumerate() {
+            if i + 1 == row.len() {
+                result.push_str(cell);
+            } else {
+                let pad = widths[i] - computation::grapheme_count(cell);
+                result.push_str(cell);
+                result.push_str(&" ".repeat(pad + 2));
+            }
+        }
+        result.push('\n');
+    }
+    result
+}
+
+/// One line of an edit script produced by `diff_lines`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum DiffOp {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// Compute a line-level edit script turning `a` into `b` (longest common subsequence).
+fn diff_lines<'a>(a: &[&'a str], b: &[&'a str]) -> Vec<(DiffOp, &'a str)> {
+    // lcs[i][j] = length of the LCS of a[i..] and b[j..]
+    let mut lcs = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut ops = Vec::with_capacity(a.len() + b.len());
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        if a[i] == b[j] {
+            ops.push((DiffOp::Equal, a[i]));
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            ops.push((DiffOp::Delete, a[i]));
+            i += 1;
+        } else {
+            ops.push((DiffOp::Insert, b[j]));
+            j += 1;
+        }
+    }
+    ops.extend(a[i..].iter().map(|l| (DiffOp::Delete, *l)));
+    ops.extend(b[j..].iter().map(|l| (DiffOp::Insert, *l)));
+    ops
+}
+
+/// Format a unified diff hunk range (`start,count`, count omitted when 1).
+fn hunk_range(start: usize, count: usize) -> String {
+    match count {
+        0 => format!("{},0", start),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{}", start + 1, count),
+    }
+}
+
+/// Render an edit script as a unified diff with `context` lines around each change.
+fn unified_diff(ops: &[(DiffOp, &str)], context: usize) -> String {
+    let changes: Vec<usize> = ops
+        .iter()
+        .enumerate()
+        .filter(|(_, (op, _))| *op != DiffOp::Equal)
+        .map(|(i, _)| i)
+        .collect();
+    if changes.is_empty() {
+        return String::new();
+    }
+
+    // Group nearby changes into hunks of [start, end) op indices
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for &c in &changes {
+        let start = c.saturating_sub(context);
+        let end = (c + context + 1).min(ops.len());
+        match hunks.last_mut() {
+            Some((_, last_end)) if start <= *last_end => *last_end = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    let mut result = String::from("--- a\n+++ b\n");
+    let (mut old_line, mut new_line, mut pos) = (0usize, 0usize, 0usize);
+    for (start, end) in hunks {
+        // Advance line counters to the hunk start
+        for (op, _) in &ops[pos..start] {
+            match op {
+                DiffOp::Equal => {
+                    old_line += 1;
+                    new_line += 1;
+                }
+                DiffOp::Delete => old_line += 1,
+                DiffOp::Insert => new_line += 1,
+            }
+        }
+        let hunk = &ops[start..end];
+        let old_count = hunk.iter().filter(|(op, _)| *op != DiffOp::Insert).count();
+        let new_count = hunk.iter().filter(|(op, _)| *op != DiffOp::Delete).count();
+        result.push_str(&format!(
+            "@@ -{} +{} @@\n",
+            hunk_range(old_line, old_count),
+            hunk_range(new_line, new_count)
+        ));
+        for (op, line) in hunk {
+            let marker = match op {
+                DiffOp::Equal => ' ',
+                DiffOp::Delete => '-',
+                DiffOp::Insert => '+',
+            };
+            result.push(marker);
+            result.push_str(line);
+            result.push('\n');
+        }
+        old_line += old_count;
+        new_line += new_count;
+        pos = end;
+    }
+    result
+}
+
+/// Convert a count from the stack to `usize`, rejecting negatives.
+fn non_negative(n: i64, op: &str) -> Result<usize, String> {
+    usize::try_from(n).map_err(|_| format!("{}: count must be non-negative", op))
+}
+
+// ========== Line selection ==========
+
+/// `head` ( output n -- output ) Keep the first n lines.
+pub fn head(state: &mut State) -> Result<(), String> {
+    with_output_and_int(state, "head", |content, n| {
+        let n = non_negative(n, "head")?;
+        let result: String = content.split_inclusive('\n').take(n).collect();
+        Ok(Value::output(result))
+    })
+}
+
+/// `tail` ( output n -- output ) Keep the last n lines.
+pub fn tail(state: &mut State) -> Result<(), String> {
+    with_output_and_int(state, "tail", |content, n| {
+        let n = non_negative(n, "tail")?;
+        let lines: Vec<&str> = content.split_inclusive('\n').collect();
+        let skip = lines.len().saturating_sub(n);
+        Ok(Value::output(lines[skip..].concat()))
+    })
+}
+
+/// `line-count` ( output -- n ) Count the lines in an output.
+pub fn line_count(state: &mut State) -> Result<(), String> {
+    let content = pop_output(state, "line-count")?;
+    state.stack.push(Value::Int(content.lines().count() as i64));
+    Ok(())
+}
+
+/// `nth-line` ( output n -- str ) Push line n (1-based) of an output.
+pub fn nth_line(state: &mut State) -> Result<(), String> {
+    with_output_and_int(state, "nth-line", |content, n| {
+        if n < 1 {
+            return Err("nth-line: line number must be 1 or greater".into());
+        }
+        let line = content
+            .lines()
+            .nth((n - 1) as usize)
+            .ok_or_else(|| format!("nth-line: output has no line {}", n))?;
+        Ok(Value::Str(line.to_string()))
+    })
+}
+
+// ========== Trimming and parsing ==========
+
+/// Pop the text of a Str or Output.
+pub(crate) fn pop_text(state: &mut State, op: &str) -> Result<String, String> {
+    match state.stack.pop() {
+        Some(Value::Str(s)) | Some(Value::Output(s, _)) => Ok(s),
+        Some(other) => {
+            state.stack.push(other);
+            Err(format!("{}: requires string or output", op))
+        }
+        None => Err(format!("{}: stack underflow", op)),
+    }
+}
+
+/// `chomp` ( output/str -- str ) Drop one trailing newline (`\n` or `\r\n`).
+pub fn chomp(state: &mut State) -> Result<(), String> {
+    let text = pop_text(state, "chomp")?;
+    let chomped = text
+        .strip_suffix("\r\n")
+        .or_else(|| text.strip_suffix('\n'))
+        .unwrap_or(&text);
+    state.stack.push(Value::Str(chomped.to_string()));
+    Ok(())
+}
+
+/// `trim` ( output/str -- str ) Drop leading and trailing whitespace.
+pub fn trim(state: &mut State) -> Result<(), String> {
+    let text = pop_text(state, "trim")?;
+    state.stack.push(Value::Str(text.trim().to_string()));
+    Ok(())
+}
+
+/// `out>int` ( output -- int ) Parse command output such as `wc -l`'s as a number.
+pub fn out_to_int(state: &mut State) -> Result<(), String> {
+    let content = pop_output(state, "out>int")?;
+    match tokenizer::parse_int(content.trim()) {
+        Some(n) => {
+            state.stack.push(Value::Int(n));
+            Ok(())
+        }
+        None => {
+            let e = format!("out>int: not a number: \"{}\"", content.trim());
+            state.stack.push(Value::output(content));
+            Err(e)
+        }
+    }
+}
+
+// ========== Sorting and deduplication ==========
+
+/// `sort-lines` ( output -- output ) Sort lines bytewise (no locale rules).
+pub fn sort_lines(state: &mut State) -> Result<(), String> {
+    let content = pop_output(state, "sort-lines")?;
+    let mut lines: Vec<&str> = content.lines().collect();
+    lines.sort();
+    state.stack.push(Value::output(join_lines(&lines)));
+    Ok(())
+}
+
+/// `sort-lines-num` ( output -- output ) Sort lines by their leading number.
+pub fn sort_lines_num(state: &mut State) -> Result<(), String> {
+    let content = pop_output(state, "sort-lines-num")?;
+    let mut lines: Vec<&str> = content.lines().collect();
+    lines.sort_by(|a, b| leading_number(a).total_cmp(&leading_number(b)).then_with(|| a.cmp(b)));
+    state.stack.push(Value::output(join_lines(&lines)));
+    Ok(())
+}
+
+/// `uniq-lines` ( output -- output ) Collapse adjacent duplicate lines.
+pub fn uniq_lines(state: &mut State) -> Result<(), String> {
+    let content = pop_output(state, "uniq-lines")?;
+    let mut lines: Vec<&str> = content.lines().collect();
+    lines.dedup();
+    state.stack.push(Value::output(join_lines(&lines)));
+    Ok(())
+}
+
+/// `count-uniq` ( output -- output ) Collapse adjacent duplicates, prefixing each with its count.
+pub fn count_uniq(state: &mut State) -> Result<(), String> {
+    let content = pop_output(state, "count-uniq")?;
+    let mut groups: Vec<(usize, &str)> = Vec::new();
+    for line in content.lines() {
+        match groups.last_mut() {
+            Some((count, last)) if *last == line => *count += 1,
+            _ => groups.push((1, line)),
+        }
+    }
+    let lines: Vec<String> = groups
+        .iter()
+        .map(|(count, line)| format!("{} {}", count, line))
+        .collect();
+    state.stack.push(Value::output(join_lines(&lines)));
+    Ok(())
+}
+
+// ========== Field extraction ==========
+
+/// Shared implementation of `field` and `field-by` ( line n -- str ).
+fn field_impl(state: &mut State, op: &str, delim: Option<&str>) -> Result<(), String> {
+    let len = state.stack.len();
+    if len < 2 {
+        return Err(format!("{}: stack underflow", op));
+    }
+    let f = match (&state.stack[len - 2], &state.stack[len - 1]) {
+        (Value::Str(line), Value::Int(n)) => {
+            let n = field_number(*n, op)?;
+            pick_field(line, n, delim).to_string()
+        }
+        _ => return Err(format!("{}: requires string and integer", op)),
+    };
+    state.stack.truncate(len - 2);
+    state.stack.push(Value::Str(f));
+    Ok(())
+}
+
+/// Shared implementation of `fields` and `fields-by` ( output n -- output ).
+fn fields_impl(state: &mut State, op: &str, delim: Option<&str>) -> Result<(), String> {
+    with_output_and_int(state, op, |content, n| {
+        let n = field_number(n, op)?;
+        let lines: Vec<&str> = content.lines().map(|l| pick_field(l, n, delim)).collect();
+        Ok(Value::output(join_lines(&lines)))
+    })
+}
+
+/// `field` ( line n -- str ) Pick whitespace-separated field n (1-based) of a line.
+pub fn field(state: &mut State) -> Result<(), String> {
+    field_impl(state, "field", None)
+}
+
+/// `fields` ( output n -- output ) Pick whitespace-separated field n of every line.
+pub fn fields(state: &mut State) -> Result<(), String> {
+    fields_impl(state, "fields", None)
+}
+
+/// `field-by` ( line n delim -- str ) Pick field n of a line split on delim.
+pub fn field_by(state: &mut State) -> Result<(), String> {
+    let delim = pop_delimiter(state, "field-by")?;
+    let result = field_impl(state, "field-by", Some(&delim));
+    if result.is_err() {
+        state.stack.push(Value::Str(delim));
+    }
+    result
+}
+
+/// `fields-by` ( output n delim -- output ) Pick field n of every line split on delim.
+pub fn fields_by(state: &mut State) -> Result<(), String> {
+    let delim = pop_delimiter(state, "fields-by")?;
+    let result = fields_impl(state, "fields-by", Some(&delim));
+    if result.is_err() {
+        state.stack.push(Value::Str(delim));
+    }
+    result
+}
+
+// ========== Table formatting ==========
+
+/// `table` ( output -- output ) Align whitespace-separated fields into columns.
+pub fn table(state: &mut State) -> Result<(), String> {
+    let content = pop_output(state, "table")?;
+    let rows: Vec<Vec<&str>> = content
+        .lines()
+        .map(|l| l.split_whitespace().collect())
+        .collect();
+    state.stack.push(Value::output(align_columns(&rows)));
+    Ok(())
+}
+
+/// `table-by` ( output delim -- output ) Align delimiter-separated fields into columns.
+pub fn table_by(state: &mut State) -> Result<(), String> {
+    let delim = pop_delimiter(state, "table-by")?;
+    let content = match pop_output(state, "table-by") {
+        Ok(content) => content,
+        Err(e) => {
+            state.stack.push(Value::Str(delim));
+            return Err(e);
+        }
+    };
+    let rows: Vec<Vec<&str>> = content
+        .lines()
+        .map(|l| l.split(delim.as_str()).collect())
+        .collect();
+    state.stack.push(Value::output(align_columns(&rows)));
+    Ok(())
+}
+
+/// `table-header` ( output -- output ) Highlight the first line of an output in bold.
+pub fn table_header(state: &mut State) -> Result<(), String> {
+    let content = pop_output(state, "table-header")?;
+    let theme = &state.theme;
+    let result = match content.split_once('\n') {
+        Some((first, rest)) => format!("{}\n{}", theme.paint("emphasis", first), rest),
+        None if content.is_empty() => content,
+        None => theme.paint("emphasis", &content),
+    };
+    state.stack.push(Value::output(result));
+    Ok(())
+}
+
+// ========== Formatting ==========
+
+/// One `{...}` placeholder of a `fmt` template.
+#[derive(Debug, PartialEq)]
+struct Placeholder {
+    /// Which argument, counting from the deepest one `fmt` takes
+    index: usize,
+    fill: char,
+    /// `<`, `>`, or `^`; `None` means numbers right, text left
+    align: Option<char>,
+    width: usize,
+    /// Longest text to show (longer values are cut)
+    precision: Option<usize>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Piece {
+    Text(String),
+    Arg(Placeholder),
+}
+
+/// Widest a placeholder may pad to, so a typo can't allocate gigabytes.
+const MAX_FMT_WIDTH: usize = 10_000;
+
+/// Parse the inside of a placeholder: `[index][:[[fill]align][0][width][.precision]]`.
+fn parse_placeholder(inner: &str, next: &mut usize) -> Option<Placeholder> {
+    let (index, spec) = inner.split_once(':').unwrap_or((inner, ""));
+    let index = if index.is_empty() {
+        *next += 1;
+        *next - 1
+    } else {
+        index.parse().ok()?
+    };
+    // The argument count is the largest index plus one
+    index.checked_add(1)?;
+    let mut p = Placeholder { index, fill: ' ', align: None, width: 0, precision: None };
+    let chars: Vec<char> = spec.chars().collect();
+    let mut i = 0;
+    let is_align = |c: char| matches!(c, '<' | '>' | '^');
+    if chars.len() >= 2 && is_align(chars[1]) {
+        p.fill = chars[0];
+        p.align = Some(chars[1]);
+        i = 2;
+    } else if chars.first().is_some_and(|&c| is_align(c)) {
+        p.align = Some(chars[0]);
+        i = 1;
+    }
+    if chars.get(i) == Some(&'0') && p.align.is_none() {
+        p.fill = '0';
+        p.align = Some('>');
+        i += 1;
+    }
+    let rest: String = chars[i..].iter().collect();
+    let (width, precision) = match rest.split_once('.') {
+        Some((w, prec)) => (w, Some(prec.parse().ok()?)),
+        None => (rest.as_str(), None),
+    };
+    if !width.is_empty() {
+        p.width = width.parse().ok().filter(|&w| w <= MAX_FMT_WIDTH)?;
+    }
+    p.precision = precision;
+    Some(p)
+}
+
+/// Split a template into literal text and placeholders. `{{` and `}}`
+/// stand for literal braces.
+fn parse_template(template: &str) -> Result<Vec<Piece>, String> {
+    let mut pieces = Vec::new();
+    let mut text = String::new();
+    let mut next = 0;
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                text.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                text.push('}');
+            }
+            '{' => {
+                let mut inner = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => inner.push(c),
+                        None => return Err("fmt: unclosed {".into()),
+                    }
+                }
+                let p = parse_placeholder(&inner, &mut next)
+                    .ok_or_else(|| format!("fmt: bad placeholder {{{}}}", inner))?;
+                pieces.push(Piece::Text(std::mem::take(&mut text)));
+                pieces.push(Piece::Arg(p));
+            }
+            '}' => return Err("fmt: unmatched }".into()),
+            c => text.push(c),
+        }
+    }
+    pieces.push(Piece::Text(text));
+    Ok(pieces)
+}
+
+/// Render one argument for a placeholder, padded to its width.
+fn render_arg(val: &Value, p: &Placeholder) -> String {
+    let text = match val {
+        Value::Output(s, _) => s.strip_suffix('\n').unwrap_or(s).to_string(),
+        _ => val.to_string(),
+    };
+    let text = match p.precision {
+        Some(max) => computation::grapheme_prefix(&text, max).to_string(),
+        None => text,
+    };
+    let len = computation::grapheme_count(&text);
+    if len >= p.width {
+        return text;
+    }
+    let pad = p.width - len;
+    let align = p.align.unwrap_or(if matches!(val, Value::Int(_)) { '>' } else { '<' });
+    let fill = |n: usize| p.fill.to_string().repeat(n);
+    match align {
+        '>' if p.fill == '0' && text.starts_with('-') => format!("-{}{}", fill(pad), &text[1..]),
+        '>' => format!("{}{}", fill(pad), text),
+        '^' => format!("{}{}{}", fill(pad / 2), text, fill(pad - pad / 2)),
+        _ => format!("{}{}", text, fill(pad)),
+    }
+}
+
+/// `fmt` ( args... template -- str ) Fill `{}` placeholders with the values
+/// below the template, in order. `{N}` picks an argument by position (from
+/// 0, the deepest), and `{:>8}`, `{:<8}`, `{:^8}`, `{:-<8}`, `{:05}`, and
+/// `{:.3}` set alignment, fill, width, and maximum length.
+pub fn fmt(state: &mut State) -> Result<(), String> {
+    let template = match state.stack.pop() {
+        Some(Value::Str(s)) => s,
+        Some(other) => {
+            state.stack.push(other);
+            return Err("fmt: template must be a string".into());
+        }
+        None => return Err("fmt: stack underflow".into()),
+    };
+    let pieces = match parse_template(&template) {
+        Ok(pieces) => pieces,
+        Err(e) => {
+            state.stack.push(Value::Str(template));
+            return Err(e);
+        }
+    };
+    let count = pieces
+        .iter()
+        .filter_map(|piece| match piece {
+            Piece::Arg(p) => Some(p.index + 1),
+            Piece::Text(_) => None,
+        })
+        .max()
+        .unwrap_or(0);
+    if state.stack.len() < count {
+        state.stack.push(Value::Str(template));
+        return Err(format!(
+            "fmt: template needs {} value{}",
+            count,
+            if count == 1 { "" } else { "s" }
+        ));
+    }
+    let args = state.stack.split_off(state.stack.len() - count);
+    let mut result = String::new();
+    for piece in &pieces {
+        match piece {
+            Piece::Text(text) => result.push_str(text),
+            Piece::Arg(p) => result.push_str(&render_arg(&args[p.index], p)),
+        }
+    }
+    state.stack.push(Value::Str(result));
+    Ok(())
+}
+
+// ========== Comparison ==========
+
+/// `diff` ( output1 output2 -- output ) Unified diff between two outputs.
+///
+/// Sets the exit code like diff(1): 0 if identical, 1 if different.
+pub fn diff(state: &mut State) -> Result<(), String> {
+    if state.stack.len() < 2 {
+        return Err("diff: stack underflow".into());
+    }
+    let new = state.stack.pop().unwrap();
+    let old = state.stack.pop().unwrap();
+    match (old, new) {
+        (Value::Output(old, _), Value::Output(new, _)) => {
+            let a: Vec<&str> = old.lines().collect();
+            let b: Vec<&str> = new.lines().collect();
+            let result = unified_diff(&diff_lines(&a, &b), 3);
+            state.last_exit_code = if result.is_empty() { 0 } else { 1 };
+            state.stack.push(Value::output(result));
+            Ok(())
+        }
+        (o, n) => {
+            state.stack.push(o);
+            state.stack.push(n);
+            Err("diff: requires two outputs".into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state_with(vals: Vec<Value>) -> State {
+        let mut s = State::new();
+        s.stack = vals;
+        s
+    }
+
+    fn output(s: &str) -> Value {
+        Value::output(s)
+    }
+
+    // ===== chomp / trim / out>int =====
+
+    #[test]
+    fn test_chomp() {
+        let mut s = state_with(vec![output("x\n\n")]);
+        chomp(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::Str("x\n".into())]);
+        let mut s = state_with(vec![Value::Str("y\r\n".into())]);
+        chomp(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::Str("y".into())]);
+        let mut s = state_with(vec![Value::Str("z".into())]);
+        chomp(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::Str("z".into())]);
+    }
+
+    #[test]
+    fn test_trim() {
+        let mut s = state_with(vec![output("  a b \n")]);
+        trim(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::Str("a b".into())]);
+        let mut s = state_with(vec![Value::Int(1)]);
+        assert!(trim(&mut s).is_err());
+        assert_eq!(s.stack, vec![Value::Int(1)]);
+    }
+
+    #[test]
+    fn test_out_to_int() {
+        let mut s = state_with(vec![output("      12\n")]);
+        out_to_int(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::Int(12)]);
+        let mut s = state_with(vec![output("12 file\n")]);
+        assert_eq!(
+            out_to_int(&mut s),
+            Err("out>int: not a number: \"12 file\"".into())
+        );
+        assert_eq!(s.stack, vec![output("12 file\n")]);
+        let mut s = state_with(vec![Value::Str("12".into())]);
+        assert!(out_to_int(&mut s).is_err());
+    }
+
+    // ===== head / tail =====
+
+    #[test]
+    fn test_head() {
+        let mut s = state_with(vec![output("a\nb\nc\n"), Value::Int(2)]);
+        head(&mut s).unwrap();
+        assert_eq!(s.stack, vec![output("a\nb\n")]);
+    }
+
+    #[test]
+    fn test_head_more_than_available() {
+        let mut s = state_with(vec![output("a\nb\n"), Value::Int(10)]);
+        head(&mut s).unwrap();
+        assert_eq!(s.stack, vec![output("a\nb\n")]);
+    }
+
+    #[test]
+    fn test_head_negative() {
+        let mut s = state_with(vec![output("a\n"), Value::Int(-1)]);
+        assert!(head(&mut s).is_err());
+        assert_eq!(s.stack, vec![output("a\n"), Value::Int(-1)]);
+        let mut s = state_with(vec![output("a\n"), Value::Int(-1)]);
+        assert!(tail(&mut s).is_err());
+        assert_eq!(s.stack, vec![output("a\n"), Value::Int(-1)]);
+    }
+
+    #[test]
+    fn test_head_wrong_type() {
+        let mut s = state_with(vec![Value::Str("a".into()), Value::Int(1)]);
+        assert!(head(&mut s).is_err());
+        assert_eq!(s.stack.len(), 2);
+    }
+
+    #[test]
+    fn test_tail() {
+        let mut s = state_with(vec![output("a\nb\nc\n"), Value::Int(2)]);
+        tail(&mut s).unwrap();
+        assert_eq!(s.stack, vec![output("b\nc\n")]);
+    }
+
+    #[test]
+    fn test_tail_no_trailing_newline() {
+        let mut s = state_with(vec![output("a\nb\nc"), Value::Int(1)]);
+        tail(&mut s).unwrap();
+        assert_eq!(s.stack, vec![output("c")]);
+    }
+
+    #[test]
+    fn test_tail_underflow() {
+        let mut s = state_with(vec![Value::Int(1)]);
+        assert!(tail(&mut s).is_err());
+    }
+
+    // ===== line-count / nth-line =====
+
+    #[test]
+    fn test_line_count() {
+        let mut s = state_with(vec![output("a\nb\nc\n")]);
+        line_count(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::Int(3)]);
+    }
+
+    #[test]
+    fn test_line_count_empty() {
+        let mut s = state_with(vec![output("")]);
+        line_count(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::Int(0)]);
+    }
+
+    #[test]
+    fn test_line_count_wrong_type() {
+        let mut s = state_with(vec![Value::Int(1)]);
+        assert!(line_count(&mut s).is_err());
+        assert_eq!(s.stack, vec![Value::Int(1)]);
+    }
+
+    #[test]
+    fn test_nth_line() {
+        let mut s = state_with(vec![output("a\nb\nc\n"), Value::Int(2)]);
+        nth_line(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::Str("b".into())]);
+    }
+
+    #[test]
+    fn test_nth_line_out_of_range() {
+        let mut s = state_with(vec![output("a\n"), Value::Int(3)]);
+        assert!(nth_line(&mut s).is_err());
+        assert_eq!(s.stack, vec![output("a\n"), Value::Int(3)]);
+    }
+
+    #[test]
+    fn test_nth_line_zero() {
+        let mut s = state_with(vec![output("a\n"), Value::Int(0)]);
+        assert!(nth_line(&mut s).is_err());
+        assert_eq!(s.stack.len(), 2);
+    }
+
+    // ===== sort / uniq =====
+
+    #[test]
+    fn test_sort_lines() {
+        let mut s = state_with(vec![output("b\nB\na\n")]);
+        sort_lines(&mut s).unwrap();
+        assert_eq!(s.stack, vec![output("B\na\nb\n")]);
+    }
+
+    #[test]
+    fn test_sort_lines_wrong_type() {
+        let mut s = state_with(vec![Value::Str("b\na".into())]);
+        assert!(sort_lines(&mut s).is_err());
+        assert_eq!(s.stack.len(), 1);
+    }
+
+    #[test]
+    fn test_sort_lines_num() {
+        let mut s = state_with(vec![output("10 ten\n9 nine\n-1 neg\nfoo\n")]);
+        sort_lines_num(&mut s).unwrap();
+        assert_eq!(s.stack, vec![output("-1 neg\nfoo\n9 nine\n10 ten\n")]);
+    }
+
+    #[test]
+    fn test_uniq_lines_adjacent_only() {
+        let mut s = state_with(vec![output("a\na\nb\na\n")]);
+        uniq_lines(&mut s).unwrap();
+        assert_eq!(s.stack, vec![output("a\nb\na\n")]);
+    }
+
+    #[test]
+    fn test_count_uniq() {
+        let mut s = state_with(vec![output("a\na\nb\n")]);
+        count_uniq(&mut s).unwrap();
+        assert_eq!(s.stack, vec![output("2 a\n1 b\n")]);
+    }
+
+    #[test]
+    fn test_count_uniq_empty() {
+        let mut s = state_with(vec![output("")]);
+        count_uniq(&mut s).unwrap();
+        assert_eq!(s.stack, vec![output("")]);
+    }
+
+    // ===== field extraction =====
+
+    #[test]
+    fn test_field_whitespace() {
+        let mut s = state_with(vec![Value::Str("  root   42  bash".into()), Value::Int(2)]);
+        field(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::Str("42".into())]);
+    }
+
+    #[test]
+    fn test_field_missing_is_empty() {
+        let mut s = state_with(vec![Value::Str("a b".into()), Value::Int(5)]);
+        field(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::Str("".into())]);
+    }
+
+    #[test]
+    fn test_field_zero() {
+        let mut s = state_with(vec![Value::Str("a b".into()), Value::Int(0)]);
+        assert!(field(&mut s).is_err());
+        assert_eq!(s.stack, vec![Value::Str("a b".into()), Value::Int(0)]);
+        let mut s = state_with(vec![
+            Value::Str("a:b".into()),
+            Value::Int(0),
+            Value::Str(":".into()),
+        ]);
+        assert!(field_by(&mut s).is_err());
+        assert_eq!(s.stack.len(), 3);
+        let mut s = state_with(vec![output("a b\n"), Value::Int(0)]);
+        assert!(fields(&mut s).is_err());
+        assert_eq!(s.stack, vec![output("a b\n"), Value::Int(0)]);
+    }
+
+    #[test]
+    fn test_field_wrong_type() {
+        let mut s = state_with(vec![output("a b"), Value::Int(1)]);
+        assert!(field(&mut s).is_err());
+        assert_eq!(s.stack.len(), 2);
+    }
+
+    #[test]
+    fn test_fields() {
+        let mut s = state_with(vec![output("a 1\nb 2\n"), Value::Int(2)]);
+        fields(&mut s).unwrap();
+        assert_eq!(s.stack, vec![output("1\n2\n")]);
+    }
+
+    #[test]
+    fn test_field_by() {
+        let mut s = state_with(vec![
+            Value::Str("root:x:0:0".into()),
+            Value::Int(3),
+            Value::Str(":".into()),
+        ]);
+        field_by(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::Str("0".into())]);
+    }
+
+    #[test]
+    fn test_field_by_keeps_empty_fields() {
+        let mut s = state_with(vec![
+            Value::Str("a,,c".into()),
+            Value::Int(3),
+            Value::Str(",".into()),
+        ]);
+        field_by(&mut s).unwrap();
+        assert_eq!(s.stack, vec![Value::Str("c".into())]);
+    }
+
+    #[test]
+    fn test_field_by_empty_delimiter() {
+        let mut s = state_with(vec![
+            Value::Str("abc".into()),
+            Value::Int(1),
+            Value::Str("".into()),
+        ]);
+        assert!(field_by(&mut s).is_err());
+        assert_eq!(s.stack.len(), 3);
+    }
+
+    #[test]
+    fn test_fields_by() {
+        let mut s = state_with(vec![
+            output("root:x:0\nbin:x:1\n"),
+            Value::Int(1),
+            Value::Str(":".into()),
+        ]);
+        fields_by(&mut s).unwrap();
+        assert_eq!(s.stack, vec![output("root\nbin\n")]);
+    }
+
+    // ===== table =====
+
+    #[test]
+    fn test_table_aligns_columns() {
+        let mut s = state_with(vec![output("NAME PID\nbash 1\nsshd 12345\n")]);
+        table(&mut s).unwrap();
+        assert_eq!(
+            s.stack,
+            vec![output("NAME  PID\nbash  1\nsshd  12345\n")]
+        );
+    }
+
+    #[test]
+    fn test_table_ragged_rows() {
+        let mut s = state_with(vec![output("a bb ccc\ndddd\n")]);
+        table(&mut s).unwrap();
+        assert_eq!(s.stack, vec![output("a     bb  ccc\ndddd\n")]);
+    }
+
+    #[test]
+    fn test_table_by() {
+        let mut s = state_with(vec![output("k,value\nlonger,v\n"), Value::Str(",".into())]);
+        table_by(&mut s).unwrap();
+        assert_eq!(s.stack, vec![output("k       value\nlonger  v\n")]);
+    }
+
+    #[test]
+    fn test_table_by_wrong_type() {
+        let mut s = state_with(vec![Value::Str("a,b".into()), Value::Str(",".into())]);
+        assert_eq!(table_by(&mut s), Err("table-by: requires output".into()));
+        assert_eq!(
+            s.stack,
+            vec![Value::Str("a,b".into()), Value::Str(",".into())]
+        );
+    }
+
+    #[test]
+    fn test_table_header() {
+        let mut s = state_with(vec![output("H\nrow\n")]);
+        table_header(&mut s).unwrap();
+        assert_eq!(s.stack, vec![output("\x1b[1mH\x1b[0m\nrow\n")]);
+    }
+
+    #[test]
+    fn test_table_header_empty() {
+        let mut s = state_with(vec![output("")]);
+        table_header(&mut s).unwrap();
+        assert_eq!(s.stack, vec![output("")]);
+    }
+
+    // ===== diff =====
+
+    #[test]
+    fn test_diff_identical() {
+        let mut s = state_with(vec![output("a\nb\n"), output("a\nb\n")]);
+        diff(&mut s).unwrap();
+        assert_eq!(s.stack, vec![output("")]);
+        assert_eq!(s.last_exit_code, 0);
+    }
+
+    #[test]
+    fn test_diff_changed_line() {
+        let mut s = state_with(vec![output("a\nb\nc\n"), output("a\nB\nc\n")]);
+        diff(&mut s).unwrap();
+        assert_eq!(
+            s.stack,
+            vec![output("--- a\n+++ b\n@@ -1,3 +1,3 @@\n a\n-b\n+B\n c\n")]
+        );
+        assert_eq!(s.last_exit_code, 1);
+    }
+
+    #[test]
+    fn test_diff_insert_into_empty() {
+        let mut s = state_with(vec![output(""), output("x\n")]);
+        diff(&mut s).unwrap();
+        assert_eq!(s.stack, vec![output("--- a\n+++ b\n@@ -0,0 +1 @@\n+x\n")]);
+    }
+
+    #[test]
+    fn test_diff_separate_hunks() {
+        let line = |n: i32| match n {
+            2 => "two\n".to_string(),
+            19 => "nineteen\n".to_string(),
+            _ => format!("{}\n", n),
+        };
+        let old: String = (1..=20).map(|n| format!("{}\n", n)).collect();
+        let new: String = (1..=20).map(line).collect();
+        let mut s = state_with(vec![output(&old), output(&new)]);
+        diff(&mut s).unwrap();
+        match &s.stack[0] {
+            Value::Output(d, _) => {
+                assert_eq!(d.matches("@@ -").count(), 2);
+                assert!(d.contains("@@ -1,5 +1,5 @@\n"));
+                assert!(d.contains("@@ -16,5 +16,5 @@\n"));
+            }
+            other => panic!("expected Output, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_diff_wrong_type() {
+        let mut s = state_with(vec![output("a"), Value::Str("a".into())]);
+        assert!(diff(&mut s).is_err());
+        assert_eq!(s.stack.len(), 2);
+    }
+
+    // ===== fmt =====
+
+    fn fmt_with(vals: Vec<Value>) -> Result<Value, String> {
+        let mut s = state_with(vals);
+        fmt(&mut s)?;
+        assert_eq!(s.stack.len(), 1);
+        Ok(s.stack.pop().unwrap())
+    }
+
+    #[test]
+    fn test_fmt_positional_and_auto() {
+        let got = fmt_with(vec![
+            Value::Str("web".into()),
+            Value::Int(3),
+            Value::Str("{} has {} ({0})".into()),
+        ]);
+        assert_eq!(got, Ok(Value::Str("web has 3 (web)".into())));
+    }
+
+    #[test]
+    fn test_fmt_width_and_alignment() {
+        let got = fmt_with(vec![
+            Value::Str("ab".into()),
+            Value::Int(42),
+            Value::Int(-7),
+            output("done\n"),
+            Value::Str("[{:6}][{:6}][{:04}][{:*^8}][{0:>4}][{3:.2}]".into()),
+        ]);
+        assert_eq!(
+            got,
+            Ok(Value::Str("[ab    ][    42][-007][**done**][  ab][do]".into()))
+        );
+    }
+
+    #[test]
+    fn test_fmt_braces_and_errors() {
+        assert_eq!(
+            fmt_with(vec![Value::Str("{{}}".into())]),
+            Ok(Value::Str("{}".into()))
+        );
+        let mut s = state_with(vec![Value::Int(1), Value::Str("{} {}".into())]);
+        assert_eq!(fmt(&mut s), Err("fmt: template needs 2 values".into()));
+        assert_eq!(s.stack, vec![Value::Int(1), Value::Str("{} {}".into())]);
+        let mut s = state_with(vec![Value::Str("{x}".into())]);
+        assert_eq!(fmt(&mut s), Err("fmt: bad placeholder {x}".into()));
+        let mut s = state_with(vec![Value::Str("{".into())]);
+        assert_eq!(fmt(&mut s), Err("fmt: unclosed {".into()));
+    }
+
+    #[test]
+    fn test_fmt_huge_index_and_width() {
+        let mut s = state_with(vec![Value::Str("{18446744073709551615}".into())]);
+        assert_eq!(fmt(&mut s), Err("fmt: bad placeholder {18446744073709551615}".into()));
+        assert_eq!(s.stack, vec![Value::Str("{18446744073709551615}".into())]);
+        let mut s = state_with(vec![Value::Int(1), Value::Str("{9999999999}".into())]);
+        assert_eq!(fmt(&mut s), Err("fmt: template needs 10000000000 values".into()));
+        let mut s = state_with(vec![Value::Int(1), Value::Str("{:99999999999}".into())]);
+        assert_eq!(fmt(&mut s), Err("fmt: bad placeholder {:99999999999}".into()));
+        assert_eq!(
+            fmt_with(vec![Value::Int(1), Value::Str("{:10000}".into())]).map(|v| v.to_string().len()),
+            Ok(10_000)
+        );
+    }
+}
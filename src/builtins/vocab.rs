@@ -0,0 +1,160 @@
+use crate::outln;
+use crate::types::{State, Value};
+
+/// Pop the name of an existing vocabulary.
+fn pop_vocab(state: &mut State, op: &str) -> Result<String, String> {
+    match state.stack.pop() {
+        Some(Value::Str(name)) if state.vocabularies.contains(&name) => Ok(name),
+        Some(Value::Str(name)) => {
+            let e = format!("{}: no vocabulary '{}'", op, name);
+            state.stack.push(Value::Str(name));
+            Err(e)
+        }
+        Some(other) => {
+            state.stack.push(other);
+            Err(format!("{}: requires vocabulary name", op))
+        }
+        None => Err(format!("{}: stack underflow", op)),
+    }
+}
+
+/// `vocabulary` ( name -- ) Create a vocabulary (a no-op if it exists).
+///
+/// Its words are reachable as `name:word`, or bare once `also` adds it to
+/// the search order.
+pub fn vocabulary(state: &mut State) -> Result<(), String> {
+    let name = match state.stack.pop() {
+        Some(Value::Str(s)) => s,
+        Some(other) => {
+            state.stack.push(other);
+            return Err("vocabulary: requires name string".into());
+        }
+        None => return Err("vocabulary: stack underflow".into()),
+    };
+    if name.is_empty() || name == "builtin" || name.contains(|c: char| c == ':' || c.is_whitespace()) {
+        let e = format!("vocabulary: invalid name '{}'", name);
+        state.stack.push(Value::Str(name));
+        return Err(e);
+    }
+    if !state.vocabularies.contains(&name) {
+        state.vocabularies.push(name);
+    }
+    Ok(())
+}
+
+/// `in` ( name -- ) Put the next `: ... ;` definition into a vocabulary.
+pub fn in_vocab(state: &mut State) -> Result<(), String> {
+    state.definition_vocab = Some(pop_vocab(state, "in")?);
+    Ok(())
+}
+
+/// `also` ( name -- ) Search a vocabulary first, so its words work unqualified.
+pub fn also(state: &mut State) -> Result<(), String> {
+    let name = pop_vocab(state, "also")?;
+    state.search_order.retain(|v| *v != name);
+    state.search_order.insert(0, name);
+    Ok(())
+}
+
+/// `previous` ( -- ) Drop the first vocabulary from the search order.
+pub fn previous(state: &mut State) -> Result<(), String> {
+    if state.search_order.is_empty() {
+        return Err("previous: search order is empty".into());
+    }
+    state.search_order.remove(0);
+    Ok(())
+}
+
+/// `order` ( -- ) Print the search order, first searched first.
+pub fn order(state: &mut State) -> Result<(), String> {
+    let mut names = state.search_order.clone();
+    names.push("(main)".to_string());
+    outln!(state, "{}", names.join(" "));
+    Ok(())
+}
+
+/// Split a dictionary key into its vocabulary and bare name, if it belongs
+/// to a declared vocabulary.
+pub fn split_qualified<'a>(state: &State, key: &'a str) -> Option<(&'a str, &'a str)> {
+    let (vocab, name) = key.split_once(':')?;
+    (!name.is_empty() && state.vocabularies.iter().any(|v| v == vocab)).then_some((vocab, name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builtins;
+    use crate::eval;
+    use crate::types::Word;
+
+    fn new_state() -> State {
+        let mut s = State::new();
+        builtins::register_builtins(&mut s);
+        s
+    }
+
+    #[test]
+    fn test_define_in_vocabulary() {
+        let mut s = new_state();
+        eval::eval_line(&mut s, "\"git\" vocabulary \"git\" in : status 1 ;").unwrap();
+        assert!(s.dict.contains_key("git:status"));
+        assert!(!s.dict.contains_key("status"));
+        // `in` only covers the next definition
+        eval::eval_line(&mut s, ": other 2 ;").unwrap();
+        assert!(s.dict.contains_key("other"));
+
+        eval::eval_line(&mut s, "git:status").unwrap();
+        assert_eq!(s.stack, vec![Value::Int(1)]);
+    }
+
+    #[test]
+    fn test_search_order() {
+        let mut s = new_state();
+        eval::eval_line(&mut s, "\"a\" vocabulary \"b\" vocabulary").unwrap();
+        eval::eval_line(&mut s, "\"a\" in : name \"a\" ;").unwrap();
+        eval::eval_line(&mut s, "\"b\" in : name \"b\" ;").unwrap();
+
+        // Not in the search order: the bare name is just a string
+        eval::eval_line(&mut s, "name").unwrap();
+        eval::eval_line(&mut s, "\"a\" also \"b\" also name").unwrap();
+        eval::eval_line(&mut s, "previous name").unwrap();
+        assert_eq!(
+            s.stack,
+            vec![
+                Value::Str("name".into()),
+                Value::Str("b".into()),
+                Value::Str("a".into())
+            ]
+        );
+        assert_eq!(s.search_order, vec!["a"]);
+    }
+
+    #[test]
+    fn test_vocabulary_shadows_main_dictionary() {
+        let mut s = new_state();
+        eval::eval_line(&mut s, "\"v\" vocabulary \"v\" in : dup 7 ; \"v\" also").unwrap();
+        eval::eval_line(&mut s, "dup").unwrap();
+        assert_eq!(s.stack, vec![Value::Int(7)]);
+        assert!(matches!(s.dict.get("dup"), Some(Word::Builtin(..))));
+    }
+
+    #[test]
+    fn test_unknown_vocabulary() {
+        let mut s = new_state();
+        let err = eval::eval_line(&mut s, "\"nope\" in").unwrap_err();
+        assert_eq!(err, "in: no vocabulary 'nope'");
+        assert_eq!(s.stack, vec![Value::Str("nope".into())]);
+        assert!(eval::eval_line(&mut s, "clear \"a:b\" vocabulary").is_err());
+        assert!(eval::eval_line(&mut s, "clear \"builtin\" vocabulary").is_err());
+        assert!(eval::eval_line(&mut s, "clear previous").is_err());
+    }
+
+    #[test]
+    fn test_split_qualified() {
+        let mut s = new_state();
+        s.vocabularies.push("git".into());
+        assert_eq!(split_qualified(&s, "git:status"), Some(("git", "status")));
+        assert_eq!(split_qualified(&s, "http:get"), None);
+        assert_eq!(split_qualified(&s, "git:"), None);
+    }
+}
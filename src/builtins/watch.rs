@@ -0,0 +1,209 @@
+use std::io::{IsTerminal, Write};
+use std::time::{Duration, SystemTime};
+
+use crate::eval;
+use crate::interrupt;
+use crate::types::{State, Value};
+
+/// How often `watch-path` checks the path for changes.
+const PATH_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Pop the body string (top) and the value beneath it for `watch`/`watch-path`.
+fn pop_body_and_arg(state: &mut State, op: &str) -> Result<(Value, String), String> {
+    if state.stack.len() < 2 {
+        return Err(format!("{}: stack underflow", op));
+    }
+    let body = match state.stack.pop().unwrap() {
+        Value::Str(b) => b,
+        other => {
+            state.stack.push(other);
+            return Err(format!("{}: body must be a string", op));
+        }
+    };
+    Ok((state.stack.pop().unwrap(), body))
+}
+
+/// Evaluate `body` on an empty stack and render what it leaves behind.
+///
+/// The caller's stack is untouched, so repeated runs don't accumulate values.
+/// Errors are rendered, except when the body called `exit`: that error is
+/// returned so the watch stops and the shell can quit.
+fn run_body(state: &mut State, body: &str) -> Result<String, String> {
+    let saved = std::mem::take(&mut state.stack);
+    let result = eval::eval_line(state, body);
+    let produced = std::mem::replace(&mut state.stack, saved);
+    if state.exit_requested.is_some() {
+        return Err(result.err().unwrap_or_else(|| "exit".into()));
+    }
+
+    let mut rendered = String::new();
+    for val in produced {
+        let text = val.to_string();
+        rendered.push_str(&text);
+        if !text.ends_with('\n') {
+            rendered.push('\n');
+        }
+    }
+    if let Err(e) = result {
+        rendered.push_str(&format!("Error: {}\n", e));
+    }
+    Ok(rendered)
+}
+
+/// Clear the screen (when attached to a terminal) and print one iteration.
+fn show(header: &str, rendered: &str) {
+    let mut out = std::io::stdout();
+    if out.is_terminal() {
+        print!("\x1b[2J\x1b[H");
+    }
+    print!("{}\n\n{}", header, rendered);
+    let _ = out.flush();
+}
+
+/// `watch` ( seconds body -- ) Re-run body every n seconds until Ctrl-C.
+pub fn watch(state: &mut State) -> Result<(), String> {
+    let (interval, body) = pop_body_and_arg(state, "watch")?;
+    let secs = match interval {
+        Value::Int(n) if n > 0 => n as u64,
+        other => {
+            state.stack.push(other);
+            state.stack.push(Value::Str(body));
+            return Err("watch: interval must be a positive integer".into());
+        }
+    };
+
+    let header = format!("Every {}s: {}", secs, body);
+    loop {
+        let rendered = run_body(state, &body)?;
+        show(&header, &rendered);
+        if !interrupt::sleep(Duration::from_secs(secs)) {
+            println!();
+            return Ok(());
+        }
+    }
+}
+
+/// Change signature of a path: its mtime, and for directories the newest
+/// mtime and number of its entries (so additions and removals count too).
+fn path_signature(path: &str) -> Option<(SystemTime, usize)> {
+    let meta = std::fs::metadata(path).ok()?;
+    let mut newest = meta.modified().ok()?;
+    let mut entries = 0;
+    if meta.is_dir() {
+        for entry in std::fs::read_dir(path).ok()?.flatten() {
+            entries += 1;
+            if let Ok(modified) = entry.metadata().and_then(|m| m.modified()) {
+                newest = newest.max(modified);
+            }
+        }
+    }
+    Some((newest, entries))
+}
+
+/// `watch-path` ( path body -- ) Run body now and again whenever path changes, until Ctrl-C.
+pub fn watch_path(state: &mut State) -> Result<(), String> {
+    let (target, body) = pop_body_and_arg(state, "watch-path")?;
+    let path = match target {
+        Value::Str(p) => p,
+        other => {
+            state.stack.push(other);
+            state.stack.push(Value::Str(body));
+            return Err("watch-path: requires path string".into());
+        }
+    };
+    let mut last = path_signature(&path)
+        .ok_or_else(|| format!("watch-path: {}: no such file or directory", path))?;
+
+    let header = format!("On change of {}: {}", path, body);
+    show(&header, &run_body(state, &body)?);
+    loop {
+        if !interrupt::sleep(PATH_POLL_INTERVAL) {
+            println!();
+            return Ok(());
+        }
+        // A path that briefly vanishes (editor save via rename) is not a change
+        if let Some(sig) = path_signature(&path) {
+            if sig != last {
+                last = sig;
+                show(&header, &run_body(state, &body)?);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builtins;
+
+    fn new_state() -> State {
+        let mut s = State::new();
+        builtins::register_builtins(&mut s);
+        s
+    }
+
+    #[test]
+    fn test_run_body_renders_results_and_keeps_stack() {
+        let mut s = new_state();
+        s.stack.push(Value::Int(7));
+        let rendered = run_body(&mut s, "1 2 + \"done\"").unwrap();
+        assert_eq!(rendered, "3\ndone\n");
+        assert_eq!(s.stack, vec![Value::Int(7)]);
+    }
+
+    #[test]
+    fn test_run_body_reports_errors() {
+        let mut s = new_state();
+        let rendered = run_body(&mut s, "drop").unwrap();
+        assert!(rendered.starts_with("Error: "));
+        assert!(s.stack.is_empty());
+    }
+
+    #[test]
+    fn test_watch_stops_on_exit() {
+        let mut s = new_state();
+        s.stack.push(Value::Int(1));
+        s.stack.push(Value::Str("3 exit".into()));
+        assert_eq!(watch(&mut s), Err("exit".to_string()));
+        assert_eq!(s.exit_requested, Some(3));
+        assert!(s.stack.is_empty());
+    }
+
+    #[test]
+    fn test_watch_rejects_bad_interval() {
+        let mut s = new_state();
+        s.stack.push(Value::Int(0));
+        s.stack.push(Value::Str("1".into()));
+        assert!(watch(&mut s).is_err());
+        assert_eq!(s.stack.len(), 2);
+    }
+
+    #[test]
+    fn test_watch_requires_string_body() {
+        let mut s = new_state();
+        s.stack.push(Value::Int(1));
+        s.stack.push(Value::Int(2));
+        assert!(watch(&mut s).is_err());
+        assert_eq!(s.stack, vec![Value::Int(1), Value::Int(2)]);
+    }
+
+    #[test]
+    fn test_watch_path_missing() {
+        let mut s = new_state();
+        s.stack.push(Value::Str("/nonexistent/yafsh_watch".into()));
+        s.stack.push(Value::Str("1".into()));
+        assert!(watch_path(&mut s).is_err());
+    }
+
+    #[test]
+    fn test_path_signature_tracks_directory_entries() {
+        let dir = std::env::temp_dir().join("yafsh_test_watch_sig");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir(&dir).unwrap();
+        let path = dir.to_string_lossy().to_string();
+        let before = path_signature(&path).unwrap();
+        std::fs::write(dir.join("new.txt"), "x").unwrap();
+        assert_ne!(path_signature(&path).unwrap(), before);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,239 @@
+use crate::eval;
+use crate::loops::{self, LoopBody};
+use crate::tokenizer::Token;
+use crate::types::{ControlFlow, State};
+
+/// One step of a defined word's compiled body.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Op {
+    /// A token, evaluated as typed (quoted ones are plain strings)
+    Token(Token),
+    /// A complete loop, split into its parts when the word was defined
+    Loop(Box<CompiledLoop>),
+}
+
+/// A loop from a definition, with the tokens it came from.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompiledLoop {
+    pub kind: LoopKind,
+    /// `begin` ... `until` (etc.) as written, replayed when the loop can't
+    /// run directly (see [`run`])
+    pub source: Vec<Token>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum LoopKind {
+    /// `begin ... until`
+    BeginUntil(Vec<Op>),
+    /// `begin ... again`
+    BeginAgain(Vec<Op>),
+    /// `begin ... while ... repeat`: the condition part and the body
+    BeginWhile(Vec<Op>, Vec<Op>),
+    /// `do ... loop` (or `?do`)
+    Do(Vec<Op>),
+    /// `do ... +loop`
+    DoPlus(Vec<Op>),
+}
+
+/// Compile a definition's tokens, resolving each complete loop once so
+/// calling the word doesn't collect and split loop bodies again.
+///
+/// Anything that isn't a well-formed loop (e.g. `repeat` without `while`)
+/// stays as plain tokens and behaves, and fails, as it would when typed.
+pub fn compile(tokens: &[Token]) -> Vec<Op> {
+    let mut ops = Vec::new();
+    let mut pos = 0;
+    while pos < tokens.len() {
+        match matching_end(tokens, pos).and_then(|end| compile_loop(&tokens[pos..=end])) {
+            Some(compiled) => {
+                pos += compiled.source.len();
+                ops.push(Op::Loop(Box::new(compiled)));
+            }
+            None => {
+                ops.push(Op::Token(tokens[pos].clone()));
+                pos += 1;
+            }
+        }
+    }
+    ops
+}
+
+/// Index of the keyword closing the loop opened at `start`, if it is one.
+fn matching_end(tokens: &[Token], start: usize) -> Option<usize> {
+    let mut open: Vec<&str> = Vec::new();
+    for (i, token) in tokens.iter().enumerate().skip(start) {
+        match token.keyword() {
+            "begin" => open.push("begin"),
+            "do" | "?do" => open.push("do"),
+            "until" | "repeat" | "again" if open.last() == Some(&"begin") => {
+                open.pop();
+            }
+            "loop" | "+loop" if open.last() == Some(&"do") => {
+                open.pop();
+            }
+            "until" | "repeat" | "again" | "loop" | "+loop" => return None,
+            _ if i == start => return None,
+            _ => {}
+        }
+        if open.is_empty() {
+            return Some(i);
+        }
+    }
+    None
+}
+
+/// Compile a loop spanning all of `source` (opener to closer).
+fn compile_loop(source: &[Token]) -> Option<CompiledLoop> {
+    let opener = source.first()?.keyword();
+    let closer = source.last()?.keyword();
+    let inner = &source[1..source.len() - 1];
+    let kind = match (opener, closer) {
+        ("begin", "until") if loops::top_level_while(inner).is_none() => {
+            LoopKind::BeginUntil(compile(inner))
+        }
+        ("begin", "again") if loops::top_level_while(inner).is_none() => {
+            LoopKind::BeginAgain(compile(inner))
+        }
+        ("begin", "repeat") => {
+            let at = loops::top_level_while(inner)?;
+            LoopKind::BeginWhile(compile(&inner[..at]), compile(&inner[at + 1..]))
+        }
+        ("do" | "?do", "loop") => LoopKind::Do(compile(inner)),
+        ("do" | "?do", "+loop") => LoopKind::DoPlus(compile(inner)),
+        _ => return None,
+    };
+    Some(CompiledLoop {
+        kind,
+        source: source.to_vec(),
+    })
+}
+
+/// Run a compiled body.
+///
+/// A loop runs directly only when evaluation is in its normal mode; while
+/// an `if` branch is being skipped, or an `each` body or definition is
+/// being collected, its source tokens are fed through instead.
+pub fn run(state: &mut State, ops: &[Op]) -> Result<(), String> {
+    for op in ops {
+        match op {
+            Op::Token(token) => eval::eval_token(state, &token.text, token.quoted)?,
+            Op::Loop(compiled) if evaluating_normally(state) => run_loop(state, &compiled.kind)?,
+            Op::Loop(compiled) => {
+                for token in &compiled.source {
+                    eval::eval_token(state, &token.text, token.quoted)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Whether tokens are being executed rather than collected or skipped.
+fn evaluating_normally(state: &State) -> bool {
+    state.collecting_each.is_none()
+        && state.collecting_loop.is_none()
+        && state.defining.is_none()
+        && matches!(state.control_flow, ControlFlow::Normal)
+}
+
+fn run_loop(state: &mut State, kind: &LoopKind) -> Result<(), String> {
+    match kind {
+        LoopKind::BeginUntil(body) => loops::execute_begin_until(state, body.as_slice()),
+        LoopKind::BeginAgain(body) => loops::execute_begin_again(state, body.as_slice()),
+        LoopKind::BeginWhile(before, after) => {
+            loops::execute_begin_while(state, before.as_slice(), after.as_slice())
+        }
+        LoopKind::Do(body) => {
+            let (start, limit) = loops::pop_bounds(state)?;
+            loops::execute_do_loop(state, start, limit, body.as_slice())
+        }
+        LoopKind::DoPlus(body) => {
+            let (start, limit) = loops::pop_bounds(state)?;
+            loops::execute_do_plus_loop(state, start, limit, body.as_slice())
+        }
+    }
+}
+
+impl LoopBody for [Op] {
+    fn run(&self, state: &mut State) -> Result<(), String> {
+        run(state, self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::tokenizer::tokenize;
+
+    fn tokens(src: &str) -> Vec<Token> {
+        tokenize(src)
+    }
+
+    fn token_ops(src: &str) -> Vec<Op> {
+        tokens(src).into_iter().map(Op::Token).collect()
+    }
+
+    #[test]
+    fn test_compile_plain_tokens() {
+        assert_eq!(compile(&tokens("dup * 1 +")), token_ops("dup * 1 +"));
+    }
+
+    #[test]
+    fn test_compile_nested_loops() {
+        let ops = compile(&tokens("0 begin 0 2 do i + loop 1 + dup 9 > until"));
+        assert_eq!(ops[0], Op::Token(Token::new("0", false)));
+        let Op::Loop(outer) = &ops[1] else {
+            panic!("expected a loop, got {:?}", ops[1]);
+        };
+        assert_eq!(outer.source.len(), 13);
+        let LoopKind::BeginUntil(body) = &outer.kind else {
+            panic!("expected begin/until, got {:?}", outer.kind);
+        };
+        assert_eq!(
+            body[2],
+            Op::Loop(Box::new(CompiledLoop {
+                kind: LoopKind::Do(token_ops("i +")),
+                source: tokens("do i + loop"),
+            }))
+        );
+    }
+
+    #[test]
+    fn test_compile_while_split_once() {
+        let ops = compile(&tokens("begin dup while 1 - repeat"));
+        let Op::Loop(compiled) = &ops[0] else {
+            panic!("expected a loop");
+        };
+        assert_eq!(
+            compiled.kind,
+            LoopKind::BeginWhile(token_ops("dup"), token_ops("1 -"))
+        );
+    }
+
+    #[test]
+    fn test_compile_begin_again() {
+        let ops = compile(&tokens("begin 1 + again"));
+        let Op::Loop(compiled) = &ops[0] else {
+            panic!("expected a loop");
+        };
+        assert_eq!(compiled.kind, LoopKind::BeginAgain(token_ops("1 +")));
+    }
+
+    #[test]
+    fn test_compile_leaves_malformed_loops_as_tokens() {
+        for src in ["begin 1 repeat", "do 1 until", "begin 1", "begin do until loop"] {
+            assert_eq!(compile(&tokens(src)), token_ops(src), "{}", src);
+        }
+    }
+
+    #[test]
+    fn test_compile_quoted_keywords_stay_tokens() {
+        assert_eq!(compile(&tokens("\"begin\" 1 until")), token_ops("\"begin\" 1 until"));
+        let ops = compile(&tokens("begin \"until\" until"));
+        let Op::Loop(compiled) = &ops[0] else {
+            panic!("expected a loop");
+        };
+        assert_eq!(compiled.kind, LoopKind::BeginUntil(token_ops("\"until\"")));
+    }
+}
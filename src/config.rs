@@ -1,17 +1,419 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use toml::Value as TomlValue;
+
+use crate::eval;
+use crate::types::State;
+
 /// Version string for the shell.
 pub const VERSION: &str = "0.5.0";
 
-/// Return the path to the RC configuration file (~/.yafshrc).
-pub fn rc_path() -> Option<std::path::PathBuf> {
-    dirs_or_home().map(|h| h.join(".yafshrc"))
+/// Return the path to the settings file (`config.toml` in [`config_dir`]).
+pub fn settings_path() -> Option<PathBuf> {
+    config_dir().map(|d| d.join("config.toml"))
+}
+
+/// Return the path to the RC configuration file.
+///
+/// `yafshrc` in [`config_dir`] when it exists (or `$YAFSH_CONFIG` is set),
+/// else the legacy `~/.yafshrc`.
+pub fn rc_path() -> Option<PathBuf> {
+    resolve_rc(&env_var)
+}
+
+/// Return the path to the history file.
+///
+/// `$YAFSH_HISTFILE` if set; else the legacy `~/.yafsh_history` when it
+/// exists; else `history` in `$XDG_DATA_HOME/yafsh` (default
+/// `~/.local/share/yafsh`).
+pub fn history_path() -> Option<PathBuf> {
+    resolve_history(&env_var)
+}
+
+/// Return the path to the history timing sidecar file: the history path
+/// with `_meta` appended (`~/.yafsh_history_meta` for the legacy file).
+pub fn history_meta_path() -> Option<PathBuf> {
+    history_path().map(|p| {
+        let mut name = p.into_os_string();
+        name.push("_meta");
+        PathBuf::from(name)
+    })
+}
+
+/// Return the directory holding `config.toml` and `yafshrc`:
+/// `$YAFSH_CONFIG` if set, else `$XDG_CONFIG_HOME/yafsh` (default
+/// `~/.config/yafsh`).
+pub fn config_dir() -> Option<PathBuf> {
+    resolve_config_dir(&env_var)
+}
+
+fn resolve_config_dir(env: &dyn Fn(&str) -> Option<String>) -> Option<PathBuf> {
+    if let Some(dir) = env("YAFSH_CONFIG") {
+        return Some(PathBuf::from(dir));
+    }
+    xdg_dir(env, "XDG_CONFIG_HOME", ".config").map(|d| d.join("yafsh"))
+}
+
+fn resolve_rc(env: &dyn Fn(&str) -> Option<String>) -> Option<PathBuf> {
+    let preferred = resolve_config_dir(env).map(|d| d.join("yafshrc"));
+    if env("YAFSH_CONFIG").is_some() || preferred.as_ref().is_some_and(|p| p.exists()) {
+        return preferred;
+    }
+    home(env).map(|h| h.join(".yafshrc"))
 }
 
-/// Return the path to the history file (~/.yafsh_history).
-pub fn history_path() -> Option<std::path::PathBuf> {
-    dirs_or_home().map(|h| h.join(".yafsh_history"))
+fn resolve_history(env: &dyn Fn(&str) -> Option<String>) -> Option<PathBuf> {
+    if let Some(file) = env("YAFSH_HISTFILE") {
+        return Some(PathBuf::from(file));
+    }
+    let legacy = home(env).map(|h| h.join(".yafsh_history"));
+    if legacy.as_ref().is_some_and(|p| p.exists()) {
+        return legacy;
+    }
+    resolve_data_dir(env).map(|d| d.join("history"))
+}
+
+/// Return the directory for state files: `$XDG_DATA_HOME/yafsh` (default
+/// `~/.local/share/yafsh`).
+pub fn data_dir() -> Option<PathBuf> {
+    resolve_data_dir(&env_var)
+}
+
+fn resolve_data_dir(env: &dyn Fn(&str) -> Option<String>) -> Option<PathBuf> {
+    xdg_dir(env, "XDG_DATA_HOME", ".local/share").map(|d| d.join("yafsh"))
+}
+
+/// Return the path to the list of allowed per-directory RC files.
+pub fn trusted_rc_path() -> Option<PathBuf> {
+    data_dir().map(|d| d.join("trusted_rc"))
+}
+
+/// Evaluate an RC file line by line, skipping blank lines and `#` comments.
+///
+/// Errors are reported with the file name and don't stop later lines,
+/// unless `set-errexit` is on.
+pub fn source_file(state: &mut State, path: &std::path::Path) {
+    if let Ok(contents) = std::fs::read_to_string(path) {
+        source_text(state, path, &contents);
+    }
+}
+
+/// [`source_file`] for RC text already read from `path`.
+pub fn source_text(state: &mut State, path: &std::path::Path, contents: &str) {
+    for line in contents.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        if let Err(e) = eval::eval_line(state, trimmed) {
+            if state.exit_requested.is_some() {
+                return;
+            }
+            eprintln!("{}: {}", path.display(), e);
+            if state.errexit {
+                return;
+            }
+        }
+    }
+}
+
+/// `$var` if it is an absolute path (as the XDG spec requires), else
+/// `fallback` under the home directory.
+fn xdg_dir(env: &dyn Fn(&str) -> Option<String>, var: &str, fallback: &str) -> Option<PathBuf> {
+    match env(var).map(PathBuf::from) {
+        Some(dir) if dir.is_absolute() => Some(dir),
+        _ => home(env).map(|h| h.join(fallback)),
+    }
 }
 
 /// Get the user's home directory from $HOME.
-fn dirs_or_home() -> Option<std::path::PathBuf> {
-    std::env::var("HOME").ok().map(std::path::PathBuf::from)
+fn home(env: &dyn Fn(&str) -> Option<String>) -> Option<PathBuf> {
+    env("HOME").map(PathBuf::from)
+}
+
+/// An environment variable, treating an empty value as unset.
+fn env_var(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|v| !v.is_empty())
+}
+
+/// Apply a settings file to the shell state.
+///
+/// A bad or unknown setting is skipped and reported, so one typo doesn't
+/// discard the rest. A syntax error stops everything. Returns the messages
+/// to show, empty when all went well.
+pub fn apply_settings(state: &mut State, text: &str) -> Vec<String> {
+    let table = match text.parse::<toml::Table>() {
+        Ok(table) => table,
+        Err(e) => return vec![syntax_error(text, &e)],
+    };
+    let mut entries = Vec::new();
+    flatten(String::new(), table, &mut entries);
+    entries
+        .into_iter()
+        .filter_map(|(key, value)| {
+            apply_setting(state, &key, &value)
+                .err()
+                .map(|e| format!("{}: {}", key, e))
+        })
+        .collect()
+}
+
+/// Collect the leaves of `table` as `(key, value)` pairs, where `key` is the
+/// full dotted path (`[history]` + `size` gives "history.size").
+fn flatten(prefix: String, table: toml::Table, entries: &mut Vec<(String, TomlValue)>) {
+    for (key, value) in table {
+        let key = if prefix.is_empty() {
+            key
+        } else {
+            format!("{}.{}", prefix, key)
+        };
+        match value {
+            TomlValue::Table(inner) => flatten(key, inner, entries),
+            value => entries.push((key, value)),
+        }
+    }
+}
+
+/// A parse error as "line N: message".
+fn syntax_error(text: &str, e: &toml::de::Error) -> String {
+    let message = e.message().trim_end();
+    match e.span() {
+        Some(span) => {
+            let line = text[..span.start.min(text.len())].matches('\n').count() + 1;
+            format!("line {}: {}", line, message)
+        }
+        None => message.to_string(),
+    }
+}
+
+/// Apply one `section.name = value` setting.
+fn apply_setting(state: &mut State, key: &str, value: &TomlValue) -> Result<(), String> {
+    match key {
+        "history.size" => {
+            state.history_config.max_size = match value {
+                TomlValue::Integer(n) if *n > 0 => *n as usize,
+                _ => return Err(expected("a positive integer", value)),
+            }
+        }
+        "history.dedupe" => state.history_config.dedupe = bool_setting(value)?,
+        "history.ignore_space" => state.history_config.ignore_space = bool_setting(value)?,
+        "history.ignore" => {
+            let TomlValue::Array(items) = value else {
+                return Err(expected("an array of strings", value));
+            };
+            for item in items {
+                state
+                    .history_config
+                    .ignore_patterns
+                    .push(str_setting(item)?);
+            }
+        }
+        "editing.keymode" => {
+            state.vi_mode = match str_setting(value)?.as_str() {
+                "vi" => true,
+                "emacs" => false,
+                _ => return Err("expected \"vi\" or \"emacs\"".into()),
+            }
+        }
+        "editing.autosuggest" => state.autosuggest = bool_setting(value)?,
+        "editing.stack_preview" => state.stack_preview = bool_setting(value)?,
+        "prompt.continuation" => state.continuation_prompt = Some(str_setting(value)?),
+        "prompt.separator" => state.segment_separator = str_setting(value)?,
+        "prompt.cmdtime_min" => {
+            state.cmdtime_min = match value {
+                TomlValue::Integer(n) if *n >= 0 => Duration::from_secs(*n as u64),
+                _ => return Err(expected("a non-negative integer", value)),
+            }
+        }
+        "shell.trace" => {
+            state.trace = match value {
+                TomlValue::Integer(n) if (0..=3).contains(n) => *n as u8,
+                TomlValue::Boolean(on) => 2 * u8::from(*on),
+                _ => return Err(expected("0-3 or a boolean", value)),
+            }
+        }
+        "shell.autocd" => state.autocd = bool_setting(value)?,
+        "shell.suggest" => state.suggest = bool_setting(value)?,
+        "shell.coerce" => state.coerce = bool_setting(value)?,
+        "shell.exit_status" => state.exit_status = bool_setting(value)?,
+        "shell.errexit" => state.errexit = bool_setting(value)?,
+        "shell.glob_dotfiles" => state.glob_dotfiles = bool_setting(value)?,
+        "shell.pager" => state.pager = bool_setting(value)?,
+        "shell.max_iterations" => {
+            state.max_iterations = match value {
+                TomlValue::Integer(n) if *n >= 0 => *n as u64,
+                _ => return Err(expected("a non-negative integer", value)),
+            }
+        }
+        "shell.report_time" => {
+            state.report_time = match value {
+                TomlValue::Integer(n) if *n >= 0 => Some(*n as u64),
+                TomlValue::Boolean(false) => None,
+                _ => return Err(expected("a non-negative integer or false", value)),
+            }
+        }
+        "shell.local_rc" => state.local_rc.enabled = bool_setting(value)?,
+        "theme.color" => state.theme.enabled = bool_setting(value)?,
+        _ => match key.strip_prefix("theme.") {
+            Some(class) => state.theme.set(class, &str_setting(value)?)?,
+            None => return Err("unknown setting".into()),
+        },
+    }
+    Ok(())
+}
+
+fn bool_setting(value: &TomlValue) -> Result<bool, String> {
+    match value {
+        TomlValue::Boolean(b) => Ok(*b),
+        _ => Err(expected("a boolean", value)),
+    }
+}
+
+fn str_setting(value: &TomlValue) -> Result<String, String> {
+    match value {
+        TomlValue::String(s) => Ok(s.clone()),
+        _ => Err(expected("a string", value)),
+    }
+}
+
+fn expected(what: &str, got: &TomlValue) -> String {
+    format!("expected {}, got {}", what, got.type_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_settings() {
+        let mut state = State::new();
+        let text = r#"
+[history]
+size = 500
+ignore = ["exit", "history*"]
+
+[editing]
+keymode = "vi"
+stack_preview = true
+
+[prompt]
+continuation = ".. "
+cmdtime_min = 10
+
+[shell]
+trace = 1
+report_time = 5
+errexit = true
+glob_dotfiles = false
+
+[theme]
+color = false
+keyword = "bold blue"
+"#;
+        assert_eq!(apply_settings(&mut state, text), Vec::<String>::new());
+        assert_eq!(state.history_config.max_size, 500);
+        assert_eq!(
+            state.history_config.ignore_patterns,
+            vec!["exit", "history*"]
+        );
+        assert!(state.vi_mode);
+        assert!(state.stack_preview);
+        assert_eq!(state.continuation_prompt.as_deref(), Some(".. "));
+        assert_eq!(state.cmdtime_min, Duration::from_secs(10));
+        assert_eq!(state.trace, 1);
+        assert_eq!(state.report_time, Some(5));
+        assert!(state.errexit);
+        assert!(!state.glob_dotfiles);
+        assert!(!state.theme.enabled);
+        state.theme.enabled = true;
+        assert_eq!(state.theme.start("keyword"), "\x1b[1;34m");
+    }
+
+    #[test]
+    fn test_bad_settings_are_reported_and_skipped() {
+        let mut state = State::new();
+        let text = "[history]\nsize = \"big\"\ndedupe = false\n[shell]\nautocdd = true\n[theme]\nfoo = \"red\"\n";
+        let errors = apply_settings(&mut state, text);
+        assert_eq!(
+            errors,
+            vec![
+                "history.size: expected a positive integer, got string".to_string(),
+                "shell.autocdd: unknown setting".to_string(),
+                "theme.foo: unknown class 'foo' (one of: string, number, keyword, word, unknown, match, hint, output, command, pop, push, emphasis)".to_string(),
+            ]
+        );
+        assert!(!state.history_config.dedupe);
+    }
+
+    /// An environment with only the given variables set.
+    fn env_of(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: Vec<(String, String)> = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        move |name| vars.iter().find(|(k, _)| k == name).map(|(_, v)| v.clone())
+    }
+
+    #[test]
+    fn test_xdg_and_override_paths() {
+        let env = env_of(&[("HOME", "/nonexistent/home")]);
+        assert_eq!(
+            resolve_config_dir(&env),
+            Some(PathBuf::from("/nonexistent/home/.config/yafsh"))
+        );
+        // Neither rc exists: the legacy path is used
+        assert_eq!(
+            resolve_rc(&env),
+            Some(PathBuf::from("/nonexistent/home/.yafshrc"))
+        );
+        assert_eq!(
+            resolve_history(&env),
+            Some(PathBuf::from(
+                "/nonexistent/home/.local/share/yafsh/history"
+            ))
+        );
+
+        let env = env_of(&[
+            ("HOME", "/nonexistent/home"),
+            ("XDG_CONFIG_HOME", "/x/config"),
+            ("XDG_DATA_HOME", "relative/ignored"),
+        ]);
+        assert_eq!(
+            resolve_config_dir(&env),
+            Some(PathBuf::from("/x/config/yafsh"))
+        );
+        assert_eq!(
+            resolve_history(&env),
+            Some(PathBuf::from(
+                "/nonexistent/home/.local/share/yafsh/history"
+            ))
+        );
+
+        let env = env_of(&[("YAFSH_CONFIG", "/t/cfg"), ("YAFSH_HISTFILE", "/t/hist")]);
+        assert_eq!(resolve_rc(&env), Some(PathBuf::from("/t/cfg/yafshrc")));
+        assert_eq!(resolve_history(&env), Some(PathBuf::from("/t/hist")));
+    }
+
+    #[test]
+    fn test_existing_files_win() {
+        let root = std::env::temp_dir().join(format!("yafsh_config_{}", std::process::id()));
+        let home = root.join("home");
+        std::fs::create_dir_all(home.join(".config/yafsh")).unwrap();
+        std::fs::write(home.join(".config/yafsh/yafshrc"), "").unwrap();
+        std::fs::write(home.join(".yafsh_history"), "").unwrap();
+        let env = env_of(&[("HOME", home.to_str().unwrap())]);
+
+        assert_eq!(resolve_rc(&env), Some(home.join(".config/yafsh/yafshrc")));
+        assert_eq!(resolve_history(&env), Some(home.join(".yafsh_history")));
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_syntax_error_stops() {
+        let mut state = State::new();
+        let errors = apply_settings(&mut state, "[history]\nsize = 5\nnope\n");
+        assert_eq!(errors, vec!["line 3: key with no value, expected `=`".to_string()]);
+        assert_eq!(state.history_config.max_size, 100);
+    }
 }
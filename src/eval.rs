@@ -1,25 +1,42 @@
 use std::fs;
 use std::io::Write;
+#[cfg(feature = "native")]
 use std::os::unix::fs::PermissionsExt;
+use std::time::{Duration, Instant};
 
-use crate::builtins::system::exec_word;
+use crate::builtins::{computation, files};
+use crate::builtins::system::{cd, exec_word, expand_tilde};
+use crate::compile;
 use crate::loops;
+use crate::suggest;
 use crate::tokenizer;
-use crate::types::{ControlFlow, LoopType, SkipTarget, State, Value, Word};
+use crate::theme::Theme;
+use crate::types::{sorted_entries, ControlFlow, LoopType, SkipTarget, State, Value, Word};
 
 // ========== PATH lookup ==========
 
-/// Check if a file exists and is executable.
-fn is_executable(path: &str) -> bool {
+/// Check if a path is a regular file with an execute bit set.
+pub(crate) fn is_executable(path: &str) -> bool {
     if let Ok(meta) = fs::metadata(path) {
-        meta.permissions().mode() & 0o111 != 0
+        meta.is_file() && has_exec_bit(&meta)
     } else {
         false
     }
 }
 
+#[cfg(feature = "native")]
+fn has_exec_bit(meta: &fs::Metadata) -> bool {
+    meta.permissions().mode() & 0o111 != 0
+}
+
+/// Without Unix permissions (wasm) nothing can be run anyway.
+#[cfg(not(feature = "native"))]
+fn has_exec_bit(_meta: &fs::Metadata) -> bool {
+    false
+}
+
 /// Find a command in PATH, return its absolute path if found.
-fn find_in_path(cmd: &str) -> Option<String> {
+pub(crate) fn find_in_path(cmd: &str) -> Option<String> {
     // Absolute path
     if cmd.starts_with('/') {
         return if is_executable(cmd) {
@@ -54,6 +71,126 @@ fn find_in_path(cmd: &str) -> Option<String> {
     None
 }
 
+// ========== Brace expansion ==========
+
+/// Most words one brace pattern may expand to, so a typo like
+/// `{1..100000000}` fails instead of filling memory.
+const MAX_BRACE_ITEMS: usize = 100_000;
+
+/// Find the first brace group that expands: its start, its end (after the
+/// `}`), and the items it stands for. Groups without a top-level comma or
+/// a valid range (`{}`, `{x}`, `{1..}`) are ordinary text, as in bash.
+fn find_brace_group(s: &str) -> Result<Option<(usize, usize, Vec<String>)>, String> {
+    for (start, _) in s.match_indices('{') {
+        let mut depth = 0;
+        let mut commas = Vec::new();
+        let mut end = None;
+        for (i, c) in s[start..].char_indices() {
+            match c {
+                '{' => depth += 1,
+                '}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        end = Some(start + i);
+                        break;
+                    }
+                }
+                ',' if depth == 1 => commas.push(start + i),
+                _ => {}
+            }
+        }
+        let Some(end) = end else {
+            return Ok(None);
+        };
+        let items = if commas.is_empty() {
+            match brace_range(&s[start + 1..end])? {
+                Some(items) => items,
+                None => continue,
+            }
+        } else {
+            let mut items = Vec::new();
+            let mut from = start + 1;
+            for comma in commas.into_iter().chain(std::iter::once(end)) {
+                items.push(s[from..comma].to_string());
+                from = comma + 1;
+            }
+            items
+        };
+        return Ok(Some((start, end + 1, items)));
+    }
+    Ok(None)
+}
+
+/// The items of a `{first..last}` or `{first..last..step}` range of
+/// integers or single letters. Integers written with a leading zero are
+/// zero-padded to the same width, so `{01..10}` gives `01 02 ... 10`.
+fn brace_range(inner: &str) -> Result<Option<Vec<String>>, String> {
+    let parts: Vec<&str> = inner.split("..").collect();
+    let step = match parts.len() {
+        2 => 1,
+        3 => match parts[2].parse::<i64>() {
+            Ok(step) => step.unsigned_abs().max(1),
+            Err(_) => return Ok(None),
+        },
+        _ => return Ok(None),
+    };
+    let (first, last) = (parts[0], parts[1]);
+    let (a, b, letters) = match (first.parse::<i64>(), last.parse::<i64>()) {
+        (Ok(a), Ok(b)) => (a, b, false),
+        _ => {
+            let mut chars = (first.chars(), last.chars());
+            match (chars.0.next(), chars.0.next(), chars.1.next(), chars.1.next()) {
+                (Some(a), None, Some(b), None)
+                    if a.is_ascii_alphabetic() && b.is_ascii_alphabetic() =>
+                {
+                    (a as i64, b as i64, true)
+                }
+                _ => return Ok(None),
+            }
+        }
+    };
+    // Checked before adding 1, which overflows for the full i64 range
+    let steps = a.abs_diff(b) / step;
+    if steps >= MAX_BRACE_ITEMS as u64 {
+        return Err(format!("{{{}}}: brace range has too many items", inner));
+    }
+    let count = steps + 1;
+    let padded = |n: &str| n.trim_start_matches('-').len() > 1 && n.trim_start_matches('-').starts_with('0');
+    let width = if !letters && (padded(first) || padded(last)) {
+        first.len().max(last.len())
+    } else {
+        0
+    };
+    // Each item lies between a and b, but a step near 2^63 doesn't fit i64
+    let items = (0..count as i128)
+        .map(|k| (if a <= b { a as i128 + k * step as i128 } else { a as i128 - k * step as i128 }) as i64)
+        .map(|n| match letters {
+            true => (n as u8 as char).to_string(),
+            false if n < 0 => format!("-{:0>w$}", n.unsigned_abs(), w = width.saturating_sub(1)),
+            false => format!("{:0>w$}", n, w = width),
+        })
+        .collect();
+    Ok(Some(items))
+}
+
+/// Expand brace patterns bash-style: `file.{txt,md}` gives `file.txt` and
+/// `file.md`, `{1..3}` gives `1 2 3`, and groups nest (`a{b,c{d,e}}`).
+/// A token with nothing to expand comes back alone.
+fn expand_braces(token: &str) -> Result<Vec<String>, String> {
+    let Some((start, end, items)) = find_brace_group(token)? else {
+        return Ok(vec![token.to_string()]);
+    };
+    let mut words = Vec::new();
+    for item in items {
+        let word = format!("{}{}{}", &token[..start], item, &token[end..]);
+        words.extend(expand_braces(&word)?);
+        if words.len() > MAX_BRACE_ITEMS {
+            return Err(format!("{}: brace pattern has too many items", token));
+        }
+    }
+    Ok(words)
+}
+
 // ========== Glob expansion ==========
 
 /// Check if a string contains glob characters.
@@ -62,7 +199,7 @@ fn has_glob_chars(s: &str) -> bool {
 }
 
 /// Simple glob matching: `*` matches any sequence, `?` matches one char.
-fn glob_matches(pattern: &str, text: &str) -> bool {
+pub(crate) fn glob_matches(pattern: &str, text: &str) -> bool {
     let pat: Vec<char> = pattern.chars().collect();
     let txt: Vec<char> = text.chars().collect();
     glob_match_helper(&pat, &txt, 0, 0)
@@ -99,8 +236,10 @@ fn glob_match_helper(pat: &[char], txt: &[char], pi: usize, ti: usize) -> bool {
     }
 }
 
-/// Expand a glob pattern to matching file paths.
-fn expand_glob(pattern: &str) -> Vec<String> {
+/// Expand a glob pattern to matching file paths. Names starting with `.`
+/// match only a pattern that starts with `.` too, unless `dotfiles` is set
+/// (see `set-glob-dotfiles`).
+pub(crate) fn expand_glob(pattern: &str, dotfiles: bool) -> Vec<String> {
     let (dir, file_pattern) = match pattern.rsplit_once('/') {
         Some((d, f)) => (d.to_string(), f),
         None => (".".to_string(), pattern),
@@ -114,6 +253,7 @@ fn expand_glob(pattern: &str) -> Vec<String> {
     let mut matches: Vec<String> = entries
         .filter_map(|e| e.ok())
         .map(|e| e.file_name().to_string_lossy().to_string())
+        .filter(|name| dotfiles || !name.starts_with('.') || file_pattern.starts_with('.'))
         .filter(|name| glob_matches(file_pattern, name))
         .collect();
 
@@ -131,27 +271,17 @@ fn expand_glob(pattern: &str) -> Vec<String> {
 
 // ========== Trace helpers ==========
 
-// ANSI color codes
-const C_RESET: &str = "\x1b[0m";
-const C_BOLD: &str = "\x1b[1m";
-const C_DIM: &str = "\x1b[2m";
-const C_RED: &str = "\x1b[31m";
-const C_GREEN: &str = "\x1b[32m";
-const C_YELLOW: &str = "\x1b[33m";
-const C_MAGENTA: &str = "\x1b[35m";
-const C_CYAN: &str = "\x1b[36m";
-
 /// Format a single value for trace display (compact, no colors).
 fn trace_fmt_value(val: &Value) -> String {
     match val {
         Value::Str(s) => format!("\"{}\"", s),
         Value::Int(n) => format!("{}", n),
-        Value::Output(s) => {
+        Value::Output(s, _) => {
             let line_count = s.lines().count();
             if line_count <= 1 {
                 let trimmed = s.trim_end();
-                if trimmed.len() > 30 {
-                    format!("<<{}...>>", &trimmed[..27])
+                if computation::grapheme_count(trimmed) > 30 {
+                    format!("<<{}...>>", computation::grapheme_prefix(trimmed, 27))
                 } else {
                     format!("<<{}>>", trimmed)
                 }
@@ -159,45 +289,50 @@ fn trace_fmt_value(val: &Value) -> String {
                 format!("<<output {} lines>>", line_count)
             }
         }
+        Value::Map(map) => format!("<<map {} keys>>", map.len()),
+        Value::List(items) => format!("<<list {} items>>", items.len()),
     }
 }
 
-/// Format a single value for trace display with colors.
-fn trace_fmt_value_colored(val: &Value) -> String {
+/// Format a single value for trace display with theme colors.
+fn trace_fmt_value_colored(theme: &Theme, val: &Value) -> String {
     match val {
-        Value::Str(s) => format!("{C_YELLOW}\"{}\"{C_RESET}", s),
-        Value::Int(n) => format!("{C_CYAN}{}{C_RESET}", n),
-        Value::Output(s) => {
+        Value::Str(s) => theme.paint("string", &format!("\"{}\"", s)),
+        Value::Int(n) => theme.paint("number", &n.to_string()),
+        Value::Output(s, _) => {
             let line_count = s.lines().count();
             if line_count <= 1 {
                 let trimmed = s.trim_end();
-                if trimmed.len() > 30 {
-                    format!("{C_MAGENTA}<<{C_RESET}{}...{C_MAGENTA}>>{C_RESET}", &trimmed[..27])
+                let (open, close) = (theme.paint("output", "<<"), theme.paint("output", ">>"));
+                if computation::grapheme_count(trimmed) > 30 {
+                    format!("{}{}...{}", open, computation::grapheme_prefix(trimmed, 27), close)
                 } else {
-                    format!("{C_MAGENTA}<<{C_RESET}{}{C_MAGENTA}>>{C_RESET}", trimmed)
+                    format!("{}{}{}", open, trimmed, close)
                 }
             } else {
-                format!("{C_MAGENTA}<<output {} lines>>{C_RESET}", line_count)
+                theme.paint("output", &format!("<<output {} lines>>", line_count))
             }
         }
+        Value::Map(map) => theme.paint("output", &format!("<<map {} keys>>", map.len())),
+        Value::List(items) => theme.paint("output", &format!("<<list {} items>>", items.len())),
     }
 }
 
 /// Format the stack contents for trace display with colors.
-fn trace_fmt_stack(stack: &[Value]) -> String {
+fn trace_fmt_stack(theme: &Theme, stack: &[Value]) -> String {
     if stack.is_empty() {
-        format!("{C_DIM}(empty){C_RESET}")
+        theme.paint("hint", "(empty)")
     } else {
         stack
             .iter()
-            .map(trace_fmt_value_colored)
+            .map(|v| trace_fmt_value_colored(theme, v))
             .collect::<Vec<_>>()
             .join(" ")
     }
 }
 
 /// Describe the diff between stack states before and after a token execution.
-fn trace_describe_diff(before: &[Value], after: &[Value]) -> String {
+fn trace_describe_diff(theme: &Theme, before: &[Value], after: &[Value]) -> String {
     // Find common prefix length
     let common = before
         .iter()
@@ -212,16 +347,16 @@ fn trace_describe_diff(before: &[Value], after: &[Value]) -> String {
 
     if !popped.is_empty() {
         let items: Vec<String> = popped.iter().rev().map(trace_fmt_value).collect();
-        parts.push(format!("{C_RED}pop{C_RESET} {}", items.join(", ")));
+        parts.push(format!("{} {}", theme.paint("pop", "pop"), items.join(", ")));
     }
 
     if !pushed.is_empty() {
         let items: Vec<String> = pushed.iter().map(trace_fmt_value).collect();
-        parts.push(format!("{C_GREEN}push{C_RESET} {}", items.join(", ")));
+        parts.push(format!("{} {}", theme.paint("push", "push"), items.join(", ")));
     }
 
     if parts.is_empty() {
-        format!("{C_DIM}(no stack change){C_RESET}")
+        theme.paint("hint", "(no stack change)")
     } else {
         parts.join("; ")
     }
@@ -233,53 +368,145 @@ fn trace_describe_diff(before: &[Value], after: &[Value]) -> String {
 ///   1 = push/pop description only
 ///   2 = push/pop + stack state
 ///   3 = push/pop + doc string + stack state
-fn trace_print_step(
-    level: u8,
-    step: usize,
-    token: &str,
-    is_quoted: bool,
-    before: &[Value],
-    after: &[Value],
-    doc: Option<&str>,
-) {
+fn trace_print_step(state: &State, token: &str, is_quoted: bool, before: &[Value], doc: Option<&str>) {
+    let (theme, level, step, after) = (&state.theme, state.trace, state.trace_step, &state.stack);
     let display_token = if is_quoted {
-        format!("{C_YELLOW}\"{}\"", token)
+        format!("{}\"{}\"", theme.start("string"), token)
     } else {
-        format!("{C_BOLD}{}", token)
+        format!("{}{}", theme.start("emphasis"), token)
     };
 
-    let desc = trace_describe_diff(before, after);
+    let desc = trace_describe_diff(theme, before, after);
+    let (dim, reset) = (theme.start("hint"), theme.reset());
 
     eprintln!(
-        "  {C_DIM}Step {}{C_RESET} {:<20}{C_RESET} \u{2192} {}",
+        "  {dim}Step {}{reset} {:<20}{reset} \u{2192} {}",
         step, display_token, desc,
     );
     if level >= 3 {
         if let Some(doc) = doc {
             eprintln!(
-                "  {C_DIM}{:>28} {}{C_RESET}",
+                "  {dim}{:>28} {}{reset}",
                 "", doc
             );
         }
     }
     if level >= 2 {
-        let stack_display = trace_fmt_stack(after);
+        let stack_display = trace_fmt_stack(theme, after);
         eprintln!(
-            "  {C_DIM}{:>28} Stack:{C_RESET} {}",
+            "  {dim}{:>28} Stack:{reset} {}",
             "", stack_display
         );
     }
     let _ = std::io::stderr().flush();
 }
 
+/// Quote a string as a JSON string literal.
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// A value in full as JSON: a string, a number, `{"output": text}`,
+/// `{"map": {key: value, ...}}`, or `{"list": [value, ...]}`.
+fn trace_json_value(val: &Value) -> String {
+    match val {
+        Value::Str(s) => json_string(s),
+        Value::Int(n) => n.to_string(),
+        Value::Output(s, _) => format!("{{\"output\":{}}}", json_string(s)),
+        Value::Map(map) => {
+            let entries: Vec<String> = sorted_entries(map)
+                .into_iter()
+                .map(|(key, val)| format!("{}:{}", json_string(key), trace_json_value(val)))
+                .collect();
+            format!("{{\"map\":{{{}}}}}", entries.join(","))
+        }
+        Value::List(items) => format!("{{\"list\":{}}}", trace_json_values(items)),
+    }
+}
+
+fn trace_json_values(vals: &[Value]) -> String {
+    let items: Vec<String> = vals.iter().map(trace_json_value).collect();
+    format!("[{}]", items.join(","))
+}
+
+/// One trace step as a JSON object: the token, what it popped and pushed
+/// (bottom of the stack first), the stack depth after, how long it took,
+/// and its error if it failed.
+fn trace_json_step(
+    state: &State,
+    token: &str,
+    is_quoted: bool,
+    before: &[Value],
+    elapsed: Duration,
+    error: Option<&str>,
+) -> String {
+    let after = &state.stack;
+    let common = before
+        .iter()
+        .zip(after.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let mut line = format!(
+        "{{\"step\":{},\"token\":{},\"quoted\":{},\"pops\":{},\"pushes\":{},\"depth\":{},\"micros\":{}",
+        state.trace_step,
+        json_string(token),
+        is_quoted,
+        trace_json_values(&before[common..]),
+        trace_json_values(&after[common..]),
+        after.len(),
+        elapsed.as_micros(),
+    );
+    if let Some(e) = error {
+        line.push_str(&format!(",\"error\":{}", json_string(e)));
+    }
+    line.push('}');
+    line
+}
+
+/// Record a finished step: as a JSON line if `trace-file` is set, otherwise
+/// on stderr at the current trace level.
+fn trace_record_step(
+    state: &mut State,
+    token: &str,
+    is_quoted: bool,
+    before: &[Value],
+    started: Instant,
+    error: Option<&str>,
+) {
+    state.trace_step += 1;
+    if state.trace_file.is_some() {
+        let line = trace_json_step(state, token, is_quoted, before, started.elapsed(), error);
+        if let Some(file) = state.trace_file.as_mut() {
+            let _ = writeln!(file, "{}", line);
+        }
+    } else if state.trace > 0 {
+        let doc = trace_lookup_doc(state, token, is_quoted);
+        trace_print_step(state, token, is_quoted, before, doc);
+    }
+}
+
 /// Look up the doc string for a token from the dictionary.
 fn trace_lookup_doc<'a>(state: &'a State, token: &str, is_quoted: bool) -> Option<&'a str> {
     if is_quoted {
         return None;
     }
-    match state.dict.get(token)? {
+    match state.lookup(token)? {
         Word::Builtin(_, Some(doc)) => Some(doc),
-        Word::Defined(_) => Some("(user-defined word)"),
+        Word::Defined(..) => Some("(user-defined word)"),
         _ => None,
     }
 }
@@ -287,20 +514,37 @@ fn trace_lookup_doc<'a>(state: &'a State, token: &str, is_quoted: bool) -> Optio
 // ========== Token evaluation ==========
 
 /// Handle word definition collection (: name ... ;).
-fn handle_word_definition(state: &mut State, token: &str) -> Result<(), String> {
+fn handle_word_definition(state: &mut State, token: &str, is_quoted: bool) -> Result<(), String> {
     if let Some(ref name) = state.defining.clone() {
         if name == "UNNAMED" {
             // This token is the word name
             state.defining = Some(token.to_string());
-        } else if token == ";" {
-            // End definition
-            let name = name.clone();
+        } else if token == ";" && !is_quoted {
+            // End definition, in the vocabulary chosen with `in` if any
+            let name = match state.definition_vocab.take() {
+                Some(vocab) => format!("{}:{}", vocab, name),
+                None => name.clone(),
+            };
             let body = std::mem::take(&mut state.def_body);
-            state.dict.insert(name, Word::Defined(body));
             state.defining = None;
+            // Replacing a builtin breaks everything built on it, so it takes
+            // an explicit `redefine-ok`; replacing other words just warns
+            let redefine_ok = std::mem::take(&mut state.redefine_ok);
+            match state.dict.get(&name) {
+                Some(Word::Builtin(..)) if !redefine_ok => {
+                    return Err(format!(
+                        "{}: is a builtin; use redefine-ok before : to replace it",
+                        name
+                    ));
+                }
+                Some(Word::Builtin(..)) | None => {}
+                Some(_) if state.editing.as_ref() == Some(&name) => {}
+                Some(_) => eprintln!("warning: redefining {}", name),
+            }
+            state.dict_mut().insert(name, Word::defined(body));
         } else {
             // Accumulate token into body
-            state.def_body.push(token.to_string());
+            state.def_body.push(tokenizer::Token::new(token, is_quoted));
         }
     }
     Ok(())
@@ -313,8 +557,8 @@ fn handle_control_flow_skipping(
     target: SkipTarget,
     depth: usize,
 ) -> Result<(), String> {
-    if token == "if" {
-        // Nested if: increase depth
+    if matches!(token, "if" | "each" | "each-word" | "each-char") {
+        // Nested if (or each, which also ends at then): increase depth
         state.control_flow = ControlFlow::Skipping {
             target,
             depth: depth + 1,
@@ -385,24 +629,42 @@ fn handle_control_flow_keywords(state: &mut State, token: &str) -> Result<bool,
         // Start begin...until or begin...while...repeat loop
         state.collecting_loop = Some((LoopType::BeginUntil, Vec::new(), 0));
         Ok(true)
-    } else if token == "do" {
-        // Start do...loop or do...+loop
+    } else if token == "do" || token == "?do" {
+        // Start do...loop or do...+loop (counted loops never run when
+        // start = limit, so `?do` is the same loop under its Forth name)
         state.collecting_loop = Some((LoopType::DoLoop, Vec::new(), 0));
         Ok(true)
     } else if token == "each" {
         // Start each...then - pop Output from stack
         match state.stack.pop() {
-            Some(Value::Output(content)) => {
-                state.collecting_each = Some((content, Vec::new()));
+            Some(Value::Output(content, _)) => {
+                let items = loops::each_items(token, &content);
+                state.collecting_each = Some((token.to_string(), items, Vec::new()));
                 Ok(true)
             }
             Some(_) => Err("each: requires Output on stack".into()),
             None => Err("each: stack underflow".into()),
         }
+    } else if token == "each-word" || token == "each-char" {
+        // Same as each, but also over a Str
+        match state.stack.pop() {
+            Some(Value::Output(content, _)) | Some(Value::Str(content)) => {
+                let items = loops::each_items(token, &content);
+                state.collecting_each = Some((token.to_string(), items, Vec::new()));
+                Ok(true)
+            }
+            Some(other) => {
+                state.stack.push(other);
+                Err(format!("{}: requires string or output", token))
+            }
+            None => Err(format!("{}: stack underflow", token)),
+        }
     } else if token == "until" {
         Err("until: no matching begin".into())
     } else if token == "repeat" {
         Err("repeat: no matching begin".into())
+    } else if token == "again" {
+        Err("again: no matching begin".into())
     } else if token == "loop" {
         Err("loop: no matching do".into())
     } else if token == "+loop" {
@@ -414,26 +676,43 @@ fn handle_control_flow_keywords(state: &mut State, token: &str) -> Result<bool,
 
 /// Handle execution of a single token (integers, dictionary lookup, PATH lookup, globs).
 fn handle_token_execution(state: &mut State, token: &str, is_quoted: bool) -> Result<(), String> {
+    // Words run from here on aren't the line's lone token
+    let lone_token = std::mem::take(&mut state.lone_token);
+
     // Integer?
-    if !is_quoted && tokenizer::is_int(token) {
-        let n: i64 = token.parse().unwrap();
-        state.stack.push(Value::Int(n));
-        return Ok(());
+    if !is_quoted {
+        if let Some(n) = tokenizer::parse_int(token) {
+            state.stack.push(Value::Int(n));
+            return Ok(());
+        }
     }
 
-    // Dictionary lookup (only for unquoted tokens)
-    if !is_quoted {
-        if let Some(word) = state.dict.get(token).cloned() {
+    // Dictionary lookup (only for unquoted tokens). Inside its own
+    // expansion an alias is skipped, so `"--color" ls` can alias `ls`.
+    if !is_quoted && !state.expanding_aliases.iter().any(|a| a == token) {
+        if let Some(word) = state.lookup(token).cloned() {
             match word {
                 Word::Builtin(f, _) => {
                     return f(state);
                 }
-                Word::Defined(tokens) => {
-                    // Execute defined word: each token is unquoted
-                    for t in &tokens {
-                        eval_token(state, t, false)?;
-                    }
-                    return Ok(());
+                Word::Defined(_, ops) => {
+                    // Execute defined word: tokens keep their quoting, and
+                    // loops run from the body compiled at definition
+                    return compile::run(state, &ops);
+                }
+                Word::Alias(text) => {
+                    state.expanding_aliases.push(token.to_string());
+                    let result = tokenizer::tokenize(&text)
+                        .iter()
+                        .try_for_each(|t| eval_token(state, &t.text, t.quoted));
+                    state.expanding_aliases.pop();
+                    return result;
+                }
+                Word::Deferred(Some(target)) => {
+                    return eval_token(state, &target, false);
+                }
+                Word::Deferred(None) => {
+                    return Err(format!("{}: deferred word is not set (use is)", token));
                 }
                 Word::ShellCmd(cmd) => {
                     state.stack.push(Value::Str(cmd));
@@ -443,21 +722,43 @@ fn handle_token_execution(state: &mut State, token: &str, is_quoted: bool) -> Re
         }
     }
 
-    // Quoted string: push as literal
+    // Quoted string: push with environment variables filled in
     if is_quoted {
-        state.stack.push(Value::Str(token.to_string()));
+        state.stack.push(Value::Str(interpolate(token)));
         return Ok(());
     }
 
-    // Unquoted: try PATH lookup
-    if let Some(full_path) = find_in_path(token) {
+    // Brace expansion: each word is evaluated as a bare token, so words
+    // that are globs or start with `~` expand further
+    if token.contains('{') {
+        let words = expand_braces(token)?;
+        if words.len() != 1 || words[0] != token {
+            for word in words {
+                push_expanded_word(state, &word);
+            }
+            return Ok(());
+        }
+    }
+
+    // Unquoted: `~` and `~user` name home directories from here on
+    let expanded = expand_tilde(token);
+    let token = expanded.as_str();
+
+    // Try PATH lookup
+    if let Some(full_path) = state.runner.find(token) {
         state.stack.push(Value::Str(full_path));
         return exec_word(state);
     }
 
+    // autocd: a directory name alone on the line changes into it
+    if state.autocd && lone_token && std::path::Path::new(token).is_dir() {
+        state.stack.push(Value::Str(token.to_string()));
+        return cd(state);
+    }
+
     // Glob expansion
     if has_glob_chars(token) {
-        let matches = expand_glob(token);
+        let matches = expand_glob(token, state.glob_dotfiles);
         if !matches.is_empty() {
             for m in matches {
                 state.stack.push(Value::Str(m));
@@ -466,26 +767,135 @@ fn handle_token_execution(state: &mut State, token: &str, is_quoted: bool) -> Re
         }
     }
 
+    // Unknown token: let the user's hook decide, if there is one
+    if !state.in_not_found_hook && state.dict.contains_key(NOT_FOUND_HOOK) {
+        return run_not_found_hook(state, token);
+    }
+
+    // Probably a typo? Hint at the closest known name, but still push the string
+    if state.suggest {
+        if let Some(name) = suggest::suggest(state, token) {
+            eprintln!("{}: did you mean {}?", token, name);
+        }
+    }
+
     // Otherwise: push as string literal
     state.stack.push(Value::Str(token.to_string()));
     Ok(())
 }
 
+/// Push one word of a brace expansion: globs expand to their matches, and
+/// everything else is a string (after `~` expansion).
+fn push_expanded_word(state: &mut State, word: &str) {
+    let word = expand_tilde(word);
+    if has_glob_chars(&word) {
+        let matches = expand_glob(&word, state.glob_dotfiles);
+        if !matches.is_empty() {
+            state.stack.extend(matches.into_iter().map(Value::Str));
+            return;
+        }
+    }
+    state.stack.push(Value::Str(word));
+}
+
+/// Fill in `$VAR` / `${VAR}` from the environment (see `tokenizer::interpolate_env`).
+fn interpolate(token: &str) -> String {
+    tokenizer::interpolate_env(token, |name| std::env::var(name).ok()).into_owned()
+}
+
+/// Control flow and definition keywords, handled before any dictionary
+/// lookup (and highlighted as keywords).
+pub const KEYWORDS: &[&str] = &[
+    ":", ";", "if", "else", "then", "begin", "until", "while", "repeat", "again", "do", "?do",
+    "loop", "+loop", "each", "each-word", "each-char",
+];
+
+/// Describe how an unquoted token would be handled, following the same
+/// order as `handle_token_execution`: "keyword", "integer", "builtin",
+/// "word" (with the vocabulary-qualified name if found through the search
+/// order), "alias <text>", "deferred <target>", "braces <n> words",
+/// "command <path>", "directory <path>" (autocd, alone on a line), "glob <n> matches",
+/// "on-not-found", or "string".
+pub fn resolve(state: &State, token: &str) -> String {
+    if KEYWORDS.contains(&token) {
+        return "keyword".into();
+    }
+    if tokenizer::parse_int(token).is_some() {
+        return "integer".into();
+    }
+    if let Some(word) = state.lookup(token) {
+        // The search order wins over a bare name, as in `lookup`
+        let vocab = state
+            .search_order
+            .iter()
+            .find(|v| state.dict.contains_key(&format!("{}:{}", v, token)));
+        return match (word, vocab) {
+            (Word::Builtin(..), _) => "builtin".into(),
+            (Word::Defined(..), Some(vocab)) => format!("word {}:{}", vocab, token),
+            (Word::Defined(..), None) => "word".into(),
+            (Word::Alias(text), _) => format!("alias {}", text),
+            (Word::Deferred(Some(target)), _) => format!("deferred {}", target),
+            (Word::Deferred(None), _) => "deferred".into(),
+            (Word::ShellCmd(path), _) => format!("command {}", path),
+        };
+    }
+    if token.contains('{') {
+        if let Ok(words) = expand_braces(token) {
+            if words.len() != 1 || words[0] != token {
+                return format!("braces {} words", words.len());
+            }
+        }
+    }
+    let expanded = expand_tilde(token);
+    let token = expanded.as_str();
+    if let Some(path) = state.runner.find(token) {
+        return format!("command {}", path);
+    }
+    if state.autocd && std::path::Path::new(token).is_dir() {
+        return format!("directory {}", token);
+    }
+    if has_glob_chars(token) {
+        let matches = expand_glob(token, state.glob_dotfiles).len();
+        if matches > 0 {
+            return format!("glob {} matches", matches);
+        }
+    }
+    if state.dict.contains_key(NOT_FOUND_HOOK) {
+        return NOT_FOUND_HOOK.into();
+    }
+    "string".into()
+}
+
+/// Name of the user-definable word invoked for unknown tokens.
+const NOT_FOUND_HOOK: &str = "on-not-found";
+
+/// Invoke `on-not-found` with the unknown token pushed as a Str.
+///
+/// The hook decides what happens: leave the string, drop it, warn, or error.
+/// Unknown tokens inside the hook itself fall back to plain strings.
+fn run_not_found_hook(state: &mut State, token: &str) -> Result<(), String> {
+    state.stack.push(Value::Str(token.to_string()));
+    state.in_not_found_hook = true;
+    let result = eval_token(state, NOT_FOUND_HOOK, false);
+    state.in_not_found_hook = false;
+    result
+}
+
 /// Evaluate a single token within the current interpreter state.
 pub fn eval_token(state: &mut State, token: &str, is_quoted: bool) -> Result<(), String> {
     // 1. Are we collecting an each...then body?
     if state.collecting_each.is_some() {
-        return loops::handle_each_collection(state, token);
+        return loops::handle_each_collection(state, token, is_quoted);
     }
 
     // 2. Are we collecting a loop body?
     if state.collecting_loop.is_some() {
-        return loops::handle_loop_collection(state, token);
+        return loops::handle_loop_collection(state, token, is_quoted);
     }
 
     // 3. Are we defining a word?
     if state.defining.is_some() {
-        return handle_word_definition(state, token);
+        return handle_word_definition(state, token, is_quoted);
     }
 
     // 4. Are we skipping (control flow)?
@@ -494,31 +904,33 @@ pub fn eval_token(state: &mut State, token: &str, is_quoted: bool) -> Result<(),
     }
 
     // Trace: snapshot stack before execution
-    let trace_level = state.trace;
-    let stack_before = if trace_level > 0 {
-        Some(state.stack.clone())
+    let stack_before = if state.trace > 0 || state.trace_file.is_some() {
+        Some((state.stack.clone(), Instant::now()))
     } else {
         None
     };
 
     // 5. Is it a control flow keyword?
-    if !is_quoted && handle_control_flow_keywords(state, token)? {
-        if let Some(before) = stack_before {
-            state.trace_step += 1;
-            let doc = trace_lookup_doc(state, token, is_quoted);
-            trace_print_step(trace_level, state.trace_step, token, is_quoted, &before, &state.stack, doc);
+    if !is_quoted {
+        let handled = handle_control_flow_keywords(state, token);
+        if let Some((before, started)) = &stack_before {
+            if handled != Ok(false) {
+                let error = handled.as_ref().err().map(String::as_str);
+                trace_record_step(state, token, is_quoted, before, *started, error);
+            }
+        }
+        if handled? {
+            return Ok(());
         }
-        return Ok(());
     }
 
     // 6. Execute normally
     let result = handle_token_execution(state, token, is_quoted);
 
-    // Trace: print step after execution
-    if let Some(before) = stack_before {
-        state.trace_step += 1;
-        let doc = trace_lookup_doc(state, token, is_quoted);
-        trace_print_step(trace_level, state.trace_step, token, is_quoted, &before, &state.stack, doc);
+    // Trace: record step after execution
+    if let Some((before, started)) = stack_before {
+        let error = result.as_ref().err().map(String::as_str);
+        trace_record_step(state, token, is_quoted, &before, started, error);
     }
 
     result
@@ -529,8 +941,16 @@ pub fn eval_line(state: &mut State, line: &str) -> Result<(), String> {
     // Reset trace step counter for each new line
     state.trace_step = 0;
 
-    let tokens = tokenizer::tokenize(line);
+    // Temp files from `psub` on this line go away when it's done, and a
+    // `<stdin` no command used doesn't carry over to the next line
+    let psub_mark = state.psub_files.len();
+    let result = eval_tokens(state, &tokenizer::tokenize(line));
+    files::remove_psub_files(state, psub_mark);
+    state.stdin_file = None;
+    result
+}
 
+fn eval_tokens(state: &mut State, tokens: &[tokenizer::Token]) -> Result<(), String> {
     // Handle special `: name` prefix -- consume name early
     if tokens.len() >= 2 && tokens[0].text == ":" && !tokens[0].quoted {
         state.defining = Some(tokens[1].text.clone());
@@ -542,7 +962,8 @@ pub fn eval_line(state: &mut State, line: &str) -> Result<(), String> {
     }
 
     // Normal evaluation
-    for token in &tokens {
+    state.lone_token = tokens.len() == 1;
+    for token in tokens {
         eval_token(state, &token.text, token.quoted)?;
     }
     Ok(())
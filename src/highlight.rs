@@ -1,24 +1,63 @@
 use std::borrow::Cow;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
 use rustyline::completion::{Completer, FilenameCompleter, Pair};
 use rustyline::highlight::{CmdKind, Highlighter};
-use rustyline::hint::Hinter;
+use rustyline::hint::{Hint, Hinter, HistoryHinter};
 use rustyline::validate::{ValidationContext, ValidationResult, Validator};
-use rustyline::{Context, Helper, Result};
+use rustyline::{
+    Cmd, ConditionalEventHandler, Context, EditMode, Event, EventContext, EventHandler, Helper,
+    InputMode, KeyCode, KeyEvent, Modifiers, RepeatCount, Result,
+};
 
+use crate::builtins::system::expand_tilde;
+use crate::eval;
 use crate::multiline;
+use crate::preview;
+use crate::suggest::scan_path;
+use crate::terminal;
+use crate::theme::Theme;
 use crate::tokenizer;
+use crate::types::{CompletionKind, Dict, Value};
 
 /// The rustyline helper for yafsh.
 ///
-/// Combines syntax highlighting, input validation (multiline detection),
-/// tab-completion (dictionary words + filenames), and hinting.
+/// Combines syntax highlighting, tab-completion (dictionary words +
+/// filenames), and hinting.
 pub struct YafshHelper {
-    /// Set of known dictionary words, synced before each readline.
-    pub dict_words: HashSet<String>,
+    /// Known dictionary words and their one-line summaries, synced before each readline.
+    pub dict_words: HashMap<String, String>,
     /// Filename completer for path completion.
     file_completer: FilenameCompleter,
+    /// Executable names in $PATH, for spotting unknown tokens.
+    executables: HashSet<String>,
+    /// The $PATH value `executables` was scanned from.
+    executables_path: Option<String>,
+    /// Argument completion source per word, synced before each readline.
+    completers: HashMap<String, CompletionKind>,
+    /// Result of `$rprompt`, drawn right-aligned on the input line.
+    rprompt: Option<String>,
+    /// Visible width of the left prompt, for right-aligning `rprompt`.
+    prompt_width: usize,
+    /// Whether to suggest the rest of a matching history entry (see `set-autosuggest`).
+    autosuggest: bool,
+    /// Stack and words to predict the typed line against (see
+    /// `set-stack-preview`); `None` while the preview is off.
+    stack_preview: Option<(Vec<Value>, Rc<Dict>)>,
+    /// Colors per token class; plain text while colors are off.
+    theme: Theme,
+    /// Whether vi keybindings are active (see `set-keymode`).
+    vi: bool,
+    /// Set while vi normal (command) mode is active, shared with the key handlers.
+    vi_normal: Arc<AtomicBool>,
+    /// Whether the REPL reads continuation lines itself, with their own
+    /// prompt, instead of keeping the buffer open for incomplete input.
+    prompted_continuation: bool,
 }
 
 impl Default for YafshHelper {
@@ -30,13 +69,101 @@ impl Default for YafshHelper {
 impl YafshHelper {
     pub fn new() -> Self {
         YafshHelper {
-            dict_words: HashSet::new(),
+            dict_words: HashMap::new(),
             file_completer: FilenameCompleter::new(),
+            executables: HashSet::new(),
+            executables_path: None,
+            completers: HashMap::new(),
+            rprompt: None,
+            prompt_width: 0,
+            autosuggest: false,
+            stack_preview: None,
+            theme: Theme::default(),
+            vi: false,
+            vi_normal: Arc::new(AtomicBool::new(false)),
+            prompted_continuation: false,
         }
     }
 
-    /// Update the set of known dictionary words.
-    pub fn update_words(&mut self, words: impl IntoIterator<Item = String>) {
+    /// Read continuation lines one at a time behind a prompt (on), or keep
+    /// incomplete input open in the buffer for multi-line editing (off).
+    pub fn set_prompted_continuation(&mut self, on: bool) {
+        self.prompted_continuation = on;
+    }
+
+    /// Turn fish-style history suggestions on or off.
+    pub fn set_autosuggest(&mut self, on: bool) {
+        self.autosuggest = on;
+    }
+
+    /// Turn the live stack preview on with a copy of the current stack and a
+    /// share of the dictionary, or off with `None`.
+    pub fn set_stack_preview(&mut self, preview: Option<(&[Value], &Rc<Dict>)>) {
+        self.stack_preview = preview.map(|(stack, words)| (stack.to_vec(), Rc::clone(words)));
+    }
+
+    /// Use the shell's current colors (see `theme`, `set-color`).
+    pub fn set_theme(&mut self, theme: &Theme) {
+        self.theme.clone_from(theme);
+    }
+
+    /// Switch vi-mode tracking on or off. Every line starts in insert mode.
+    pub fn set_vi(&mut self, vi: bool) {
+        self.vi = vi;
+        self.vi_normal.store(false, Ordering::Relaxed);
+    }
+
+    /// Key bindings that follow vi insert/normal switches, so `$vimode` in
+    /// the prompt can be redrawn. They never change what the keys do.
+    pub fn vi_mode_bindings(&self) -> Vec<(KeyEvent, EventHandler)> {
+        let tracker = |enters_normal| {
+            EventHandler::Conditional(Box::new(ViModeTracker {
+                normal: self.vi_normal.clone(),
+                enters_normal,
+            }))
+        };
+        let mut bindings = vec![(KeyEvent(KeyCode::Esc, Modifiers::NONE), tracker(true))];
+        for key in ['i', 'a', 'I', 'A', 's', 'S', 'c', 'C', 'R'] {
+            bindings.push((KeyEvent::from(key), tracker(false)));
+        }
+        bindings
+    }
+
+    /// Set the right-side prompt to show alongside the given left prompt.
+    pub fn set_rprompt(&mut self, prompt: &str, rprompt: Option<String>) {
+        self.prompt_width = visible_width(prompt);
+        self.rprompt = rprompt.filter(|r| !r.is_empty());
+    }
+
+    /// Update the per-word argument completion sources.
+    pub fn set_completers(&mut self, completers: &HashMap<String, CompletionKind>) {
+        self.completers.clone_from(completers);
+    }
+
+    /// Rescan $PATH for executables if it changed since the last scan.
+    pub fn refresh_executables(&mut self) {
+        let path_var = std::env::var("PATH").unwrap_or_default();
+        if self.executables_path.as_deref() != Some(path_var.as_str()) {
+            self.executables = scan_path(&path_var).into_iter().collect();
+            self.executables_path = Some(path_var);
+        }
+    }
+
+    /// Whether a bare token resolves to nothing: not a word, integer,
+    /// executable, or existing path. Flags, globs, brace patterns, and
+    /// history references are plain arguments, so they never count as unknown.
+    fn is_unknown(&self, token: &str) -> bool {
+        if token.starts_with(['-', '!']) || token.contains(['*', '?', '[', '{']) {
+            return false;
+        }
+        !(self.dict_words.contains_key(token)
+            || tokenizer::is_int(token)
+            || self.executables.contains(token)
+            || Path::new(&expand_tilde(token)).exists())
+    }
+
+    /// Update the known dictionary words, as (name, summary) pairs.
+    pub fn update_words(&mut self, words: impl IntoIterator<Item = (String, String)>) {
         self.dict_words.clear();
         self.dict_words.extend(words);
     }
@@ -46,22 +173,53 @@ impl Helper for YafshHelper {}
 
 // ========== Highlighter ==========
 
-/// ANSI color codes.
-const YELLOW: &str = "\x1b[33m";
-const MAGENTA: &str = "\x1b[35m";
-const CYAN: &str = "\x1b[36m";
-const GREEN: &str = "\x1b[32m";
-const RESET: &str = "\x1b[0m";
+/// Whether `closer` ends a structure opened by `opener`.
+fn closes(opener: &str, closer: &str) -> bool {
+    matches!(
+        (opener, closer),
+        (":", ";")
+            | ("if" | "each" | "each-word" | "each-char", "then")
+            | ("do" | "?do", "loop" | "+loop")
+            | ("begin", "until" | "repeat" | "again")
+    )
+}
 
-/// Keywords that get magenta highlighting.
-const KEYWORDS: &[&str] = &[
-    ":", ";", "if", "else", "then", "begin", "until", "while", "repeat", "do", "loop", "+loop",
-    "each", "exit", "quit",
-];
+/// Token indices of the control keyword at (or just before) the cursor and
+/// its partner: `if`/`then`, `:`/`;`, `do`/`loop`, `begin`/`until`, and so
+/// on. `None` when the cursor isn't on a keyword or it is unbalanced.
+fn matching_delimiter(
+    tokens: &[tokenizer::TokenWithPosition],
+    pos: usize,
+) -> Option<(usize, usize)> {
+    let at = tokens
+        .iter()
+        .position(|t| !t.quoted && t.position <= pos && pos <= t.end)?;
+    let mut open: Vec<usize> = Vec::new();
+    for (i, tok) in tokens.iter().enumerate() {
+        if tok.quoted {
+            continue;
+        }
+        let text = tok.text.as_str();
+        if matches!(
+            text,
+            ":" | "if" | "each" | "each-word" | "each-char" | "do" | "?do" | "begin"
+        ) {
+            open.push(i);
+        } else if let Some(&o) = open.last() {
+            if closes(&tokens[o].text, text) {
+                open.pop();
+                if o == at || i == at {
+                    return Some((o, i));
+                }
+            }
+        }
+    }
+    None
+}
 
 impl Highlighter for YafshHelper {
-    fn highlight<'l>(&self, line: &'l str, _pos: usize) -> Cow<'l, str> {
-        if line.is_empty() {
+    fn highlight<'l>(&self, line: &'l str, pos: usize) -> Cow<'l, str> {
+        if line.is_empty() || !self.theme.enabled {
             return Cow::Borrowed(line);
         }
 
@@ -72,24 +230,18 @@ impl Highlighter for YafshHelper {
 
         let mut result = String::with_capacity(line.len() + tokens.len() * 10);
         let mut last_end: usize = 0;
-
-        for tok in &tokens {
+        // The control keyword under the cursor and its partner, if any
+        let matched = matching_delimiter(&tokens, pos);
+        // Names defined earlier on this line (`: name ... ;`) are known words
+        let mut defined: HashSet<&str> = HashSet::new();
+
+        for (i, tok) in tokens.iter().enumerate() {
+            let defines = i > 0 && !tokens[i - 1].quoted && tokens[i - 1].text == ":";
+            if defines && !tok.quoted {
+                defined.insert(&tok.text);
+            }
             let start = tok.position;
-            // Calculate end position in the original string
-            let end = if tok.quoted {
-                // For quoted tokens, position points to opening quote
-                // end is after closing quote (or end of string for unclosed)
-                let content_start = start + 1; // after opening "
-                let content_end = content_start + tok.text.len();
-                // Check if there's a closing quote
-                if content_end < line.len() && line.as_bytes()[content_end] == b'"' {
-                    content_end + 1
-                } else {
-                    content_end
-                }
-            } else {
-                start + tok.text.len()
-            };
+            let end = tok.end;
 
             // Append any gap between last token end and this token start
             if start > last_end {
@@ -98,29 +250,32 @@ impl Highlighter for YafshHelper {
 
             let token_text = &line[start..end.min(line.len())];
 
-            // Determine color
-            if tok.quoted {
-                // Strings are yellow
-                result.push_str(YELLOW);
-                result.push_str(token_text);
-                result.push_str(RESET);
-            } else if KEYWORDS.contains(&tok.text.as_str()) {
-                // Keywords are magenta
-                result.push_str(MAGENTA);
-                result.push_str(token_text);
-                result.push_str(RESET);
-            } else if tok.text.parse::<i64>().is_ok() {
-                // Numbers are cyan
-                result.push_str(CYAN);
-                result.push_str(token_text);
-                result.push_str(RESET);
-            } else if self.dict_words.contains(&tok.text) {
-                // Dictionary words are green
-                result.push_str(GREEN);
-                result.push_str(token_text);
-                result.push_str(RESET);
+            // Determine the theme class
+            let class = if matched.is_some_and(|(a, b)| i == a || i == b) {
+                // Matching control keywords stand out together
+                Some("match")
+            } else if tok.quoted {
+                Some("string")
+            } else if eval::KEYWORDS.contains(&tok.text.as_str()) {
+                Some("keyword")
+            } else if tokenizer::is_int(&tok.text) {
+                Some("number")
+            } else if self.dict_words.contains_key(&tok.text) {
+                Some("word")
+            } else if !defines
+                && !defined.contains(tok.text.as_str())
+                // Leave the word still being typed alone
+                && end != pos
+                && self.is_unknown(&tok.text)
+            {
+                // Tokens that resolve to nothing
+                Some("unknown")
             } else {
-                result.push_str(token_text);
+                None
+            };
+            match class {
+                Some(class) => result.push_str(&self.theme.paint(class, token_text)),
+                None => result.push_str(token_text),
             }
 
             last_end = end.min(line.len());
@@ -140,16 +295,54 @@ impl Highlighter for YafshHelper {
     }
 
     fn highlight_prompt<'b, 's: 'b, 'p: 'b>(&'s self, prompt: &'p str, _default: bool) -> Cow<'b, str> {
+        // Swap the `$vimode` indicator in place; both have the same width, so
+        // rustyline's cursor bookkeeping (based on the raw prompt) still holds
+        if self.vi && self.vi_normal.load(Ordering::Relaxed) {
+            if let Some(at) = prompt.rfind(VI_INSERT_INDICATOR) {
+                let mut shown = prompt.to_string();
+                shown.replace_range(at..at + VI_INSERT_INDICATOR.len(), VI_NORMAL_INDICATOR);
+                return Cow::Owned(shown);
+            }
+        }
         Cow::Borrowed(prompt)
     }
 }
 
+// ========== Vi mode ==========
+
+/// What `$vimode` shows in vi mode; redrawn as [`VI_NORMAL_INDICATOR`]
+/// while normal mode is active.
+pub const VI_INSERT_INDICATOR: &str = "[I]";
+const VI_NORMAL_INDICATOR: &str = "[N]";
+
+/// Records vi mode switches. Esc enters normal mode; the insert commands
+/// (`i`, `a`, `c`, ...) leave it when pressed in normal mode.
+struct ViModeTracker {
+    normal: Arc<AtomicBool>,
+    enters_normal: bool,
+}
+
+impl ConditionalEventHandler for ViModeTracker {
+    fn handle(&self, _evt: &Event, _n: RepeatCount, _positive: bool, ctx: &EventContext) -> Option<Cmd> {
+        if ctx.mode() == EditMode::Vi {
+            if self.enters_normal {
+                self.normal.store(true, Ordering::Relaxed);
+            } else if ctx.input_mode() == InputMode::Command {
+                self.normal.store(false, Ordering::Relaxed);
+            }
+        }
+        // Fall through to the default binding
+        None
+    }
+}
+
 // ========== Validator ==========
 
 impl Validator for YafshHelper {
     fn validate(&self, ctx: &mut ValidationContext) -> Result<ValidationResult> {
-        let input = ctx.input();
-        if multiline::is_incomplete(input) {
+        // With a continuation prompt the REPL reads the next line itself,
+        // since rustyline can't show a prompt before each line of a buffer
+        if !self.prompted_continuation && multiline::is_incomplete(ctx.input()) {
             Ok(ValidationResult::Incomplete)
         } else {
             Ok(ValidationResult::Valid(None))
@@ -171,21 +364,32 @@ impl Completer for YafshHelper {
         // Find the word being typed
         let (word_start, word) = find_word_at(line, pos);
 
+        // Arguments come before the word that consumes them, so a registered
+        // completer applies when its word follows the cursor: `"sr|" cd`
+        if let Some(kind) = next_token(line, pos).and_then(|w| self.completers.get(w)) {
+            return Ok((word_start, self.complete_argument(*kind, word)));
+        }
+
         if word.is_empty() {
             return Ok((pos, Vec::new()));
         }
 
-        // Try dictionary word completion
-        let mut completions: Vec<Pair> = self
+        // Try dictionary word completion; the listing shows each word's
+        // summary so it doubles as a quick reference
+        let mut matches: Vec<(&String, &String)> = self
             .dict_words
             .iter()
-            .filter(|w| w.starts_with(word))
-            .map(|w| Pair {
-                display: w.clone(),
+            .filter(|(w, _)| w.starts_with(word))
+            .collect();
+        matches.sort();
+        let name_width = matches.iter().map(|(w, _)| w.chars().count()).max().unwrap_or(0);
+        let mut completions: Vec<Pair> = matches
+            .into_iter()
+            .map(|(w, summary)| Pair {
+                display: completion_display(w, summary, name_width),
                 replacement: w.clone(),
             })
             .collect();
-        completions.sort_by(|a, b| a.display.cmp(&b.display));
 
         // Also try filename completion
         if let Ok((file_start, file_completions)) = self.file_completer.complete(line, pos, ctx) {
@@ -206,6 +410,95 @@ impl Completer for YafshHelper {
     }
 }
 
+/// Longest summary shown next to a word in the completion list.
+const SUMMARY_WIDTH: usize = 60;
+
+/// A completion list entry: the word, padded, then its summary (if any).
+fn completion_display(name: &str, summary: &str, name_width: usize) -> String {
+    if summary.is_empty() {
+        return name.to_string();
+    }
+    let summary = if summary.chars().count() > SUMMARY_WIDTH {
+        let cut: String = summary.chars().take(SUMMARY_WIDTH - 3).collect();
+        format!("{}...", cut)
+    } else {
+        summary.to_string()
+    };
+    format!("{:<width$}  {}", name, summary, width = name_width)
+}
+
+impl YafshHelper {
+    /// Candidates for an argument typed as `word`, from the given source.
+    ///
+    /// A leading quote is kept and each candidate is closed with one, so
+    /// `"sr` completes to `"src/"`, and Tab again lists what's inside.
+    fn complete_argument(&self, kind: CompletionKind, word: &str) -> Vec<Pair> {
+        let quoted = word.starts_with('"');
+        let prefix = word.trim_start_matches('"').trim_end_matches('"');
+        let names = |iter: &mut dyn Iterator<Item = String>| -> Vec<(String, String)> {
+            iter.filter(|name| name.starts_with(prefix))
+                .map(|name| (name.clone(), name))
+                .collect()
+        };
+        let mut candidates = match kind {
+            CompletionKind::Dirs => path_candidates(prefix, true),
+            CompletionKind::Files => path_candidates(prefix, false),
+            CompletionKind::Env => names(&mut std::env::vars_os().map(|(k, _)| k.to_string_lossy().into_owned())),
+            CompletionKind::Words => names(&mut self.dict_words.keys().cloned()),
+            CompletionKind::Commands => {
+                let path_var = std::env::var("PATH").unwrap_or_default();
+                names(&mut scan_path(&path_var).into_iter())
+            }
+        };
+        candidates.sort();
+        candidates.dedup();
+        candidates
+            .into_iter()
+            .map(|(display, value)| Pair {
+                display,
+                replacement: if quoted { format!("\"{}\"", value) } else { value },
+            })
+            .collect()
+    }
+}
+
+/// Files (or only directories) matching a partially typed path, as
+/// (display name, full replacement) pairs. Directories get a trailing `/`.
+fn path_candidates(prefix: &str, dirs_only: bool) -> Vec<(String, String)> {
+    let (dir_part, name_prefix) = match prefix.rfind('/') {
+        Some(i) => (&prefix[..=i], &prefix[i + 1..]),
+        None => ("", prefix),
+    };
+    let search = if dir_part.is_empty() { ".".to_string() } else { expand_tilde(dir_part) };
+    let Ok(entries) = fs::read_dir(&search) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            // Hidden entries only when asked for with a leading dot
+            if !name.starts_with(name_prefix) || (name.starts_with('.') && !name_prefix.starts_with('.')) {
+                return None;
+            }
+            let is_dir = entry.path().is_dir();
+            if dirs_only && !is_dir {
+                return None;
+            }
+            let slash = if is_dir { "/" } else { "" };
+            Some((format!("{}{}", name, slash), format!("{}{}{}", dir_part, name, slash)))
+        })
+        .collect()
+}
+
+/// The token after the one the cursor is in, if any.
+fn next_token(line: &str, pos: usize) -> Option<&str> {
+    let rest = &line[pos..];
+    // Skip the rest of the current word, then the gap
+    let after_word = rest.find(char::is_whitespace)?;
+    rest[after_word..].split_whitespace().next()
+}
+
 /// Find the word being typed at the cursor position.
 /// Returns (start_position, word_slice).
 fn find_word_at(line: &str, pos: usize) -> (usize, &str) {
@@ -217,12 +510,300 @@ fn find_word_at(line: &str, pos: usize) -> (usize, &str) {
     (start, &line[start..pos])
 }
 
-// ========== Hinter (no-op) ==========
+// ========== Hinter (inline hints and right prompt) ==========
 
-impl Hinter for YafshHelper {
-    type Hint = String;
+/// What gets drawn after the cursor: an optional inline hint, then `$rprompt`
+/// padded to the right edge. Only a history suggestion can be accepted (with
+/// the right-arrow key); everything else is display-only.
+pub struct DisplayHint {
+    display: String,
+    completion: Option<String>,
+}
+
+impl Hint for DisplayHint {
+    fn display(&self) -> &str {
+        &self.display
+    }
 
-    fn hint(&self, _line: &str, _pos: usize, _ctx: &Context<'_>) -> Option<String> {
+    fn completion(&self) -> Option<&str> {
+        self.completion.as_deref()
+    }
+}
+
+impl YafshHelper {
+    /// Inline hint for the word just typed: its stack effect when it is a
+    /// documented word, otherwise (with `set-autosuggest` on) the rest of the
+    /// most recent history entry starting with the line.
+    /// Returns the hint text and whether it can be accepted.
+    fn inline_hint(&self, line: &str, pos: usize, ctx: &Context<'_>) -> Option<(String, bool)> {
+        let (_, word) = find_word_at(line, pos);
+        if let Some(effect) = self.dict_words.get(word).and_then(|doc| stack_effect(doc)) {
+            return Some((format!(" {}", effect), false));
+        }
+        if self.autosuggest {
+            let rest = HistoryHinter::new().hint(line, pos, ctx)?;
+            if !rest.contains('\n') {
+                return Some((rest, true));
+            }
+        }
         None
     }
+
+    /// The dimmed stack predicted for `line`, when the preview is on and the
+    /// line only uses pure words. Cut to the terminal width so it stays on
+    /// one row below the input.
+    fn preview_line(&self, line: &str) -> Option<String> {
+        let (stack, words) = self.stack_preview.as_ref()?;
+        if line.trim().is_empty() {
+            return None;
+        }
+        let text = match preview::simulate(stack, words, line)? {
+            Ok(predicted) => format!("→ {}", preview::format_stack(&predicted)),
+            Err(e) => format!("→ {}", e),
+        };
+        let width = terminal::width().unwrap_or(80).saturating_sub(1);
+        let text: String = text.chars().take(width).collect();
+        Some(self.theme.paint("hint", &text))
+    }
+}
+
+/// The `( before -- after )` part at the start of a doc string.
+fn stack_effect(doc: &str) -> Option<&str> {
+    if !doc.starts_with('(') {
+        return None;
+    }
+    let end = doc.find(')')?;
+    Some(&doc[..=end])
+}
+
+impl Hinter for YafshHelper {
+    type Hint = DisplayHint;
+
+    /// Draw the inline hint and `$rprompt` while the cursor is at the end of
+    /// a single-line input. `$rprompt` only shows when it fits. rustyline
+    /// drops hints when a line is accepted, so neither clutters scrollback.
+    fn hint(&self, line: &str, pos: usize, ctx: &Context<'_>) -> Option<DisplayHint> {
+        if pos != line.len() || line.contains('\n') {
+            return None;
+        }
+        let (text, acceptable) = self.inline_hint(line, pos, ctx).unwrap_or_default();
+        let mut display = if text.is_empty() {
+            String::new()
+        } else {
+            self.theme.paint("hint", &text)
+        };
+
+        if let (Some(rprompt), Some(width)) = (&self.rprompt, terminal::width()) {
+            let used = self.prompt_width + visible_width(line) + visible_width(&text);
+            // Keep the last column free so the terminal never wraps
+            if let Some(free) = width.checked_sub(used + visible_width(rprompt) + 1) {
+                display.push_str(&" ".repeat(free));
+                display.push_str(rprompt);
+            }
+        }
+
+        if let Some(predicted) = self.preview_line(line) {
+            display.push('\n');
+            display.push_str(&predicted);
+        }
+
+        if display.is_empty() {
+            return None;
+        }
+        Some(DisplayHint {
+            display,
+            completion: acceptable.then_some(text),
+        })
+    }
+}
+
+/// Width of a string on screen, ignoring ANSI escape sequences.
+fn visible_width(s: &str) -> usize {
+    let mut width = 0;
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // Skip a CSI sequence: ESC [ params... final byte in @..~
+            if chars.next() == Some('[') {
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        break;
+                    }
+                }
+            }
+        } else {
+            width += 1;
+        }
+    }
+    width
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_visible_width_ignores_ansi() {
+        assert_eq!(visible_width("yafsh> "), 7);
+        assert_eq!(visible_width("\x1b[32mmain\x1b[0m 12:30"), 10);
+        assert_eq!(visible_width(""), 0);
+    }
+
+    #[test]
+    fn test_next_token() {
+        assert_eq!(next_token("\"sr cd", 3), Some("cd"));
+        assert_eq!(next_token(" cd", 0), Some("cd"));
+        assert_eq!(next_token("\"src\" cd", 2), Some("cd"));
+        assert_eq!(next_token("ls", 2), None);
+        assert_eq!(next_token("ls  ", 2), None);
+    }
+
+    #[test]
+    fn test_path_candidates() {
+        let root = std::env::temp_dir().join(format!("yafsh_complete_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::create_dir_all(root.join(".hidden")).unwrap();
+        fs::write(root.join("setup.sh"), "").unwrap();
+        let base = format!("{}/", root.display());
+
+        let all = path_candidates(&format!("{}s", base), false);
+        let mut shown: Vec<&str> = all.iter().map(|(d, _)| d.as_str()).collect();
+        shown.sort();
+        assert_eq!(shown, vec!["setup.sh", "src/"]);
+
+        let dirs = path_candidates(&base, true);
+        assert_eq!(dirs, vec![("src/".to_string(), format!("{}src/", base))]);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_complete_argument_keeps_quotes() {
+        let mut helper = YafshHelper::new();
+        helper.update_words(["dup", "drop", "swap"].map(|w| (w.to_string(), String::new())));
+        let pairs = helper.complete_argument(CompletionKind::Words, "\"d");
+        let replacements: Vec<&str> = pairs.iter().map(|p| p.replacement.as_str()).collect();
+        assert_eq!(replacements, vec!["\"drop\"", "\"dup\""]);
+    }
+
+    #[test]
+    fn test_completion_display() {
+        assert_eq!(completion_display("dup", "", 5), "dup");
+        assert_eq!(
+            completion_display("dup", "( a -- a a ) Duplicate top item", 5),
+            "dup    ( a -- a a ) Duplicate top item"
+        );
+        let long = "x".repeat(100);
+        assert_eq!(completion_display("w", &long, 1).chars().count(), 1 + 2 + SUMMARY_WIDTH);
+    }
+
+    #[test]
+    fn test_stack_effect() {
+        assert_eq!(stack_effect("( a -- a a ) Duplicate top item"), Some("( a -- a a )"));
+        assert_eq!(stack_effect("List all available words"), None);
+        assert_eq!(stack_effect(": dup *"), None);
+    }
+
+    #[test]
+    fn test_hint_shows_stack_effect() {
+        let history = rustyline::history::DefaultHistory::new();
+        let ctx = Context::new(&history);
+        let mut helper = YafshHelper::new();
+        helper.update_words([("dup".to_string(), "( a -- a a ) Duplicate top item".to_string())]);
+
+        let hint = helper.hint("1 dup", 5, &ctx).unwrap();
+        assert_eq!(hint.display(), "\x1b[2m ( a -- a a )\x1b[0m");
+        assert_eq!(hint.completion(), None);
+        assert!(helper.hint("1 dup ", 6, &ctx).is_none());
+        assert!(helper.hint("1 dup", 3, &ctx).is_none());
+    }
+
+    #[test]
+    fn test_hint_history_suggestion() {
+        use rustyline::history::History;
+        let mut history = rustyline::history::DefaultHistory::new();
+        history.add("\"src\" find-files").unwrap();
+        let ctx = Context::new(&history);
+        let mut helper = YafshHelper::new();
+        assert!(helper.hint("\"sr", 3, &ctx).is_none());
+
+        helper.set_autosuggest(true);
+        let hint = helper.hint("\"sr", 3, &ctx).unwrap();
+        assert_eq!(hint.completion(), Some("c\" find-files"));
+    }
+
+    fn matched_texts(line: &str, pos: usize) -> Option<(String, String)> {
+        let tokens = tokenizer::tokenize_with_positions(line);
+        matching_delimiter(&tokens, pos)
+            .map(|(a, b)| (tokens[a].text.clone(), tokens[b].text.clone()))
+    }
+
+    #[test]
+    fn test_matching_delimiter_nested() {
+        let line = ": f 0 do begin 1 until loop ;";
+        // Cursor on `loop` finds `do`, skipping the inner begin/until
+        let (a, b) = matched_texts(line, line.find("loop").unwrap()).unwrap();
+        assert_eq!((a.as_str(), b.as_str()), ("do", "loop"));
+        // Right after `;` (cursor at end of line) still counts
+        let (a, b) = matched_texts(line, line.len()).unwrap();
+        assert_eq!((a.as_str(), b.as_str()), (":", ";"));
+        // And the other way round, from the opener
+        let (_, b) = matched_texts(line, line.find("begin").unwrap() + 2).unwrap();
+        assert_eq!(b, "until");
+    }
+
+    #[test]
+    fn test_matching_delimiter_none() {
+        assert_eq!(matched_texts("1 if 2", 3), None);
+        assert_eq!(matched_texts("1 if 2 then", 6), None);
+        assert_eq!(matched_texts("\"then\" if", 2), None);
+    }
+
+    #[test]
+    fn test_highlight_matching_delimiter() {
+        let helper = YafshHelper::new();
+        let out = helper.highlight("1 if 2 then", 11);
+        let matched = helper.theme.start("match");
+        assert_eq!(out.matches(matched).count(), 2);
+        let out = helper.highlight("1 if 2 then", 6);
+        assert!(!out.contains(matched));
+    }
+
+    #[test]
+    fn test_highlight_follows_theme() {
+        let mut helper = YafshHelper::new();
+        let mut theme = Theme::default();
+        theme.set("number", "bold").unwrap();
+        helper.set_theme(&theme);
+        assert_eq!(helper.highlight("42", 0), "\x1b[1m42\x1b[0m");
+
+        theme.enabled = false;
+        helper.set_theme(&theme);
+        assert!(matches!(helper.highlight("42 \"a\"", 0), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_highlight_unknown_tokens_red() {
+        let mut helper = YafshHelper::new();
+        helper.update_words([("dup".to_string(), String::new())]);
+        helper.executables = HashSet::from(["sh".to_string()]);
+        let unknown = helper.theme.start("unknown").to_string();
+        let red = |line: &str| helper.highlight(line, line.len() + 1).contains(&unknown);
+
+        assert!(red("1 dpu"));
+        assert!(!red("1 dup"));
+        assert!(!red("sh -la *.rs !!"));
+        assert!(!red(": sq dup ; 3 sq"));
+        assert!(!red("\"dpu\" /tmp"));
+        // The word at the cursor is still being typed
+        assert!(!helper.highlight("1 dp", 4).contains(&unknown));
+    }
+
+    #[test]
+    fn test_set_rprompt_ignores_empty() {
+        let mut helper = YafshHelper::new();
+        helper.set_rprompt("\x1b[1myafsh>\x1b[0m ", Some(String::new()));
+        assert_eq!(helper.prompt_width, 7);
+        assert!(helper.rprompt.is_none());
+    }
 }
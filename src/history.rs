@@ -0,0 +1,264 @@
+use std::collections::HashMap;
+
+use crate::eval::glob_matches;
+use crate::tokenizer;
+
+/// What gets recorded in the interactive history, set from `~/.yafshrc`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HistoryConfig {
+    /// Most entries kept (older ones are dropped first)
+    pub max_size: usize,
+    /// Skip a line identical to the one before it
+    pub dedupe: bool,
+    /// Skip lines typed with a leading space
+    pub ignore_space: bool,
+    /// Glob patterns; a line matching any of them isn't recorded
+    pub ignore_patterns: Vec<String>,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        HistoryConfig {
+            max_size: 100,
+            dedupe: true,
+            ignore_space: false,
+            ignore_patterns: Vec::new(),
+        }
+    }
+}
+
+impl HistoryConfig {
+    /// Whether a line, as typed (before trimming), should be recorded.
+    ///
+    /// Duplicates are left to the line editor, which knows the previous entry.
+    pub fn should_record(&self, raw: &str) -> bool {
+        if self.ignore_space && raw.starts_with(' ') {
+            return false;
+        }
+        let line = raw.trim();
+        !self
+            .ignore_patterns
+            .iter()
+            .any(|pattern| glob_matches(pattern, line))
+    }
+}
+
+/// Expand history references in an input line.
+///
+/// Whole unquoted tokens are replaced: `!!` by the previous entry, `!n` by
+/// entry n (as numbered by `history`), and `!-n` by the n-th most recent
+/// entry. `history` is oldest first. Returns `Ok(None)` when the line has no
+/// references, so callers can tell whether to echo the expansion.
+pub fn expand(line: &str, history: &[String]) -> Result<Option<String>, String> {
+    let mut out = String::with_capacity(line.len());
+    let mut expanded = false;
+    let mut word = String::new();
+    let mut in_quote = false;
+
+    let mut flush = |word: &mut String, out: &mut String| -> Result<(), String> {
+        match resolve(word, history)? {
+            Some(entry) => {
+                out.push_str(entry);
+                expanded = true;
+            }
+            None => out.push_str(word),
+        }
+        word.clear();
+        Ok(())
+    };
+
+    let mut prev = None;
+    for c in line.chars() {
+        // `\"` inside quotes doesn't end the string
+        if c == '"' && !(in_quote && prev == Some('\\')) {
+            in_quote = !in_quote;
+        }
+        prev = Some(c);
+        if c.is_whitespace() && !in_quote {
+            flush(&mut word, &mut out)?;
+            out.push(c);
+        } else {
+            word.push(c);
+        }
+    }
+    flush(&mut word, &mut out)?;
+
+    Ok(expanded.then_some(out))
+}
+
+/// The entry a `!` token refers to, `None` if the token isn't a reference.
+fn resolve<'h>(token: &str, history: &'h [String]) -> Result<Option<&'h str>, String> {
+    let Some(spec) = token.strip_prefix('!') else {
+        return Ok(None);
+    };
+    let index = if spec == "!" {
+        history.len().checked_sub(1)
+    } else if let Some(back) = spec.strip_prefix('-') {
+        match back.parse::<usize>() {
+            Ok(n) if n > 0 => history.len().checked_sub(n),
+            _ => return Ok(None),
+        }
+    } else {
+        match spec.parse::<usize>() {
+            Ok(n) if n > 0 => Some(n - 1),
+            _ => return Ok(None),
+        }
+    };
+    index
+        .and_then(|i| history.get(i))
+        .map(|entry| Some(entry.as_str()))
+        .ok_or_else(|| format!("{}: event not found", token))
+}
+
+/// Timing and outcome of one history entry, kept in a sidecar file next to
+/// the plain history (see `config::history_meta_path`).
+#[derive(Clone, Debug, PartialEq)]
+pub struct HistoryRecord {
+    /// Unix seconds when the line started running
+    pub timestamp: u64,
+    pub duration_ms: u64,
+    /// Exit code afterwards (1 if the line failed with an error)
+    pub exit_code: i32,
+    pub line: String,
+}
+
+impl HistoryRecord {
+    /// One tab-separated line; newlines in multi-line entries become spaces.
+    pub fn to_line(&self) -> String {
+        let line: Vec<&str> = self.line.split_whitespace().collect();
+        format!(
+            "{}\t{}\t{}\t{}\n",
+            self.timestamp,
+            self.duration_ms,
+            self.exit_code,
+            line.join(" ")
+        )
+    }
+
+    /// Parse a line written by `to_line`, skipping anything malformed.
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.splitn(4, '\t');
+        Some(HistoryRecord {
+            timestamp: fields.next()?.parse().ok()?,
+            duration_ms: fields.next()?.parse().ok()?,
+            exit_code: fields.next()?.parse().ok()?,
+            line: fields.next()?.trim_end_matches('\n').to_string(),
+        })
+    }
+}
+
+/// Summary of recorded history for `history-stats`.
+pub struct HistoryStats<'r> {
+    /// Words and commands by how often they were used, most used first
+    pub top_words: Vec<(String, usize)>,
+    /// Slowest entries, slowest first
+    pub slowest: Vec<&'r HistoryRecord>,
+}
+
+/// Count word usage and find the slowest entries, `limit` of each.
+///
+/// Literals (numbers and quoted strings) aren't words, so they don't count.
+pub fn stats(records: &[HistoryRecord], limit: usize) -> HistoryStats<'_> {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for record in records {
+        for token in tokenizer::tokenize(&record.line) {
+            if !token.quoted && !tokenizer::is_int(&token.text) {
+                *counts.entry(token.text).or_default() += 1;
+            }
+        }
+    }
+    let mut top_words: Vec<(String, usize)> = counts.into_iter().collect();
+    top_words.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    top_words.truncate(limit);
+
+    let mut slowest: Vec<&HistoryRecord> = records.iter().collect();
+    slowest.sort_by_key(|r| std::cmp::Reverse(r.duration_ms));
+    slowest.truncate(limit);
+
+    HistoryStats { top_words, slowest }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_should_record() {
+        let mut config = HistoryConfig::default();
+        assert!(config.should_record(" ls"));
+        config.ignore_space = true;
+        assert!(!config.should_record(" ls"));
+        assert!(config.should_record("ls"));
+
+        config.ignore_patterns = vec!["exit".into(), "history*".into()];
+        assert!(!config.should_record("exit"));
+        assert!(!config.should_record("history-clear"));
+        assert!(config.should_record("\"exit\" type"));
+    }
+
+    fn record(duration_ms: u64, line: &str) -> HistoryRecord {
+        HistoryRecord {
+            timestamp: 1_700_000_000,
+            duration_ms,
+            exit_code: 0,
+            line: line.into(),
+        }
+    }
+
+    #[test]
+    fn test_record_round_trip() {
+        let r = record(1250, ": sq\ndup * ;");
+        assert_eq!(r.to_line(), "1700000000\t1250\t0\t: sq dup * ;\n");
+        let parsed = HistoryRecord::parse(&r.to_line()).unwrap();
+        assert_eq!(parsed.line, ": sq dup * ;");
+        assert_eq!(parsed.duration_ms, 1250);
+        assert_eq!(HistoryRecord::parse("garbage"), None);
+    }
+
+    #[test]
+    fn test_stats() {
+        let records = vec![
+            record(5, "\"src\" ls"),
+            record(900, "\"make\" exec"),
+            record(20, "ls 2 3 +"),
+        ];
+        let st = stats(&records, 2);
+        assert_eq!(
+            st.top_words,
+            vec![("ls".to_string(), 2), ("+".to_string(), 1)]
+        );
+        let slow: Vec<u64> = st.slowest.iter().map(|r| r.duration_ms).collect();
+        assert_eq!(slow, vec![900, 20]);
+    }
+
+    fn hist() -> Vec<String> {
+        vec!["ls".into(), "2 3 +".into(), "\"a\" .".into()]
+    }
+
+    #[test]
+    fn test_no_references() {
+        assert_eq!(expand("1 2 +", &hist()), Ok(None));
+        assert_eq!(expand("\"!!\" .", &hist()), Ok(None));
+        assert_eq!(expand("!foo !0", &hist()), Ok(None));
+    }
+
+    #[test]
+    fn test_bang_bang() {
+        assert_eq!(expand("!!", &hist()), Ok(Some("\"a\" .".into())));
+        assert_eq!(expand("!! !!", &hist()), Ok(Some("\"a\" . \"a\" .".into())));
+    }
+
+    #[test]
+    fn test_numbered_and_relative() {
+        assert_eq!(expand("!1", &hist()), Ok(Some("ls".into())));
+        assert_eq!(expand("!2 .", &hist()), Ok(Some("2 3 + .".into())));
+        assert_eq!(expand("!-3", &hist()), Ok(Some("ls".into())));
+    }
+
+    #[test]
+    fn test_event_not_found() {
+        assert_eq!(expand("!9", &hist()), Err("!9: event not found".into()));
+        assert_eq!(expand("!!", &[]), Err("!!: event not found".into()));
+        assert_eq!(expand("!-4", &hist()), Err("!-4: event not found".into()));
+    }
+}
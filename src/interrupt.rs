@@ -0,0 +1,69 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// Set by the SIGINT handler, cleared before each line is evaluated.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Granularity at which interruptible sleeps check the flag.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Install the Ctrl-C handler.
+///
+/// While a line is being evaluated the terminal is in cooked mode, so Ctrl-C
+/// delivers SIGINT. Child processes in the foreground group still die from it;
+/// the shell itself just records the interrupt for long-running words to notice.
+#[cfg(feature = "native")]
+pub fn install_handler() {
+    let _ = ctrlc::set_handler(|| INTERRUPTED.store(true, Ordering::SeqCst));
+}
+
+/// Whether Ctrl-C has been pressed since the last `clear`.
+pub fn is_interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Forget any pending interrupt.
+pub fn clear() {
+    INTERRUPTED.store(false, Ordering::SeqCst);
+}
+
+/// Sleep for `duration`, waking early on Ctrl-C. Returns false if interrupted.
+///
+/// A duration too long to represent as a deadline sleeps until interrupted.
+pub fn sleep(duration: Duration) -> bool {
+    let deadline = deadline_after(duration);
+    loop {
+        if is_interrupted() {
+            return false;
+        }
+        let wait = match deadline {
+            Some(deadline) => {
+                let now = Instant::now();
+                if now >= deadline {
+                    return true;
+                }
+                POLL_INTERVAL.min(deadline - now)
+            }
+            None => POLL_INTERVAL,
+        };
+        std::thread::sleep(wait);
+    }
+}
+
+/// When a sleep of `duration` starting now ends, or `None` if that is past
+/// what `Instant` can hold.
+fn deadline_after(duration: Duration) -> Option<Instant> {
+    Instant::now().checked_add(duration)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deadline_after_huge_duration() {
+        assert!(deadline_after(Duration::from_millis(10)).is_some());
+        assert_eq!(deadline_after(Duration::from_secs(i64::MAX as u64)), None);
+        assert_eq!(deadline_after(Duration::MAX), None);
+    }
+}
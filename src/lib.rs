@@ -1,8 +1,20 @@
 pub mod builtins;
+pub mod compile;
 pub mod config;
 pub mod eval;
+#[cfg(feature = "native")]
 pub mod highlight;
+pub mod history;
+pub mod interrupt;
+pub mod local_rc;
 pub mod loops;
 pub mod multiline;
+pub mod preview;
+pub mod prompt_cache;
+pub mod sink;
+pub mod suggest;
+pub mod terminal;
+pub mod theme;
 pub mod tokenizer;
 pub mod types;
+pub mod undo;
@@ -0,0 +1,299 @@
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use sha2::{Digest, Sha256};
+
+use crate::config;
+use crate::types::{State, Word};
+
+/// Name of a project-local RC file.
+pub const LOCAL_RC_NAME: &str = ".yafshrc.local";
+
+/// Per-directory RC files: found by walking up from the working directory,
+/// loaded only once allowed with `local-allow`, and unloaded on leaving.
+#[derive(Clone, Default)]
+pub struct LocalRc {
+    /// Whether local RC files are looked for at all (see `set-local-rc`)
+    pub enabled: bool,
+    /// File listing allowed RC files and their content hashes
+    pub trust_file: Option<PathBuf>,
+    /// The loaded file, if any
+    active: Option<ActiveRc>,
+    /// File we already said isn't allowed, so the note shows once
+    notified: Option<PathBuf>,
+}
+
+/// A loaded local RC file and the dictionary entries it replaced.
+#[derive(Clone)]
+struct ActiveRc {
+    path: PathBuf,
+    /// Each word or alias the file set, with what it was before (`None`: new)
+    saved: Vec<(String, Option<Word>)>,
+}
+
+/// The nearest local RC file in `dir` or one of its parents.
+pub fn find(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|d| d.join(LOCAL_RC_NAME))
+        .find(|p| p.is_file())
+}
+
+/// Load, unload, or switch local RC files to match the working directory.
+///
+/// Called before each prompt, so every way of changing directory counts.
+pub fn sync(state: &mut State) {
+    if let Ok(cwd) = std::env::current_dir() {
+        sync_dir(state, &cwd);
+    }
+}
+
+/// [`sync`] for a given directory.
+pub fn sync_dir(state: &mut State, dir: &Path) {
+    let found = if state.local_rc.enabled {
+        find(dir)
+    } else {
+        None
+    };
+    let active = state.local_rc.active.as_ref().map(|a| a.path.clone());
+    if found == active {
+        return;
+    }
+    unload(state);
+    let Some(path) = found else {
+        state.local_rc.notified = None;
+        return;
+    };
+    // Read once, so the bytes that were checked are the bytes that run
+    let contents = std::fs::read(&path).unwrap_or_default();
+    if is_trusted(state, &path, &contents) {
+        load(state, path, &String::from_utf8_lossy(&contents));
+    } else if state.local_rc.notified.as_ref() != Some(&path) {
+        eprintln!(
+            "yafsh: {} is not allowed; run local-allow to load it",
+            path.display()
+        );
+        state.local_rc.notified = Some(path);
+    }
+}
+
+/// Evaluate a local RC file's text, remembering the dictionary entries
+/// (words, aliases, deferred words) it changes.
+///
+/// Settings it changes, such as `set-max-iterations`, stay in effect.
+fn load(state: &mut State, path: PathBuf, contents: &str) {
+    let before = state.dict.clone();
+    config::source_text(state, &path, contents);
+    let saved = state
+        .dict
+        .iter()
+        .filter(|(name, word)| !before.get(*name).is_some_and(|old| same_word(old, word)))
+        .map(|(name, _)| (name.clone(), before.get(name).cloned()))
+        .collect();
+    eprintln!("yafsh: loaded {}", path.display());
+    state.local_rc.active = Some(ActiveRc { path, saved });
+}
+
+/// Whether a dictionary entry was left as it was.
+fn same_word(a: &Word, b: &Word) -> bool {
+    match (a, b) {
+        (Word::Builtin(..), Word::Builtin(..)) => true,
+        (Word::Defined(_, a), Word::Defined(_, b)) => Rc::ptr_eq(a, b),
+        (Word::Alias(a), Word::Alias(b)) | (Word::ShellCmd(a), Word::ShellCmd(b)) => a == b,
+        (Word::Deferred(a), Word::Deferred(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Undo the active local RC file's definitions, restoring what they replaced.
+fn unload(state: &mut State) {
+    let Some(active) = state.local_rc.active.take() else {
+        return;
+    };
+    for (name, before) in active.saved {
+        match before {
+            Some(word) => state.dict_mut().insert(name, word),
+            None => state.dict_mut().remove(&name),
+        };
+    }
+    eprintln!("yafsh: unloaded {}", active.path.display());
+}
+
+/// Hex SHA-256 of a file's contents, so edits revoke trust.
+fn content_hash(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Trust file entries as (hash, path) pairs.
+fn read_trusted(state: &State) -> Vec<(String, PathBuf)> {
+    let Some(file) = &state.local_rc.trust_file else {
+        return Vec::new();
+    };
+    std::fs::read_to_string(file)
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|l| l.split_once('\t'))
+        .map(|(hash, path)| (hash.to_string(), PathBuf::from(path)))
+        .collect()
+}
+
+fn write_trusted(state: &State, entries: &[(String, PathBuf)]) -> Result<(), String> {
+    let file = state
+        .local_rc
+        .trust_file
+        .as_ref()
+        .ok_or("no place to record allowed files (HOME not set)")?;
+    if let Some(dir) = file.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    let text: String = entries
+        .iter()
+        .map(|(hash, path)| format!("{}\t{}\n", hash, path.display()))
+        .collect();
+    std::fs::write(file, text).map_err(|e| e.to_string())
+}
+
+/// Whether `path` was allowed with `contents`.
+fn is_trusted(state: &State, path: &Path, contents: &[u8]) -> bool {
+    let hash = content_hash(contents);
+    read_trusted(state)
+        .iter()
+        .any(|(h, p)| *h == hash && p == path)
+}
+
+/// Allow the local RC file for `dir` with its current contents.
+pub fn allow(state: &mut State, dir: &Path) -> Result<PathBuf, String> {
+    let path =
+        find(dir).ok_or_else(|| format!("no {} here or in a parent directory", LOCAL_RC_NAME))?;
+    let contents = std::fs::read(&path).map_err(|_| format!("cannot read {}", path.display()))?;
+    let hash = content_hash(&contents);
+    let mut entries = read_trusted(state);
+    entries.retain(|(_, p)| *p != path);
+    entries.push((hash, path.clone()));
+    write_trusted(state, &entries)?;
+    state.local_rc.notified = None;
+    Ok(path)
+}
+
+/// Revoke the local RC file for `dir`, unloading it if it is active.
+pub fn deny(state: &mut State, dir: &Path) -> Result<PathBuf, String> {
+    let path =
+        find(dir).ok_or_else(|| format!("no {} here or in a parent directory", LOCAL_RC_NAME))?;
+    let mut entries = read_trusted(state);
+    entries.retain(|(_, p)| *p != path);
+    write_trusted(state, &entries)?;
+    if state
+        .local_rc
+        .active
+        .as_ref()
+        .is_some_and(|a| a.path == path)
+    {
+        unload(state);
+    }
+    // Don't nag right after an explicit deny
+    state.local_rc.notified = Some(path.clone());
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builtins;
+    use crate::eval;
+
+    fn setup(name: &str) -> (State, PathBuf) {
+        let root =
+            std::env::temp_dir().join(format!("yafsh_local_rc_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("proj/sub")).unwrap();
+        std::fs::write(
+            root.join("proj").join(LOCAL_RC_NAME),
+            ": build \"built\" ;\nredefine-ok : dup 7 ;\n",
+        )
+        .unwrap();
+        let mut state = State::new();
+        builtins::register_builtins(&mut state);
+        state.local_rc.enabled = true;
+        state.local_rc.trust_file = Some(root.join("trusted"));
+        (state, root)
+    }
+
+    #[test]
+    fn test_unload_restores_aliases() {
+        let (mut state, root) = setup("alias");
+        let proj = root.join("proj");
+        std::fs::write(
+            proj.join(LOCAL_RC_NAME),
+            "\"build\" \"b\" alias\n\"x\" defer\n",
+        )
+        .unwrap();
+        eval::eval_line(&mut state, "\"old\" \"b\" alias").unwrap();
+        allow(&mut state, &proj).unwrap();
+        sync_dir(&mut state, &proj);
+        assert!(matches!(state.dict.get("b"), Some(Word::Alias(t)) if t == "build"));
+        assert!(state.dict.contains_key("x"));
+
+        sync_dir(&mut state, &root);
+        assert!(matches!(state.dict.get("b"), Some(Word::Alias(t)) if t == "old"));
+        assert!(!state.dict.contains_key("x"));
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_untrusted_not_loaded() {
+        let (mut state, root) = setup("untrusted");
+        sync_dir(&mut state, &root.join("proj/sub"));
+        assert!(!state.dict.contains_key("build"));
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_allow_load_and_unload_on_leave() {
+        let (mut state, root) = setup("allow");
+        let proj = root.join("proj");
+        allow(&mut state, &proj.join("sub")).unwrap();
+
+        sync_dir(&mut state, &proj.join("sub"));
+        eval::eval_line(&mut state, "build dup").unwrap();
+        assert_eq!(
+            state.stack,
+            vec![
+                crate::types::Value::Str("built".into()),
+                crate::types::Value::Int(7)
+            ]
+        );
+
+        // Leaving removes `build` and brings the builtin `dup` back
+        sync_dir(&mut state, &root);
+        assert!(!state.dict.contains_key("build"));
+        assert!(matches!(state.dict.get("dup"), Some(Word::Builtin(..))));
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_edit_revokes_trust() {
+        let (mut state, root) = setup("edit");
+        let proj = root.join("proj");
+        allow(&mut state, &proj).unwrap();
+        std::fs::write(proj.join(LOCAL_RC_NAME), ": build \"evil\" ;\n").unwrap();
+        sync_dir(&mut state, &proj);
+        assert!(!state.dict.contains_key("build"));
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_deny_unloads() {
+        let (mut state, root) = setup("deny");
+        let proj = root.join("proj");
+        allow(&mut state, &proj).unwrap();
+        sync_dir(&mut state, &proj);
+        assert!(state.dict.contains_key("build"));
+        deny(&mut state, &proj).unwrap();
+        assert!(!state.dict.contains_key("build"));
+        sync_dir(&mut state, &proj);
+        assert!(!state.dict.contains_key("build"));
+        let _ = std::fs::remove_dir_all(&root);
+    }
+}
@@ -1,11 +1,53 @@
 use crate::eval;
+use crate::interrupt;
+use crate::tokenizer::Token;
 use crate::types::{LoopInfo, LoopType, State, Value};
 
+/// Default for `set-max-iterations`: enough for real work, but a runaway
+/// `begin ... until` gives up within seconds.
+pub const DEFAULT_MAX_ITERATIONS: u64 = 10_000_000;
+
+/// Called before each loop iteration: fails on Ctrl-C or once the loop has
+/// already run `max_iterations` times (0 means no limit).
+fn check_iteration(state: &State, op: &str, done: u64) -> Result<(), String> {
+    check_interrupt(op)?;
+    if state.max_iterations > 0 && done >= state.max_iterations {
+        return Err(format!(
+            "{}: stopped after {} iterations (see set-max-iterations)",
+            op, state.max_iterations
+        ));
+    }
+    Ok(())
+}
+
+/// Fails once Ctrl-C has been pressed.
+fn check_interrupt(op: &str) -> Result<(), String> {
+    if interrupt::is_interrupted() {
+        return Err(format!("{}: interrupted", op));
+    }
+    Ok(())
+}
+
 // ========== Loop body splitting ==========
 
+/// Position of the `while` belonging to a loop body, skipping any inside
+/// nested loops.
+pub fn top_level_while(body: &[Token]) -> Option<usize> {
+    let mut depth = 0usize;
+    for (i, token) in body.iter().enumerate() {
+        match token.keyword() {
+            "begin" | "do" | "?do" => depth += 1,
+            "until" | "repeat" | "again" | "loop" | "+loop" => depth = depth.saturating_sub(1),
+            "while" if depth == 0 => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
 /// Split tokens at the `while` keyword, returning (before_while, after_while).
-fn split_while_body(tokens: &[String]) -> Result<(Vec<String>, Vec<String>), String> {
-    if let Some(pos) = tokens.iter().position(|t| t == "while") {
+fn split_while_body(tokens: &[Token]) -> Result<(Vec<Token>, Vec<Token>), String> {
+    if let Some(pos) = top_level_while(tokens) {
         Ok((tokens[..pos].to_vec(), tokens[pos + 1..].to_vec()))
     } else {
         Err("repeat: no matching while".into())
@@ -14,24 +56,64 @@ fn split_while_body(tokens: &[String]) -> Result<(Vec<String>, Vec<String>), Str
 
 // ========== Loop executors ==========
 
+/// Run one pass of a loop body with `info` on the loop stack.
+///
+/// Returns `Ok(false)` if the body ran `leave`, meaning the loop should stop.
+fn run_iteration<B: LoopBody + ?Sized>(
+    state: &mut State,
+    info: LoopInfo,
+    body: &B,
+) -> Result<bool, String> {
+    state.loop_stack.push(info);
+    let result = body.run(state);
+    state.loop_stack.pop();
+    match result {
+        Ok(()) => Ok(true),
+        Err(_) if std::mem::take(&mut state.leave_requested) => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Something a loop executor can run once per iteration: tokens collected
+/// at the prompt, or a defined word's compiled body (see [`crate::compile`]).
+pub trait LoopBody {
+    fn run(&self, state: &mut State) -> Result<(), String>;
+}
+
+impl LoopBody for [Token] {
+    fn run(&self, state: &mut State) -> Result<(), String> {
+        for token in self {
+            eval::eval_token(state, &token.text, token.quoted)?;
+        }
+        Ok(())
+    }
+}
+
+/// Pop the `start limit` pair a counted loop runs over.
+pub fn pop_bounds(state: &mut State) -> Result<(i64, i64), String> {
+    match (state.stack.pop(), state.stack.pop()) {
+        (Some(Value::Int(limit)), Some(Value::Int(start))) => Ok((start, limit)),
+        _ => Err("do: stack underflow (needs start and limit)".into()),
+    }
+}
+
 /// Execute a `begin ... until` loop.
 ///
 /// Runs the body, then pops a condition from the stack.
 /// If condition is `Int(0)` (false), loops again.
 /// If condition is non-zero, exits.
 /// Executes at least once (condition checked at end).
-pub fn execute_begin_until(state: &mut State, body: &[String]) -> Result<(), String> {
-    loop {
-        // Push loop info for nesting tracking
-        state.loop_stack.push(LoopInfo::BeginUntilLoop);
+pub fn execute_begin_until<B: LoopBody + ?Sized>(
+    state: &mut State,
+    body: &B,
+) -> Result<(), String> {
+    for done in 0.. {
+        check_iteration(state, "begin", done)?;
 
-        // Execute body
-        for token in body {
-            eval::eval_token(state, token, false)?;
+        if !run_iteration(state, LoopInfo::BeginUntilLoop, body)? {
+            return Ok(());
         }
 
-        state.loop_stack.pop();
-
         // Check condition
         match state.stack.pop() {
             Some(Value::Int(0)) => {
@@ -45,6 +127,23 @@ pub fn execute_begin_until(state: &mut State, body: &[String]) -> Result<(), Str
             None => return Err("until: stack underflow (needs condition)".into()),
         }
     }
+    Ok(())
+}
+
+/// Execute a `begin ... again` loop.
+///
+/// Runs the body until it calls `leave` (or `exit`), or fails. The loop is
+/// meant to run forever, so only Ctrl-C stops it, not `set-max-iterations`.
+pub fn execute_begin_again<B: LoopBody + ?Sized>(
+    state: &mut State,
+    body: &B,
+) -> Result<(), String> {
+    loop {
+        check_interrupt("begin")?;
+        if !run_iteration(state, LoopInfo::BeginAgainLoop, body)? {
+            return Ok(());
+        }
+    }
 }
 
 /// Execute a `begin ... while ... repeat` loop.
@@ -53,72 +152,63 @@ pub fn execute_begin_until(state: &mut State, body: &[String]) -> Result<(), Str
 /// If condition is non-zero (true), runs `after_while` and repeats.
 /// If condition is zero (false), exits.
 /// May not execute body if condition is initially false.
-pub fn execute_begin_while(
+pub fn execute_begin_while<B: LoopBody + ?Sized>(
     state: &mut State,
-    before_while: &[String],
-    after_while: &[String],
+    before_while: &B,
+    after_while: &B,
 ) -> Result<(), String> {
-    loop {
-        state.loop_stack.push(LoopInfo::BeginWhileLoop);
+    for done in 0.. {
+        check_iteration(state, "begin", done)?;
 
         // Execute before_while (condition computation)
-        for token in before_while {
-            eval::eval_token(state, token, false)?;
+        if !run_iteration(state, LoopInfo::BeginWhileLoop, before_while)? {
+            return Ok(());
         }
 
         // Check condition
         match state.stack.pop() {
             Some(Value::Int(0)) => {
                 // Condition false, exit loop
-                state.loop_stack.pop();
                 return Ok(());
             }
             Some(Value::Int(_)) => {
                 // Condition true, execute body and repeat
             }
-            Some(_) => {
-                state.loop_stack.pop();
-                return Err("while: requires integer condition".into());
-            }
-            None => {
-                state.loop_stack.pop();
-                return Err("while: stack underflow (needs condition)".into());
-            }
+            Some(_) => return Err("while: requires integer condition".into()),
+            None => return Err("while: stack underflow (needs condition)".into()),
         }
 
         // Execute after_while (loop body)
-        for token in after_while {
-            eval::eval_token(state, token, false)?;
+        if !run_iteration(state, LoopInfo::BeginWhileLoop, after_while)? {
+            return Ok(());
         }
-
-        state.loop_stack.pop();
     }
+    Ok(())
 }
 
 /// Execute a `do ... loop` counted loop.
 ///
 /// Loops from `start` to `limit - 1` with step 1.
 /// The loop index is accessible via `i`.
-pub fn execute_do_loop(
+pub fn execute_do_loop<B: LoopBody + ?Sized>(
     state: &mut State,
     start: i64,
     limit: i64,
-    body: &[String],
+    body: &B,
 ) -> Result<(), String> {
     let mut idx = start;
+    let mut done = 0;
     while idx < limit {
+        check_iteration(state, "do", done)?;
+        done += 1;
         let loop_info = LoopInfo::DoCountedLoop {
             start,
             limit,
             current: idx,
         };
-        state.loop_stack.push(loop_info);
-
-        for token in body {
-            eval::eval_token(state, token, false)?;
+        if !run_iteration(state, loop_info, body)? {
+            return Ok(());
         }
-
-        state.loop_stack.pop();
         idx += 1;
     }
     Ok(())
@@ -128,14 +218,14 @@ pub fn execute_do_loop(
 ///
 /// Like `do_loop` but pops step from stack after each body execution.
 /// Supports ascending (start < limit) and descending (start > limit) loops.
-pub fn execute_do_plus_loop(
+pub fn execute_do_plus_loop<B: LoopBody + ?Sized>(
     state: &mut State,
     start: i64,
     limit: i64,
-    body: &[String],
+    body: &B,
 ) -> Result<(), String> {
     let mut idx = start;
-    loop {
+    for done in 0.. {
         // Check if we should continue
         let should_continue = if start < limit {
             idx < limit
@@ -146,29 +236,34 @@ pub fn execute_do_plus_loop(
         if !should_continue {
             return Ok(());
         }
+        check_iteration(state, "do", done)?;
 
         let loop_info = LoopInfo::DoPlusCountedLoop {
             start,
             limit,
             current: idx,
         };
-        state.loop_stack.push(loop_info);
-
-        for token in body {
-            eval::eval_token(state, token, false)?;
+        if !run_iteration(state, loop_info, body)? {
+            return Ok(());
         }
 
-        state.loop_stack.pop();
-
         // Get step from stack
         match state.stack.pop() {
             Some(Value::Int(step)) => {
-                idx += step;
+                // A step of 0, or one heading away from the limit, never ends
+                if (start < limit && step <= 0) || (start > limit && step >= 0) {
+                    return Err(format!(
+                        "+loop: step {} never reaches limit {} from {}",
+                        step, limit, idx
+                    ));
+                }
+                idx = idx.checked_add(step).ok_or("+loop: loop index overflow")?;
             }
             Some(_) => return Err("+loop: requires integer step".into()),
             None => return Err("+loop: stack underflow (needs step)".into()),
         }
     }
+    Ok(())
 }
 
 // ========== Loop body collection ==========
@@ -178,30 +273,40 @@ pub fn execute_do_plus_loop(
 /// Called for each token while `collecting_loop` is active.
 /// Tracks nesting depth for inner begin/do pairs and dispatches
 /// to the appropriate executor when the terminating keyword is found.
-pub fn handle_loop_collection(state: &mut State, token: &str) -> Result<(), String> {
+pub fn handle_loop_collection(
+    state: &mut State,
+    token: &str,
+    is_quoted: bool,
+) -> Result<(), String> {
     let (loop_type, mut body, depth) = state.collecting_loop.take().unwrap();
+    let token = Token::new(token, is_quoted);
 
-    match (token, &loop_type, depth) {
+    match (token.keyword(), &loop_type, depth) {
         // ---- begin...until ----
         ("until", LoopType::BeginUntil, 0) => {
             // End of begin...until loop (not nested)
-            execute_begin_until(state, &body)?;
+            execute_begin_until(state, body.as_slice())?;
         }
         ("until", LoopType::BeginUntil, d) => {
             // Nested until, add to body and decrement depth
-            body.push(token.to_string());
+            body.push(token);
             state.collecting_loop = Some((loop_type, body, d - 1));
         }
 
+        // ---- begin...again ----
+        ("again", LoopType::BeginUntil, 0) => {
+            execute_begin_again(state, body.as_slice())?;
+        }
+
         // ---- begin...while transition ----
         ("while", LoopType::BeginUntil, 0) => {
             // This is actually begin...while...repeat, switch type
-            body.push("while".to_string());
+            body.push(token);
             state.collecting_loop = Some((LoopType::BeginWhile, body, 0));
         }
         ("while", LoopType::BeginWhile, _) => {
             // Inside while mode, just add token
-            body.push("while".to_string());
+            body.push(token);
             state.collecting_loop = Some((loop_type, body, depth));
         }
 
@@ -209,59 +314,57 @@ pub fn handle_loop_collection(state: &mut State, token: &str) -> Result<(), Stri
         ("repeat", LoopType::BeginWhile, 0) => {
             // End of begin...while...repeat (not nested)
             let (before_while, after_while) = split_while_body(&body)?;
-            execute_begin_while(state, &before_while, &after_while)?;
+            execute_begin_while(state, before_while.as_slice(), after_while.as_slice())?;
         }
         ("repeat", LoopType::BeginWhile, d) => {
             // Nested repeat, add to body and decrement depth
-            body.push(token.to_string());
+            body.push(token);
             state.collecting_loop = Some((loop_type, body, d - 1));
         }
 
         // ---- do...loop ----
         ("loop", LoopType::DoLoop | LoopType::DoPlusLoop, 0) => {
             // End of do...loop (not nested)
-            match (state.stack.pop(), state.stack.pop()) {
-                (Some(Value::Int(limit)), Some(Value::Int(start))) => {
-                    execute_do_loop(state, start, limit, &body)?;
-                }
-                _ => return Err("do: stack underflow (needs start and limit)".into()),
-            }
+            let (start, limit) = pop_bounds(state)?;
+            execute_do_loop(state, start, limit, body.as_slice())?;
         }
         ("loop", LoopType::DoLoop | LoopType::DoPlusLoop, d) => {
             // Nested loop, add to body and decrement depth
-            body.push(token.to_string());
+            body.push(token);
             state.collecting_loop = Some((loop_type, body, d - 1));
         }
 
         // ---- do...+loop ----
         ("+loop", LoopType::DoPlusLoop | LoopType::DoLoop, 0) => {
             // End of do...+loop (not nested)
-            match (state.stack.pop(), state.stack.pop()) {
-                (Some(Value::Int(limit)), Some(Value::Int(start))) => {
-                    execute_do_plus_loop(state, start, limit, &body)?;
-                }
-                _ => return Err("do: stack underflow (needs start and limit)".into()),
-            }
+            let (start, limit) = pop_bounds(state)?;
+            execute_do_plus_loop(state, start, limit, body.as_slice())?;
         }
         ("+loop", LoopType::DoPlusLoop | LoopType::DoLoop, d) => {
             // Nested +loop, add to body and decrement depth
-            body.push(token.to_string());
+            body.push(token);
+            state.collecting_loop = Some((loop_type, body, d - 1));
+        }
+
+        // ---- Nested closer of another kind (an inner loop ends) ----
+        ("until" | "repeat" | "again" | "loop" | "+loop", _, d) if d > 0 => {
+            body.push(token);
             state.collecting_loop = Some((loop_type, body, d - 1));
         }
 
         // ---- Nesting: begin/do increase depth ----
         ("begin", _, _) => {
-            body.push(token.to_string());
+            body.push(token);
             state.collecting_loop = Some((loop_type, body, depth + 1));
         }
-        ("do", _, _) => {
-            body.push(token.to_string());
+        ("do" | "?do", _, _) => {
+            body.push(token);
             state.collecting_loop = Some((loop_type, body, depth + 1));
         }
 
         // ---- Regular token ----
         (_, _, _) => {
-            body.push(token.to_string());
+            body.push(token);
             state.collecting_loop = Some((loop_type, body, depth));
         }
     }
@@ -269,29 +372,51 @@ pub fn handle_loop_collection(state: &mut State, token: &str) -> Result<(), Stri
     Ok(())
 }
 
+/// Split text into the items an `each` variant iterates over: lines for
+/// `each`, whitespace-separated words for `each-word`, and characters for
+/// `each-char` (not counting a final newline).
+pub fn each_items(keyword: &str, text: &str) -> Vec<String> {
+    match keyword {
+        "each-word" => text.split_whitespace().map(String::from).collect(),
+        "each-char" => {
+            let text = text.strip_suffix('\n').unwrap_or(text);
+            text.chars().map(String::from).collect()
+        }
+        _ => text.lines().map(String::from).collect(),
+    }
+}
+
 /// Handle `each ... then` body collection.
 ///
-/// Collects tokens until `then`, then executes the body for each line
-/// of the output content.
-pub fn handle_each_collection(state: &mut State, token: &str) -> Result<(), String> {
-    let (output_content, mut body) = state.collecting_each.take().unwrap();
-
-    if token == "then" {
-        // End of each...then - execute body for each line
-        let lines: Vec<String> = output_content.lines().map(|l| l.to_string()).collect();
-        for line in &lines {
-            // Push line onto stack as Str
-            state.stack.push(Value::Str(line.clone()));
-            // Execute body tokens
-            for t in &body {
-                eval::eval_token(state, t, false)?;
+/// Collects tokens until `then`, then executes the body for each item,
+/// with the item's position available through `i`.
+pub fn handle_each_collection(
+    state: &mut State,
+    token: &str,
+    is_quoted: bool,
+) -> Result<(), String> {
+    let (keyword, items, mut body) = state.collecting_each.take().unwrap();
+
+    if token == "then" && !is_quoted {
+        // End of each...then - execute body for each item
+        for (index, item) in items.into_iter().enumerate() {
+            if interrupt::is_interrupted() {
+                return Err(format!("{}: interrupted", keyword));
+            }
+            // Push item onto stack as Str
+            state.stack.push(Value::Str(item));
+            let info = LoopInfo::EachLoop {
+                current: index as i64,
+            };
+            if !run_iteration(state, info, body.as_slice())? {
+                break;
             }
         }
         Ok(())
     } else {
         // Accumulate token into body
-        body.push(token.to_string());
-        state.collecting_each = Some((output_content, body));
+        body.push(Token::new(token, is_quoted));
+        state.collecting_each = Some((keyword, items, body));
         Ok(())
     }
 }
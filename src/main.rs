@@ -1,13 +1,21 @@
 use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
+use rustyline::config::Configurer;
 use rustyline::error::ReadlineError;
-use rustyline::Editor;
+use rustyline::{CompletionType, EditMode, Editor};
 
 use yafsh::builtins;
 use yafsh::config;
 use yafsh::eval;
 use yafsh::highlight::YafshHelper;
-use yafsh::types::{State, Value};
+use yafsh::history::{self, HistoryRecord};
+use yafsh::interrupt;
+use yafsh::local_rc;
+use yafsh::multiline;
+use yafsh::theme;
+use yafsh::types::{State, Value, MAIN_STACK};
 
 /// Count inputs (Str/Int) vs outputs (Output) on the stack.
 fn count_stack(stack: &[Value]) -> (usize, usize) {
@@ -15,36 +23,42 @@ fn count_stack(stack: &[Value]) -> (usize, usize) {
     let mut outputs = 0;
     for val in stack {
         match val {
-            Value::Str(_) | Value::Int(_) => inputs += 1,
-            Value::Output(_) => outputs += 1,
+            Value::Str(_) | Value::Int(_) | Value::Map(_) | Value::List(_) => inputs += 1,
+            Value::Output(_, _) => outputs += 1,
         }
     }
     (inputs, outputs)
 }
 
-/// Build the default prompt string based on stack state.
+/// Build the default prompt string based on stack state, naming the active
+/// stack unless it's the main one.
 fn build_default_prompt(state: &State) -> String {
     let (inputs, outputs) = count_stack(&state.stack);
     let total = inputs + outputs;
+    let name = if state.stack_name == MAIN_STACK {
+        "yafsh".to_string()
+    } else {
+        format!("yafsh:{}", state.stack_name)
+    };
 
     if total == 0 {
-        "yafsh> ".to_string()
+        format!("{}> ", name)
     } else if outputs == 0 {
-        format!("yafsh[{}]> ", inputs)
+        format!("{}[{}]> ", name, inputs)
     } else if inputs == 0 {
-        format!("yafsh[:{}]> ", outputs)
+        format!("{}[:{}]> ", name, outputs)
     } else {
-        format!("yafsh[{}:{}]> ", inputs, outputs)
+        format!("{}[{}:{}]> ", name, inputs, outputs)
     }
 }
 
-/// Evaluate the custom `$prompt` word and return the prompt string.
+/// Evaluate a prompt word (`$prompt`, `$rprompt`) and return the prompt string.
 ///
-/// Saves the current stack, clears it, evaluates `$prompt`, collects the
+/// Saves the current stack, clears it, evaluates the word, collects the
 /// resulting stack items into the prompt string, then restores the original stack.
-fn eval_custom_prompt(state: &mut State) -> Option<String> {
-    // Check if $prompt is defined in the dictionary
-    if !state.dict.contains_key("$prompt") {
+fn eval_prompt_word(state: &mut State, word: &str) -> Option<String> {
+    // Check if the word is defined in the dictionary
+    if !state.dict.contains_key(word) {
         return None;
     }
 
@@ -52,8 +66,8 @@ fn eval_custom_prompt(state: &mut State) -> Option<String> {
     let saved_stack = std::mem::take(&mut state.stack);
     state.prompt_eval_original_stack = Some(saved_stack.clone());
 
-    // Evaluate $prompt
-    let result = eval::eval_line(state, "$prompt");
+    // Evaluate the prompt word
+    let result = eval::eval_line(state, word);
 
     // Collect the prompt from the stack
     let prompt = if result.is_ok() {
@@ -80,88 +94,229 @@ fn eval_custom_prompt(state: &mut State) -> Option<String> {
 
 /// Auto-type: if top of stack is Output, print it (but keep it on stack).
 fn auto_type_output(state: &State) {
-    if let Some(Value::Output(s)) = state.stack.last() {
-        print!("{}", s);
+    if let Some(Value::Output(s, _)) = state.stack.last() {
+        if !builtins::io::page_if_long(state, s) {
+            print!("{}", s);
+        }
     }
 }
 
-/// Load and evaluate the RC file (~/.yafshrc) if it exists.
-fn load_rc(state: &mut State) {
-    if let Some(path) = config::rc_path() {
-        if path.exists() {
-            if let Ok(contents) = std::fs::read_to_string(&path) {
-                for line in contents.lines() {
-                    let trimmed = line.trim();
-                    if trimmed.is_empty() || trimmed.starts_with('#') {
-                        continue;
-                    }
-                    if let Err(e) = eval::eval_line(state, trimmed) {
-                        eprintln!("~/.yafshrc: {}", e);
-                    }
-                }
+/// Evaluate a line typed at the REPL, recording the stack it replaced for
+/// `undo` and how long it took for `$cmdtime`.
+fn eval_recorded(state: &mut State, line: &str) -> Result<(), String> {
+    let before = state.stack.clone();
+    let started = Instant::now();
+    let result = eval::eval_line(state, line);
+    state.last_duration = Some(started.elapsed());
+    state.undo.record(before, &state.stack);
+    result
+}
+
+/// Apply the settings file (config.toml) if it exists.
+fn load_settings(state: &mut State) {
+    if let Some(path) = config::settings_path() {
+        if let Ok(contents) = std::fs::read_to_string(&path) {
+            for e in config::apply_settings(state, &contents) {
+                eprintln!("{}: {}", path.display(), e);
             }
         }
     }
 }
 
+/// RC files slower than this to evaluate get a note at startup.
+const SLOW_RC: Duration = Duration::from_millis(500);
+
+/// Load and evaluate the RC file: `path` if given with `--rc`, else yafshrc
+/// or ~/.yafshrc if it exists. Reports how long it took if it was slow.
+fn load_rc(state: &mut State, path: Option<PathBuf>) {
+    let Some(path) = path.or_else(config::rc_path) else {
+        return;
+    };
+    let started = Instant::now();
+    config::source_file(state, &path);
+    let elapsed = started.elapsed();
+    if elapsed >= SLOW_RC && state.exit_requested.is_none() {
+        eprintln!(
+            "yafsh: {} took {:.2}s to load (start with --no-rc to skip it)",
+            path.display(),
+            elapsed.as_secs_f64()
+        );
+    }
+}
+
+/// Current time as Unix seconds.
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Append one timing record to the history sidecar file.
+///
+/// Each record goes out in a single write to an O_APPEND file, so
+/// concurrent sessions don't interleave within a line.
+fn append_history_record(path: &Path, record: &HistoryRecord) {
+    let file = std::fs::OpenOptions::new().create(true).append(true).open(path);
+    if let Ok(mut file) = file {
+        let _ = file.write_all(record.to_line().as_bytes());
+    }
+}
+
 /// Run the interactive REPL with rustyline (when stdin is a TTY).
-fn run_interactive(state: &mut State) {
+fn run_interactive(state: &mut State) -> i32 {
     let helper = YafshHelper::new();
-    let mut rl = match Editor::with_config(
-        rustyline::Config::builder()
-            .auto_add_history(true)
-            .build(),
-    ) {
+    let history_config = state.history_config.clone();
+    let editor = rustyline::Config::builder()
+        .auto_add_history(false)
+        // List mode prints the candidates' display text (word summaries)
+        .completion_type(CompletionType::List)
+        .max_history_size(history_config.max_size)
+        .and_then(|b| b.history_ignore_dups(history_config.dedupe))
+        .and_then(|b| Editor::with_config(b.build()));
+    let mut rl = match editor {
         Ok(rl) => rl,
         Err(e) => {
             eprintln!("Failed to initialize editor: {}", e);
-            run_simple(state);
-            return;
+            return run_simple(state);
         }
     };
 
+    for (key, handler) in helper.vi_mode_bindings() {
+        rl.bind_sequence(key, handler);
+    }
     rl.set_helper(Some(helper));
 
-    // Load history
-    if let Some(path) = config::history_path() {
-        let _ = rl.load_history(&path);
+    // Load history. Entries are appended to the file as they are entered
+    // (under a file lock, merging with whatever other sessions wrote), so
+    // concurrent shells don't clobber each other's history on exit.
+    let history_path = config::history_path();
+    let history_meta_path = config::history_meta_path();
+    if let Some(path) = &history_path {
+        // The XDG data directory may not exist yet
+        if let Some(dir) = path.parent() {
+            let _ = std::fs::create_dir_all(dir);
+        }
+        let _ = rl.load_history(path);
     }
+    state.history = rl.history().iter().cloned().collect();
 
     println!("yafsh {}", config::VERSION);
     println!("Type 'exit' to quit, Ctrl-D for EOF");
     println!();
 
+    // Lines of a multi-line entry read so far (empty at a fresh prompt)
+    let mut pending = String::new();
+
     loop {
-        // Build prompt (custom or default)
-        let prompt = eval_custom_prompt(state).unwrap_or_else(|| build_default_prompt(state));
+        // Load or unload .yafshrc.local files after any change of directory
+        if pending.is_empty() {
+            local_rc::sync(state);
+        }
+
+        // Build prompt: custom or default, or the continuation prompt
+        let (prompt, rprompt) = if pending.is_empty() {
+            let prompt = eval_prompt_word(state, "$prompt").unwrap_or_else(|| build_default_prompt(state));
+            (prompt, eval_prompt_word(state, "$rprompt"))
+        } else {
+            let prompt = eval_prompt_word(state, "$prompt2")
+                .or_else(|| state.continuation_prompt.clone())
+                .unwrap_or_else(|| "...> ".to_string());
+            (prompt, None)
+        };
 
         // Sync dictionary words to helper for completion and highlighting
         if let Some(helper) = rl.helper_mut() {
-            helper.update_words(state.dict.keys().cloned());
+            helper.update_words(state.visible_words().into_iter().map(|(name, word)| (name, word.summary())));
+            helper.set_completers(&state.completers);
+            helper.refresh_executables();
+            helper.set_rprompt(&prompt, rprompt);
+            helper.set_vi(state.vi_mode);
+            helper.set_autosuggest(state.autosuggest);
+            helper.set_prompted_continuation(
+                state.dict.contains_key("$prompt2") || state.continuation_prompt.is_some(),
+            );
+            helper.set_theme(&state.theme);
+            helper.set_stack_preview(state.stack_preview.then_some((&state.stack, &state.dict)));
         }
+        rl.set_edit_mode(if state.vi_mode { EditMode::Vi } else { EditMode::Emacs });
 
         match rl.readline(&prompt) {
             Ok(line) => {
-                let trimmed = line.trim();
-                if trimmed.is_empty() {
+                if !pending.is_empty() {
+                    pending.push('\n');
+                }
+                pending.push_str(&line);
+                if multiline::is_incomplete(&pending) {
                     continue;
                 }
-                if trimmed == "exit" || trimmed == "quit" {
-                    println!("Goodbye!");
-                    break;
+                let entry = std::mem::take(&mut pending);
+                let mut trimmed = entry.trim();
+                if trimmed.is_empty() {
+                    continue;
                 }
 
-                match eval::eval_line(state, trimmed) {
-                    Ok(()) => {
+                // Expand !! / !n, echoing the result like other shells do
+                let expanded = match history::expand(trimmed, &state.history) {
+                    Ok(expanded) => expanded,
+                    Err(e) => {
+                        eprintln!("Error: {}", e);
+                        continue;
+                    }
+                };
+                if let Some(line) = &expanded {
+                    println!("{}", line);
+                    trimmed = line.trim();
+                }
+                let record = history_config.should_record(&entry);
+                if record && rl.add_history_entry(trimmed).unwrap_or(false) {
+                    state.history.push(trimmed.to_string());
+                    let excess = state.history.len().saturating_sub(history_config.max_size);
+                    state.history.drain(..excess);
+                    if let Some(path) = &history_path {
+                        let _ = rl.append_history(path);
+                    }
+                }
+                interrupt::clear();
+                let started_at = unix_now();
+                let started = Instant::now();
+                let result = eval_recorded(state, trimmed);
+                let exit_code = match (result, state.exit_requested) {
+                    (_, Some(code)) => code,
+                    (Ok(()), None) => {
                         auto_type_output(state);
+                        state.last_exit_code
                     }
-                    Err(e) => {
+                    (Err(e), None) => {
                         eprintln!("Error: {}", e);
+                        1
+                    }
+                };
+                if record {
+                    if let Some(path) = &history_meta_path {
+                        let record = HistoryRecord {
+                            timestamp: started_at,
+                            duration_ms: started.elapsed().as_millis() as u64,
+                            exit_code,
+                            line: trimmed.to_string(),
+                        };
+                        append_history_record(path, &record);
                     }
                 }
+
+                if state.exit_requested.is_some() {
+                    println!("Goodbye!");
+                    break;
+                }
+
+                // `history-clear` empties the mirror; forget the editor's copy too
+                if state.history.is_empty() {
+                    let _ = rl.clear_history();
+                }
             }
             Err(ReadlineError::Interrupted) => {
-                // Ctrl-C: cancel current line, continue
+                // Ctrl-C: cancel current entry (including continuation lines), continue
+                pending.clear();
                 println!();
                 continue;
             }
@@ -175,17 +330,17 @@ fn run_interactive(state: &mut State) {
             }
         }
     }
-
-    // Save history
-    if let Some(path) = config::history_path() {
-        let _ = rl.save_history(&path);
-    }
+    state.exit_requested.unwrap_or(0)
 }
 
 /// Run the simple REPL for pipe mode (when stdin is not a TTY).
-fn run_simple(state: &mut State) {
+///
+/// Returns the status to exit with: the one given to `exit`, or else the
+/// last line's (unless turned off with `set-exit-status`).
+fn run_simple(state: &mut State) -> i32 {
     let stdin = io::stdin();
     let mut line = String::new();
+    let mut status = 0;
 
     loop {
         line.clear();
@@ -199,19 +354,23 @@ fn run_simple(state: &mut State) {
                 if trimmed.is_empty() {
                     continue;
                 }
-                if trimmed == "exit" || trimmed == "quit" {
-                    break;
-                }
-
+                interrupt::clear();
+                // No undo snapshots here: nobody can type `undo` between lines
                 match eval::eval_line(state, trimmed) {
                     Ok(()) => {
                         auto_type_output(state);
                         io::stdout().flush().ok();
+                        status = state.last_exit_code;
                     }
+                    Err(_) if state.exit_requested.is_some() => break,
                     Err(e) => {
                         eprintln!("Error: {}", e);
+                        status = 1;
                     }
                 }
+                if state.errexit && status != 0 {
+                    break;
+                }
             }
             Err(e) => {
                 eprintln!("Read error: {}", e);
@@ -219,18 +378,77 @@ fn run_simple(state: &mut State) {
             }
         }
     }
+    let status = if state.exit_status { status } else { 0 };
+    state.exit_requested.unwrap_or(status)
 }
 
 fn main() {
+    const USAGE: &str = "usage: yafsh [--restricted] [--no-rc | --rc <path>]";
+    let mut restricted = false;
+    let mut no_rc = false;
+    let mut rc_file = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--restricted" => restricted = true,
+            "--no-rc" => no_rc = true,
+            "--rc" => match args.next() {
+                Some(path) => rc_file = Some(PathBuf::from(path)),
+                None => {
+                    eprintln!("yafsh: --rc requires a path");
+                    eprintln!("{}", USAGE);
+                    std::process::exit(2);
+                }
+            },
+            _ => {
+                eprintln!("yafsh: unknown option {}", arg);
+                eprintln!("{}", USAGE);
+                std::process::exit(2);
+            }
+        }
+    }
+    if no_rc && rc_file.is_some() {
+        eprintln!("yafsh: --no-rc and --rc can't be used together");
+        eprintln!("{}", USAGE);
+        std::process::exit(2);
+    }
+    if let Some(path) = &rc_file {
+        if !path.is_file() {
+            eprintln!("yafsh: --rc: {}: no such file", path.display());
+            std::process::exit(2);
+        }
+    }
+
     let mut state = State::new();
     builtins::register_builtins(&mut state);
+    interrupt::install_handler();
+    // Before settings and RC files, so they are restricted too
+    if restricted {
+        if let Err(e) = builtins::system::restrict(&mut state) {
+            eprintln!("yafsh: --restricted: {}", e);
+            std::process::exit(1);
+        }
+    }
+    // NO_COLOR or redirected output: plain text (the RC can still turn colors on)
+    state.theme.enabled = theme::color_supported();
+    // Typo hints only for someone typing: scripts often push bare strings
+    state.suggest = io::stdin().is_terminal();
 
-    // Load RC file
-    load_rc(&mut state);
+    // Settings first, so RC words can still override them
+    load_settings(&mut state);
+    state.local_rc.trust_file = config::trusted_rc_path();
+    if !no_rc {
+        load_rc(&mut state, rc_file);
+    }
+    if let Some(code) = state.exit_requested {
+        std::process::exit(code);
+    }
 
-    if io::stdin().is_terminal() {
-        run_interactive(&mut state);
+    let code = if io::stdin().is_terminal() {
+        run_interactive(&mut state)
     } else {
-        run_simple(&mut state);
-    }
+        run_simple(&mut state)
+    };
+    io::stdout().flush().ok();
+    std::process::exit(code);
 }
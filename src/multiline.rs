@@ -1,21 +1,18 @@
 /// Check whether the given input text is incomplete and needs continuation lines.
 ///
 /// Returns `true` if the input has:
-/// - Unclosed double quotes (odd count of `"`)
+/// - Unclosed double quotes
 /// - Unbalanced `:` vs `;`
-/// - Unbalanced `begin` vs `until`/`repeat`
+/// - Unbalanced `begin` vs `until`/`repeat`/`again`
 /// - Unbalanced `do` vs `loop`/`+loop`
 /// - Unbalanced `if`/`each` vs `then`
 pub fn is_incomplete(text: &str) -> bool {
-    // Check unclosed quotes: odd number of unescaped double-quotes
-    let quote_count = text.chars().filter(|&c| c == '"').count();
-    if quote_count % 2 != 0 {
+    // Tokenize by whitespace for keyword balancing (ignore quoted regions)
+    let (words, unclosed_quote) = extract_words(text);
+    if unclosed_quote {
         return true;
     }
 
-    // Tokenize by whitespace for keyword balancing (ignore quoted regions)
-    let words = extract_words(text);
-
     let mut colon_depth: i32 = 0;
     let mut begin_depth: i32 = 0;
     let mut do_depth: i32 = 0;
@@ -26,10 +23,10 @@ pub fn is_incomplete(text: &str) -> bool {
             ":" => colon_depth += 1,
             ";" => colon_depth -= 1,
             "begin" => begin_depth += 1,
-            "until" | "repeat" => begin_depth -= 1,
-            "do" => do_depth += 1,
+            "until" | "repeat" | "again" => begin_depth -= 1,
+            "do" | "?do" => do_depth += 1,
             "loop" | "+loop" => do_depth -= 1,
-            "if" | "each" => if_each_depth += 1,
+            "if" | "each" | "each-word" | "each-char" => if_each_depth += 1,
             "then" => if_each_depth -= 1,
             _ => {}
         }
@@ -38,13 +35,19 @@ pub fn is_incomplete(text: &str) -> bool {
     colon_depth > 0 || begin_depth > 0 || do_depth > 0 || if_each_depth > 0
 }
 
-/// Extract words from text, skipping content inside double quotes.
-fn extract_words(text: &str) -> Vec<String> {
+/// Extract words from text, skipping content inside double quotes (where
+/// `\"` doesn't end the string). Also returns whether a quote is left open.
+fn extract_words(text: &str) -> (Vec<String>, bool) {
     let mut words = Vec::new();
     let mut current = String::new();
     let mut in_quote = false;
+    let mut chars = text.chars().peekable();
 
-    for c in text.chars() {
+    while let Some(c) = chars.next() {
+        if in_quote && c == '\\' && chars.peek() == Some(&'"') {
+            chars.next();
+            continue;
+        }
         if c == '"' {
             in_quote = !in_quote;
             continue;
@@ -63,7 +66,7 @@ fn extract_words(text: &str) -> Vec<String> {
     if !current.is_empty() {
         words.push(current);
     }
-    words
+    (words, in_quote)
 }
 
 #[cfg(test)]
@@ -85,6 +88,12 @@ mod tests {
         assert!(!is_incomplete("\"hello world\""));
     }
 
+    #[test]
+    fn test_escaped_quote_does_not_close() {
+        assert!(is_incomplete(r#""say \"hi"#));
+        assert!(!is_incomplete(r#""say \"hi\"""#));
+    }
+
     #[test]
     fn test_incomplete_colon_no_semicolon() {
         assert!(is_incomplete(": greet \"hello\""));
@@ -115,6 +124,12 @@ mod tests {
         assert!(!is_incomplete("begin dup 0 > while 1 - repeat"));
     }
 
+    #[test]
+    fn test_complete_begin_again() {
+        assert!(is_incomplete("begin 1 +"));
+        assert!(!is_incomplete("begin 1 + dup 5 = if leave then again"));
+    }
+
     #[test]
     fn test_incomplete_do_no_loop() {
         assert!(is_incomplete("0 5 do i"));
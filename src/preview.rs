@@ -0,0 +1,261 @@
+use std::collections::HashMap;
+
+use crate::tokenizer::{self, Token};
+use crate::types::{State, Value, Word};
+
+/// Builtins with no effect beyond the stack, safe to run while typing.
+pub const PURE_WORDS: &[&str] = &[
+    // Stack
+    "dup",
+    "swap",
+    "drop",
+    "clear",
+    "over",
+    "rot",
+    // Arithmetic and formatting
+    "+",
+    "-",
+    "*",
+    "/",
+    "mod",
+    "/mod",
+    "*/",
+    "wrap+",
+    "wrap*",
+    "min",
+    "max",
+    "abs",
+    "negate",
+    "clamp",
+    "hex",
+    "oct",
+    "bin",
+    ">base",
+    "base>",
+    // Comparison and logic
+    "=",
+    ">",
+    "<",
+    ">=",
+    "<=",
+    "<>",
+    "and",
+    "or",
+    "not",
+    "xor",
+    // Types
+    ">int",
+    "typeof",
+    "int?",
+    "str?",
+    "output?",
+    "map?",
+    "list?",
+    "out-status",
+    "out-command",
+    // Strings and outputs
+    "concat",
+    "length",
+    "length-chars",
+    "length-bytes",
+    "substr",
+    "substr-chars",
+    "substr-bytes",
+    "?prefix",
+    "?suffix",
+    "?wrap",
+    "fmt",
+    ">output",
+    ">string",
+    "head",
+    "tail",
+    "line-count",
+    "nth-line",
+    "chomp",
+    "trim",
+    "out>int",
+    "sort-lines",
+    "sort-lines-num",
+    "uniq-lines",
+    "count-uniq",
+    "field",
+    "fields",
+    "field-by",
+    "fields-by",
+    // Maps
+    "map-new",
+    "map-set",
+    "map-get",
+    "map-has?",
+    "map-del",
+    "map-keys",
+    // CSV/TSV
+    "csv-parse",
+    "tsv-parse",
+    "csv-get",
+    "csv-emit",
+    // TOML/YAML
+    "toml-parse",
+    "yaml-parse",
+    "get-path",
+    // Colors
+    "fg",
+    "bg",
+    "bold",
+    "dim",
+    "underline",
+    "reset-style",
+    "color-wrap",
+];
+
+/// How deeply defined words are expanded before giving up.
+const MAX_DEPTH: usize = 16;
+/// Most tokens run for one preview, so a typo can't stall the line editor.
+const MAX_STEPS: usize = 10_000;
+
+/// Predict the stack after running `line` on a copy of `stack`.
+///
+/// Only literals, pure builtins, and defined words made of them are run.
+/// Returns `None` as soon as anything else appears (commands, control flow,
+/// I/O), and `Some(Err(..))` when a word would fail, e.g. on underflow.
+pub fn simulate(
+    stack: &[Value],
+    words: &HashMap<String, Word>,
+    line: &str,
+) -> Option<Result<Vec<Value>, String>> {
+    let mut scratch = State::new();
+    scratch.stack = stack.to_vec();
+    let mut steps = 0;
+    for token in tokenizer::tokenize(line) {
+        if let Err(e) = run(&mut scratch, words, &token, 0, &mut steps)? {
+            return Some(Err(e));
+        }
+    }
+    Some(Ok(scratch.stack))
+}
+
+/// Run one token; `None` if it can't be predicted.
+fn run(
+    state: &mut State,
+    words: &HashMap<String, Word>,
+    token: &Token,
+    depth: usize,
+    steps: &mut usize,
+) -> Option<Result<(), String>> {
+    *steps += 1;
+    if depth > MAX_DEPTH || *steps > MAX_STEPS {
+        return None;
+    }
+    if token.quoted {
+        state.stack.push(Value::Str(token.text.clone()));
+        return Some(Ok(()));
+    }
+    let token = token.text.as_str();
+    if let Some(n) = tokenizer::parse_int(token) {
+        state.stack.push(Value::Int(n));
+        return Some(Ok(()));
+    }
+    match words.get(token)? {
+        Word::Builtin(f, _) if PURE_WORDS.contains(&token) => Some(f(state)),
+        Word::Defined(body, _) => {
+            for t in body {
+                if let Err(e) = run(state, words, t, depth + 1, steps)? {
+                    return Some(Err(e));
+                }
+            }
+            Some(Ok(()))
+        }
+        _ => None,
+    }
+}
+
+/// Render a predicted stack the way `.s` prints it, on one line.
+pub fn format_stack(stack: &[Value]) -> String {
+    let mut out = format!("<{}>", stack.len());
+    for val in stack {
+        out.push(' ');
+        match val {
+            Value::Str(s) => out.push_str(&format!("\"{}\"", s)),
+            Value::Int(n) => out.push_str(&n.to_string()),
+            Value::Output(s, _) => {
+                let mut lines = s.trim_end().lines();
+                let first = lines.next().unwrap_or("");
+                let more = if lines.next().is_some() { " …" } else { "" };
+                out.push_str(&format!("«{}{}»", first, more));
+            }
+            Value::Map(_) | Value::List(_) => out.push_str(&val.to_string()),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builtins;
+    use crate::eval;
+
+    fn new_state() -> State {
+        let mut s = State::new();
+        builtins::register_builtins(&mut s);
+        s
+    }
+
+    #[test]
+    fn test_simulate_pure_words() {
+        let s = new_state();
+        let stack = vec![Value::Int(2)];
+        let out = simulate(&stack, &s.dict, "3 + dup \"x\"").unwrap().unwrap();
+        assert_eq!(
+            out,
+            vec![Value::Int(5), Value::Int(5), Value::Str("x".into())]
+        );
+        // The real stack is untouched
+        assert_eq!(stack, vec![Value::Int(2)]);
+    }
+
+    #[test]
+    fn test_simulate_defined_words() {
+        let mut s = new_state();
+        eval::eval_line(&mut s, ": sq dup * ;").unwrap();
+        let out = simulate(&[], &s.dict, "4 sq").unwrap().unwrap();
+        assert_eq!(out, vec![Value::Int(16)]);
+    }
+
+    #[test]
+    fn test_simulate_stops_at_impure() {
+        let mut s = new_state();
+        eval::eval_line(&mut s, ": hi \"hi\" . ;").unwrap();
+        assert_eq!(simulate(&[], &s.dict, "1 ."), None);
+        assert_eq!(simulate(&[], &s.dict, "hi"), None);
+        assert_eq!(simulate(&[], &s.dict, "ls"), None);
+        assert_eq!(simulate(&[], &s.dict, "1 if 2 then"), None);
+    }
+
+    #[test]
+    fn test_simulate_reports_errors() {
+        let s = new_state();
+        assert_eq!(
+            simulate(&[], &s.dict, "1 +"),
+            Some(Err("+: stack underflow".into()))
+        );
+    }
+
+    #[test]
+    fn test_simulate_runaway_recursion() {
+        let mut s = new_state();
+        s.dict_mut().insert("loop-me".into(), Word::defined(tokenizer::tokenize("loop-me")));
+        assert_eq!(simulate(&[], &s.dict, "loop-me"), None);
+    }
+
+    #[test]
+    fn test_format_stack() {
+        let stack = vec![
+            Value::Int(1),
+            Value::Str("a".into()),
+            Value::output("x\ny\n"),
+        ];
+        assert_eq!(format_stack(&stack), "<3> 1 \"a\" «x …»");
+        assert_eq!(format_stack(&[]), "<0>");
+    }
+}
@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// A cached prompt value and when it was computed.
+struct Entry {
+    value: String,
+    fetched: Instant,
+    /// A background refresh for this key is in flight.
+    refreshing: bool,
+}
+
+#[derive(Default)]
+struct Inner {
+    entries: HashMap<String, Entry>,
+    /// Bumped by `clear`, so refreshes started before it are thrown away.
+    generation: u64,
+}
+
+/// Cache for prompt helpers that would otherwise spawn a process per render.
+///
+/// Lookups are stale-while-revalidate: a missing value is computed on the
+/// spot, but a stale one is returned immediately while a background thread
+/// recomputes it for the next prompt. Cloning shares the same cache.
+#[derive(Clone, Default)]
+pub struct PromptCache {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl PromptCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Return the value for `key`, computing it with `compute` when missing
+    /// and refreshing it in the background when older than `max_age`.
+    pub fn get<F>(&self, key: &str, max_age: Duration, compute: F) -> String
+    where
+        F: FnOnce() -> String + Send + 'static,
+    {
+        let generation = {
+            let mut inner = self.inner.lock().unwrap();
+            let generation = inner.generation;
+            if let Some(entry) = inner.entries.get_mut(key) {
+                if entry.fetched.elapsed() >= max_age && !entry.refreshing {
+                    entry.refreshing = true;
+                    let cache = self.clone();
+                    let key = key.to_string();
+                    std::thread::spawn(move || cache.store(key, compute(), generation));
+                }
+                return entry.value.clone();
+            }
+            generation
+        };
+        let value = compute();
+        self.store(key.to_string(), value.clone(), generation);
+        value
+    }
+
+    /// Save a value computed for `generation`, unless the cache was cleared
+    /// since then.
+    fn store(&self, key: String, value: String, generation: u64) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.generation != generation {
+            return;
+        }
+        let entry = Entry {
+            value,
+            fetched: Instant::now(),
+            refreshing: false,
+        };
+        inner.entries.insert(key, entry);
+    }
+
+    /// Drop every cached value so the next lookups recompute synchronously.
+    /// Background refreshes still running are discarded when they finish.
+    pub fn clear(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.entries.clear();
+        inner.generation += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_miss_computes_and_hit_reuses() {
+        let cache = PromptCache::new();
+        let calls = Arc::new(AtomicUsize::new(0));
+        for _ in 0..3 {
+            let calls = calls.clone();
+            let value = cache.get("k", Duration::from_secs(60), move || {
+                calls.fetch_add(1, Ordering::SeqCst);
+                "v".to_string()
+            });
+            assert_eq!(value, "v");
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_stale_value_served_while_refreshing() {
+        let cache = PromptCache::new();
+        cache.get("k", Duration::ZERO, || "old".to_string());
+        // Stale: the old value comes back at once, the new one lands later
+        assert_eq!(cache.get("k", Duration::ZERO, || "new".to_string()), "old");
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while cache.get("k", Duration::from_secs(60), || -> String {
+            unreachable!()
+        }) != "new"
+        {
+            assert!(
+                Instant::now() < deadline,
+                "background refresh never finished"
+            );
+            std::thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn test_clear_drops_refresh_in_flight() {
+        let cache = PromptCache::new();
+        cache.get("k", Duration::ZERO, || "old".to_string());
+        let (release, wait) = std::sync::mpsc::channel::<()>();
+        let (done, finished) = std::sync::mpsc::channel();
+        cache.get("k", Duration::ZERO, move || {
+            wait.recv().unwrap();
+            done.send(()).unwrap();
+            "stale".to_string()
+        });
+        cache.clear();
+        assert_eq!(cache.get("k", Duration::from_secs(60), || "fresh".to_string()), "fresh");
+
+        // The refresh started before the clear lands too late to count
+        release.send(()).unwrap();
+        finished.recv().unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(
+            cache.get("k", Duration::from_secs(60), || -> String { unreachable!() }),
+            "fresh"
+        );
+    }
+
+    #[test]
+    fn test_clear_forces_recompute() {
+        let cache = PromptCache::new();
+        cache.get("k", Duration::from_secs(60), || "a".to_string());
+        cache.clear();
+        assert_eq!(
+            cache.get("k", Duration::from_secs(60), || "b".to_string()),
+            "b"
+        );
+    }
+}
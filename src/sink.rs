@@ -0,0 +1,92 @@
+use std::io::{self, Write};
+
+/// Where builtins write what they print (`.`, `.s`, `words`, `help`, `see`,
+/// ...), held in `State::out`.
+///
+/// Interactive exchanges with the terminal (the prompts and menus of `ask`,
+/// `ask-secret`, `confirm`, and `choose`, `watch`'s screen, the clipboard
+/// escape) bypass it, since they only make sense on a terminal. So do
+/// diagnostics, which go to stderr to stay out of captured output: warnings
+/// such as `warning: redefining`, `dry-run` lines, the timing reports of
+/// `timed` and slow commands, trace output, and hook and rc errors.
+#[derive(Default)]
+pub enum Sink {
+    /// The process's stdout
+    #[default]
+    Stdout,
+    /// Kept in memory until taken with [`Sink::take_captured`]
+    Capture(Vec<u8>),
+    /// Any other writer, for embedding the interpreter
+    Writer(Box<dyn Write>),
+}
+
+impl Sink {
+    /// A sink that keeps everything written to it.
+    pub fn capture() -> Self {
+        Sink::Capture(Vec::new())
+    }
+
+    pub fn is_stdout(&self) -> bool {
+        matches!(self, Sink::Stdout)
+    }
+
+    /// Everything captured so far, emptying the buffer; "" unless capturing.
+    pub fn take_captured(&mut self) -> String {
+        match self {
+            Sink::Capture(buf) => String::from_utf8_lossy(&std::mem::take(buf)).into_owned(),
+            _ => String::new(),
+        }
+    }
+}
+
+impl Write for Sink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Sink::Stdout => io::stdout().write(buf),
+            Sink::Capture(captured) => captured.write(buf),
+            Sink::Writer(w) => w.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Sink::Stdout => io::stdout().flush(),
+            Sink::Capture(_) => Ok(()),
+            Sink::Writer(w) => w.flush(),
+        }
+    }
+}
+
+/// `print!` to `state.out`. Write errors (such as a closed pipe) are ignored.
+#[macro_export]
+macro_rules! out {
+    ($state:expr, $($arg:tt)*) => {{
+        let _ = ::std::io::Write::write_fmt(&mut $state.out, format_args!($($arg)*));
+    }};
+}
+
+/// `println!` to `state.out`. Write errors (such as a closed pipe) are ignored.
+#[macro_export]
+macro_rules! outln {
+    ($state:expr) => {
+        $crate::out!($state, "\n")
+    };
+    ($state:expr, $($arg:tt)*) => {
+        $crate::out!($state, "{}\n", format_args!($($arg)*))
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capture_and_take() {
+        let mut sink = Sink::capture();
+        write!(sink, "a{}", 1).unwrap();
+        assert_eq!(sink.take_captured(), "a1");
+        assert_eq!(sink.take_captured(), "");
+        assert!(!sink.is_stdout());
+        assert_eq!(Sink::default().take_captured(), "");
+    }
+}
@@ -0,0 +1,154 @@
+use std::fs;
+
+use crate::eval::is_executable;
+use crate::types::State;
+
+/// Edit distance between two strings, counting insertions, deletions,
+/// substitutions, and swaps of adjacent characters as one edit each
+/// (optimal string alignment), so `gti` is one edit away from `git`.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut d = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    d[0] = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            d[i][j] = (d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1)
+                .min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+/// Whether a token looks like a mistyped command name worth correcting.
+///
+/// Short tokens, flags, paths, and anything with unusual characters are
+/// almost always meant as plain strings, so they never get suggestions.
+fn looks_like_command(token: &str) -> bool {
+    token.chars().count() >= 3
+        && token.starts_with(|c: char| c.is_ascii_alphabetic())
+        && token
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '+' | '?' | '>'))
+}
+
+/// Executable names found in $PATH, rescanned only when $PATH changes.
+fn path_executables(state: &mut State) -> &[String] {
+    let path_var = std::env::var("PATH").unwrap_or_default();
+    let stale = state
+        .path_cache
+        .as_ref()
+        .is_none_or(|(cached_for, _)| *cached_for != path_var);
+    if stale {
+        let names = scan_path(&path_var);
+        state.path_cache = Some((path_var, names));
+    }
+    &state.path_cache.as_ref().unwrap().1
+}
+
+/// Sorted, deduplicated names of the executables in a $PATH-style list.
+pub(crate) fn scan_path(path_var: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    for dir in path_var.split(':').filter(|d| !d.is_empty()) {
+        let Ok(entries) = fs::read_dir(dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            if is_executable(&entry.path().to_string_lossy()) {
+                names.push(entry.file_name().to_string_lossy().to_string());
+            }
+        }
+    }
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// Suggest the closest dictionary word or PATH executable for an unknown token.
+///
+/// Allows one edit for tokens of up to four characters and two for longer
+/// ones. Ties go to dictionary words, then to the alphabetically first name.
+pub fn suggest(state: &mut State, token: &str) -> Option<String> {
+    if !looks_like_command(token) {
+        return None;
+    }
+    let max = if token.chars().count() <= 4 { 1 } else { 2 };
+
+    let best_word = closest(state.dict.keys(), token, max);
+    let best_exe = closest(path_executables(state).iter(), token, max);
+    match (best_word, best_exe) {
+        (Some((dw, word)), Some((de, _))) if dw <= de => Some(word),
+        (_, Some((_, exe))) => Some(exe),
+        (word, None) => word.map(|(_, word)| word),
+    }
+}
+
+/// The name within `max` edits of `token` that is fewest edits away,
+/// alphabetically first on ties, with its distance.
+fn closest<'a>(
+    names: impl Iterator<Item = &'a String>,
+    token: &str,
+    max: usize,
+) -> Option<(usize, String)> {
+    names
+        .map(|name| (edit_distance(token, name), name))
+        .filter(|&(d, _)| d > 0 && d <= max)
+        .min()
+        .map(|(d, name)| (d, name.clone()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Word;
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("git", "git"), 0);
+        assert_eq!(edit_distance("gti", "git"), 1);
+        assert_eq!(edit_distance("dup", "drop"), 2);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn test_looks_like_command() {
+        assert!(looks_like_command("gti"));
+        assert!(looks_like_command("sort-line"));
+        assert!(!looks_like_command("ls"));
+        assert!(!looks_like_command("-la"));
+        assert!(!looks_like_command("src/main.rs"));
+        assert!(!looks_like_command("42abc"));
+    }
+
+    #[test]
+    fn test_suggest_dictionary_word() {
+        let mut s = State::new();
+        s.dict_mut().insert(
+            "square".to_string(),
+            Word::defined(crate::tokenizer::tokenize("dup *")),
+        );
+        assert_eq!(suggest(&mut s, "sqaure"), Some("square".to_string()));
+        assert_eq!(suggest(&mut s, "-sqaure"), None);
+    }
+
+    #[test]
+    #[cfg(feature = "native")]
+    fn test_path_executables_cached() {
+        let mut s = State::new();
+        assert!(path_executables(&mut s).iter().any(|name| name == "sh"));
+        let path_var = std::env::var("PATH").unwrap_or_default();
+        assert_eq!(
+            s.path_cache.as_ref().map(|(p, _)| p.as_str()),
+            Some(path_var.as_str())
+        );
+    }
+}
@@ -0,0 +1,27 @@
+/// Columns and rows of the terminal attached to stdout, or None when stdout
+/// isn't a terminal.
+///
+/// Asked of the terminal on every call, so it always reflects the current
+/// window size after a resize; nothing needs to listen for SIGWINCH.
+#[cfg(feature = "native")]
+pub fn size() -> Option<(usize, usize)> {
+    // SAFETY: TIOCGWINSZ only writes a winsize struct into the pointer we pass.
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    let ok = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } == 0;
+    (ok && size.ws_col > 0 && size.ws_row > 0).then_some((size.ws_col as usize, size.ws_row as usize))
+}
+
+#[cfg(not(feature = "native"))]
+pub fn size() -> Option<(usize, usize)> {
+    None
+}
+
+/// Number of columns in the terminal attached to stdout.
+pub fn width() -> Option<usize> {
+    size().map(|(cols, _)| cols)
+}
+
+/// Number of rows in the terminal attached to stdout.
+pub fn rows() -> Option<usize> {
+    size().map(|(_, rows)| rows)
+}
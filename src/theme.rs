@@ -0,0 +1,149 @@
+use std::io::IsTerminal;
+
+use crate::builtins::style;
+
+/// SGR sequence that clears all colors and attributes.
+const RESET: &str = "\x1b[0m";
+
+/// Token classes and their default styles, in listing order.
+///
+/// The first six color the input line; the rest color trace output.
+const DEFAULTS: &[(&str, &str)] = &[
+    ("string", "yellow"),
+    ("number", "cyan"),
+    ("keyword", "magenta"),
+    ("word", "green"),
+    ("unknown", "red"),
+    ("match", "bold underline magenta"),
+    ("hint", "dim"),
+    ("output", "magenta"),
+    ("command", "blue"),
+    ("pop", "red"),
+    ("push", "green"),
+    ("emphasis", "bold"),
+];
+
+/// Colors for highlighting and trace output, keyed by token class.
+///
+/// Set with `theme` (usually from `~/.yafshrc`). With colors turned off
+/// (`NO_COLOR`, output not a terminal, or `"off" set-color`), everything
+/// renders as plain text.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Theme {
+    /// Whether any escape sequences are emitted at all
+    pub enabled: bool,
+    /// (class, style as given, escape sequence), in `DEFAULTS` order
+    styles: Vec<(&'static str, String, String)>,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            enabled: true,
+            styles: DEFAULTS
+                .iter()
+                .map(|(class, spec)| {
+                    let seq = style::style_sequence(spec).unwrap_or_default();
+                    (*class, spec.to_string(), seq)
+                })
+                .collect(),
+        }
+    }
+}
+
+impl Theme {
+    /// Set the style for a class. `spec` is a space-separated list of colors
+    /// (as accepted by `fg`) and attributes; "" leaves the class uncolored.
+    pub fn set(&mut self, class: &str, spec: &str) -> Result<(), String> {
+        let seq = style::style_sequence(spec).ok_or_else(|| format!("unknown style '{}'", spec))?;
+        let entry = self
+            .styles
+            .iter_mut()
+            .find(|(c, _, _)| *c == class)
+            .ok_or_else(|| format!("unknown class '{}' (one of: {})", class, class_names()))?;
+        entry.1 = spec.to_string();
+        entry.2 = seq;
+        Ok(())
+    }
+
+    /// The escape sequence starting `class`, or "" when colors are off.
+    pub fn start(&self, class: &str) -> &str {
+        if !self.enabled {
+            return "";
+        }
+        self.styles
+            .iter()
+            .find(|(c, _, _)| *c == class)
+            .map_or("", |(_, _, seq)| seq.as_str())
+    }
+
+    /// The escape sequence ending a styled span, or "" when colors are off.
+    pub fn reset(&self) -> &str {
+        if self.enabled {
+            RESET
+        } else {
+            ""
+        }
+    }
+
+    /// `text` in the style for `class`.
+    pub fn paint(&self, class: &str, text: &str) -> String {
+        let start = self.start(class);
+        if start.is_empty() {
+            return text.to_string();
+        }
+        format!("{}{}{}", start, text, RESET)
+    }
+
+    /// (class, style) pairs in listing order.
+    pub fn entries(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.styles.iter().map(|(c, spec, _)| (*c, spec.as_str()))
+    }
+}
+
+/// Known class names, comma-separated, for error messages.
+fn class_names() -> String {
+    let names: Vec<&str> = DEFAULTS.iter().map(|(c, _)| *c).collect();
+    names.join(", ")
+}
+
+/// Whether colors should be on at startup: `NO_COLOR` is unset or empty
+/// (see no-color.org) and standard output is a terminal.
+pub fn color_supported() -> bool {
+    std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty()) && std::io::stdout().is_terminal()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_defaults() {
+        let theme = Theme::default();
+        assert_eq!(theme.paint("string", "a"), "\x1b[33ma\x1b[0m");
+        assert_eq!(theme.start("match"), "\x1b[1;4;35m");
+        assert_eq!(theme.start("nonsense"), "");
+    }
+
+    #[test]
+    fn test_set() {
+        let mut theme = Theme::default();
+        theme.set("keyword", "bold #ff8000").unwrap();
+        assert_eq!(theme.start("keyword"), "\x1b[1;38;2;255;128;0m");
+        theme.set("word", "").unwrap();
+        assert_eq!(theme.paint("word", "dup"), "dup");
+        assert!(theme.set("keyword", "mauve").is_err());
+        assert!(theme.set("nonsense", "red").is_err());
+    }
+
+    #[test]
+    fn test_disabled_is_plain() {
+        let theme = Theme {
+            enabled: false,
+            ..Theme::default()
+        };
+        assert_eq!(theme.paint("string", "a"), "a");
+        assert_eq!(theme.start("hint"), "");
+        assert_eq!(theme.reset(), "");
+    }
+}
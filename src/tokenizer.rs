@@ -1,10 +1,42 @@
+use std::borrow::Cow;
+
 /// A token with its quote status.
 /// `text` is the token content, `quoted` indicates if it was inside double quotes.
+#[derive(Clone, Debug, PartialEq)]
 pub struct Token {
     pub text: String,
     pub quoted: bool,
 }
 
+impl Token {
+    pub fn new(text: impl Into<String>, quoted: bool) -> Self {
+        Token {
+            text: text.into(),
+            quoted,
+        }
+    }
+
+    /// The text as keyword matching sees it: quoted tokens are never keywords.
+    pub fn keyword(&self) -> &str {
+        if self.quoted {
+            ""
+        } else {
+            &self.text
+        }
+    }
+}
+
+/// Written back as typed, with quotes if it was quoted.
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.quoted {
+            write!(f, "\"{}\"", self.text.replace('"', "\\\""))
+        } else {
+            f.write_str(&self.text)
+        }
+    }
+}
+
 /// A token with its position in the original input string.
 /// Used by the syntax highlighter to map tokens back to byte offsets.
 pub struct TokenWithPosition {
@@ -12,63 +44,21 @@ pub struct TokenWithPosition {
     pub quoted: bool,
     /// Byte offset of the start of this token in the original input (including the opening quote for quoted tokens).
     pub position: usize,
+    /// Byte offset just past the token, including a closing quote.
+    pub end: usize,
 }
 
 /// Tokenize a line of input with quote awareness.
 ///
 /// - Quoted strings (`"hello world"`) become a single token with `quoted = true`.
+/// - Inside quotes, `\"` stands for a literal `"`; other backslashes are kept.
 /// - Whitespace outside quotes separates tokens.
 /// - Returns a list of (text, is_quoted) pairs.
 pub fn tokenize(line: &str) -> Vec<Token> {
-    let mut tokens = Vec::new();
-    let mut current = String::new();
-    let mut in_quote = false;
-    let chars = line.chars();
-
-    for c in chars {
-        match c {
-            '"' if !in_quote => {
-                // Start of quoted string: flush any current unquoted token
-                if !current.is_empty() {
-                    tokens.push(Token {
-                        text: std::mem::take(&mut current),
-                        quoted: false,
-                    });
-                }
-                in_quote = true;
-            }
-            '"' if in_quote => {
-                // End of quoted string: emit as quoted token (even if empty)
-                tokens.push(Token {
-                    text: std::mem::take(&mut current),
-                    quoted: true,
-                });
-                in_quote = false;
-            }
-            c if c.is_whitespace() && !in_quote => {
-                // Whitespace outside quotes: token separator
-                if !current.is_empty() {
-                    tokens.push(Token {
-                        text: std::mem::take(&mut current),
-                        quoted: false,
-                    });
-                }
-            }
-            _ => {
-                current.push(c);
-            }
-        }
-    }
-
-    // Flush remaining token
-    if !current.is_empty() {
-        tokens.push(Token {
-            text: current,
-            quoted: in_quote, // unclosed quote stays quoted
-        });
-    }
-
-    tokens
+    tokenize_with_positions(line)
+        .into_iter()
+        .map(|t| Token::new(t.text, t.quoted))
+        .collect()
 }
 
 /// Tokenize a line of input with quote awareness, tracking byte positions.
@@ -81,8 +71,9 @@ pub fn tokenize_with_positions(line: &str) -> Vec<TokenWithPosition> {
     let mut current_start: usize = 0;
     let mut in_quote = false;
     let mut quote_start: usize = 0;
+    let mut chars = line.char_indices().peekable();
 
-    for (i, c) in line.char_indices() {
+    while let Some((i, c)) = chars.next() {
         match c {
             '"' if !in_quote => {
                 // Start of quoted string: flush any current unquoted token
@@ -91,17 +82,24 @@ pub fn tokenize_with_positions(line: &str) -> Vec<TokenWithPosition> {
                         text: std::mem::take(&mut current),
                         quoted: false,
                         position: current_start,
+                        end: i,
                     });
                 }
                 in_quote = true;
                 quote_start = i;
             }
+            '\\' if in_quote && chars.peek().is_some_and(|&(_, next)| next == '"') => {
+                // Escaped quote: part of the string
+                chars.next();
+                current.push('"');
+            }
             '"' if in_quote => {
-                // End of quoted string: emit as quoted token
+                // End of quoted string: emit as quoted token (even if empty)
                 tokens.push(TokenWithPosition {
                     text: std::mem::take(&mut current),
                     quoted: true,
                     position: quote_start,
+                    end: i + 1,
                 });
                 in_quote = false;
             }
@@ -112,6 +110,7 @@ pub fn tokenize_with_positions(line: &str) -> Vec<TokenWithPosition> {
                         text: std::mem::take(&mut current),
                         quoted: false,
                         position: current_start,
+                        end: i,
                     });
                 }
             }
@@ -124,22 +123,107 @@ pub fn tokenize_with_positions(line: &str) -> Vec<TokenWithPosition> {
         }
     }
 
-    // Flush remaining token
+    // Flush remaining token; an unclosed quote stays quoted
     if !current.is_empty() {
         let pos = if in_quote { quote_start } else { current_start };
         tokens.push(TokenWithPosition {
             text: current,
             quoted: in_quote,
             position: pos,
+            end: line.len(),
         });
     }
 
     tokens
 }
 
+/// Parse an integer literal: decimal, or `0x`/`0o`/`0b` prefixed hex/octal/binary.
+///
+/// A leading `-` negates any form (e.g. `-0x10` is -16).
+pub fn parse_int(s: &str) -> Option<i64> {
+    let (negative, digits) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s),
+    };
+    let radix = match digits.get(..2) {
+        Some("0x") | Some("0X") => 16,
+        Some("0o") | Some("0O") => 8,
+        Some("0b") | Some("0B") => 2,
+        _ => return s.parse().ok(),
+    };
+    let body = &digits[2..];
+    // from_str_radix would accept a second sign after the prefix
+    if body.starts_with(['+', '-']) {
+        return None;
+    }
+    let magnitude = u64::from_str_radix(body, radix).ok()?;
+    if negative {
+        0i64.checked_sub_unsigned(magnitude)
+    } else {
+        i64::try_from(magnitude).ok()
+    }
+}
+
+/// Expand `$NAME` and `${NAME}` in a string using `lookup` (the process
+/// environment, in eval). Names are letters, digits, and `_`, not starting
+/// with a digit.
+///
+/// A variable `lookup` doesn't know is left as written, so strings naming
+/// words such as `"$basename"` keep working and a misspelled name stays
+/// visible. `\$` stands for a literal `$`; other backslashes are kept.
+pub fn interpolate_env(s: &str, lookup: impl Fn(&str) -> Option<String>) -> Cow<'_, str> {
+    if !s.contains('$') {
+        return Cow::Borrowed(s);
+    }
+    let is_name_start = |c: char| c.is_ascii_alphabetic() || c == '_';
+    let is_name_char = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(i) = rest.find(['$', '\\']) {
+        out.push_str(&rest[..i]);
+        let after = &rest[i + 1..];
+        if rest[i..].starts_with('\\') {
+            match after.strip_prefix('$') {
+                Some(tail) => {
+                    out.push('$');
+                    rest = tail;
+                }
+                None => {
+                    out.push('\\');
+                    rest = after;
+                }
+            }
+            continue;
+        }
+        // `${NAME}` or `$NAME`: the name, and how much of `after` it spans
+        let (name, len) = match after.strip_prefix('{') {
+            Some(braced) => match braced.find('}') {
+                Some(end) if braced[..end].starts_with(is_name_start)
+                    && braced[..end].chars().all(is_name_char) =>
+                {
+                    (&braced[..end], end + 2)
+                }
+                _ => ("", 0),
+            },
+            None if after.starts_with(is_name_start) => {
+                let end = after.find(|c: char| !is_name_char(c)).unwrap_or(after.len());
+                (&after[..end], end)
+            }
+            None => ("", 0),
+        };
+        match lookup(name) {
+            Some(value) if !name.is_empty() => out.push_str(&value),
+            _ => out.push_str(&rest[i..i + 1 + len]),
+        }
+        rest = &after[len..];
+    }
+    out.push_str(rest);
+    Cow::Owned(out)
+}
+
 /// Check if a string represents an integer.
 pub fn is_int(s: &str) -> bool {
-    s.parse::<i64>().is_ok()
+    parse_int(s).is_some()
 }
 
 #[cfg(test)]
@@ -166,6 +250,19 @@ mod tests {
         assert!(!tokens[1].quoted);
     }
 
+    #[test]
+    fn test_escaped_quote() {
+        let tokens = tokenize(r#""say \"hi\"" a\b"#);
+        assert_eq!(tokens[0], Token::new(r#"say "hi""#, true));
+        assert_eq!(tokens[0].to_string(), r#""say \"hi\"""#);
+        // Other backslashes, and any outside quotes, are kept
+        assert_eq!(tokens[1], Token::new(r"a\b", false));
+        assert_eq!(tokenize(r#""\d+""#)[0].text, r"\d+");
+        let tokens = tokenize_with_positions(r#""a\"b" c"#);
+        assert_eq!((tokens[0].position, tokens[0].end), (0, 6));
+        assert_eq!(tokens[1].position, 7);
+    }
+
     #[test]
     fn test_empty_quoted_string() {
         let tokens = tokenize("\"\" foo");
@@ -183,6 +280,20 @@ mod tests {
         assert!(!is_int("12abc"));
     }
 
+    #[test]
+    fn test_prefixed_integers() {
+        assert_eq!(parse_int("0x1F"), Some(31));
+        assert_eq!(parse_int("0b1010"), Some(10));
+        assert_eq!(parse_int("0o755"), Some(493));
+        assert_eq!(parse_int("-0x10"), Some(-16));
+        assert_eq!(parse_int("-0x8000000000000000"), Some(i64::MIN));
+        assert_eq!(parse_int("0x8000000000000000"), None);
+        assert_eq!(parse_int("0x"), None);
+        assert_eq!(parse_int("0x-1"), None);
+        assert_eq!(parse_int("0b102"), None);
+        assert_eq!(parse_int("007"), Some(7));
+    }
+
     #[test]
     fn test_mixed() {
         let tokens = tokenize(": greet \"hello\" . ;");
@@ -259,4 +370,30 @@ mod tests {
         assert_eq!(tokens[1].text, "b");
         assert_eq!(tokens[1].position, 4);
     }
+
+    // ===== interpolate_env tests =====
+
+    fn env(name: &str) -> Option<String> {
+        match name {
+            "HOME" => Some("/home/u".into()),
+            "EMPTY" => Some(String::new()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_interpolate_env() {
+        assert_eq!(interpolate_env("$HOME/bin", env), "/home/u/bin");
+        assert_eq!(interpolate_env("${HOME}bin", env), "/home/ubin");
+        assert_eq!(interpolate_env("a${EMPTY}b $EMPTY.", env), "ab .");
+        assert_eq!(interpolate_env("no vars", env), "no vars");
+    }
+
+    #[test]
+    fn test_interpolate_env_leaves_unknown_and_escaped() {
+        assert_eq!(interpolate_env("$basename", env), "$basename");
+        assert_eq!(interpolate_env("${NOPE} $5 $ ${HOME", env), "${NOPE} $5 $ ${HOME");
+        assert_eq!(interpolate_env("\\$HOME is $HOME", env), "$HOME is /home/u");
+        assert_eq!(interpolate_env("C:\\dir \\\\$HOME", env), "C:\\dir \\$HOME");
+    }
 }
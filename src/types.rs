@@ -1,4 +1,15 @@
 use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::builtins::system::{CommandRunner, SystemRunner};
+use crate::compile::{self, Op};
+use crate::history::HistoryConfig;
+use crate::local_rc::LocalRc;
+use crate::prompt_cache::PromptCache;
+use crate::sink::Sink;
+use crate::undo::UndoHistory;
+use crate::theme::Theme;
+use crate::tokenizer::Token;
 
 /// Core value types on the stack.
 #[derive(Clone, Debug, PartialEq)]
@@ -7,8 +18,28 @@ pub enum Value {
     Str(String),
     /// Integer value
     Int(i64),
-    /// Output from a shell command (automatically pipes to next command as stdin)
-    Output(String),
+    /// Output from a shell command (automatically pipes to next command as stdin),
+    /// with the command it came from, if any (see `out-status`)
+    Output(String, Option<Origin>),
+    /// String keys to values (see `map-new`)
+    Map(HashMap<String, Value>),
+    /// An ordered sequence of values (e.g. rows from `csv-parse`)
+    List(Vec<Value>),
+}
+
+/// The external command an Output came from, and how it exited.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Origin {
+    /// The command's name, without its directory
+    pub command: String,
+    pub code: i32,
+}
+
+impl Value {
+    /// An Output that didn't come from an external command.
+    pub fn output(text: impl Into<String>) -> Self {
+        Value::Output(text.into(), None)
+    }
 }
 
 impl std::fmt::Display for Value {
@@ -16,29 +47,111 @@ impl std::fmt::Display for Value {
         match self {
             Value::Str(s) => write!(f, "{}", s),
             Value::Int(n) => write!(f, "{}", n),
-            Value::Output(s) => write!(f, "{}", s),
+            Value::Output(s, _) => write!(f, "{}", s),
+            Value::Map(map) => {
+                write!(f, "{{")?;
+                for (i, (key, val)) in sorted_entries(map).into_iter().enumerate() {
+                    let sep = if i == 0 { "" } else { ", " };
+                    write!(f, "{}{:?}: ", sep, key)?;
+                    fmt_nested(f, val)?;
+                }
+                write!(f, "}}")
+            }
+            Value::List(items) => {
+                write!(f, "[")?;
+                for (i, val) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    fmt_nested(f, val)?;
+                }
+                write!(f, "]")
+            }
+        }
+    }
+}
+
+/// A value inside a map or list: numbers and containers as they are,
+/// text quoted.
+fn fmt_nested(f: &mut std::fmt::Formatter<'_>, val: &Value) -> std::fmt::Result {
+    match val {
+        Value::Int(_) | Value::Map(_) | Value::List(_) => write!(f, "{}", val),
+        _ => write!(f, "{:?}", val.to_string()),
+    }
+}
+
+/// A map's entries in key order, for stable display.
+pub fn sorted_entries(map: &HashMap<String, Value>) -> Vec<(&String, &Value)> {
+    let mut entries: Vec<_> = map.iter().collect();
+    entries.sort_by(|a, b| a.0.cmp(b.0));
+    entries
+}
+
+impl Value {
+    /// Type name as reported by `typeof`: "str", "int", "output", "map", or
+    /// "list".
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Str(_) => "str",
+            Value::Int(_) => "int",
+            Value::Output(_, _) => "output",
+            Value::Map(_) => "map",
+            Value::List(_) => "list",
         }
     }
 }
 
 pub type Stack = Vec<Value>;
 
+/// Word names and their definitions.
+pub type Dict = HashMap<String, Word>;
+
 /// A builtin function that operates on the full interpreter state.
 pub type BuiltinFn = fn(&mut State) -> Result<(), String>;
 
 /// Word types in the dictionary.
 #[derive(Clone)]
-#[allow(dead_code)]
 pub enum Word {
     /// Native builtin function with optional doc string
     Builtin(BuiltinFn, Option<&'static str>),
-    /// User-defined word (list of tokens to replay)
-    Defined(Vec<String>),
+    /// User-defined word: its tokens as written, and the same body with
+    /// loops compiled (see [`Word::defined`])
+    Defined(Vec<Token>, Rc<[Op]>),
+    /// Alias (source text expanded in place, see `alias`)
+    Alias(String),
+    /// Deferred word: runs the word named by `is`, looked up at call time
+    Deferred(Option<String>),
     /// External shell command (cached path)
-    #[allow(dead_code)]
     ShellCmd(String),
 }
 
+impl Word {
+    /// A user-defined word, with its loops compiled once up front.
+    pub fn defined(tokens: Vec<Token>) -> Self {
+        let ops = compile::compile(&tokens);
+        Word::Defined(tokens, ops.into())
+    }
+
+    /// One-line description for listings: the doc string of a builtin, or
+    /// the start of a defined word's body.
+    pub fn summary(&self) -> String {
+        match self {
+            Word::Builtin(_, doc) => doc.unwrap_or_default().to_string(),
+            Word::Defined(tokens, _) => {
+                let mut summary = format!(": {}", tokens.iter().take(6).map(Token::to_string).collect::<Vec<_>>().join(" "));
+                if tokens.len() > 6 {
+                    summary.push_str(" ...");
+                }
+                summary
+            }
+            Word::Alias(text) => format!("alias for {}", text),
+            Word::Deferred(Some(target)) => format!("deferred: {}", target),
+            Word::Deferred(None) => "deferred (not set)".to_string(),
+            Word::ShellCmd(path) => path.clone(),
+        }
+    }
+}
+
 /// Loop type during body collection.
 #[derive(Clone, Debug)]
 pub enum LoopType {
@@ -55,6 +168,9 @@ pub enum LoopInfo {
     DoPlusCountedLoop { start: i64, limit: i64, current: i64 },
     BeginUntilLoop,
     BeginWhileLoop,
+    BeginAgainLoop,
+    /// `each`, `each-word`, or `each-char`, at the item numbered `current` (from 0)
+    EachLoop { current: i64 },
 }
 
 /// Control flow target for skipping.
@@ -71,26 +187,181 @@ pub enum ControlFlow {
     Skipping { target: SkipTarget, depth: usize },
 }
 
+/// Candidates offered when completing an argument of a particular word.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompletionKind {
+    /// Directories only
+    Dirs,
+    /// Files and directories
+    Files,
+    /// Environment variable names
+    Env,
+    /// Dictionary words
+    Words,
+    /// Executables in $PATH
+    Commands,
+}
+
+impl CompletionKind {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "dirs" => Some(CompletionKind::Dirs),
+            "files" => Some(CompletionKind::Files),
+            "env" => Some(CompletionKind::Env),
+            "words" => Some(CompletionKind::Words),
+            "commands" => Some(CompletionKind::Commands),
+            _ => None,
+        }
+    }
+}
+
+/// A named piece of the prompt rendered by `$prompt-segments`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PromptSegment {
+    pub name: String,
+    /// yafsh source producing the segment text (usually a single word)
+    pub source: String,
+    /// Color spec as accepted by `fg`, or "" for unstyled
+    pub style: Value,
+    /// yafsh source that must leave a true value for the segment to show
+    pub condition: Option<String>,
+}
+
+/// A stack that isn't active, kept with its own undo history.
+#[derive(Clone, Debug, Default)]
+pub struct NamedStack {
+    pub values: Stack,
+    pub undo: UndoHistory,
+}
+
+/// Name of the stack the shell starts on.
+pub const MAIN_STACK: &str = "main";
+
 /// The full interpreter state.
 pub struct State {
     pub stack: Stack,
-    pub dict: HashMap<String, Word>,
+    /// Shared with the line editor's stack preview, so it isn't copied at
+    /// every prompt; change it through `dict_mut`
+    pub dict: Rc<Dict>,
+    /// The builtins as registered, reachable as "builtin:name" even when shadowed
+    pub builtins: HashMap<String, Word>,
+    /// Whether the next definition may replace a builtin (see `redefine-ok`)
+    pub redefine_ok: bool,
+    /// Word being reloaded by `edit`, which replaces it without a warning
+    pub editing: Option<String>,
+    /// Declared vocabularies, in creation order. A vocabulary's words live in
+    /// `dict` under qualified names such as "git:status" (see `vocabulary`)
+    pub vocabularies: Vec<String>,
+    /// Vocabularies searched before the main dictionary, first match wins (see `also`)
+    pub search_order: Vec<String>,
+    /// Vocabulary the next definition goes into (see `in`)
+    pub definition_vocab: Option<String>,
     /// Currently defining a word (name)
     pub defining: Option<String>,
     /// Body of word being defined (accumulated tokens)
-    pub def_body: Vec<String>,
+    pub def_body: Vec<Token>,
     /// Exit code of last shell command
     pub last_exit_code: i32,
+    /// Status to leave the shell with, once `exit` has run
+    pub exit_requested: Option<i32>,
+    /// Status code of the last HTTP response (0 if no request has been made)
+    pub last_http_status: u16,
     /// Control flow state for if/then/else
     pub control_flow: ControlFlow,
     /// Directory stack for pushd/popd
     pub dir_stack: Vec<String>,
+    /// Previous working directory, for `"-" cd` (mirrored to $OLDPWD)
+    pub oldpwd: Option<String>,
+    /// Whether a bare directory name changes into it (see `set-autocd`)
+    pub autocd: bool,
+    /// Whether the line being run is a single token, the only place autocd
+    /// applies; cleared by the first token executed
+    pub lone_token: bool,
+    /// Set while the `on-not-found` hook runs, so unknown tokens inside it don't recurse
+    pub in_not_found_hook: bool,
+    /// Aliases being expanded, so an alias can use the command it shadows
+    pub expanding_aliases: Vec<String>,
+    /// Set while `$preexec`/`$postexec` run, so their own commands don't re-trigger them
+    pub in_exec_hook: bool,
+    /// Whether line editing uses vi keybindings instead of emacs ones (see `set-keymode`)
+    pub vi_mode: bool,
+    /// Whether the line editor suggests the rest of a matching history entry (see `set-autosuggest`)
+    pub autosuggest: bool,
+    /// Whether the line editor previews the stack the typed line would leave (see `set-stack-preview`)
+    pub stack_preview: bool,
+    /// Colors for highlighting and trace output (see `theme`, `set-color`)
+    pub theme: Theme,
+    /// Whether unknown tokens print "did you mean ...?" suggestions (see `set-suggest`)
+    pub suggest: bool,
+    /// Whether arithmetic and comparisons accept numeric Str/Output values (see `set-coerce`)
+    pub coerce: bool,
+    /// Whether pipe mode exits with the last line's status rather than 0 (see `set-exit-status`)
+    pub exit_status: bool,
+    /// Whether pipe mode and RC files stop at the first line that fails (see `set-errexit`)
+    pub errexit: bool,
+    /// Whether `*` and `?` match names starting with `.` (see `set-glob-dotfiles`)
+    pub glob_dotfiles: bool,
+    /// Whether Outputs taller than the terminal are shown through $PAGER (see `set-pager`)
+    pub pager: bool,
+    /// Most iterations any one loop may run before failing, 0 for no limit (see `set-max-iterations`)
+    pub max_iterations: u64,
+    /// Seconds an external command must run before its times are printed, if at all (see `set-report-time`)
+    pub report_time: Option<u64>,
+    /// Whether external commands are printed instead of run (see `dry-run`)
+    pub dry_run: bool,
+    /// Whether external commands' stderr is captured into their Output (see `merge-stderr`)
+    pub merge_stderr: bool,
+    /// Finds and runs external commands; a `MockRunner` makes evaluation
+    /// independent of PATH and the machine's programs
+    pub runner: Box<dyn CommandRunner>,
+    /// Where builtins print; `Sink::capture()` collects it instead of stdout
+    pub out: Sink,
+    /// In restricted mode, the directory `cd` is confined to (see `set-restricted`)
+    pub restricted: Option<std::path::PathBuf>,
+    /// Temp files made by `psub`, removed when the line that made them ends
+    pub psub_files: Vec<std::path::PathBuf>,
+    /// A file the next external command reads as its stdin (see `<stdin`)
+    pub stdin_file: Option<std::path::PathBuf>,
+    /// Executable names in $PATH, keyed by the $PATH value they were scanned from
+    pub path_cache: Option<(String, Vec<String>)>,
+    /// Cached results of slow prompt helpers such as `$gitbranch`
+    pub prompt_cache: PromptCache,
+    /// Lines entered at the interactive prompt, oldest first (mirrors the
+    /// line editor's history for `history` and `!n`)
+    pub history: Vec<String>,
+    /// Which lines get recorded in the history, and how many are kept
+    pub history_config: HistoryConfig,
+    /// Completion source for the arguments of specific words (see `complete-with`)
+    pub completers: HashMap<String, CompletionKind>,
+    /// Registered prompt segments, in display order
+    pub prompt_segments: Vec<PromptSegment>,
+    /// Text placed between non-empty prompt segments
+    pub segment_separator: String,
+    /// Prompt for continuation lines of an incomplete entry, unless `$prompt2`
+    /// is defined; with neither, incomplete input stays open in the editor
+    pub continuation_prompt: Option<String>,
+    /// How long the last line entered took to run, for `$cmdtime`
+    pub last_duration: Option<std::time::Duration>,
+    /// Shortest line duration `$cmdtime` shows (see `set-cmdtime-min`)
+    pub cmdtime_min: std::time::Duration,
+    /// Per-directory `.yafshrc.local` files (see `set-local-rc`, `local-allow`)
+    pub local_rc: LocalRc,
+    /// Stacks from before earlier lines, for `undo` and `redo`
+    pub undo: UndoHistory,
+    /// Stacks parked with `stash`, by name
+    pub stashes: HashMap<String, Vec<Value>>,
+    /// Name of the active stack (see `stack-switch`)
+    pub stack_name: String,
+    /// The stacks that aren't active, by name
+    pub other_stacks: HashMap<String, NamedStack>,
     /// Stack of active loops for i/j index access
     pub loop_stack: Vec<LoopInfo>,
+    /// Set by `leave` until the innermost loop stops
+    pub leave_requested: bool,
     /// Collecting loop body: (loop_type, body_tokens, nesting_depth)
-    pub collecting_loop: Option<(LoopType, Vec<String>, usize)>,
-    /// Collecting each body: (output_content, body_tokens)
-    pub collecting_each: Option<(String, Vec<String>)>,
+    pub collecting_loop: Option<(LoopType, Vec<Token>, usize)>,
+    /// Collecting each body: (keyword, items to iterate over, body_tokens)
+    pub collecting_each: Option<(String, Vec<String>, Vec<Token>)>,
     /// Cached result of evaluating the `$prompt` word (custom prompt string)
     pub custom_prompt: Option<String>,
     /// Saved stack during prompt evaluation so $stack/$in/$out see the real stack
@@ -99,6 +370,8 @@ pub struct State {
     pub trace: u8,
     /// Step counter for trace output (reset per eval_line)
     pub trace_step: usize,
+    /// Where `trace-file` writes steps as JSON lines (replacing stderr trace)
+    pub trace_file: Option<std::io::LineWriter<std::fs::File>>,
 }
 
 impl Default for State {
@@ -111,19 +384,111 @@ impl State {
     pub fn new() -> Self {
         State {
             stack: Vec::new(),
-            dict: HashMap::new(),
+            dict: Rc::new(HashMap::new()),
+            builtins: HashMap::new(),
+            redefine_ok: false,
+            editing: None,
+            vocabularies: Vec::new(),
+            search_order: Vec::new(),
+            definition_vocab: None,
             defining: None,
             def_body: Vec::new(),
             last_exit_code: 0,
+            exit_requested: None,
+            last_http_status: 0,
             control_flow: ControlFlow::Normal,
             dir_stack: Vec::new(),
+            oldpwd: None,
+            autocd: false,
+            lone_token: false,
+            in_not_found_hook: false,
+            expanding_aliases: Vec::new(),
+            in_exec_hook: false,
+            vi_mode: false,
+            autosuggest: false,
+            stack_preview: false,
+            theme: Theme::default(),
+            suggest: false,
+            coerce: false,
+            exit_status: true,
+            errexit: false,
+            glob_dotfiles: true,
+            pager: true,
+            dry_run: false,
+            merge_stderr: false,
+            runner: Box::new(SystemRunner),
+            out: Sink::Stdout,
+            restricted: None,
+            psub_files: Vec::new(),
+            stdin_file: None,
+            max_iterations: crate::loops::DEFAULT_MAX_ITERATIONS,
+            report_time: None,
+            path_cache: None,
+            prompt_cache: PromptCache::new(),
+            history: Vec::new(),
+            history_config: HistoryConfig::default(),
+            completers: HashMap::new(),
+            prompt_segments: Vec::new(),
+            segment_separator: " ".to_string(),
+            continuation_prompt: None,
+            last_duration: None,
+            cmdtime_min: crate::builtins::introspection::DEFAULT_CMDTIME_MIN,
+            local_rc: LocalRc::default(),
+            undo: UndoHistory::default(),
+            stashes: HashMap::new(),
+            stack_name: MAIN_STACK.to_string(),
+            other_stacks: HashMap::new(),
             loop_stack: Vec::new(),
+            leave_requested: false,
             collecting_loop: None,
             collecting_each: None,
             custom_prompt: None,
             prompt_eval_original_stack: None,
             trace: 0,
             trace_step: 0,
+            trace_file: None,
+        }
+    }
+
+    /// The dictionary, for defining or removing words. Copies it first if
+    /// the line editor still holds the previous one.
+    pub fn dict_mut(&mut self) -> &mut Dict {
+        Rc::make_mut(&mut self.dict)
+    }
+
+    /// Look up a word. Unqualified names are tried in the search-order
+    /// vocabularies first (see `also`), then in the main dictionary;
+    /// "builtin:name" always finds the original builtin.
+    pub fn lookup(&self, name: &str) -> Option<&Word> {
+        if let Some(builtin) = name.strip_prefix("builtin:") {
+            return self.builtins.get(builtin);
+        }
+        self.search_order
+            .iter()
+            .find_map(|vocab| self.dict.get(&format!("{}:{}", vocab, name)))
+            .or_else(|| self.dict.get(name))
+    }
+
+    /// Every name a token can currently resolve to, with its word: the
+    /// dictionary keys, bare names from search-order vocabularies, and
+    /// "builtin:name" for builtins that something else shadows.
+    /// When a name repeats, the entry that `lookup` finds comes last.
+    pub fn visible_words(&self) -> Vec<(String, &Word)> {
+        let mut words: Vec<(String, &Word)> =
+            self.dict.iter().map(|(name, word)| (name.clone(), word)).collect();
+        for vocab in self.search_order.iter().rev() {
+            let prefix = format!("{}:", vocab);
+            for (name, word) in self.dict.iter() {
+                if let Some(bare) = name.strip_prefix(&prefix) {
+                    words.push((bare.to_string(), word));
+                }
+            }
+        }
+        for (name, word) in &self.builtins {
+            if !matches!(self.lookup(name), Some(Word::Builtin(..))) {
+                words.push((format!("builtin:{}", name), word));
+            }
         }
+        words
     }
 }
@@ -0,0 +1,117 @@
+use crate::types::Value;
+
+/// Most stack snapshots kept for `undo`.
+pub const UNDO_LIMIT: usize = 100;
+
+/// Snapshots of the stack from before each line that changed it, for
+/// `undo` and `redo`.
+///
+/// The REPL calls [`UndoHistory::record`] after every line it evaluates;
+/// lines that leave the stack as it was don't take a slot.
+#[derive(Clone, Debug, Default)]
+pub struct UndoHistory {
+    /// Earlier stacks, oldest first
+    undo: Vec<Vec<Value>>,
+    /// Stacks undone, most recently undone last
+    redo: Vec<Vec<Value>>,
+    /// Set so the current line's change isn't recorded (by `undo`, `redo`,
+    /// and switching stacks)
+    restored: bool,
+}
+
+impl UndoHistory {
+    /// Note that a line turned the stack `before` into `after`.
+    pub fn record(&mut self, before: Vec<Value>, after: &[Value]) {
+        if std::mem::take(&mut self.restored) || before == after {
+            return;
+        }
+        self.undo.push(before);
+        if self.undo.len() > UNDO_LIMIT {
+            self.undo.remove(0);
+        }
+        self.redo.clear();
+    }
+
+    /// Don't record the change made by the line being evaluated.
+    pub fn skip_line(&mut self) {
+        self.restored = true;
+    }
+
+    /// Swap `stack` for the one before the last recorded line.
+    /// Returns false if there is nothing to undo.
+    pub fn undo(&mut self, stack: &mut Vec<Value>) -> bool {
+        let Some(previous) = self.undo.pop() else {
+            return false;
+        };
+        self.redo.push(std::mem::replace(stack, previous));
+        self.restored = true;
+        true
+    }
+
+    /// Reapply the stack most recently undone.
+    /// Returns false if there is nothing to redo.
+    pub fn redo(&mut self, stack: &mut Vec<Value>) -> bool {
+        let Some(next) = self.redo.pop() else {
+            return false;
+        };
+        self.undo.push(std::mem::replace(stack, next));
+        self.restored = true;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_undo_redo() {
+        let mut history = UndoHistory::default();
+        let mut stack = vec![Value::Int(1)];
+        history.record(vec![], &stack);
+        let before = stack.clone();
+        stack.clear();
+        history.record(before, &stack);
+
+        assert!(history.undo(&mut stack));
+        history.record(vec![], &stack); // the `undo` line itself
+        assert_eq!(stack, vec![Value::Int(1)]);
+        assert!(history.undo(&mut stack));
+        assert_eq!(stack, vec![]);
+        assert!(!history.undo(&mut stack));
+
+        assert!(history.redo(&mut stack));
+        assert_eq!(stack, vec![Value::Int(1)]);
+        assert!(history.redo(&mut stack));
+        assert_eq!(stack, vec![]);
+        assert!(!history.redo(&mut stack));
+    }
+
+    #[test]
+    fn test_new_change_drops_redo() {
+        let mut history = UndoHistory::default();
+        let mut stack = vec![Value::Int(1)];
+        history.record(vec![], &stack);
+        assert!(history.undo(&mut stack));
+        history.record(vec![Value::Int(1)], &stack);
+        history.record(vec![], &[Value::Int(2)]);
+        assert!(!history.redo(&mut stack));
+    }
+
+    #[test]
+    fn test_unchanged_lines_not_recorded_and_limit() {
+        let mut history = UndoHistory::default();
+        history.record(vec![Value::Int(1)], &[Value::Int(1)]);
+        assert!(!history.undo(&mut vec![]));
+        for n in 0..(UNDO_LIMIT as i64 + 5) {
+            history.record(vec![Value::Int(n)], &[]);
+        }
+        let mut stack = vec![];
+        let mut count = 0;
+        while history.undo(&mut stack) {
+            count += 1;
+        }
+        assert_eq!(count, UNDO_LIMIT);
+        assert_eq!(stack, vec![Value::Int(5)]);
+    }
+}
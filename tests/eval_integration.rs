@@ -1,6 +1,8 @@
 use yafsh::builtins;
+use yafsh::builtins::system::{MockRunner, StdinSource};
 use yafsh::eval;
-use yafsh::types::{State, Value};
+use yafsh::sink::Sink;
+use yafsh::types::{Origin, State, Value};
 
 /// Create a fresh state with all builtins registered.
 fn new_state() -> State {
@@ -105,32 +107,35 @@ fn eval_rot() {
 // ========== Shell execution ==========
 
 #[test]
+#[cfg(feature = "native")]
 fn eval_echo() {
     let stack = eval("hello /bin/echo");
     assert_eq!(stack.len(), 1);
     match &stack[0] {
-        Value::Output(s) => assert_eq!(s.trim(), "hello"),
+        Value::Output(s, _) => assert_eq!(s.trim(), "hello"),
         other => panic!("expected Output, got {:?}", other),
     }
 }
 
 #[test]
+#[cfg(feature = "native")]
 fn eval_echo_multiple_args() {
     let stack = eval("hello world /bin/echo");
     assert_eq!(stack.len(), 1);
     match &stack[0] {
-        Value::Output(s) => assert_eq!(s.trim(), "hello world"),
+        Value::Output(s, _) => assert_eq!(s.trim(), "hello world"),
         other => panic!("expected Output, got {:?}", other),
     }
 }
 
 #[test]
+#[cfg(feature = "native")]
 fn eval_path_lookup() {
     // "echo" should be found in PATH
     let stack = eval("hello echo");
     assert_eq!(stack.len(), 1);
     match &stack[0] {
-        Value::Output(s) => assert_eq!(s.trim(), "hello"),
+        Value::Output(s, _) => assert_eq!(s.trim(), "hello"),
         other => panic!("expected Output, got {:?}", other),
     }
 }
@@ -138,12 +143,13 @@ fn eval_path_lookup() {
 // ========== Auto-piping ==========
 
 #[test]
+#[cfg(feature = "native")]
 fn eval_auto_pipe() {
     // echo produces Output, then wc -c counts its bytes via stdin
     let s = eval_lines(&["hello echo", "\"-c\" wc"]);
     assert_eq!(s.stack.len(), 1);
     match &s.stack[0] {
-        Value::Output(out) => {
+        Value::Output(out, _) => {
             let n: i64 = out.trim().parse().unwrap();
             assert_eq!(n, 6); // "hello\n" = 6 bytes
         }
@@ -154,13 +160,14 @@ fn eval_auto_pipe() {
 // ========== Depth control ==========
 
 #[test]
+#[cfg(feature = "native")]
 fn eval_depth_control() {
     // "extra" "hello" 1 echo -> echo only takes 1 arg, "extra" stays
     let stack = eval("extra hello 1 echo");
     assert_eq!(stack.len(), 2);
     assert_eq!(stack[0], Value::Str("extra".into()));
     match &stack[1] {
-        Value::Output(s) => assert_eq!(s.trim(), "hello"),
+        Value::Output(s, _) => assert_eq!(s.trim(), "hello"),
         other => panic!("expected Output, got {:?}", other),
     }
 }
@@ -188,6 +195,67 @@ fn eval_word_overwrites() {
     assert_eq!(s.stack, vec![Value::Int(2)]);
 }
 
+#[test]
+fn eval_word_cannot_replace_builtin() {
+    let mut s = new_state();
+    let err = eval::eval_line(&mut s, ": dup 42 ;").unwrap_err();
+    assert_eq!(err, "dup: is a builtin; use redefine-ok before : to replace it");
+    // The builtin still works, and no definition is left half-open
+    eval::eval_line(&mut s, "1 dup").unwrap();
+    assert_eq!(s.stack, vec![Value::Int(1), Value::Int(1)]);
+}
+
+#[test]
+fn eval_redefine_ok_and_builtin_escape() {
+    let s = eval_lines(&["redefine-ok : dup 42 ;", "1 dup builtin:dup"]);
+    assert_eq!(
+        s.stack,
+        vec![Value::Int(1), Value::Int(42), Value::Int(42)]
+    );
+}
+
+#[test]
+fn eval_redefine_ok_covers_one_definition() {
+    let mut s = eval_lines(&["redefine-ok : dup 42 ;"]);
+    assert!(eval::eval_line(&mut s, ": drop 0 ;").is_err());
+}
+
+#[test]
+fn eval_deferred_word_is_rebound() {
+    let s = eval_lines(&[
+        "\"greeting\" defer",
+        ": greet greeting \"!\" concat ;",
+        ": hello \"hi\" ;",
+        ": fake \"stub\" ;",
+        "\"hello\" \"greeting\" is greet",
+        "\"fake\" \"greeting\" is greet",
+    ]);
+    assert_eq!(
+        s.stack,
+        vec![Value::Str("hi!".into()), Value::Str("stub!".into())]
+    );
+}
+
+#[test]
+fn eval_deferred_word_not_set() {
+    let mut s = eval_lines(&["\"later\" defer"]);
+    let err = eval::eval_line(&mut s, "later").unwrap_err();
+    assert_eq!(err, "later: deferred word is not set (use is)");
+    let err = eval::eval_line(&mut s, "\"nope\" \"later\" is").unwrap_err();
+    assert_eq!(err, "is: unknown word 'nope'");
+    let err = eval::eval_line(&mut s, "clear \"dup\" \"drop\" is").unwrap_err();
+    assert_eq!(err, "is: drop is not a deferred word (declare it with defer)");
+
+    // Cycles through other deferred words are refused too
+    let mut s = eval_lines(&["\"a\" defer \"b\" defer", "\"a\" \"b\" is"]);
+    let err = eval::eval_line(&mut s, "\"b\" \"a\" is").unwrap_err();
+    assert_eq!(err, "is: a can't run itself");
+    assert_eq!(
+        s.stack,
+        vec![Value::Str("b".into()), Value::Str("a".into())]
+    );
+}
+
 #[test]
 fn eval_word_definition_multitoken() {
     // Define a word that pushes two values
@@ -267,12 +335,82 @@ fn eval_glob_no_match() {
     assert_eq!(stack, vec![Value::Str("zzzzz_no_match_*".into())]);
 }
 
+// ========== Brace expansion ==========
+
+fn strs(items: &[&str]) -> Vec<Value> {
+    items.iter().map(|s| Value::Str(s.to_string())).collect()
+}
+
+/// Helper: an Output from `command`, which exited with `code`.
+fn output_of(command: &str, code: i32, text: impl Into<String>) -> Value {
+    let origin = Origin { command: command.into(), code };
+    Value::Output(text.into(), Some(origin))
+}
+
+#[test]
+fn eval_brace_alternatives() {
+    assert_eq!(eval("notes.{txt,md}"), strs(&["notes.txt", "notes.md"]));
+    assert_eq!(eval("a{b,c{d,e}}f"), strs(&["abf", "acdf", "acef"]));
+    assert_eq!(eval("{x,y}{1,2}"), strs(&["x1", "x2", "y1", "y2"]));
+    // Quoted strings are never expanded
+    assert_eq!(eval("\"{a,b}\""), strs(&["{a,b}"]));
+}
+
+#[test]
+fn eval_brace_quoted_in_bodies_stay_literal() {
+    assert_eq!(eval(": f \"{a,b}\" ; f"), strs(&["{a,b}"]));
+    assert_eq!(eval(": f {a,b} ; f"), strs(&["a", "b"]));
+    assert_eq!(eval("0 1 do \"{a,b}\" loop"), strs(&["{a,b}"]));
+    assert_eq!(eval("\"x\" each-word \"{a,b}\" then"), strs(&["x", "{a,b}"]));
+}
+
+#[test]
+fn eval_brace_ranges() {
+    assert_eq!(eval("{1..3}"), strs(&["1", "2", "3"]));
+    assert_eq!(eval("{3..1}"), strs(&["3", "2", "1"]));
+    assert_eq!(eval("{1..10..4}"), strs(&["1", "5", "9"]));
+    assert_eq!(eval("v{08..10}"), strs(&["v08", "v09", "v10"]));
+    assert_eq!(eval("{-1..1}"), strs(&["-1", "0", "1"]));
+    assert_eq!(eval("{a..c}"), strs(&["a", "b", "c"]));
+}
+
+#[test]
+fn eval_brace_non_patterns_stay_literal() {
+    assert_eq!(eval("{}"), strs(&["{}"]));
+    assert_eq!(eval("{x}"), strs(&["{x}"]));
+    assert_eq!(eval("{1..}"), strs(&["{1..}"]));
+    assert_eq!(eval("{a,b"), strs(&["{a,b"]));
+}
+
+#[test]
+fn eval_brace_range_too_large() {
+    let mut s = new_state();
+    let result = eval::eval_line(&mut s, "{1..1000000000}");
+    assert_eq!(
+        result,
+        Err("{1..1000000000}: brace range has too many items".into())
+    );
+    assert!(s.stack.is_empty());
+    assert_eq!(
+        eval::eval_line(&mut s, "{-9223372036854775808..9223372036854775807}"),
+        Err("{-9223372036854775808..9223372036854775807}: brace range has too many items".into())
+    );
+    assert_eq!(
+        eval("{-9223372036854775808..9223372036854775807..9223372036854775807}"),
+        strs(&["-9223372036854775808", "-1", "9223372036854775806"])
+    );
+    assert_eq!(
+        eval("{9223372036854775807..-9223372036854775808..-9223372036854775808}"),
+        strs(&["9223372036854775807", "-1"])
+    );
+}
+
 // ========== Type conversions via eval ==========
 
 #[test]
 fn eval_to_output() {
     let s = eval_lines(&["\"data\" >output"]);
-    assert_eq!(s.stack, vec![Value::Output("data".into())]);
+    assert_eq!(s.stack, vec![Value::output("data")]);
 }
 
 #[test]
@@ -300,6 +438,7 @@ fn eval_exit_code_success() {
 }
 
 #[test]
+#[cfg(feature = "native")]
 fn eval_exit_code_failure() {
     let s = eval_lines(&["/bin/false", "?"]);
     assert_eq!(s.stack.len(), 2);
@@ -370,6 +509,80 @@ fn eval_negative_arithmetic() {
     assert_eq!(eval("-3 7 +"), vec![Value::Int(4)]);
 }
 
+#[test]
+fn eval_random_range_in_bounds() {
+    let s = eval_lines(&["10 20 random-range dup 10 >= swap 20 <= and"]);
+    assert_eq!(s.stack, vec![Value::Int(1)]);
+}
+
+#[test]
+fn eval_uuids_differ() {
+    let s = eval_lines(&["uuid uuid ="]);
+    assert_eq!(s.stack, vec![Value::Int(0)]);
+}
+
+#[test]
+fn eval_overflow_is_an_error() {
+    let mut s = new_state();
+    let err = eval::eval_line(&mut s, "9223372036854775807 1 +").unwrap_err();
+    assert_eq!(err, "+: integer overflow");
+}
+
+#[test]
+fn eval_wrapping_arithmetic() {
+    assert_eq!(
+        eval("9223372036854775807 1 wrap+"),
+        vec![Value::Int(i64::MIN)]
+    );
+}
+
+#[test]
+fn eval_prefixed_integer_literals() {
+    assert_eq!(
+        eval("0x1F 0b1010 0o755 -0x10"),
+        vec![Value::Int(31), Value::Int(10), Value::Int(493), Value::Int(-16)]
+    );
+}
+
+#[test]
+fn eval_base_conversion_round_trip() {
+    assert_eq!(eval("0o644 oct"), vec![Value::Str("644".into())]);
+    assert_eq!(eval("\"ff\" 16 base> 2 >base"), vec![Value::Str("11111111".into())]);
+}
+
+#[test]
+fn eval_min_max_abs_clamp() {
+    assert_eq!(
+        eval("3 9 min 3 9 max -4 abs 7 negate 150 0 100 clamp"),
+        vec![Value::Int(3), Value::Int(9), Value::Int(4), Value::Int(-7), Value::Int(100)]
+    );
+}
+
+#[test]
+fn eval_sleep_ms_consumes_duration() {
+    assert_eq!(eval("1 5 sleep-ms"), vec![Value::Int(1)]);
+}
+
+#[test]
+fn eval_filesystem_tests_drive_if() {
+    assert_eq!(
+        eval("\"/\" dir? if \"yes\" else \"no\" then \"/nonexistent/yafsh\" exists?"),
+        vec![Value::Str("yes".into()), Value::Int(0)]
+    );
+}
+
+#[test]
+fn eval_color_words_compose() {
+    assert_eq!(
+        eval(r#""red" fg "err" concat reset-style concat"#),
+        vec![Value::Str("\x1b[31merr\x1b[0m".into())]
+    );
+    assert_eq!(
+        eval(r#""ok" "bright-green" color-wrap"#),
+        vec![Value::Str("\x1b[92mok\x1b[0m".into())]
+    );
+}
+
 // ========== Comparisons ==========
 
 #[test]
@@ -516,6 +729,68 @@ fn eval_concat_empty() {
     assert_eq!(s.stack, vec![Value::Str("hello".into())]);
 }
 
+// ========== Output line processing ==========
+
+#[test]
+fn eval_head_tail() {
+    let s = eval_lines(&["\"a\nb\nc\nd\n\" >output 3 head 2 tail"]);
+    assert_eq!(s.stack, vec![Value::output("b\nc\n")]);
+}
+
+#[test]
+fn eval_line_count_and_nth_line() {
+    let s = eval_lines(&["\"x\ny\nz\n\" >output dup line-count swap 3 nth-line"]);
+    assert_eq!(s.stack, vec![Value::Int(3), Value::Str("z".into())]);
+}
+
+#[test]
+fn eval_sort_uniq_pipeline() {
+    let s = eval_lines(&["\"b\na\nb\nc\nb\n\" >output sort-lines count-uniq"]);
+    assert_eq!(s.stack, vec![Value::output("1 a\n3 b\n1 c\n")]);
+}
+
+#[test]
+fn eval_fields_column_selection() {
+    let s = eval_lines(&["\"alice 30\nbob 25\n\" >output 2 fields"]);
+    assert_eq!(s.stack, vec![Value::output("30\n25\n")]);
+}
+
+#[test]
+fn eval_field_by_delimiter() {
+    let s = eval_lines(&["\"root:x:0:0\" 1 \":\" field-by"]);
+    assert_eq!(s.stack, vec![Value::Str("root".into())]);
+}
+
+#[test]
+fn eval_table_by() {
+    let s = eval_lines(&["\"name:uid\nroot:0\n\" >output \":\" table-by"]);
+    assert_eq!(s.stack, vec![Value::output("name  uid\nroot  0\n")]);
+}
+
+#[test]
+fn eval_diff_two_outputs() {
+    let mut s = new_state();
+    eval::eval_line(&mut s, "\"x\ny\n\" >output \"x\nz\n\" >output diff").unwrap();
+    assert_eq!(
+        s.stack,
+        vec![Value::output("--- a\n+++ b\n@@ -1,2 +1,2 @@\n x\n-y\n+z\n")]
+    );
+    assert_eq!(s.last_exit_code, 1);
+}
+
+// ========== Hashing ==========
+
+#[test]
+fn eval_sha256_of_command_output() {
+    let s = eval_lines(&["\"abc\" >output sha256"]);
+    assert_eq!(
+        s.stack,
+        vec![Value::Str(
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad".into()
+        )]
+    );
+}
+
 // ========== Environment builtins ==========
 
 #[test]
@@ -548,6 +823,29 @@ fn eval_unsetenv() {
     assert_eq!(s.stack, vec![Value::Str("".into())]);
 }
 
+#[test]
+fn eval_quoted_strings_interpolate_env() {
+    let s = eval_lines(&[
+        "\"/opt/yafsh\" \"YAFSH_TEST_INTERP\" setenv",
+        "\"$YAFSH_TEST_INTERP/bin\" \"${YAFSH_TEST_INTERP}2 \\$YAFSH_TEST_INTERP\"",
+        ": libdir \"$YAFSH_TEST_INTERP/lib\" ;",
+        "libdir \"$basename\"",
+        "foo$YAFSH_TEST_INTERP",
+    ]);
+    std::env::remove_var("YAFSH_TEST_INTERP");
+    assert_eq!(
+        s.stack,
+        vec![
+            Value::Str("/opt/yafsh/bin".into()),
+            Value::Str("/opt/yafsh2 $YAFSH_TEST_INTERP".into()),
+            Value::Str("/opt/yafsh/lib".into()),
+            Value::Str("$basename".into()),
+            // Bare words are never expanded
+            Value::Str("foo$YAFSH_TEST_INTERP".into()),
+        ]
+    );
+}
+
 // ========== File I/O ==========
 
 #[test]
@@ -586,6 +884,23 @@ fn eval_append_file() {
     std::fs::remove_file(&path).ok();
 }
 
+#[test]
+fn eval_tee_keeps_output() {
+    let dir = std::env::temp_dir();
+    let path = dir.join("yafsh_eval_tee_test.txt");
+    let path_str = path.to_string_lossy().to_string();
+
+    let s = eval_lines(&[
+        "\"tee content\" >output",
+        &format!("\"{}\" tee", path_str),
+    ]);
+    assert_eq!(s.stack, vec![Value::output("tee content")]);
+
+    let contents = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(contents, "tee content");
+    std::fs::remove_file(&path).ok();
+}
+
 // ========== pushd/popd ==========
 
 #[test]
@@ -597,6 +912,302 @@ fn eval_pushd_popd() {
     assert_eq!(std::env::current_dir().unwrap(), original);
 }
 
+#[test]
+fn eval_pushd_swap_and_cd_dash() {
+    let original = std::env::current_dir().unwrap();
+    let original_str = original.to_string_lossy().to_string();
+
+    // No argument: pushd swaps with the top of the directory stack
+    let mut s = eval_lines(&["\"/tmp\" pushd", "pushd"]);
+    assert_eq!(std::env::current_dir().unwrap(), original);
+    assert_eq!(s.dir_stack, vec!["/tmp".to_string()]);
+
+    eval::eval_line(&mut s, "\"-\" cd").unwrap();
+    assert_eq!(std::env::current_dir().unwrap().to_string_lossy(), "/tmp");
+    assert_eq!(s.oldpwd.as_deref(), Some(original_str.as_str()));
+    assert_eq!(std::env::var("OLDPWD").unwrap(), original_str);
+
+    std::env::set_current_dir(&original).unwrap();
+}
+
+#[test]
+fn eval_autocd_only_when_enabled() {
+    let original = std::env::current_dir().unwrap();
+
+    // Off by default: the directory name is just a string
+    let s = eval_lines(&["/tmp"]);
+    assert_eq!(s.stack, vec![Value::Str("/tmp".into())]);
+    assert_eq!(std::env::current_dir().unwrap(), original);
+
+    let s = eval_lines(&["\"on\" set-autocd", "/tmp", "\"-\" cd"]);
+    assert!(s.stack.is_empty());
+    assert_eq!(std::env::current_dir().unwrap(), original);
+
+    // Only a directory alone on the line: as an argument or inside a word
+    // it stays a string
+    let s = eval_lines(&["\"on\" set-autocd", "/tmp 1", ": go /tmp ;", "go"]);
+    assert_eq!(
+        s.stack,
+        vec![Value::Str("/tmp".into()), Value::Int(1), Value::Str("/tmp".into())]
+    );
+    assert_eq!(std::env::current_dir().unwrap(), original);
+}
+
+#[test]
+#[cfg(feature = "native")]
+fn eval_tilde_expands_in_tokens_and_exec_args() {
+    let home = std::env::var("HOME").unwrap();
+    // Unquoted tokens expand; quoted ones stay literal until a command gets them
+    let s = eval_lines(&["~/yafsh-no-such-file \"~\""]);
+    assert_eq!(
+        s.stack,
+        vec![
+            Value::Str(format!("{}/yafsh-no-such-file", home)),
+            Value::Str("~".into()),
+        ]
+    );
+    let s = eval_lines(&["\"-n\" \"~/x\" \"a~\" echo"]);
+    assert_eq!(s.stack, vec![output_of("echo", 0, format!("{}/x a~", home))]);
+}
+
+#[test]
+#[cfg(feature = "native")]
+fn eval_psub_feeds_file_only_commands() {
+    let s = eval_lines(&["\"x\" echo psub \"y\" 1 echo psub \"diff\" exec"]);
+    assert_eq!(s.stack, vec![output_of("diff", 1, "1c1\n< x\n---\n> y\n")]);
+
+    // The temp file is gone once its line is done
+    let s = eval_lines(&["\"x\" >output psub"]);
+    match &s.stack[..] {
+        [Value::Str(path)] => assert!(!std::path::Path::new(path).exists()),
+        other => panic!("expected a path, got {:?}", other),
+    }
+    assert!(s.psub_files.is_empty());
+}
+
+#[test]
+#[cfg(feature = "native")]
+fn eval_stdin_from_file() {
+    let path = std::env::temp_dir().join("yafsh_eval_stdin_test.txt");
+    std::fs::write(&path, "b\na\n").unwrap();
+    let file = path.to_string_lossy().to_string();
+
+    // Outputs already on the stack are left alone
+    let s = eval_lines(&[&format!("\"x\" >output \"{}\" <stdin sort", file)]);
+    assert_eq!(
+        s.stack,
+        vec![Value::output("x"), output_of("sort", 0, "a\nb\n")]
+    );
+
+    // The redirect ends with its line
+    let s = eval_lines(&[&format!("\"{}\" <stdin", file), "\"-c\" wc"]);
+    assert_eq!(s.stack, vec![output_of("wc", 0, "0\n")]);
+    let _ = std::fs::remove_file(&path);
+
+    let mut s = new_state();
+    let err = eval::eval_line(&mut s, "\"/no/such/yafsh/file\" <stdin").unwrap_err();
+    assert!(err.starts_with("<stdin: /no/such/yafsh/file: "), "{}", err);
+    assert_eq!(s.stack, vec![Value::Str("/no/such/yafsh/file".into())]);
+}
+
+#[test]
+fn eval_sh_c_runs_one_liners() {
+    // Pipes and && chains run in sh; an Output below is stdin, a Str is left alone
+    assert_eq!(
+        eval("\"keep\" \"b a b\" >output \"tr ' ' '\\n' | sort -u && exit 2\" sh-c ?"),
+        vec![
+            Value::Str("keep".into()),
+            output_of("sh", 2, "a\nb\n"),
+            Value::Int(2)
+        ]
+    );
+
+    let mut s = new_state();
+    assert_eq!(eval::eval_line(&mut s, "1 sh-c"), Err("sh-c: requires a string".into()));
+    assert_eq!(s.stack, vec![Value::Int(1)]);
+}
+
+#[test]
+fn eval_with_mock_runner_needs_no_programs() {
+    let runner = MockRunner::new().on("git", 0, "main\n").on("wc", 0, "1\n");
+    let calls = runner.calls();
+    let mut s = new_state();
+    s.runner = Box::new(runner);
+    // Bare words resolve through the runner, not PATH, and auto-pipe as usual
+    eval::eval_line(&mut s, "\"branch\" \"--show-current\" git \"-l\" wc out-command").unwrap();
+    assert_eq!(
+        s.stack,
+        vec![output_of("wc", 0, "1\n"), Value::Str("wc".into())]
+    );
+    assert_eq!(calls.borrow().len(), 2);
+    assert_eq!(calls.borrow()[0].args, vec!["branch".to_string(), "--show-current".to_string()]);
+    assert_eq!(calls.borrow()[1].stdin, StdinSource::Data("main\n".into()));
+
+    // Anything the runner doesn't know isn't a command, even if it's on PATH
+    s.stack.clear();
+    eval::eval_line(&mut s, "ls").unwrap();
+    assert_eq!(s.stack, vec![Value::Str("ls".into())]);
+    assert_eq!(calls.borrow().len(), 2);
+}
+
+#[test]
+fn eval_captures_printed_output() {
+    let mut s = new_state();
+    s.out = Sink::capture();
+    eval::eval_line(&mut s, "2 3 + . \"a\" type \"b\" . .s").unwrap();
+    assert_eq!(s.out.take_captured(), "5\nab\n<0>\n");
+    assert_eq!(s.out.take_captured(), "");
+}
+
+#[test]
+#[cfg(feature = "native")]
+fn eval_spawn_detached_starts_own_session() {
+    let mut s = eval_lines(&["\"x\" >output \"30\" \"sleep\" spawn-detached"]);
+    let pid = match s.stack.pop() {
+        Some(Value::Int(pid)) => pid,
+        other => panic!("expected a pid, got {:?}", other),
+    };
+    // The Output isn't consumed, and the child leads a session of its own
+    assert_eq!(s.stack, vec![Value::output("x")]);
+    assert_eq!(unsafe { libc::getsid(pid as libc::pid_t) }, pid as libc::pid_t);
+    eval::eval_line(&mut s, &format!("{} \"KILL\" kill", pid)).unwrap();
+}
+
+#[cfg(feature = "native")]
+#[test]
+fn eval_merge_stderr() {
+    // Interleaved in the order written, with the exit code kept
+    let s = eval_lines(&[
+        "\"on\" merge-stderr",
+        "\"-c\" \"echo out; echo err >&2; echo out2; exit 3\" sh ?",
+    ]);
+    assert_eq!(
+        s.stack,
+        vec![output_of("sh", 3, "out\nerr\nout2\n"), Value::Int(3)]
+    );
+
+    // Piped stdin still works
+    let s = eval_lines(&["\"on\" merge-stderr", "\"x\" >output \"-c\" \"cat; echo err >&2\" sh"]);
+    assert_eq!(s.stack, vec![output_of("sh", 0, "xerr\n")]);
+
+    let s = eval_lines(&["\"on\" merge-stderr", "\"off\" merge-stderr", "\"-c\" \"echo err >&2\" sh"]);
+    assert_eq!(s.stack, vec![output_of("sh", 0, "")]);
+}
+
+#[cfg(feature = "native")]
+#[test]
+fn eval_output_remembers_exit_status() {
+    assert_eq!(
+        eval("\"-c\" \"echo hi; exit 4\" sh out-command swap out-status"),
+        vec![
+            Value::Str("sh".into()),
+            output_of("sh", 4, "hi\n"),
+            Value::Int(4)
+        ]
+    );
+
+    // Each par-exec output keeps its own status, whatever `?` says
+    let s = eval_lines(&["\"false\" \"true\" 2 par-exec out-status rot out-status"]);
+    assert_eq!(
+        s.stack,
+        vec![
+            output_of("true", 0, ""),
+            Value::Int(0),
+            output_of("false", 1, ""),
+            Value::Int(1)
+        ]
+    );
+    assert_eq!(s.last_exit_code, 1);
+
+    let mut s = new_state();
+    let result = eval::eval_line(&mut s, "\"x\" >output out-status");
+    assert_eq!(result, Err("out-status: output is not from a command".into()));
+    assert_eq!(s.stack, vec![Value::output("x")]);
+}
+
+// ========== on-not-found hook ==========
+
+#[test]
+fn eval_unknown_token_without_hook_is_string() {
+    assert_eq!(eval("gti"), vec![Value::Str("gti".into())]);
+}
+
+#[test]
+fn eval_not_found_hook_receives_token() {
+    let s = eval_lines(&[
+        ": on-not-found \"unknown: \" swap concat ;",
+        "gti",
+    ]);
+    assert_eq!(s.stack, vec![Value::Str("unknown: gti".into())]);
+}
+
+#[test]
+fn eval_not_found_hook_can_drop_and_does_not_recurse() {
+    // The hook body itself uses an unknown token, which must stay a plain string
+    let s = eval_lines(&[": on-not-found drop zzz-unknown ;", "gti status"]);
+    assert_eq!(
+        s.stack,
+        vec![Value::Str("zzz-unknown".into()), Value::Str("zzz-unknown".into())]
+    );
+}
+
+#[test]
+fn eval_not_found_hook_skips_known_tokens() {
+    let s = eval_lines(&[": on-not-found drop ;", "\"quoted\" 42"]);
+    assert_eq!(s.stack, vec![Value::Str("quoted".into()), Value::Int(42)]);
+}
+
+// ========== Aliases ==========
+
+#[test]
+#[cfg(feature = "native")]
+fn eval_alias_expands_in_place() {
+    let s = eval_lines(&["\"hello world echo\" \"greet\" alias", "greet"]);
+    match &s.stack[..] {
+        [Value::Output(out, _)] => assert_eq!(out.trim(), "hello world"),
+        other => panic!("expected one Output, got {:?}", other),
+    }
+}
+
+#[test]
+#[cfg(feature = "native")]
+fn eval_alias_can_shadow_its_command() {
+    // Inside its own expansion `echo` is the command, not the alias
+    let s = eval_lines(&["\"hi echo\" \"echo\" alias", "echo"]);
+    assert_eq!(s.stack, vec![output_of("echo", 0, "hi\n")]);
+}
+
+#[test]
+#[cfg(feature = "native")]
+fn eval_unalias_restores_command() {
+    let s = eval_lines(&["\"hi echo\" \"echo\" alias", "\"echo\" unalias", "echo"]);
+    assert_eq!(s.stack, vec![output_of("echo", 0, "\n")]);
+}
+
+// ========== Exec hooks ==========
+
+#[cfg(feature = "native")]
+#[test]
+fn eval_preexec_and_postexec_hooks() {
+    let s = eval_lines(&[
+        ": $preexec \"YAFSH_TEST_PREEXEC\" setenv ;",
+        ": $postexec >string \"YAFSH_TEST_POSTEXEC\" setenv drop ;",
+        "\"-c\" \"exit 3\" sh",
+    ]);
+    assert_eq!(std::env::var("YAFSH_TEST_PREEXEC").unwrap(), "sh -c exit 3");
+    assert_eq!(std::env::var("YAFSH_TEST_POSTEXEC").unwrap(), "3");
+    // Hooks leave the stack and exit code of the real command alone
+    assert_eq!(s.stack, vec![output_of("sh", 3, "")]);
+    assert_eq!(s.last_exit_code, 3);
+}
+
+#[test]
+#[cfg(feature = "native")]
+fn eval_exec_hook_errors_are_not_fatal() {
+    let s = eval_lines(&[": $preexec drop drop ;", "\"hi\" echo"]);
+    assert_eq!(s.stack, vec![output_of("echo", 0, "hi\n")]);
+}
+
 // ========== Introspection ==========
 
 #[test]
@@ -764,6 +1375,28 @@ fn eval_do_loop_nested_with_ij() {
     );
 }
 
+#[test]
+fn eval_do_loop_start_above_limit() {
+    // `loop` only counts up, so this runs zero times
+    let s = eval_lines(&["42 5 0 do i + loop"]);
+    assert_eq!(s.stack, vec![Value::Int(42)]);
+}
+
+#[test]
+fn eval_qdo_skips_equal_bounds() {
+    let s = eval_lines(&["42 3 3 ?do i + loop", ": sum 0 0 rot ?do i + loop ;", "0 sum 4 sum"]);
+    assert_eq!(s.stack, vec![Value::Int(42), Value::Int(0), Value::Int(6)]);
+}
+
+#[test]
+fn eval_qdo_nests_inside_do() {
+    let s = eval_lines(&["0 2 do 0 2 ?do j 10 * i + loop loop"]);
+    assert_eq!(
+        s.stack,
+        vec![Value::Int(0), Value::Int(1), Value::Int(10), Value::Int(11)]
+    );
+}
+
 // ========== do...+loop counted loops ==========
 
 #[test]
@@ -808,13 +1441,24 @@ fn eval_do_plus_loop_step_by_3() {
     );
 }
 
+#[test]
+fn eval_do_plus_loop_step_away_from_limit() {
+    let mut s = new_state();
+    let result = eval::eval_line(&mut s, "5 0 do 1 +loop");
+    assert_eq!(
+        result,
+        Err("+loop: step 1 never reaches limit 0 from 5".into())
+    );
+    assert!(eval::eval_line(&mut s, "0 10 do 0 +loop").is_err());
+}
+
 // ========== each...then ==========
 
 #[test]
 fn eval_each_iterates_lines() {
     // Create multi-line output and iterate
     let mut s = new_state();
-    s.stack.push(Value::Output("one\ntwo\nthree".into()));
+    s.stack.push(Value::output("one\ntwo\nthree"));
     eval::eval_line(&mut s, "each then").unwrap();
     // each pushes each line as Str, body is empty so they accumulate
     assert_eq!(
@@ -831,7 +1475,7 @@ fn eval_each_iterates_lines() {
 fn eval_each_with_body() {
     // Iterate and apply operations
     let mut s = new_state();
-    s.stack.push(Value::Output("hello\nworld".into()));
+    s.stack.push(Value::output("hello\nworld"));
     eval::eval_line(&mut s, "each \"!\" concat then").unwrap();
     assert_eq!(
         s.stack,
@@ -846,11 +1490,164 @@ fn eval_each_with_body() {
 fn eval_each_empty_output() {
     // Empty output: body never executes
     let mut s = new_state();
-    s.stack.push(Value::Output("".into()));
+    s.stack.push(Value::output(""));
     eval::eval_line(&mut s, "each . then").unwrap();
     assert!(s.stack.is_empty());
 }
 
+#[test]
+fn eval_each_index_with_i() {
+    let mut s = new_state();
+    s.stack.push(Value::output("a\nb\n"));
+    eval::eval_line(&mut s, "each drop i then").unwrap();
+    assert_eq!(s.stack, vec![Value::Int(0), Value::Int(1)]);
+    // j reaches an enclosing counted loop
+    s.stack.clear();
+    eval::eval_line(&mut s, "0 2 do \"x y\" each-word drop j then loop").unwrap();
+    assert_eq!(
+        s.stack,
+        vec![Value::Int(0), Value::Int(0), Value::Int(1), Value::Int(1)]
+    );
+}
+
+#[test]
+fn eval_each_word() {
+    let mut s = new_state();
+    s.stack.push(Value::output("  one two\n three\n"));
+    eval::eval_line(&mut s, "each-word \"!\" concat then").unwrap();
+    assert_eq!(
+        s.stack,
+        vec![
+            Value::Str("one!".into()),
+            Value::Str("two!".into()),
+            Value::Str("three!".into())
+        ]
+    );
+}
+
+#[test]
+fn eval_each_char() {
+    let s = eval_lines(&["\"ab\" >output each-char then", "\"c\" each-char then"]);
+    assert_eq!(
+        s.stack,
+        vec![
+            Value::Str("a".into()),
+            Value::Str("b".into()),
+            Value::Str("c".into())
+        ]
+    );
+    let mut s = new_state();
+    s.stack.push(Value::output("é\n"));
+    eval::eval_line(&mut s, "each-char then").unwrap();
+    assert_eq!(s.stack, vec![Value::Str("é".into())]);
+}
+
+#[test]
+fn eval_each_word_wrong_type() {
+    let mut s = new_state();
+    s.stack.push(Value::Int(3));
+    let result = eval::eval_line(&mut s, "each-word then");
+    assert_eq!(result, Err("each-word: requires string or output".into()));
+    assert_eq!(s.stack, vec![Value::Int(3)]);
+}
+
+#[test]
+fn eval_skipped_each_inside_if() {
+    let s = eval_lines(&["\"a\" >output 0 if each . then then 5"]);
+    assert_eq!(s.stack, vec![Value::output("a"), Value::Int(5)]);
+}
+
+#[test]
+fn eval_nested_loops_in_definition() {
+    let s = eval_lines(&[
+        ": grid 0 0 3 do 0 3 do i j * + loop loop ;",
+        ": count 0 begin 0 2 do 1 + loop dup 6 = until ;",
+        "grid count",
+    ]);
+    assert_eq!(s.stack, vec![Value::Int(9), Value::Int(6)]);
+}
+
+#[test]
+fn eval_nested_while_loops_in_definition() {
+    let s = eval_lines(&[
+        ": tri 0 swap begin dup 0 > while dup begin dup 0 > while rot 1 + rot rot 1 - repeat drop 1 - repeat drop ;",
+        "3 tri",
+    ]);
+    assert_eq!(s.stack, vec![Value::Int(6)]);
+}
+
+#[test]
+fn eval_loops_in_definition_inside_if_and_each() {
+    let s = eval_lines(&[
+        ": maybe if 0 3 do i loop then ;",
+        "0 maybe 1 maybe",
+        ": marks each drop 0 2 do i loop then ;",
+        "\"a\nb\" >output marks",
+    ]);
+    let expected: Vec<Value> = [0, 1, 2, 0, 1, 0, 1].into_iter().map(Value::Int).collect();
+    assert_eq!(s.stack, expected);
+}
+
+#[test]
+fn eval_mixed_nesting_typed_at_prompt() {
+    let s = eval_lines(&["0 begin 0 2 do loop 1 + dup 3 = until"]);
+    assert_eq!(s.stack, vec![Value::Int(3)]);
+}
+
+#[test]
+fn eval_begin_again_with_leave() {
+    let s = eval_lines(&["0 begin 1 + dup 5 = if leave then again"]);
+    assert_eq!(s.stack, vec![Value::Int(5)]);
+    assert!(s.loop_stack.is_empty());
+    assert!(!s.leave_requested);
+
+    // An intended forever loop isn't cut off by the iteration limit
+    let s = eval_lines(&["3 set-max-iterations", "0 begin 1 + dup 5 = if leave then again"]);
+    assert_eq!(s.stack, vec![Value::Int(5)]);
+}
+
+#[test]
+fn eval_leave_stops_only_innermost_loop() {
+    let s = eval_lines(&[
+        ": f 0 3 do 0 10 do i 1 = if leave then j 10 * i + loop loop ;",
+        "f",
+    ]);
+    assert_eq!(s.stack, vec![Value::Int(0), Value::Int(10), Value::Int(20)]);
+}
+
+#[test]
+fn eval_leave_from_word_inside_loops() {
+    let s = eval_lines(&[
+        ": stop? dup 3 = if leave then ;",
+        "0 begin 1 + stop? again",
+        "0 5 do i stop? drop loop 99",
+        "0 begin dup 3 < while 1 + stop? repeat",
+        ": third? i 2 = if leave then ;",
+        "\"a b c d\" each-word third? then",
+    ]);
+    assert_eq!(
+        s.stack,
+        vec![
+            Value::Int(3),
+            Value::Int(3),
+            Value::Int(99),
+            Value::Int(3),
+            Value::Str("a".into()),
+            Value::Str("b".into()),
+            Value::Str("c".into()),
+        ]
+    );
+}
+
+#[test]
+fn eval_exit_stops_again() {
+    let mut s = new_state();
+    let result = eval::eval_line(&mut s, "0 begin 1 + dup 3 = if 7 exit then again");
+    assert_eq!(result, Err("exit".into()));
+    assert_eq!(s.exit_requested, Some(7));
+    assert_eq!(s.stack, vec![Value::Int(3)]);
+}
+
 // ========== Loop error handling ==========
 
 #[test]
@@ -861,6 +1658,19 @@ fn eval_until_without_begin() {
     assert!(result.unwrap_err().contains("no matching begin"));
 }
 
+#[test]
+fn eval_again_without_begin_and_leave_outside_loop() {
+    let mut s = new_state();
+    assert_eq!(
+        eval::eval_line(&mut s, "again"),
+        Err("again: no matching begin".into())
+    );
+    assert_eq!(
+        eval::eval_line(&mut s, "leave"),
+        Err("leave: not inside a loop".into())
+    );
+}
+
 #[test]
 fn eval_repeat_without_begin() {
     let mut s = new_state();
@@ -943,6 +1753,31 @@ fn eval_each_wrong_type() {
     assert!(result.unwrap_err().contains("requires Output"));
 }
 
+#[test]
+fn eval_runaway_loops_hit_iteration_cap() {
+    let mut s = new_state();
+    eval::eval_line(&mut s, "100 set-max-iterations").unwrap();
+    let result = eval::eval_line(&mut s, "begin 0 until");
+    assert_eq!(
+        result,
+        Err("begin: stopped after 100 iterations (see set-max-iterations)".into())
+    );
+    s.stack.clear();
+    assert!(eval::eval_line(&mut s, "begin 1 while repeat").is_err());
+    s.stack.clear();
+    assert!(eval::eval_line(&mut s, "0 1000 do loop").is_err());
+    s.stack.clear();
+    assert!(eval::eval_line(&mut s, "0 1000 do 1 +loop").is_err());
+}
+
+#[test]
+fn eval_iteration_cap_allows_exact_count() {
+    let mut s = new_state();
+    eval::eval_line(&mut s, "5 set-max-iterations").unwrap();
+    eval::eval_line(&mut s, "0 0 5 do 1 + loop").unwrap();
+    assert_eq!(s.stack, vec![Value::Int(5)]);
+}
+
 // ========== Conditional string helpers ==========
 
 #[test]
@@ -1058,6 +1893,7 @@ fn eval_dollar_username() {
 }
 
 #[test]
+#[cfg(feature = "native")]
 fn eval_dollar_exitcode() {
     let s = eval_lines(&["/bin/false", "$exitcode"]);
     // Stack: Output from /bin/false, then "1"
@@ -1185,6 +2021,58 @@ fn eval_trace_numeric_levels() {
     assert_eq!(s.trace, 0);
 }
 
+#[test]
+fn eval_trace_file_json_lines() {
+    let path = std::env::temp_dir().join(format!("yafsh_trace_{}.jsonl", std::process::id()));
+    let mut s = new_state();
+    s.stack.push(Value::Str(path.display().to_string()));
+    eval::eval_line(&mut s, "trace-file").unwrap();
+    eval::eval_line(&mut s, "\"a\tb\" 2 \"x\" +").unwrap_err();
+    eval::eval_line(&mut s, "\"\" trace-file").unwrap();
+    assert!(s.trace_file.is_none());
+
+    let text = std::fs::read_to_string(&path).unwrap();
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines.len(), 5); // the last is the "" pushed to close it
+    assert!(lines[0].starts_with(
+        "{\"step\":1,\"token\":\"a\\tb\",\"quoted\":true,\"pops\":[],\"pushes\":[\"a\\tb\"],\"depth\":1,\"micros\":"
+    ));
+    assert!(lines[1].contains("\"pushes\":[2],\"depth\":2,"));
+    assert!(lines[3].starts_with("{\"step\":4,\"token\":\"+\","));
+    assert!(lines[3].ends_with(",\"error\":\"+: requires two integers\"}"));
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+#[cfg(feature = "native")]
+fn eval_restricted_mode_blocks_side_effects() {
+    let path = std::env::temp_dir().join(format!("yafsh_restricted_{}.txt", std::process::id()));
+    let mut s = new_state();
+    eval::eval_line(&mut s, "\"on\" set-restricted").unwrap();
+    eval::eval_line(&mut s, "2 3 + \"a\" \"b\" concat").unwrap();
+    s.stack.push(Value::output("x"));
+    s.stack.push(Value::Str(path.display().to_string()));
+    assert_eq!(
+        eval::eval_line(&mut s, ">file"),
+        Err(">file: not allowed in restricted mode".into())
+    );
+    assert!(!path.exists());
+    for (line, op) in [
+        ("\"x\" \"touch\" exec", "touch"),
+        ("\"true\" 1 par-exec", "par-exec"),
+        ("\"http://localhost\" http-get", "http-get"),
+        ("\"x\" >clip", ">clip"),
+        ("\"x\" psub", "psub"),
+    ] {
+        assert_eq!(
+            eval::eval_line(&mut s, line),
+            Err(format!("{}: not allowed in restricted mode", op)),
+            "{}",
+            line
+        );
+    }
+}
+
 #[test]
 fn eval_trace_invalid_level() {
     let mut s = new_state();
@@ -1200,7 +2088,7 @@ fn eval_trace_wrong_arg() {
 #[test]
 fn eval_trace_wrong_type() {
     let mut s = new_state();
-    s.stack.push(Value::Output("data".into()));
+    s.stack.push(Value::output("data"));
     assert!(eval::eval_line(&mut s, "trace").is_err());
 }
 
@@ -1232,6 +2120,17 @@ fn eval_trace_does_not_affect_string_ops() {
     );
 }
 
+#[test]
+fn eval_trace_long_multibyte_output() {
+    // Byte 27 falls inside a character; the trace cuts at a grapheme instead
+    for level in 1..=3u8 {
+        let mut s = new_state();
+        s.trace = level;
+        eval::eval_line(&mut s, "\"ééééééééééééééééééééé\" >output dup drop").unwrap();
+        assert_eq!(s.stack.len(), 1);
+    }
+}
+
 #[test]
 fn eval_trace_step_counter_resets_per_line() {
     let mut s = new_state();